@@ -7,4 +7,15 @@ pub struct Cli {
     pub port: u16,
     #[clap(long, value_parser = value_parser!(SocketAddr))]
     pub replicaof: Option<SocketAddr>,
+    // how many times per second background tasks such as the active expiration run
+    #[clap(long, default_value = "10", value_parser = value_parser!(u32).range(1..=500))]
+    pub hz: u32,
+    // the number of logical databases, selected with SELECT
+    #[clap(long, default_value = "16", value_parser = value_parser!(u32).range(1..))]
+    pub databases: u32,
+    // the directory of the RDB file, written by SAVE and loaded on startup
+    #[clap(long, default_value = ".")]
+    pub dir: String,
+    #[clap(long, default_value = "dump.rdb")]
+    pub dbfilename: String,
 }
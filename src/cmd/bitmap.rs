@@ -0,0 +1,551 @@
+use super::{wrong_args, CmdExecutor};
+use crate::{
+    db::{
+        bit_pos, count_bits, get_bit, popcount, set_bit, string_or_create, BitField, BitOp, Db,
+        Overflow, Value,
+    },
+    frame::Frame,
+    util::{bytes_to_i64, bytes_to_string},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use bytes::Bytes;
+use tracing::debug;
+
+// the greatest bit offset, strings are at most 512MB long
+const MAX_BIT_OFFSET: i64 = 512 * 1024 * 1024 * 8 - 1;
+
+pub fn parse_bit_offset(bulk: Bytes) -> Result<usize> {
+    match bytes_to_i64(bulk) {
+        Ok(offset) if (0..=MAX_BIT_OFFSET).contains(&offset) => Ok(offset as usize),
+        _ => bail!("ERR bit offset is not an integer or out of range"),
+    }
+}
+
+// A range of BITCOUNT and BITPOS, in bytes unless `bit` is set. The indexes may be negative to
+// count from the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitRange {
+    pub start: i64,
+    // None if only the start is given, the range goes on until the end
+    pub end: Option<i64>,
+    pub bit: bool,
+}
+
+impl BitRange {
+    // parse `start [end [BYTE | BIT]]`
+    fn parse(args: &[Bytes]) -> Result<Self> {
+        let bit = match args.get(2) {
+            Some(unit) if unit.eq_ignore_ascii_case(b"byte") => false,
+            Some(unit) if unit.eq_ignore_ascii_case(b"bit") => true,
+            Some(_) => bail!("ERR syntax error"),
+            None => false,
+        };
+        Ok(BitRange {
+            start: bytes_to_i64(args[0].clone())?,
+            end: args
+                .get(1)
+                .map(|end| bytes_to_i64(end.clone()))
+                .transpose()?,
+            bit,
+        })
+    }
+
+    // the range of bits of a string of `len` bytes, None if it's empty
+    fn bits(&self, len: usize) -> Option<(usize, usize)> {
+        let len = if self.bit { len * 8 } else { len } as i64;
+        let normalize = |index: i64| {
+            if index < 0 {
+                (index + len).max(0)
+            } else {
+                index
+            }
+        };
+        let start = normalize(self.start);
+        let end = normalize(self.end.unwrap_or(-1)).min(len - 1);
+        if start > end {
+            return None;
+        }
+        Some(match self.bit {
+            true => (start as usize, end as usize),
+            false => (start as usize * 8, end as usize * 8 + 7),
+        })
+    }
+}
+
+// https://redis.io/commands/setbit/
+// SETBIT key offset value, the string is padded with zeros up to the offset
+// return: :<the previous bit>\r\n
+pub struct SetBit {
+    pub key: String,
+    pub offset: usize,
+    pub bit: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SetBit {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SETBIT'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let value = string_or_create(string_db, &self.key).await?;
+        // the buffer isn't copied unless it's shared
+        let mut bytes = Vec::from(std::mem::take(value));
+        let len = bytes.len();
+        let previous = set_bit(&mut bytes, self.offset, self.bit);
+        let modified = previous != self.bit || bytes.len() != len;
+        *value = bytes.into();
+        if modified {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(previous as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SetBit {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            return Err(wrong_args("setbit"));
+        }
+        let bit = match bulks[3].as_ref() {
+            b"0" => false,
+            b"1" => true,
+            _ => bail!("ERR bit is not an integer or out of range"),
+        };
+        Ok(SetBit {
+            key: bytes_to_string(bulks[1].clone())?,
+            offset: parse_bit_offset(bulks[2].clone())?,
+            bit,
+        })
+    }
+}
+
+// https://redis.io/commands/getbit/
+// GETBIT key offset
+// return: :<the bit>\r\n, :0\r\n past the end of the string or if the key doesn't exist
+pub struct GetBit {
+    pub key: String,
+    pub offset: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GetBit {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'GETBIT'");
+        let mut inner = db.lock().await;
+        let bit = match inner.string_db().get(&self.key).await? {
+            Some(bytes) => get_bit(&bytes, self.offset),
+            None => false,
+        };
+        Ok(Frame::Integer(bit as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for GetBit {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            return Err(wrong_args("getbit"));
+        }
+        Ok(GetBit {
+            key: bytes_to_string(bulks[1].clone())?,
+            offset: parse_bit_offset(bulks[2].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/bitcount/
+// BITCOUNT key [start end [BYTE | BIT]]
+// return: :<the number of bits set>\r\n
+pub struct BitCount {
+    pub key: String,
+    pub range: Option<BitRange>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BitCount {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'BITCOUNT'");
+        let mut inner = db.lock().await;
+        let bytes = inner.string_db().get(&self.key).await?.unwrap_or_default();
+        let count = match self.range {
+            Some(range) => match range.bits(bytes.len()) {
+                Some((start, end)) => count_bits(&bytes, start..=end),
+                None => 0,
+            },
+            None => popcount(&bytes),
+        };
+        Ok(Frame::Integer(count as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for BitCount {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let range = match bulks.len() {
+            2 => None,
+            4 | 5 => Some(BitRange::parse(&bulks[2..])?),
+            3 => bail!("ERR syntax error"),
+            _ => return Err(wrong_args("bitcount")),
+        };
+        Ok(BitCount {
+            key: bytes_to_string(bulks[1].clone())?,
+            range,
+        })
+    }
+}
+
+// https://redis.io/commands/bitpos/
+// BITPOS key bit [start [end [BYTE | BIT]]]
+// Without an end, the string is considered padded with zeros when looking for a 0.
+// return: :<the position of the first bit>\r\n, or :-1\r\n if there is none
+pub struct BitPos {
+    pub key: String,
+    pub bit: bool,
+    pub range: Option<BitRange>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BitPos {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'BITPOS'");
+        let mut inner = db.lock().await;
+        let bytes = match inner.string_db().get(&self.key).await? {
+            Some(bytes) => bytes,
+            None => return Ok(Frame::Integer(if self.bit { -1 } else { 0 })),
+        };
+        let range = self.range.unwrap_or(BitRange {
+            start: 0,
+            end: None,
+            bit: false,
+        });
+        let pos = match range.bits(bytes.len()) {
+            Some((start, end)) => match bit_pos(&bytes, self.bit, start..=end) {
+                Some(pos) => pos as i64,
+                // the first 0 of the padding
+                None if !self.bit && range.end.is_none() => bytes.len() as i64 * 8,
+                None => -1,
+            },
+            None => -1,
+        };
+        Ok(Frame::Integer(pos))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for BitPos {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if !(3..=6).contains(&bulks.len()) {
+            return Err(wrong_args("bitpos"));
+        }
+        let bit = match bytes_to_i64(bulks[2].clone())? {
+            0 => false,
+            1 => true,
+            _ => bail!("ERR The bit argument must be 1 or 0."),
+        };
+        let range = match bulks.len() {
+            3 => None,
+            _ => Some(BitRange::parse(&bulks[3..])?),
+        };
+        Ok(BitPos {
+            key: bytes_to_string(bulks[1].clone())?,
+            bit,
+            range,
+        })
+    }
+}
+
+impl TryFrom<Bytes> for BitOp {
+    type Error = Error;
+
+    fn try_from(value: Bytes) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_slice() {
+            b"and" => Ok(BitOp::And),
+            b"or" => Ok(BitOp::Or),
+            b"xor" => Ok(BitOp::Xor),
+            b"not" => Ok(BitOp::Not),
+            b"diff" => Ok(BitOp::Diff),
+            b"andor" => Ok(BitOp::AndOr),
+            b"one" => Ok(BitOp::One),
+            _ => Err(anyhow!("ERR syntax error")),
+        }
+    }
+}
+
+// https://redis.io/commands/bitop/
+// BITOP <AND | OR | XOR | NOT | DIFF | ANDOR | ONE> destkey key [key ...]
+// The missing keys are considered empty strings, the destination is deleted if the result is
+// empty.
+// return: :<the length of the string stored at destkey>\r\n
+pub struct BitOpCmd {
+    pub op: BitOp,
+    pub destination: String,
+    pub keys: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BitOpCmd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'BITOP'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let mut sources = Vec::with_capacity(self.keys.len());
+        for key in self.keys.iter() {
+            sources.push(string_db.get(key).await?.unwrap_or_default());
+        }
+        let sources: Vec<&[u8]> = sources.iter().map(|source| source.as_ref()).collect();
+
+        let res = self.op.apply(&sources);
+        let len = res.len();
+        if res.is_empty() {
+            string_db.del(&self.destination).await;
+        } else {
+            string_db
+                .insert_value(self.destination, Value::String(res.into()))
+                .await;
+        }
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for BitOpCmd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 4 {
+            return Err(wrong_args("bitop"));
+        }
+        let op = BitOp::try_from(bulks[1].clone())?;
+        let keys = bulks[3..]
+            .iter()
+            .map(|key| bytes_to_string(key.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        match op {
+            BitOp::Not if keys.len() != 1 => {
+                bail!("ERR BITOP NOT must be called with a single source key.")
+            }
+            BitOp::Diff | BitOp::AndOr if keys.len() < 2 => bail!(
+                "ERR BITOP {} must be called with at least two source keys.",
+                String::from_utf8_lossy(&bulks[1]).to_uppercase()
+            ),
+            _ => {}
+        }
+        Ok(BitOpCmd {
+            op,
+            destination: bytes_to_string(bulks[2].clone())?,
+            keys,
+        })
+    }
+}
+
+// a subcommand of BITFIELD, with the overflow behavior in effect for SET and INCRBY
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitFieldOp {
+    Get {
+        field: BitField,
+        offset: usize,
+    },
+    Set {
+        field: BitField,
+        offset: usize,
+        value: i64,
+        overflow: Overflow,
+    },
+    IncrBy {
+        field: BitField,
+        offset: usize,
+        incr: i64,
+        overflow: Overflow,
+    },
+}
+
+impl BitFieldOp {
+    fn write_end(&self) -> Option<usize> {
+        match self {
+            BitFieldOp::Get { .. } => None,
+            BitFieldOp::Set { field, offset, .. } | BitFieldOp::IncrBy { field, offset, .. } => {
+                Some(offset + field.bits as usize)
+            }
+        }
+    }
+}
+
+// parse an encoding like `i16` or `u8`
+fn parse_bit_field(bulk: &[u8]) -> Result<BitField> {
+    let invalid = || {
+        anyhow!("ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.")
+    };
+    let (signed, bits) = match bulk {
+        [b'i' | b'I', bits @ ..] => (true, bits),
+        [b'u' | b'U', bits @ ..] => (false, bits),
+        _ => return Err(invalid()),
+    };
+    let bits: u32 = std::str::from_utf8(bits)
+        .ok()
+        .and_then(|bits| bits.parse().ok())
+        .ok_or_else(invalid)?;
+    match (signed, bits) {
+        (true, 1..=64) | (false, 1..=63) => Ok(BitField { signed, bits }),
+        _ => Err(invalid()),
+    }
+}
+
+// parse an offset in bits, or in integers of the field if it starts with `#`
+fn parse_field_offset(bulk: &Bytes, field: BitField) -> Result<usize> {
+    let offset = match bulk.strip_prefix(b"#") {
+        Some(index) => parse_bit_offset(Bytes::copy_from_slice(index))? * field.bits as usize,
+        None => parse_bit_offset(bulk.clone())?,
+    };
+    if offset.saturating_add(field.bits as usize) > MAX_BIT_OFFSET as usize + 1 {
+        bail!("ERR bit offset is not an integer or out of range")
+    }
+    Ok(offset)
+}
+
+// https://redis.io/commands/bitfield/
+// https://redis.io/commands/bitfield_ro/
+// BITFIELD key [GET encoding offset | [OVERFLOW <WRAP | SAT | FAIL>] <SET encoding offset value |
+//   INCRBY encoding offset increment> [GET encoding offset | ...]]
+// BITFIELD_RO key [GET encoding offset [GET encoding offset ...]]
+// return: *<n>\r\n:<the value read, the previous value or the new value>\r\n..., with $-1\r\n
+//   for the writes that overflow with OVERFLOW FAIL
+pub struct BitFieldCmd {
+    pub key: String,
+    pub ops: Vec<BitFieldOp>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BitFieldCmd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'BITFIELD'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let write_end = self.ops.iter().filter_map(|op| op.write_end()).max();
+        let write_end = match write_end {
+            Some(write_end) => write_end,
+            // the key isn't created by reads
+            None => {
+                let bytes = string_db.get(&self.key).await?.unwrap_or_default();
+                let values = self.ops.iter().map(|op| match op {
+                    BitFieldOp::Get { field, offset } => Frame::Integer(field.get(&bytes, *offset)),
+                    _ => unreachable!(),
+                });
+                return Ok(Frame::Array(values.collect()));
+            }
+        };
+
+        let value = string_or_create(string_db, &self.key).await?;
+        let mut bytes = Vec::from(std::mem::take(value));
+        // grown once for all the writes, even those that fail
+        if bytes.len() < write_end.div_ceil(8) {
+            bytes.resize(write_end.div_ceil(8), 0);
+        }
+        let mut res = Vec::with_capacity(self.ops.len());
+        let mut changes = 0;
+        for op in self.ops {
+            res.push(match op {
+                BitFieldOp::Get { field, offset } => Frame::Integer(field.get(&bytes, offset)),
+                BitFieldOp::Set {
+                    field,
+                    offset,
+                    value,
+                    overflow,
+                } => match field.add(value, 0, overflow) {
+                    Some(value) => {
+                        let previous = field.get(&bytes, offset);
+                        field.set(&mut bytes, offset, value);
+                        changes += 1;
+                        Frame::Integer(previous)
+                    }
+                    None => Frame::Null,
+                },
+                BitFieldOp::IncrBy {
+                    field,
+                    offset,
+                    incr,
+                    overflow,
+                } => match field.add(field.get(&bytes, offset), incr, overflow) {
+                    Some(value) => {
+                        field.set(&mut bytes, offset, value);
+                        changes += 1;
+                        Frame::Integer(value)
+                    }
+                    None => Frame::Null,
+                },
+            });
+        }
+        *value = bytes.into();
+        if changes > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Array(res))
+    }
+}
+
+impl BitFieldCmd {
+    // `read_only` distinguishes BITFIELD_RO, which only accepts GET, from BITFIELD
+    pub fn parse(bulks: Vec<Bytes>, read_only: bool) -> Result<Self> {
+        if bulks.len() < 2 {
+            return Err(wrong_args(if read_only {
+                "bitfield_ro"
+            } else {
+                "bitfield"
+            }));
+        }
+        let mut ops = vec![];
+        let mut overflow = Overflow::default();
+        let mut i = 2;
+        while i < bulks.len() {
+            let subcommand = bulks[i].to_ascii_lowercase();
+            if read_only && subcommand != b"get" {
+                bail!("ERR BITFIELD_RO only supports the GET subcommand")
+            }
+            let args = match subcommand.as_slice() {
+                b"get" => 2,
+                b"set" | b"incrby" => 3,
+                b"overflow" => 1,
+                _ => bail!("ERR syntax error"),
+            };
+            if i + args >= bulks.len() {
+                bail!("ERR syntax error")
+            }
+            if subcommand == b"overflow" {
+                overflow = match bulks[i + 1].to_ascii_lowercase().as_slice() {
+                    b"wrap" => Overflow::Wrap,
+                    b"sat" => Overflow::Sat,
+                    b"fail" => Overflow::Fail,
+                    _ => bail!("ERR Invalid OVERFLOW type specified"),
+                };
+                i += 2;
+                continue;
+            }
+
+            let field = parse_bit_field(&bulks[i + 1])?;
+            let offset = parse_field_offset(&bulks[i + 2], field)?;
+            ops.push(match subcommand.as_slice() {
+                b"get" => BitFieldOp::Get { field, offset },
+                b"set" => BitFieldOp::Set {
+                    field,
+                    offset,
+                    value: bytes_to_i64(bulks[i + 3].clone())?,
+                    overflow,
+                },
+                _ => BitFieldOp::IncrBy {
+                    field,
+                    offset,
+                    incr: bytes_to_i64(bulks[i + 3].clone())?,
+                    overflow,
+                },
+            });
+            i += args + 1;
+        }
+        Ok(BitFieldCmd {
+            key: bytes_to_string(bulks[1].clone())?,
+            ops,
+        })
+    }
+}
@@ -1,8 +1,13 @@
 use super::CmdExecutor;
-use crate::{db::Db, frame::Frame, CONFIG};
-use anyhow::{anyhow, Error, Result};
+use crate::{
+    db::Db,
+    frame::Frame,
+    util::{bytes_to_i64, bytes_to_string, to_unix_millis},
+    CONFIG,
+};
+use anyhow::{anyhow, bail, Error, Result};
 use bytes::Bytes;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::debug;
 
 // *2\r\n$7\r\nCOMMAND\r\n$4\r\nDOCS\r\n
@@ -55,7 +60,7 @@ impl CmdExecutor for Get {
     async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
         debug!("executing command 'GET'");
         Ok(
-            match db.inner.lock().await.string_db.get(self.key.as_ref()).await {
+            match db.lock().await.string_db().get(self.key.as_ref()).await? {
                 Some(value) => Frame::Bulk(value),
                 None => Frame::Null,
             },
@@ -74,16 +79,408 @@ pub struct Set {
 impl CmdExecutor for Set {
     async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
         debug!("executing command 'SET'");
-        db.inner
-            .lock()
+        db.lock()
             .await
-            .string_db
-            .set(self.key, self.value.clone(), self.expire)
+            .string_db()
+            .set(self.key, self.value.clone(), self.expire, self.keep_ttl)
             .await;
         Ok(Frame::Simple("OK".to_string()))
     }
 }
 
+// https://redis.io/commands/mget/
+// *3\r\n$4\r\nmget\r\n$4\r\nkey1\r\n$4\r\nkey2\r\n
+// return: *2\r\n$6\r\nvalue1\r\n$-1\r\n
+pub struct MGet {
+    pub keys: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for MGet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'MGET'");
+        let mut inner = db.lock().await;
+        let mut values = Vec::with_capacity(self.keys.len());
+        for key in self.keys.iter() {
+            // a key holding another type is reported as missing
+            values.push(match inner.string_db().get(key).await {
+                Ok(Some(value)) => Frame::Bulk(value),
+                _ => Frame::Null,
+            });
+        }
+        Ok(Frame::Array(values))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for MGet {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            bail!("ERR wrong number of arguments for 'mget' command")
+        }
+        let keys = bulks
+            .into_iter()
+            .skip(1)
+            .map(bytes_to_string)
+            .collect::<Result<_>>()?;
+        Ok(MGet { keys })
+    }
+}
+
+// https://redis.io/commands/mset/
+// *5\r\n$4\r\nmset\r\n$4\r\nkey1\r\n$6\r\nvalue1\r\n$4\r\nkey2\r\n$6\r\nvalue2\r\n
+// return: +OK\r\n
+pub struct MSet {
+    pub pairs: Vec<(String, Bytes)>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for MSet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'MSET'");
+        let mut inner = db.lock().await;
+        for (key, value) in self.pairs {
+            inner.string_db().set(key, value, None, false).await;
+        }
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for MSet {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        Ok(MSet {
+            pairs: parse_pairs(bulks, "mset")?,
+        })
+    }
+}
+
+// https://redis.io/commands/msetnx/
+// return(all keys were set): :1\r\n
+// return(at least one key already exists, nothing was set): :0\r\n
+pub struct MSetNx {
+    pub pairs: Vec<(String, Bytes)>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for MSetNx {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'MSETNX'");
+        // hold the lock across the check and the writes so that no other client can create one
+        // of the keys in between
+        let mut inner = db.lock().await;
+        for (key, _) in self.pairs.iter() {
+            if inner.string_db().check_exist(key).await {
+                return Ok(Frame::Integer(0));
+            }
+        }
+        for (key, value) in self.pairs {
+            inner.string_db().set(key, value, None, false).await;
+        }
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for MSetNx {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        Ok(MSetNx {
+            pairs: parse_pairs(bulks, "msetnx")?,
+        })
+    }
+}
+
+// parse `<cmd> key value [key value ...]`
+fn parse_pairs(bulks: Vec<Bytes>, cmd_name: &str) -> Result<Vec<(String, Bytes)>> {
+    if bulks.len() < 3 || bulks.len().is_multiple_of(2) {
+        bail!("ERR wrong number of arguments for '{cmd_name}' command")
+    }
+    let mut pairs = Vec::with_capacity(bulks.len() / 2);
+    let mut iter = bulks.into_iter().skip(1);
+    while let (Some(key), Some(value)) = (iter.next(), iter.next()) {
+        pairs.push((bytes_to_string(key)?, value));
+    }
+    Ok(pairs)
+}
+
+// https://redis.io/commands/setnx/
+// return(the key was set): :1\r\n
+// return(the key already exists): :0\r\n
+pub struct SetNx {
+    pub key: String,
+    pub value: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SetNx {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SETNX'");
+        let mut inner = db.lock().await;
+        if inner.string_db().check_exist(&self.key).await {
+            return Ok(Frame::Integer(0));
+        }
+        inner
+            .string_db()
+            .set(self.key, self.value, None, false)
+            .await;
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SetNx {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            bail!("ERR wrong number of arguments for 'setnx' command")
+        }
+        Ok(SetNx {
+            key: bytes_to_string(bulks[1].clone())?,
+            value: bulks[2].clone(),
+        })
+    }
+}
+
+// parse the expire time of SET, SETEX or GETEX and return it in milliseconds. Like EXPIRE, the
+// unix time in milliseconds the key expires at must fit in an i64, which keeps the SystemTime
+// arithmetic from overflowing. `absolute` is set for a unix time rather than a time to live
+pub fn parse_expire(bulk: Bytes, in_millis: bool, absolute: bool, cmd_name: &str) -> Result<u64> {
+    let time = bytes_to_i64(bulk)?;
+    let millis = if in_millis {
+        Some(time)
+    } else {
+        time.checked_mul(1000)
+    };
+    let now = if absolute {
+        0
+    } else {
+        to_unix_millis(SystemTime::now())
+    };
+    match millis {
+        Some(millis) if millis > 0 && now.checked_add(millis).is_some() => Ok(millis as u64),
+        _ => bail!("ERR invalid expire time in '{cmd_name}' command"),
+    }
+}
+
+// https://redis.io/commands/setex/
+// https://redis.io/commands/psetex/
+// SETEX key seconds value, PSETEX key milliseconds value
+// return: +OK\r\n
+pub struct SetEx {
+    pub key: String,
+    pub value: Bytes,
+    pub expire: Duration,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SetEx {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SETEX'");
+        db.lock()
+            .await
+            .string_db()
+            .set(self.key, self.value, Some(self.expire), false)
+            .await;
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl SetEx {
+    // `in_millis` distinguishes PSETEX from SETEX
+    pub fn parse(bulks: Vec<Bytes>, in_millis: bool) -> Result<Self> {
+        let cmd_name = if in_millis { "psetex" } else { "setex" };
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        let expire = parse_expire(bulks[2].clone(), in_millis, false, cmd_name)?;
+        Ok(SetEx {
+            key: bytes_to_string(bulks[1].clone())?,
+            value: bulks[3].clone(),
+            expire: Duration::from_millis(expire),
+        })
+    }
+}
+
+// https://redis.io/commands/getset/
+// return(the key exesits): the old value
+// return(the key doesn't exesit): $-1\r\n
+pub struct GetSet {
+    pub key: String,
+    pub value: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GetSet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'GETSET'");
+        let mut inner = db.lock().await;
+        let old = inner.string_db().get(&self.key).await?;
+        inner
+            .string_db()
+            .set(self.key, self.value, None, false)
+            .await;
+        Ok(match old {
+            Some(value) => Frame::Bulk(value),
+            None => Frame::Null,
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for GetSet {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            bail!("ERR wrong number of arguments for 'getset' command")
+        }
+        Ok(GetSet {
+            key: bytes_to_string(bulks[1].clone())?,
+            value: bulks[2].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/getdel/
+// return(the key exesits): the value, and the key is deleted
+// return(the key doesn't exesit): $-1\r\n
+pub struct GetDel {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GetDel {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'GETDEL'");
+        let mut inner = db.lock().await;
+        match inner.string_db().get(&self.key).await? {
+            Some(value) => {
+                inner.string_db().del(&self.key).await;
+                Ok(Frame::Bulk(value))
+            }
+            None => Ok(Frame::Null),
+        }
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for GetDel {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'getdel' command")
+        }
+        Ok(GetDel {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+pub enum Expiration {
+    // EX seconds | PX milliseconds
+    After(Duration),
+    // EXAT unix-time-seconds | PXAT unix-time-milliseconds
+    At(SystemTime),
+    // PERSIST
+    Persist,
+}
+
+// https://redis.io/commands/getex/
+// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]
+// return(the key exesits): the value
+// return(the key doesn't exesit): $-1\r\n
+pub struct GetEx {
+    pub key: String,
+    // when expiration is None, the ttl of the key is left untouched
+    pub expiration: Option<Expiration>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GetEx {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'GETEX'");
+        let mut inner = db.lock().await;
+        let value = match inner.string_db().get(&self.key).await? {
+            Some(value) => value,
+            None => return Ok(Frame::Null),
+        };
+
+        match self.expiration {
+            Some(Expiration::After(expire)) => {
+                inner
+                    .string_db()
+                    .set(self.key, value.clone(), Some(expire), false)
+                    .await;
+            }
+            Some(Expiration::At(expire_at)) => {
+                if expire_at > SystemTime::now() {
+                    inner
+                        .string_db()
+                        .set_expire_at(&self.key, Some(expire_at))
+                        .await;
+                } else {
+                    // a timestamp in the past deletes the key, just like redis does
+                    inner.string_db().del(&self.key).await;
+                }
+            }
+            Some(Expiration::Persist) => {
+                inner.string_db().set_expire_at(&self.key, None).await;
+            }
+            None => {}
+        }
+        Ok(Frame::Bulk(value))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for GetEx {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let len = bulks.len();
+        if len < 2 {
+            bail!("ERR wrong number of arguments for 'getex' command")
+        }
+        let key = bytes_to_string(bulks[1].clone())?;
+
+        if len == 2 {
+            return Ok(GetEx {
+                key,
+                expiration: None,
+            });
+        }
+        if len == 3 && bulks[2].eq_ignore_ascii_case(b"persist") {
+            return Ok(GetEx {
+                key,
+                expiration: Some(Expiration::Persist),
+            });
+        }
+        if len == 4 {
+            let option = bulks[2].to_ascii_lowercase();
+            let (in_millis, absolute) = match option.as_slice() {
+                b"ex" => (false, false),
+                b"px" => (true, false),
+                b"exat" => (false, true),
+                b"pxat" => (true, true),
+                _ => bail!("ERR syntax error"),
+            };
+            let time = parse_expire(bulks[3].clone(), in_millis, absolute, "getex")?;
+            let time = Duration::from_millis(time);
+            let expiration = match absolute {
+                false => Expiration::After(time),
+                true => Expiration::At(UNIX_EPOCH + time),
+            };
+            return Ok(GetEx {
+                key,
+                expiration: Some(expiration),
+            });
+        }
+
+        Err(anyhow!("ERR syntax error"))
+    }
+}
+
 pub struct Info {
     pub sections: Section,
 }
@@ -133,6 +530,10 @@ impl TryFrom<Bytes> for Section {
         let value = value.to_ascii_lowercase();
         match value.as_slice() {
             b"replication" => Ok(Section::Replication),
+            b"keyspace" => Ok(Section::Keyspace),
+            b"default" => Ok(Section::Default),
+            b"all" => Ok(Section::All),
+            b"everything" => Ok(Section::Everything),
             // TODO:
             _ => Err(anyhow!("Incomplete")),
         }
@@ -152,25 +553,319 @@ impl TryFrom<Vec<Bytes>> for Section {
 
 #[async_trait::async_trait]
 impl CmdExecutor for Info {
-    async fn execute(self: Box<Self>, _db: &mut Db) -> Result<Frame> {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
         debug!("executing command 'INFO'");
-        match self.sections {
-            Section::Replication => {
-                let res = if CONFIG.replicaof.is_none() {
-                    format!(
-                        "role:master\r\nmaster_replid:{}\r\nmaster_repl_offset:{}\r\n",
-                        CONFIG.replid, CONFIG.repl_offset
-                    )
-                } else {
-                    format!(
-                        "role:slave\r\nmaster_replid:{}\r\nmaster_repl_offset:{}\r\n",
-                        CONFIG.replid, CONFIG.repl_offset
-                    )
-                };
-                Ok(Frame::Bulk(res.into()))
+        let res = self.sections.render(db).await?;
+        Ok(Frame::Bulk(res.into()))
+    }
+}
+
+impl Section {
+    async fn render(&self, db: &mut Db) -> Result<String> {
+        match self {
+            Section::Replication => Ok(if CONFIG.replicaof.is_none() {
+                format!(
+                    "role:master\r\nmaster_replid:{}\r\nmaster_repl_offset:{}\r\n",
+                    CONFIG.replid, CONFIG.repl_offset
+                )
+            } else {
+                format!(
+                    "role:slave\r\nmaster_replid:{}\r\nmaster_repl_offset:{}\r\n",
+                    CONFIG.replid, CONFIG.repl_offset
+                )
+            }),
+            // db<index>:keys=<keys>,expires=<keys with an expiry>, only for non-empty databases
+            Section::Keyspace => {
+                let mut res = "# Keyspace\r\n".to_string();
+                let mut inner = db.lock().await;
+                for (index, string_db) in inner.string_dbs.iter_mut().enumerate() {
+                    let keys = string_db.dbsize().await;
+                    if keys > 0 {
+                        let expires = string_db.expires_size().await;
+                        res.push_str(&format!("db{index}:keys={keys},expires={expires}\r\n"));
+                    }
+                }
+                Ok(res)
+            }
+            Section::Default | Section::All | Section::Everything => {
+                Box::pin(Section::Array(vec![Section::Replication, Section::Keyspace]).render(db))
+                    .await
+            }
+            Section::Array(sections) => {
+                let mut res = Vec::with_capacity(sections.len());
+                for section in sections {
+                    res.push(Box::pin(section.render(db)).await?);
+                }
+                Ok(res.join("\r\n"))
             }
             // TODO:
             _ => Err(anyhow!("Incomplete")),
         }
     }
 }
+
+#[cfg(test)]
+mod command_test {
+    use super::*;
+    use crate::{
+        cmd::run,
+        db::{StringDb, StringDbManipulator},
+    };
+
+    fn new_db() -> Db {
+        Db::new(vec![
+            Box::new(StringDb::new()) as Box<dyn StringDbManipulator>
+        ])
+    }
+
+    async fn ttl(db: &mut Db, key: &str) -> Frame {
+        run(db, &["ttl", key]).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn set_should_clear_the_ttl_unless_keepttl() {
+        let mut db = new_db();
+        run(&mut db, &["set", "k", "1", "EX", "100"]).await.unwrap();
+        run(&mut db, &["set", "k", "2"]).await.unwrap();
+        assert_eq!(Frame::Integer(-1), ttl(&mut db, "k").await);
+
+        run(&mut db, &["set", "k", "3", "EX", "100"]).await.unwrap();
+        run(&mut db, &["set", "k", "4", "KEEPTTL"]).await.unwrap();
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "k").await);
+        assert_eq!(
+            Frame::Bulk("4".into()),
+            run(&mut db, &["get", "k"]).await.unwrap()
+        );
+
+        // a missing key has no ttl to keep
+        run(&mut db, &["set", "new", "1", "KEEPTTL"]).await.unwrap();
+        assert_eq!(Frame::Integer(-1), ttl(&mut db, "new").await);
+    }
+
+    #[tokio::test]
+    async fn ping_and_echo_should_check_their_arguments() {
+        let mut db = new_db();
+        assert_eq!(
+            Frame::Simple("PONG".into()),
+            run(&mut db, &["PING"]).await.unwrap()
+        );
+        assert_eq!(
+            Frame::Bulk("hey".into()),
+            run(&mut db, &["echo", "hey"]).await.unwrap()
+        );
+        assert!(run(&mut db, &["echo"]).await.is_err());
+        assert!(run(&mut db, &["echo", "a", "b"]).await.is_err());
+    }
+
+    fn bulks(values: &[&str]) -> Frame {
+        Frame::Array(
+            values
+                .iter()
+                .map(|value| match *value {
+                    "nil" => Frame::Null,
+                    value => Frame::Bulk(Bytes::copy_from_slice(value.as_bytes())),
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn mget_should_reply_nil_for_missing_keys_and_other_types() {
+        let mut db = new_db();
+        run(&mut db, &["mset", "a", "1", "b", "2"]).await.unwrap();
+        run(&mut db, &["rpush", "list", "x"]).await.unwrap();
+        run(&mut db, &["hset", "hash", "f", "v"]).await.unwrap();
+        assert_eq!(
+            bulks(&["1", "nil", "nil", "2", "nil", "1"]),
+            run(&mut db, &["mget", "a", "list", "missing", "b", "hash", "a"])
+                .await
+                .unwrap()
+        );
+        assert!(run(&mut db, &["mget"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn msetnx_should_set_all_the_keys_or_none() {
+        let mut db = new_db();
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["msetnx", "a", "1", "b", "2", "a", "3"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            bulks(&["3", "2"]),
+            run(&mut db, &["mget", "a", "b"]).await.unwrap()
+        );
+
+        // a single existing key, whatever its type, prevents every write
+        run(&mut db, &["sadd", "set", "x"]).await.unwrap();
+        for existing in ["b", "set"] {
+            assert_eq!(
+                Frame::Integer(0),
+                run(&mut db, &["msetnx", "c", "1", existing, "1"])
+                    .await
+                    .unwrap()
+            );
+        }
+        assert_eq!(
+            bulks(&["nil", "2"]),
+            run(&mut db, &["mget", "c", "b"]).await.unwrap()
+        );
+
+        // an expired key doesn't exist anymore
+        run(&mut db, &["set", "old", "1", "PX", "1"]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["msetnx", "old", "2", "c", "3"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            bulks(&["2", "3"]),
+            run(&mut db, &["mget", "old", "c"]).await.unwrap()
+        );
+        assert_eq!(Frame::Integer(-1), ttl(&mut db, "old").await);
+        assert!(run(&mut db, &["msetnx", "a", "1", "b"]).await.is_err());
+    }
+
+    async fn pttl(db: &mut Db, key: &str) -> i64 {
+        match run(db, &["pttl", key]).await.unwrap() {
+            Frame::Integer(pttl) => pttl,
+            frame => panic!("unexpected reply {frame:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn getex_should_set_or_persist_the_ttl() {
+        let mut db = new_db();
+        assert_eq!(
+            Frame::Null,
+            run(&mut db, &["getex", "missing", "EX", "100"])
+                .await
+                .unwrap()
+        );
+        run(&mut db, &["set", "k", "v"]).await.unwrap();
+
+        assert_eq!(
+            Frame::Bulk("v".into()),
+            run(&mut db, &["getex", "k", "EX", "100"]).await.unwrap()
+        );
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "k").await);
+
+        run(&mut db, &["getex", "k", "PX", "5000"]).await.unwrap();
+        assert!((4900..=5000).contains(&pttl(&mut db, "k").await));
+
+        let now = to_unix_millis(SystemTime::now());
+        let at = (now / 1000 + 200).to_string();
+        run(&mut db, &["getex", "k", "EXAT", &at]).await.unwrap();
+        assert!((100_000..=200_000).contains(&pttl(&mut db, "k").await));
+
+        let at = (now + 3000).to_string();
+        run(&mut db, &["getex", "k", "PXAT", &at]).await.unwrap();
+        assert!((2900..=3000).contains(&pttl(&mut db, "k").await));
+
+        // without an option the ttl is kept
+        run(&mut db, &["getex", "k"]).await.unwrap();
+        assert!(pttl(&mut db, "k").await > 0);
+
+        run(&mut db, &["getex", "k", "PERSIST"]).await.unwrap();
+        assert_eq!(Frame::Integer(-1), ttl(&mut db, "k").await);
+
+        // a time in the past deletes the key, after replying its value
+        assert_eq!(
+            Frame::Bulk("v".into()),
+            run(&mut db, &["getex", "k", "PXAT", "1"]).await.unwrap()
+        );
+        assert_eq!(Frame::Integer(-2), ttl(&mut db, "k").await);
+
+        run(&mut db, &["set", "k", "v"]).await.unwrap();
+        for args in [
+            &["getex", "k", "EX"][..],
+            &["getex", "k", "EX", "0"],
+            &["getex", "k", "EX", "-1"],
+            &["getex", "k", "EX", "abc"],
+            &["getex", "k", "KEEPTTL"],
+            &["getex", "k", "PERSIST", "EX", "1"],
+        ] {
+            assert!(run(&mut db, args).await.is_err(), "{args:?}");
+        }
+        assert_eq!(Frame::Integer(-1), ttl(&mut db, "k").await);
+    }
+
+    #[tokio::test]
+    async fn setex_should_reject_invalid_expire_times() {
+        let mut db = new_db();
+        run(&mut db, &["setex", "k", "100", "v"]).await.unwrap();
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "k").await);
+        run(&mut db, &["psetex", "p", "5000", "v"]).await.unwrap();
+        assert!((4900..=5000).contains(&pttl(&mut db, "p").await));
+
+        for (cmd, time) in [
+            ("setex", "0"),
+            ("setex", "-1"),
+            ("setex", "9223372036854775"),
+            ("psetex", "0"),
+            ("psetex", "-1"),
+            ("psetex", "9223372036854775807"),
+        ] {
+            assert_eq!(
+                format!("ERR invalid expire time in '{cmd}' command"),
+                run(&mut db, &[cmd, "new", time, "v"])
+                    .await
+                    .unwrap_err()
+                    .to_string()
+            );
+        }
+        assert_eq!(
+            "ERR value is not an integer or out of range",
+            run(&mut db, &["setex", "new", "abc", "v"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(Frame::Integer(-2), ttl(&mut db, "new").await);
+    }
+
+    #[tokio::test]
+    async fn getdel_and_getset_should_reply_the_old_value() {
+        let mut db = new_db();
+        run(&mut db, &["set", "k", "1"]).await.unwrap();
+        assert_eq!(
+            Frame::Bulk("1".into()),
+            run(&mut db, &["getdel", "k"]).await.unwrap()
+        );
+        assert_eq!(Frame::Integer(-2), ttl(&mut db, "k").await);
+        assert_eq!(Frame::Null, run(&mut db, &["getdel", "k"]).await.unwrap());
+
+        assert_eq!(
+            Frame::Null,
+            run(&mut db, &["getset", "k", "2"]).await.unwrap()
+        );
+        run(&mut db, &["expire", "k", "100"]).await.unwrap();
+        assert_eq!(
+            Frame::Bulk("2".into()),
+            run(&mut db, &["getset", "k", "3"]).await.unwrap()
+        );
+        // like SET, GETSET clears the ttl
+        assert_eq!(Frame::Integer(-1), ttl(&mut db, "k").await);
+
+        run(&mut db, &["rpush", "list", "x"]).await.unwrap();
+        for cmd in ["getdel", "getset"] {
+            let mut args = vec![cmd, "list"];
+            if cmd == "getset" {
+                args.push("v");
+            }
+            assert!(run(&mut db, &args)
+                .await
+                .unwrap_err()
+                .to_string()
+                .starts_with("WRONGTYPE"));
+        }
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["llen", "list"]).await.unwrap()
+        );
+    }
+}
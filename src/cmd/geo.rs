@@ -0,0 +1,450 @@
+use super::{wrong_args, CmdExecutor, ZAdd};
+use crate::{
+    db::{
+        decode_position, encode_position, geo_distance, geohash_string, valid_coordinates, Db,
+        GeoArea, GeoShape, Value, ZSet,
+    },
+    frame::Frame,
+    util::{bytes_to_f64, bytes_to_i64, bytes_to_string},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use bytes::Bytes;
+use tracing::debug;
+
+// the number of meters of a unit
+fn parse_unit(bulk: &Bytes) -> Result<f64> {
+    match bulk.to_ascii_lowercase().as_slice() {
+        b"m" => Ok(1.0),
+        b"km" => Ok(1000.0),
+        b"ft" => Ok(0.3048),
+        b"mi" => Ok(1609.34),
+        _ => bail!("ERR unsupported unit provided. please use M, KM, FT, MI"),
+    }
+}
+
+// parse `longitude latitude`
+fn parse_position(longitude: &Bytes, latitude: &Bytes) -> Result<(f64, f64)> {
+    let longitude = bytes_to_f64(longitude.clone())?;
+    let latitude = bytes_to_f64(latitude.clone())?;
+    if !valid_coordinates(longitude, latitude) {
+        bail!("ERR invalid longitude,latitude pair {longitude:.6},{latitude:.6}")
+    }
+    Ok((longitude, latitude))
+}
+
+// distances are replied with 4 decimals
+fn distance_frame(distance: f64) -> Frame {
+    Frame::Bulk(format!("{distance:.4}").into())
+}
+
+// coordinates are replied with up to 17 decimals, without the trailing zeros
+fn coordinate_frame(coordinate: f64) -> Frame {
+    let coordinate = format!("{coordinate:.17}");
+    let coordinate = coordinate.trim_end_matches('0').trim_end_matches('.');
+    Frame::Bulk(Bytes::copy_from_slice(coordinate.as_bytes()))
+}
+
+fn position_frame((longitude, latitude): (f64, f64)) -> Frame {
+    Frame::Array(vec![
+        coordinate_frame(longitude),
+        coordinate_frame(latitude),
+    ])
+}
+
+// https://redis.io/commands/geoadd/
+// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+// The members are added to a sorted set, with the geohash of their position as score.
+// return: :<the number of members added, or added and updated with CH>\r\n
+pub struct GeoAdd(ZAdd);
+
+#[async_trait::async_trait]
+impl CmdExecutor for GeoAdd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'GEOADD'");
+        Box::new(self.0).execute(db).await
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for GeoAdd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 5 {
+            return Err(wrong_args("geoadd"));
+        }
+        let mut zadd = ZAdd {
+            key: bytes_to_string(bulks[1].clone())?,
+            xx: false,
+            nx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: false,
+            elements: vec![],
+        };
+
+        let mut i = 2;
+        while i < bulks.len() {
+            match bulks[i].to_ascii_lowercase().as_slice() {
+                b"xx" => zadd.xx = true,
+                b"nx" => zadd.nx = true,
+                b"ch" => zadd.ch = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let triples = &bulks[i..];
+        if !triples.len().is_multiple_of(3) || (zadd.xx && zadd.nx) {
+            bail!("ERR syntax error")
+        }
+        for triple in triples.chunks(3) {
+            let (longitude, latitude) = parse_position(&triple[0], &triple[1])?;
+            let score = encode_position(longitude, latitude).expect("the position should be valid");
+            zadd.elements.push((score as f64, triple[2].clone()));
+        }
+        Ok(GeoAdd(zadd))
+    }
+}
+
+// https://redis.io/commands/geodist/
+// GEODIST key member1 member2 [M | KM | FT | MI]
+// return: $<len>\r\n<the distance>\r\n, or $-1\r\n if a member doesn't exist
+pub struct GeoDist {
+    pub key: String,
+    pub members: (Bytes, Bytes),
+    // the number of meters of the unit
+    pub unit: f64,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GeoDist {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'GEODIST'");
+        let mut inner = db.lock().await;
+        let zset = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_zset()?,
+            None => return Ok(Frame::Null),
+        };
+        let (Some(score1), Some(score2)) =
+            (zset.score(&self.members.0), zset.score(&self.members.1))
+        else {
+            return Ok(Frame::Null);
+        };
+        let ((longitude1, latitude1), (longitude2, latitude2)) =
+            (decode_position(score1), decode_position(score2));
+        let distance = geo_distance(longitude1, latitude1, longitude2, latitude2);
+        Ok(distance_frame(distance / self.unit))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for GeoDist {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let unit = match bulks.len() {
+            4 => 1.0,
+            5 => parse_unit(&bulks[4])?,
+            _ => return Err(wrong_args("geodist")),
+        };
+        Ok(GeoDist {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: (bulks[2].clone(), bulks[3].clone()),
+            unit,
+        })
+    }
+}
+
+// https://redis.io/commands/geopos/
+// https://redis.io/commands/geohash/
+// GEOPOS key [member [member ...]]
+// GEOHASH key [member [member ...]]
+// return(GEOPOS): *<n>\r\n*2\r\n$<len>\r\n<longitude>\r\n$<len>\r\n<latitude>\r\n..., *-1\r\n
+//   for a missing member
+// return(GEOHASH): *<n>\r\n$11\r\n<geohash>\r\n..., $-1\r\n for a missing member
+pub struct GeoPos {
+    pub key: String,
+    pub members: Vec<Bytes>,
+    // GEOHASH replies the standard geohash strings of the positions
+    pub hash: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GeoPos {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.hash { "GEOHASH" } else { "GEOPOS" }
+        );
+        let mut inner = db.lock().await;
+        let zset = match inner.string_db().get_value(&self.key).await {
+            Some(value) => Some(value.as_zset()?),
+            None => None,
+        };
+        let res = self
+            .members
+            .iter()
+            .map(
+                |member| match (zset.and_then(|zset| zset.score(member)), self.hash) {
+                    (Some(score), true) => Frame::Bulk(geohash_string(score).into()),
+                    (Some(score), false) => position_frame(decode_position(score)),
+                    (None, true) => Frame::Null,
+                    (None, false) => Frame::NullArray,
+                },
+            )
+            .collect();
+        Ok(Frame::Array(res))
+    }
+}
+
+impl GeoPos {
+    // `hash` distinguishes GEOHASH from GEOPOS
+    pub fn parse(bulks: Vec<Bytes>, hash: bool) -> Result<Self> {
+        if bulks.len() < 2 {
+            return Err(wrong_args(if hash { "geohash" } else { "geopos" }));
+        }
+        Ok(GeoPos {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: bulks[2..].to_vec(),
+            hash,
+        })
+    }
+}
+
+// where GEOSEARCH searches from
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+    Member(Bytes),
+    Position(f64, f64),
+}
+
+// https://redis.io/commands/geosearch/
+// https://redis.io/commands/geosearchstore/
+// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+//   BYRADIUS radius M | KM | FT | MI | BYBOX width height M | KM | FT | MI
+//   [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+// GEOSEARCHSTORE destination source ... [STOREDIST], the members are stored with their geohash,
+//   or their distance with STOREDIST, and an empty result deletes the destination
+// COUNT sorts the members by ascending distance unless an order or ANY is given, ANY returns the
+// first members found.
+// return: *<n>\r\n$<len>\r\n<member>\r\n...
+// return(WITH*): *<n>\r\n*<m>\r\n$<len>\r\n<member>\r\n[$<len>\r\n<distance>\r\n]
+//   [:<geohash>\r\n][*2\r\n<longitude><latitude>]...
+// return(GEOSEARCHSTORE): :<the number of members stored>\r\n
+pub struct GeoSearch {
+    pub key: String,
+    pub origin: GeoOrigin,
+    // the radius or the box, in `unit`
+    pub area: GeoArea,
+    pub unit: f64,
+    // sort by ascending distance, or descending if false
+    pub asc: Option<bool>,
+    // the greatest number of members to reply, 0 for all of them
+    pub count: usize,
+    pub any: bool,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+    // GEOSEARCHSTORE stores the result instead of replying it
+    pub destination: Option<String>,
+    pub store_dist: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for GeoSearch {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.destination.is_some() {
+                "GEOSEARCHSTORE"
+            } else {
+                "GEOSEARCH"
+            }
+        );
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let mut points = match string_db.get_value(&self.key).await {
+            Some(value) => {
+                let zset = value.as_zset()?;
+                let (longitude, latitude) = match &self.origin {
+                    GeoOrigin::Position(longitude, latitude) => (*longitude, *latitude),
+                    GeoOrigin::Member(member) => decode_position(
+                        zset.score(member)
+                            .ok_or_else(|| anyhow!("ERR could not decode requested zset member"))?,
+                    ),
+                };
+                let shape = GeoShape {
+                    longitude,
+                    latitude,
+                    area: self.area,
+                    conversion: self.unit,
+                };
+                shape.search(zset, if self.any { self.count } else { 0 })
+            }
+            None => vec![],
+        };
+
+        // COUNT keeps the closest members
+        let asc = match self.asc {
+            None if self.count > 0 && !self.any => Some(true),
+            asc => asc,
+        };
+        match asc {
+            Some(true) => points.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(false) => points.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        if self.count > 0 {
+            points.truncate(self.count);
+        }
+
+        let destination = match self.destination {
+            Some(destination) => destination,
+            None => {
+                let res = points
+                    .into_iter()
+                    .map(|point| {
+                        if !self.with_dist && !self.with_hash && !self.with_coord {
+                            return Frame::Bulk(point.member);
+                        }
+                        let mut res = vec![Frame::Bulk(point.member)];
+                        if self.with_dist {
+                            res.push(distance_frame(point.distance / self.unit));
+                        }
+                        if self.with_hash {
+                            res.push(Frame::Integer(point.score as i64));
+                        }
+                        if self.with_coord {
+                            res.push(position_frame((point.longitude, point.latitude)));
+                        }
+                        Frame::Array(res)
+                    })
+                    .collect();
+                return Ok(Frame::Array(res));
+            }
+        };
+
+        let len = points.len();
+        string_db.del(&destination).await;
+        if len > 0 {
+            let mut zset = ZSet::new();
+            for point in points {
+                let score = match self.store_dist {
+                    true => point.distance / self.unit,
+                    false => point.score,
+                };
+                zset.insert(point.member, score);
+            }
+            string_db
+                .insert_value(destination.clone(), Value::ZSet(zset))
+                .await;
+            inner.serve_blocked(db.index, &destination).await;
+        }
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl GeoSearch {
+    // `store` distinguishes GEOSEARCHSTORE, which takes the destination first
+    pub fn parse(bulks: Vec<Bytes>, store: bool) -> Result<Self> {
+        let cmd_name = match store {
+            true => "geosearchstore",
+            false => "geosearch",
+        };
+        let base = 2 + store as usize;
+        if bulks.len() < base {
+            return Err(wrong_args(cmd_name));
+        }
+        let mut search = GeoSearch {
+            key: bytes_to_string(bulks[base - 1].clone())?,
+            origin: GeoOrigin::Position(0.0, 0.0),
+            area: GeoArea::Radius(0.0),
+            unit: 1.0,
+            asc: None,
+            count: 0,
+            any: false,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+            destination: match store {
+                true => Some(bytes_to_string(bulks[1].clone())?),
+                false => None,
+            },
+            store_dist: false,
+        };
+
+        // FROMMEMBER and FROMLONLAT, BYRADIUS and BYBOX exclude each other
+        let (mut from_member, mut from_position, mut by_radius, mut by_box) =
+            (false, false, false, false);
+        let mut i = base;
+        while i < bulks.len() {
+            // the number of arguments after the option
+            let remaining = bulks.len() - i - 1;
+            match bulks[i].to_ascii_lowercase().as_slice() {
+                b"withdist" => search.with_dist = true,
+                b"withhash" => search.with_hash = true,
+                b"withcoord" => search.with_coord = true,
+                b"any" => search.any = true,
+                b"asc" => search.asc = Some(true),
+                b"desc" => search.asc = Some(false),
+                b"count" if remaining >= 1 => {
+                    let count = bytes_to_i64(bulks[i + 1].clone())?;
+                    if count <= 0 {
+                        bail!("ERR COUNT must be > 0")
+                    }
+                    search.count = count as usize;
+                    i += 1;
+                }
+                b"storedist" if store => search.store_dist = true,
+                b"frommember" if remaining >= 1 && !from_position => {
+                    search.origin = GeoOrigin::Member(bulks[i + 1].clone());
+                    from_member = true;
+                    i += 1;
+                }
+                b"fromlonlat" if remaining >= 2 && !from_member => {
+                    let (longitude, latitude) = parse_position(&bulks[i + 1], &bulks[i + 2])?;
+                    search.origin = GeoOrigin::Position(longitude, latitude);
+                    from_position = true;
+                    i += 2;
+                }
+                b"byradius" if remaining >= 2 && !by_box => {
+                    let radius = bytes_to_f64(bulks[i + 1].clone())?;
+                    if radius < 0.0 {
+                        bail!("ERR radius cannot be negative")
+                    }
+                    search.unit = parse_unit(&bulks[i + 2])?;
+                    search.area = GeoArea::Radius(radius);
+                    by_radius = true;
+                    i += 2;
+                }
+                b"bybox" if remaining >= 3 && !by_radius => {
+                    let width = bytes_to_f64(bulks[i + 1].clone())?;
+                    let height = bytes_to_f64(bulks[i + 2].clone())?;
+                    if width < 0.0 || height < 0.0 {
+                        bail!("ERR height or width cannot be negative")
+                    }
+                    search.unit = parse_unit(&bulks[i + 3])?;
+                    search.area = GeoArea::Box { width, height };
+                    by_box = true;
+                    i += 3;
+                }
+                _ => bail!("ERR syntax error"),
+            }
+            i += 1;
+        }
+
+        if store && (search.with_dist || search.with_hash || search.with_coord) {
+            bail!("ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options")
+        }
+        if !from_member && !from_position {
+            bail!("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {cmd_name}")
+        }
+        if !by_radius && !by_box {
+            bail!("ERR exactly one of BYRADIUS and BYBOX can be specified for {cmd_name}")
+        }
+        if search.any && search.count == 0 {
+            bail!("ERR the ANY argument requires COUNT argument")
+        }
+        Ok(search)
+    }
+}
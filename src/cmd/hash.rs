@@ -0,0 +1,872 @@
+use super::{parse_cursor, CmdExecutor, ExpireCondition, ScanOptions};
+use crate::{
+    db::{hash_or_create, Db},
+    frame::Frame,
+    util::{bytes_to_f64, bytes_to_i64, bytes_to_string, to_unix_millis},
+};
+use anyhow::{bail, Error, Result};
+use bytes::Bytes;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+// the latest unix time in milliseconds a field may expire at
+const FIELD_EXPIRE_TIME_MAX: i64 = (1 << 48) - 1;
+
+// https://redis.io/commands/hset/
+// https://redis.io/commands/hmset/
+// HSET key field value [field value ...]
+// return: :<the number of fields that were added>\r\n
+// return(HMSET): +OK\r\n
+pub struct HSet {
+    pub key: String,
+    pub pairs: Vec<(Bytes, Bytes)>,
+    // HMSET replies OK instead of the number of added fields
+    pub legacy: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HSet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HSET'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let hash = hash_or_create(string_db, &self.key).await?;
+        let mut added = 0;
+        for (field, value) in self.pairs {
+            if hash.insert(field, value, false) {
+                added += 1;
+            }
+        }
+        string_db.signal_modified(&self.key).await;
+        Ok(if self.legacy {
+            Frame::Simple("OK".to_string())
+        } else {
+            Frame::Integer(added)
+        })
+    }
+}
+
+impl HSet {
+    // `legacy` distinguishes HMSET from HSET
+    pub fn parse(bulks: Vec<Bytes>, legacy: bool) -> Result<Self> {
+        if bulks.len() < 4 || !bulks.len().is_multiple_of(2) {
+            let cmd_name = if legacy { "hmset" } else { "hset" };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(HSet {
+            key: bytes_to_string(bulks[1].clone())?,
+            pairs: bulks[2..]
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect(),
+            legacy,
+        })
+    }
+}
+
+// https://redis.io/commands/hsetnx/
+// HSETNX key field value
+// return(the field was set): :1\r\n
+// return(the field already exists): :0\r\n
+pub struct HSetNx {
+    pub key: String,
+    pub field: Bytes,
+    pub value: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HSetNx {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HSETNX'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        if let Some(value) = string_db.get_value(&self.key).await {
+            if value.as_hash()?.get(&self.field).is_some() {
+                return Ok(Frame::Integer(0));
+            }
+        }
+        hash_or_create(string_db, &self.key)
+            .await?
+            .insert(self.field, self.value, false);
+        string_db.signal_modified(&self.key).await;
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HSetNx {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for 'hsetnx' command")
+        }
+        Ok(HSetNx {
+            key: bytes_to_string(bulks[1].clone())?,
+            field: bulks[2].clone(),
+            value: bulks[3].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/hget/
+// HGET key field
+// return: $<len>\r\n<value>\r\n, or $-1\r\n if the key or the field doesn't exist
+pub struct HGet {
+    pub key: String,
+    pub field: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HGet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HGET'");
+        let mut inner = db.lock().await;
+        Ok(match inner.string_db().get_value(&self.key).await {
+            Some(value) => value
+                .as_hash()?
+                .get(&self.field)
+                .map_or(Frame::Null, |value| Frame::Bulk(value.clone())),
+            None => Frame::Null,
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HGet {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            bail!("ERR wrong number of arguments for 'hget' command")
+        }
+        Ok(HGet {
+            key: bytes_to_string(bulks[1].clone())?,
+            field: bulks[2].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/hmget/
+// HMGET key field [field ...]
+// return: *<n>\r\n$<len>\r\n<value>\r\n..., with $-1\r\n for the missing fields
+pub struct HMGet {
+    pub key: String,
+    pub fields: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HMGet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HMGET'");
+        let mut inner = db.lock().await;
+        let hash = match inner.string_db().get_value(&self.key).await {
+            Some(value) => Some(value.as_hash()?),
+            None => None,
+        };
+        Ok(Frame::Array(
+            self.fields
+                .iter()
+                .map(|field| match hash.and_then(|hash| hash.get(field)) {
+                    Some(value) => Frame::Bulk(value.clone()),
+                    None => Frame::Null,
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HMGet {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'hmget' command")
+        }
+        Ok(HMGet {
+            key: bytes_to_string(bulks[1].clone())?,
+            fields: bulks[2..].to_vec(),
+        })
+    }
+}
+
+// https://redis.io/commands/hdel/
+// HDEL key field [field ...], the key is deleted along with its last field
+// return: :<the number of removed fields>\r\n
+pub struct HDel {
+    pub key: String,
+    pub fields: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HDel {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HDEL'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let hash = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_hash_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+
+        let removed = self
+            .fields
+            .iter()
+            .filter(|field| hash.remove(field))
+            .count();
+        if hash.is_empty() {
+            string_db.del(&self.key).await;
+        } else if removed > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HDel {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'hdel' command")
+        }
+        Ok(HDel {
+            key: bytes_to_string(bulks[1].clone())?,
+            fields: bulks[2..].to_vec(),
+        })
+    }
+}
+
+// https://redis.io/commands/hlen/
+// return: :<the number of fields>\r\n, :0\r\n if the key doesn't exist
+pub struct HLen {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HLen {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HLEN'");
+        let mut inner = db.lock().await;
+        let len = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_hash()?.len(),
+            None => 0,
+        };
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HLen {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'hlen' command")
+        }
+        Ok(HLen {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/hexists/
+// https://redis.io/commands/hstrlen/
+// HEXISTS key field
+// return: :1\r\n if the field exists, :0\r\n otherwise
+// return(HSTRLEN): :<the length of the value>\r\n, :0\r\n if the field doesn't exist
+pub struct HExists {
+    pub key: String,
+    pub field: Bytes,
+    // HSTRLEN replies the length of the value instead
+    pub strlen: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HExists {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.strlen { "HSTRLEN" } else { "HEXISTS" }
+        );
+        let mut inner = db.lock().await;
+        let value = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_hash()?.get(&self.field),
+            None => None,
+        };
+        Ok(Frame::Integer(match value {
+            Some(value) if self.strlen => value.len() as i64,
+            Some(_) => 1,
+            None => 0,
+        }))
+    }
+}
+
+impl HExists {
+    // `strlen` distinguishes HSTRLEN from HEXISTS
+    pub fn parse(bulks: Vec<Bytes>, strlen: bool) -> Result<Self> {
+        if bulks.len() != 3 {
+            let cmd_name = if strlen { "hstrlen" } else { "hexists" };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(HExists {
+            key: bytes_to_string(bulks[1].clone())?,
+            field: bulks[2].clone(),
+            strlen,
+        })
+    }
+}
+
+// which parts of the fields HKEYS, HVALS and HGETALL reply
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashPart {
+    Fields,
+    Values,
+    Both,
+}
+
+// https://redis.io/commands/hgetall/
+// https://redis.io/commands/hkeys/
+// https://redis.io/commands/hvals/
+// HGETALL key
+// return: *<2n>\r\n$<len>\r\n<field>\r\n$<len>\r\n<value>\r\n...
+// return(HKEYS): *<n>\r\n$<len>\r\n<field>\r\n...
+// return(HVALS): *<n>\r\n$<len>\r\n<value>\r\n...
+pub struct HGetAll {
+    pub key: String,
+    pub part: HashPart,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HGetAll {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HGETALL'");
+        let mut inner = db.lock().await;
+        let hash = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_hash()?,
+            None => return Ok(Frame::Array(vec![])),
+        };
+
+        let mut res = Vec::with_capacity(hash.len() * 2);
+        for (field, value) in hash.iter() {
+            if self.part != HashPart::Values {
+                res.push(Frame::Bulk(field.clone()));
+            }
+            if self.part != HashPart::Fields {
+                res.push(Frame::Bulk(value.clone()));
+            }
+        }
+        Ok(Frame::Array(res))
+    }
+}
+
+impl HGetAll {
+    pub fn parse(bulks: Vec<Bytes>, part: HashPart) -> Result<Self> {
+        if bulks.len() != 2 {
+            let cmd_name = match part {
+                HashPart::Fields => "hkeys",
+                HashPart::Values => "hvals",
+                HashPart::Both => "hgetall",
+            };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(HGetAll {
+            key: bytes_to_string(bulks[1].clone())?,
+            part,
+        })
+    }
+}
+
+// https://redis.io/commands/hincrby/
+// HINCRBY key field increment, a missing field counts as 0
+// return: :<the value after the increment>\r\n
+pub struct HIncrBy {
+    pub key: String,
+    pub field: Bytes,
+    pub increment: i64,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HIncrBy {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HINCRBY'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let hash = hash_or_create(string_db, &self.key).await?;
+        let current = match hash.get(&self.field) {
+            Some(value) => match bytes_to_i64(value.clone()) {
+                Ok(current) => current,
+                Err(_) => bail!("ERR hash value is not an integer"),
+            },
+            None => 0,
+        };
+
+        let value = match current.checked_add(self.increment) {
+            Some(value) => value,
+            None => bail!("ERR increment or decrement would overflow"),
+        };
+        hash.insert(self.field, value.to_string().into(), true);
+        string_db.signal_modified(&self.key).await;
+        Ok(Frame::Integer(value))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HIncrBy {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for 'hincrby' command")
+        }
+        Ok(HIncrBy {
+            key: bytes_to_string(bulks[1].clone())?,
+            field: bulks[2].clone(),
+            increment: bytes_to_i64(bulks[3].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/hincrbyfloat/
+// HINCRBYFLOAT key field increment, a missing field counts as 0
+// return: $<len>\r\n<the value after the increment>\r\n
+pub struct HIncrByFloat {
+    pub key: String,
+    pub field: Bytes,
+    pub increment: f64,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HIncrByFloat {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HINCRBYFLOAT'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        // check the current value before creating the key
+        let current = match string_db.get_value(&self.key).await {
+            Some(value) => value.as_hash()?.get(&self.field).cloned(),
+            None => None,
+        };
+        let current = match current {
+            Some(value) => match bytes_to_f64(value) {
+                Ok(current) if current.is_finite() => current,
+                _ => bail!("ERR hash value is not a float"),
+            },
+            None => 0.0,
+        };
+
+        let value = current + self.increment;
+        if !value.is_finite() {
+            bail!("ERR increment would produce NaN or Infinity")
+        }
+        let value = Bytes::from(value.to_string());
+        hash_or_create(string_db, &self.key)
+            .await?
+            .insert(self.field, value.clone(), true);
+        string_db.signal_modified(&self.key).await;
+        Ok(Frame::Bulk(value))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HIncrByFloat {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for 'hincrbyfloat' command")
+        }
+        Ok(HIncrByFloat {
+            key: bytes_to_string(bulks[1].clone())?,
+            field: bulks[2].clone(),
+            increment: bytes_to_f64(bulks[3].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/hscan/
+// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+// return: *2\r\n$<len>\r\n<next cursor>\r\n*<2n>\r\n$<len>\r\n<field>\r\n$<len>\r\n<value>\r\n...
+pub struct HScan {
+    pub key: String,
+    pub cursor: u64,
+    pub options: ScanOptions,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HScan {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HSCAN'");
+        let mut inner = db.lock().await;
+        let (cursor, fields) = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_hash()?.scan(self.cursor, self.options.count),
+            None => (0, vec![]),
+        };
+
+        let mut res = Vec::with_capacity(fields.len() * 2);
+        for (field, value) in fields {
+            if !self.options.matches(&field) {
+                continue;
+            }
+            res.push(Frame::Bulk(field));
+            if !self.options.no_values {
+                res.push(Frame::Bulk(value));
+            }
+        }
+        Ok(Frame::Array(vec![
+            Frame::Bulk(cursor.to_string().into()),
+            Frame::Array(res),
+        ]))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HScan {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'hscan' command")
+        }
+        Ok(HScan {
+            key: bytes_to_string(bulks[1].clone())?,
+            cursor: parse_cursor(bulks[2].clone())?,
+            options: ScanOptions::parse(&bulks[3..], false, true)?,
+        })
+    }
+}
+
+// https://redis.io/commands/hrandfield/
+// HRANDFIELD key [count [WITHVALUES]], a negative count allows the same field to be returned
+// several times
+// return(without count): $<len>\r\n<field>\r\n, or $-1\r\n if the key doesn't exist
+// return(with count): *<n>\r\n$<len>\r\n<field>\r\n..., with the values after their fields
+// when WITHVALUES is given
+pub struct HRandField {
+    pub key: String,
+    pub count: Option<i64>,
+    pub with_values: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HRandField {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HRANDFIELD'");
+        let mut inner = db.lock().await;
+        let hash = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_hash()?,
+            None if self.count.is_some() => return Ok(Frame::Array(vec![])),
+            None => return Ok(Frame::Null),
+        };
+
+        let count = match self.count {
+            Some(count) => count,
+            None => {
+                return Ok(hash
+                    .random_fields(1, true)
+                    .first()
+                    .map_or(Frame::Null, |(field, _)| Frame::Bulk((*field).clone())))
+            }
+        };
+        let fields = hash.random_fields(count.unsigned_abs() as usize, count >= 0);
+        let mut res = Vec::with_capacity(fields.len() * 2);
+        for (field, value) in fields {
+            res.push(Frame::Bulk(field.clone()));
+            if self.with_values {
+                res.push(Frame::Bulk(value.clone()));
+            }
+        }
+        Ok(Frame::Array(res))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HRandField {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let with_values = match bulks.len() {
+            2 | 3 => false,
+            4 if bulks[3].eq_ignore_ascii_case(b"withvalues") => true,
+            4 => bail!("ERR syntax error"),
+            _ => bail!("ERR wrong number of arguments for 'hrandfield' command"),
+        };
+        let count = match bulks.get(2) {
+            Some(count) => {
+                let count = bytes_to_i64(count.clone())?;
+                // the reply has two elements per field with values
+                if with_values && count.unsigned_abs() > i64::MAX as u64 / 2 {
+                    bail!("ERR value is out of range")
+                }
+                Some(count)
+            }
+            None => None,
+        };
+        Ok(HRandField {
+            key: bytes_to_string(bulks[1].clone())?,
+            count,
+            with_values,
+        })
+    }
+}
+
+// parse `FIELDS numfields field [field ...]`, which ends the arguments of the field expiration
+// commands
+fn parse_fields(args: &[Bytes]) -> Result<Vec<Bytes>> {
+    if args.len() < 2 || !args[0].eq_ignore_ascii_case(b"fields") {
+        bail!("ERR Mandatory argument FIELDS is missing or not at the right position")
+    }
+    let numfields = match bytes_to_i64(args[1].clone()) {
+        Ok(numfields) if numfields > 0 => numfields as usize,
+        _ => bail!("ERR Parameter `numFields` should be greater than 0"),
+    };
+    if numfields != args.len() - 2 {
+        bail!("ERR The `numfields` parameter must match the number of arguments")
+    }
+    Ok(args[2..].to_vec())
+}
+
+// https://redis.io/commands/hexpire/
+// https://redis.io/commands/hpexpire/
+// https://redis.io/commands/hexpireat/
+// https://redis.io/commands/hpexpireat/
+// HEXPIRE key seconds [NX | XX | GT | LT] FIELDS numfields field [field ...]
+// return: *<n>\r\n followed for each field by
+//  :-2\r\n if the field (or the key) doesn't exist
+//  :0\r\n if the condition isn't met
+//  :1\r\n if the expiry was set
+//  :2\r\n if the field was deleted because the expiry is in the past
+pub struct HExpire {
+    pub key: String,
+    // milliseconds, relative to now unless `absolute` is set, in which case it's a unix time
+    pub time: i64,
+    pub absolute: bool,
+    pub condition: ExpireCondition,
+    pub fields: Vec<Bytes>,
+    // the name of the command in the error replies
+    pub cmd_name: &'static str,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HExpire {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HEXPIRE'");
+        let now = to_unix_millis(SystemTime::now());
+        let expire_at = if self.absolute {
+            self.time
+        } else {
+            self.time.saturating_add(now)
+        };
+        if expire_at > FIELD_EXPIRE_TIME_MAX {
+            bail!("ERR invalid expire time in '{}' command", self.cmd_name)
+        }
+
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let hash = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_hash_mut()?,
+            None => return Ok(Frame::Array(vec![Frame::Integer(-2); self.fields.len()])),
+        };
+
+        let mut res = Vec::with_capacity(self.fields.len());
+        let mut modified = false;
+        for field in self.fields.iter() {
+            let current = match hash.get_expire_at(field) {
+                Some(current) => current.map(to_unix_millis),
+                None => {
+                    res.push(Frame::Integer(-2));
+                    continue;
+                }
+            };
+            if !self.condition.is_met(current, expire_at) {
+                res.push(Frame::Integer(0));
+                continue;
+            }
+            modified = true;
+            if expire_at <= now {
+                hash.remove(field);
+                res.push(Frame::Integer(2));
+            } else {
+                let expire_at = UNIX_EPOCH + Duration::from_millis(expire_at as u64);
+                hash.set_expire_at(field, Some(expire_at));
+                res.push(Frame::Integer(1));
+            }
+        }
+
+        if hash.is_empty() {
+            string_db.del(&self.key).await;
+        } else if hash.has_expires() {
+            string_db.track_field_expires(&self.key).await;
+        }
+        if modified {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Array(res))
+    }
+}
+
+impl HExpire {
+    // `in_millis` distinguishes HPEXPIRE(AT) from HEXPIRE(AT), `absolute` distinguishes
+    // H(P)EXPIREAT from H(P)EXPIRE
+    pub fn parse(bulks: Vec<Bytes>, in_millis: bool, absolute: bool) -> Result<Self> {
+        let cmd_name = match (in_millis, absolute) {
+            (false, false) => "hexpire",
+            (true, false) => "hpexpire",
+            (false, true) => "hexpireat",
+            (true, true) => "hpexpireat",
+        };
+        if bulks.len() < 6 {
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+
+        let mut time = bytes_to_i64(bulks[2].clone())?;
+        if time < 0 {
+            bail!("ERR invalid expire time, must be >= 0 and <= {FIELD_EXPIRE_TIME_MAX}")
+        }
+        if !in_millis {
+            time = time.saturating_mul(1000);
+        }
+        if time > FIELD_EXPIRE_TIME_MAX {
+            bail!("ERR invalid expire time, must be >= 0 and <= {FIELD_EXPIRE_TIME_MAX}")
+        }
+
+        // at most one condition may come before FIELDS
+        let mut condition = ExpireCondition::default();
+        let mut fields_at = 4;
+        match bulks[3].to_ascii_lowercase().as_slice() {
+            b"nx" => condition.nx = true,
+            b"xx" => condition.xx = true,
+            b"gt" => condition.gt = true,
+            b"lt" => condition.lt = true,
+            _ => fields_at = 3,
+        }
+
+        Ok(HExpire {
+            key: bytes_to_string(bulks[1].clone())?,
+            time,
+            absolute,
+            condition,
+            fields: parse_fields(&bulks[fields_at..])?,
+            cmd_name,
+        })
+    }
+}
+
+// https://redis.io/commands/httl/
+// https://redis.io/commands/hpttl/
+// https://redis.io/commands/hexpiretime/
+// https://redis.io/commands/hpexpiretime/
+// HTTL key FIELDS numfields field [field ...]
+// return: *<n>\r\n followed for each field by
+//  :<remaining ttl, or the unix time of the expiry>\r\n if the field has an expiry
+//  :-1\r\n if the field exists but has no expiry
+//  :-2\r\n if the field (or the key) doesn't exist
+pub struct HTtl {
+    pub key: String,
+    pub fields: Vec<Bytes>,
+    pub in_millis: bool,
+    // reply with the unix time of the expiry instead of the remaining ttl, as H(P)EXPIRETIME does
+    pub absolute: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HTtl {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HTTL'");
+        let mut inner = db.lock().await;
+        let hash = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_hash()?,
+            None => return Ok(Frame::Array(vec![Frame::Integer(-2); self.fields.len()])),
+        };
+
+        let now = to_unix_millis(SystemTime::now());
+        let res = self
+            .fields
+            .iter()
+            .map(|field| match hash.get_expire_at(field) {
+                Some(Some(expire_at)) => {
+                    let mut millis = to_unix_millis(expire_at);
+                    if !self.absolute {
+                        millis = (millis - now).max(0);
+                    }
+                    Frame::Integer(if self.in_millis {
+                        millis
+                    } else {
+                        (millis + 500) / 1000
+                    })
+                }
+                Some(None) => Frame::Integer(-1),
+                None => Frame::Integer(-2),
+            })
+            .collect();
+        Ok(Frame::Array(res))
+    }
+}
+
+impl HTtl {
+    pub fn parse(bulks: Vec<Bytes>, in_millis: bool, absolute: bool) -> Result<Self> {
+        if bulks.len() < 5 {
+            let cmd_name = match (in_millis, absolute) {
+                (false, false) => "httl",
+                (true, false) => "hpttl",
+                (false, true) => "hexpiretime",
+                (true, true) => "hpexpiretime",
+            };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(HTtl {
+            key: bytes_to_string(bulks[1].clone())?,
+            fields: parse_fields(&bulks[2..])?,
+            in_millis,
+            absolute,
+        })
+    }
+}
+
+// https://redis.io/commands/hpersist/
+// HPERSIST key FIELDS numfields field [field ...]
+// return: *<n>\r\n followed for each field by
+//  :1\r\n if the expiry was removed
+//  :-1\r\n if the field exists but has no expiry
+//  :-2\r\n if the field (or the key) doesn't exist
+pub struct HPersist {
+    pub key: String,
+    pub fields: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for HPersist {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'HPERSIST'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let hash = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_hash_mut()?,
+            None => return Ok(Frame::Array(vec![Frame::Integer(-2); self.fields.len()])),
+        };
+
+        let res: Vec<_> = self
+            .fields
+            .iter()
+            .map(|field| match hash.get_expire_at(field) {
+                Some(Some(_)) => {
+                    hash.set_expire_at(field, None);
+                    Frame::Integer(1)
+                }
+                Some(None) => Frame::Integer(-1),
+                None => Frame::Integer(-2),
+            })
+            .collect();
+        if res.contains(&Frame::Integer(1)) {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Array(res))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for HPersist {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 5 {
+            bail!("ERR wrong number of arguments for 'hpersist' command")
+        }
+        Ok(HPersist {
+            key: bytes_to_string(bulks[1].clone())?,
+            fields: parse_fields(&bulks[2..])?,
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use super::{wrong_args, CmdExecutor};
+use crate::{
+    db::{count_registers, Db, HyperLogLog, StringDbManipulator, Value, REGISTERS},
+    frame::Frame,
+    util::bytes_to_string,
+};
+use anyhow::{Error, Result};
+use bytes::Bytes;
+use tracing::debug;
+
+// the HyperLogLog stored at `key`, created empty if the key doesn't exist
+async fn hll_or_create<'a>(
+    string_db: &'a mut Box<dyn StringDbManipulator>,
+    key: &str,
+) -> Result<(&'a mut Bytes, bool)> {
+    let created = !string_db.check_exist(key).await;
+    if created {
+        let hll = HyperLogLog::new().into_bytes();
+        string_db
+            .insert_value(key.to_string(), Value::String(hll.into()))
+            .await;
+    }
+    let value = string_db
+        .get_value_mut(key)
+        .await
+        .expect("the HyperLogLog should exist")
+        .as_string_mut()?;
+    HyperLogLog::check(value)?;
+    Ok((value, created))
+}
+
+// raise `max` to the registers of the HyperLogLogs stored at `keys`, the missing keys are empty
+// HyperLogLogs. Returns whether one of them is dense.
+async fn merge_registers(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    keys: &[String],
+    max: &mut [u8],
+) -> Result<bool> {
+    let mut dense = false;
+    for key in keys {
+        if let Some(bytes) = string_db.get(key).await? {
+            let hll = HyperLogLog::from_bytes(Vec::from(bytes))?;
+            dense |= hll.is_dense();
+            hll.merge_into(max)?;
+        }
+    }
+    Ok(dense)
+}
+
+// https://redis.io/commands/pfadd/
+// PFADD key [element [element ...]]
+// return: :1\r\n if a register was updated or the key was created, :0\r\n otherwise
+pub struct PfAdd {
+    pub key: String,
+    pub elements: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for PfAdd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PFADD'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let (value, mut updated) = hll_or_create(string_db, &self.key).await?;
+        // the buffer isn't copied unless it's shared
+        let mut hll = HyperLogLog::from_bytes(Vec::from(std::mem::take(value)))?;
+        let mut result = Ok(());
+        for element in self.elements.iter() {
+            match hll.add(element) {
+                Ok(added) => updated |= added,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if updated {
+            hll.invalidate_cache();
+        }
+        *value = hll.into_bytes().into();
+        if updated {
+            string_db.signal_modified(&self.key).await;
+        }
+        result?;
+        Ok(Frame::Integer(updated as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for PfAdd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("pfadd"));
+        }
+        Ok(PfAdd {
+            key: bytes_to_string(bulks[1].clone())?,
+            elements: bulks[2..].to_vec(),
+        })
+    }
+}
+
+// https://redis.io/commands/pfcount/
+// PFCOUNT key [key ...]
+// The cardinality of a single key is cached in the HyperLogLog, the cardinality of several keys is
+// the one of their union.
+// return: :<the estimated cardinality>\r\n, :0\r\n if the keys don't exist
+pub struct PfCount {
+    pub keys: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for PfCount {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PFCOUNT'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        if self.keys.len() > 1 {
+            let mut max = vec![0; REGISTERS];
+            merge_registers(string_db, &self.keys, &mut max).await?;
+            return Ok(Frame::Integer(count_registers(&max) as i64));
+        }
+
+        let value = match string_db.get_value_mut(&self.keys[0]).await {
+            Some(value) => value.as_string_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+        HyperLogLog::check(value)?;
+        let mut hll = HyperLogLog::from_bytes(Vec::from(std::mem::take(value)))?;
+        // the cardinality is written to the cache when it's stale
+        let stale = hll.is_cache_stale();
+        let count = hll.count();
+        *value = hll.into_bytes().into();
+        if stale {
+            string_db.signal_modified(&self.keys[0]).await;
+        }
+        Ok(Frame::Integer(count? as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for PfCount {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("pfcount"));
+        }
+        Ok(PfCount {
+            keys: bulks[1..]
+                .iter()
+                .map(|bulk| bytes_to_string(bulk.clone()))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+// https://redis.io/commands/pfmerge/
+// PFMERGE destkey [sourcekey [sourcekey ...]]
+// The destination is merged too when it exists, it becomes dense if one of the HyperLogLogs is.
+// return: +OK\r\n
+pub struct PfMerge {
+    pub destination: String,
+    pub sources: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for PfMerge {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PFMERGE'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let mut max = vec![0; REGISTERS];
+        let dense = merge_registers(string_db, std::slice::from_ref(&self.destination), &mut max)
+            .await?
+            | merge_registers(string_db, &self.sources, &mut max).await?;
+
+        let (value, _) = hll_or_create(string_db, &self.destination).await?;
+        let mut hll = HyperLogLog::from_bytes(Vec::from(std::mem::take(value)))?;
+        let mut result = if dense { hll.make_dense() } else { Ok(()) };
+        if result.is_ok() {
+            for (index, register) in max.into_iter().enumerate() {
+                if register > 0 {
+                    if let Err(e) = hll.set_register(index, register) {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+        }
+        hll.invalidate_cache();
+        *value = hll.into_bytes().into();
+        string_db.signal_modified(&self.destination).await;
+        result?;
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for PfMerge {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("pfmerge"));
+        }
+        Ok(PfMerge {
+            destination: bytes_to_string(bulks[1].clone())?,
+            sources: bulks[2..]
+                .iter()
+                .map(|bulk| bytes_to_string(bulk.clone()))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
@@ -0,0 +1,1276 @@
+use super::CmdExecutor;
+use crate::{
+    db::Db,
+    frame::Frame,
+    util::{bytes_to_i64, bytes_to_string, glob_match, to_unix_millis},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use bytes::Bytes;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+// values that take more allocations than this to free are freed in the background by UNLINK
+const LAZYFREE_THRESHOLD: usize = 64;
+
+// https://redis.io/commands/del/
+// https://redis.io/commands/unlink/
+// *3\r\n$3\r\ndel\r\n$4\r\nkey1\r\n$4\r\nkey2\r\n
+// return: the number of keys that were removed, e.g. :1\r\n
+pub struct Del {
+    pub keys: Vec<String>,
+    // UNLINK removes the keys right away but frees their values in the background
+    pub lazy: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Del {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.lazy { "UNLINK" } else { "DEL" }
+        );
+        let mut count = 0;
+        let mut lazy_free = Vec::new();
+        {
+            let mut inner = db.lock().await;
+            for key in self.keys.iter() {
+                if let Some(value) = inner.string_db().del(key).await {
+                    count += 1;
+                    // small values are cheaper to free right away than to hand over
+                    if self.lazy && value.free_effort() > LAZYFREE_THRESHOLD {
+                        lazy_free.push(value);
+                    }
+                }
+            }
+        }
+
+        if !lazy_free.is_empty() {
+            tokio::task::spawn_blocking(move || drop(lazy_free));
+        }
+        Ok(Frame::Integer(count))
+    }
+}
+
+impl Del {
+    // `lazy` distinguishes UNLINK from DEL
+    pub fn parse(bulks: Vec<Bytes>, lazy: bool) -> Result<Self> {
+        Ok(Del {
+            keys: parse_keys(bulks, if lazy { "unlink" } else { "del" })?,
+            lazy,
+        })
+    }
+}
+
+// https://redis.io/commands/exists/
+// a key mentioned multiple times is counted multiple times
+// return: :<the number of existing keys>\r\n
+pub struct Exists {
+    pub keys: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Exists {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'EXISTS'");
+        let mut inner = db.lock().await;
+        let mut count = 0;
+        for key in self.keys.iter() {
+            if inner.string_db().check_exist(key).await {
+                count += 1;
+            }
+        }
+        Ok(Frame::Integer(count))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Exists {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        Ok(Exists {
+            keys: parse_keys(bulks, "exists")?,
+        })
+    }
+}
+
+// https://redis.io/commands/type/
+// return: +string\r\n, or +none\r\n if the key doesn't exist
+pub struct Type {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Type {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'TYPE'");
+        let key_type = db.lock().await.string_db().key_type(&self.key).await;
+        Ok(Frame::Simple(key_type.to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Type {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'type' command")
+        }
+        Ok(Type {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/touch/
+// return: :<the number of keys that were touched>\r\n
+pub struct Touch {
+    pub keys: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Touch {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'TOUCH'");
+        // there is no access time to update yet, so touching a key only checks its existence
+        // (and lazily drops it if it has expired)
+        let mut inner = db.lock().await;
+        let mut count = 0;
+        for key in self.keys.iter() {
+            if inner.string_db().check_exist(key).await {
+                count += 1;
+            }
+        }
+        Ok(Frame::Integer(count))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Touch {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        Ok(Touch {
+            keys: parse_keys(bulks, "touch")?,
+        })
+    }
+}
+
+// https://redis.io/commands/expire/
+// https://redis.io/commands/pexpire/
+// https://redis.io/commands/expireat/
+// https://redis.io/commands/pexpireat/
+// EXPIRE key seconds [NX | XX | GT | LT]
+// return(the timeout was set): :1\r\n
+// return(the key doesn't exist or the condition isn't met): :0\r\n
+pub struct Expire {
+    pub key: String,
+    // milliseconds, relative to now unless `absolute` is set, in which case it's a unix time
+    pub time: i64,
+    pub absolute: bool,
+    pub condition: ExpireCondition,
+    // the name of the command in the error replies
+    pub cmd_name: &'static str,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExpireCondition {
+    // set expiry only when the key has no expiry
+    pub nx: bool,
+    // set expiry only when the key has an existing expiry
+    pub xx: bool,
+    // set expiry only when the new expiry is greater than the current one
+    pub gt: bool,
+    // set expiry only when the new expiry is less than the current one
+    pub lt: bool,
+}
+
+impl ExpireCondition {
+    // whether an expiry can be replaced by `expire_at`, both being unix times in milliseconds
+    pub fn is_met(&self, current: Option<i64>, expire_at: i64) -> bool {
+        match current {
+            // no expiry counts as an infinite ttl for GT and LT
+            None => !self.xx && !self.gt,
+            Some(current) => {
+                !self.nx && (!self.gt || expire_at > current) && (!self.lt || expire_at < current)
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Expire {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'EXPIRE'");
+        let now = to_unix_millis(SystemTime::now());
+        let expire_at = if self.absolute {
+            self.time
+        } else {
+            match now.checked_add(self.time) {
+                Some(expire_at) => expire_at,
+                None => bail!("ERR invalid expire time in '{}' command", self.cmd_name),
+            }
+        };
+
+        let mut inner = db.lock().await;
+        let current = match inner.string_db().get_expire_at(&self.key).await {
+            Some(current) => current.map(to_unix_millis),
+            None => return Ok(Frame::Integer(0)),
+        };
+
+        if !self.condition.is_met(current, expire_at) {
+            return Ok(Frame::Integer(0));
+        }
+
+        if expire_at <= now {
+            // an expiry in the past deletes the key right away
+            inner.string_db().del(&self.key).await;
+        } else {
+            let expire_at = UNIX_EPOCH + Duration::from_millis(expire_at as u64);
+            inner
+                .string_db()
+                .set_expire_at(&self.key, Some(expire_at))
+                .await;
+        }
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl Expire {
+    // `in_millis` distinguishes PEXPIRE(AT) from EXPIRE(AT), `absolute` distinguishes
+    // (P)EXPIREAT from (P)EXPIRE
+    pub fn parse(bulks: Vec<Bytes>, in_millis: bool, absolute: bool) -> Result<Self> {
+        let cmd_name = match (in_millis, absolute) {
+            (false, false) => "expire",
+            (true, false) => "pexpire",
+            (false, true) => "expireat",
+            (true, true) => "pexpireat",
+        };
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+
+        let key = bytes_to_string(bulks[1].clone())?;
+        let mut time = bytes_to_i64(bulks[2].clone())?;
+        if !in_millis {
+            time = match time.checked_mul(1000) {
+                Some(time) => time,
+                None => bail!("ERR invalid expire time in '{cmd_name}' command"),
+            };
+        }
+
+        let mut condition = ExpireCondition::default();
+        for flag in bulks[3..].iter() {
+            match flag.to_ascii_lowercase().as_slice() {
+                b"nx" => condition.nx = true,
+                b"xx" => condition.xx = true,
+                b"gt" => condition.gt = true,
+                b"lt" => condition.lt = true,
+                _ => bail!("ERR Unsupported option {}", String::from_utf8_lossy(flag)),
+            }
+        }
+        if condition.nx && (condition.xx || condition.gt || condition.lt) {
+            bail!("ERR NX and XX, GT or LT options at the same time are not compatible")
+        }
+        if condition.gt && condition.lt {
+            bail!("ERR GT and LT options at the same time are not compatible")
+        }
+
+        Ok(Expire {
+            key,
+            time,
+            absolute,
+            condition,
+            cmd_name,
+        })
+    }
+}
+
+// https://redis.io/commands/ttl/
+// https://redis.io/commands/pttl/
+// https://redis.io/commands/expiretime/
+// https://redis.io/commands/pexpiretime/
+// return(the key has an expiry): :<remaining ttl, or the unix time of the expiry>\r\n
+// return(the key exists but has no expiry): :-1\r\n
+// return(the key doesn't exist): :-2\r\n
+pub struct Ttl {
+    pub key: String,
+    pub in_millis: bool,
+    // reply with the unix time of the expiry instead of the remaining ttl, as (P)EXPIRETIME does
+    pub absolute: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Ttl {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'TTL'");
+        let mut inner = db.lock().await;
+        let millis = if self.absolute {
+            match inner.string_db().get_expire_at(&self.key).await {
+                Some(Some(expire_at)) => to_unix_millis(expire_at),
+                Some(None) => return Ok(Frame::Integer(-1)),
+                None => return Ok(Frame::Integer(-2)),
+            }
+        } else {
+            match inner.string_db().get_ttl(&self.key).await {
+                Some(Some(ttl)) => ttl.as_millis() as i64,
+                Some(None) => return Ok(Frame::Integer(-1)),
+                None => return Ok(Frame::Integer(-2)),
+            }
+        };
+
+        Ok(Frame::Integer(if self.in_millis {
+            millis
+        } else {
+            (millis + 500) / 1000
+        }))
+    }
+}
+
+impl Ttl {
+    pub fn parse(bulks: Vec<Bytes>, in_millis: bool, absolute: bool) -> Result<Self> {
+        if bulks.len() != 2 {
+            let cmd_name = match (in_millis, absolute) {
+                (false, false) => "ttl",
+                (true, false) => "pttl",
+                (false, true) => "expiretime",
+                (true, true) => "pexpiretime",
+            };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(Ttl {
+            key: bytes_to_string(bulks[1].clone())?,
+            in_millis,
+            absolute,
+        })
+    }
+}
+
+// https://redis.io/commands/persist/
+// return(the timeout was removed): :1\r\n
+// return(the key doesn't exist or has no expiry): :0\r\n
+pub struct Persist {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Persist {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PERSIST'");
+        let mut inner = db.lock().await;
+        if let Some(Some(_)) = inner.string_db().get_expire_at(&self.key).await {
+            inner.string_db().set_expire_at(&self.key, None).await;
+            return Ok(Frame::Integer(1));
+        }
+        Ok(Frame::Integer(0))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Persist {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'persist' command")
+        }
+        Ok(Persist {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/keys/
+// KEYS pattern
+// return: *<n>\r\n$<len>\r\n<key>\r\n...
+pub struct Keys {
+    pub pattern: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Keys {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'KEYS'");
+        let keys = db.lock().await.string_db().keys(&self.pattern).await;
+        Ok(Frame::Array(
+            keys.into_iter()
+                .map(|key| Frame::Bulk(key.into()))
+                .collect(),
+        ))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Keys {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'keys' command")
+        }
+        Ok(Keys {
+            pattern: bulks[1].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/scan/
+// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+// return: *2\r\n$<len>\r\n<next cursor>\r\n*<n>\r\n$<len>\r\n<key>\r\n...
+pub struct Scan {
+    pub cursor: u64,
+    pub options: ScanOptions,
+}
+
+pub struct ScanOptions {
+    pub pattern: Option<Bytes>,
+    // a hint of how many elements to visit per call
+    pub count: usize,
+    // only return the keys of this type, only supported by SCAN
+    pub key_type: Option<String>,
+    // only return the fields without their values, only supported by HSCAN
+    pub no_values: bool,
+}
+
+impl ScanOptions {
+    // parse `[MATCH pattern] [COUNT count] [TYPE type] [NOVALUES]`, TYPE and NOVALUES being
+    // only accepted when allowed
+    pub fn parse(args: &[Bytes], allow_type: bool, allow_no_values: bool) -> Result<Self> {
+        let mut options = ScanOptions {
+            pattern: None,
+            count: 10,
+            key_type: None,
+            no_values: false,
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if allow_no_values && arg.eq_ignore_ascii_case(b"novalues") {
+                options.no_values = true;
+                continue;
+            }
+            let value = match iter.next() {
+                Some(value) => value.clone(),
+                None => bail!("ERR syntax error"),
+            };
+            match arg.to_ascii_lowercase().as_slice() {
+                b"match" => {
+                    // matching everything is the same as not matching at all
+                    options.pattern = (value.as_ref() != b"*").then_some(value);
+                }
+                b"count" => {
+                    options.count = match bytes_to_i64(value)? {
+                        count if count >= 1 => count as usize,
+                        _ => bail!("ERR syntax error"),
+                    };
+                }
+                b"type" if allow_type => {
+                    options.key_type = Some(bytes_to_string(value)?.to_lowercase());
+                }
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn matches(&self, element: &[u8]) -> bool {
+        match self.pattern.as_ref() {
+            Some(pattern) => glob_match(pattern, element, false),
+            None => true,
+        }
+    }
+}
+
+pub fn parse_cursor(bulk: Bytes) -> Result<u64> {
+    String::from_utf8(bulk.into())
+        .ok()
+        .and_then(|cursor| cursor.parse().ok())
+        .ok_or_else(|| anyhow!("ERR invalid cursor"))
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Scan {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SCAN'");
+        let mut inner = db.lock().await;
+        let (cursor, keys) = inner
+            .string_db()
+            .scan(self.cursor, self.options.count)
+            .await;
+
+        let mut res = Vec::with_capacity(keys.len());
+        for key in keys {
+            if !self.options.matches(key.as_bytes()) {
+                continue;
+            }
+            // key_type() also filters out (and deletes) the expired keys
+            let key_type = inner.string_db().key_type(&key).await;
+            if key_type == "none" {
+                continue;
+            }
+            if matches!(&self.options.key_type, Some(t) if t != key_type) {
+                continue;
+            }
+            res.push(Frame::Bulk(key.into()));
+        }
+
+        Ok(Frame::Array(vec![
+            Frame::Bulk(cursor.to_string().into()),
+            Frame::Array(res),
+        ]))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Scan {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            bail!("ERR wrong number of arguments for 'scan' command")
+        }
+        Ok(Scan {
+            cursor: parse_cursor(bulks[1].clone())?,
+            options: ScanOptions::parse(&bulks[2..], true, false)?,
+        })
+    }
+}
+
+// https://redis.io/commands/randomkey/
+// return(the database isn't empty): $<len>\r\n<key>\r\n
+// return(the database is empty): $-1\r\n
+pub struct RandomKey;
+
+#[async_trait::async_trait]
+impl CmdExecutor for RandomKey {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'RANDOMKEY'");
+        Ok(match db.lock().await.string_db().random_key().await {
+            Some(key) => Frame::Bulk(key.into()),
+            None => Frame::Null,
+        })
+    }
+}
+
+// https://redis.io/commands/dbsize/
+// return: :<the number of keys>\r\n
+pub struct DbSize;
+
+#[async_trait::async_trait]
+impl CmdExecutor for DbSize {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'DBSIZE'");
+        let size = db.lock().await.string_db().dbsize().await;
+        Ok(Frame::Integer(size as i64))
+    }
+}
+
+// https://redis.io/commands/rename/
+// https://redis.io/commands/renamenx/
+// RENAME key newkey, the time to live of the key moves along with it
+// return(RENAME): +OK\r\n
+// return(RENAMENX): :1\r\n, or :0\r\n if newkey already exists
+pub struct Rename {
+    pub key: String,
+    pub new_key: String,
+    // RENAMENX only renames when newkey doesn't exist
+    pub nx: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Rename {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.nx { "RENAMENX" } else { "RENAME" }
+        );
+        let mut inner = db.lock().await;
+        if !inner.string_db().check_exist(&self.key).await {
+            bail!("ERR no such key")
+        }
+
+        if self.key == self.new_key {
+            return Ok(if self.nx {
+                Frame::Integer(0)
+            } else {
+                Frame::Simple("OK".to_string())
+            });
+        }
+        if self.nx && inner.string_db().check_exist(&self.new_key).await {
+            return Ok(Frame::Integer(0));
+        }
+
+        if let Some(entry) = inner.string_db().remove_entry(&self.key).await {
+            inner
+                .string_db()
+                .insert_entry(self.new_key.clone(), entry)
+                .await;
+            inner.serve_blocked(db.index, &self.new_key).await;
+        }
+        Ok(if self.nx {
+            Frame::Integer(1)
+        } else {
+            Frame::Simple("OK".to_string())
+        })
+    }
+}
+
+impl Rename {
+    // `nx` distinguishes RENAMENX from RENAME
+    pub fn parse(bulks: Vec<Bytes>, nx: bool) -> Result<Self> {
+        if bulks.len() != 3 {
+            let cmd_name = if nx { "renamenx" } else { "rename" };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(Rename {
+            key: bytes_to_string(bulks[1].clone())?,
+            new_key: bytes_to_string(bulks[2].clone())?,
+            nx,
+        })
+    }
+}
+
+// https://redis.io/commands/copy/
+// COPY source destination [DB destination-db] [REPLACE]
+// return(source was copied): :1\r\n
+// return(source doesn't exist, or destination exists without REPLACE): :0\r\n
+pub struct Copy {
+    pub source: String,
+    pub destination: String,
+    // the destination database, the selected one when None
+    pub db: Option<usize>,
+    pub replace: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Copy {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'COPY'");
+        let mut inner = db.lock().await;
+        let dst_index = self.db.unwrap_or(db.index);
+        if dst_index >= inner.string_dbs.len() {
+            bail!("ERR DB index is out of range")
+        }
+        if dst_index == db.index && self.source == self.destination {
+            bail!("ERR source and destination objects are the same")
+        }
+
+        let entry = match inner.string_db().clone_entry(&self.source).await {
+            Some(entry) => entry,
+            None => return Ok(Frame::Integer(0)),
+        };
+        let dst_db = &mut inner.string_dbs[dst_index];
+        if !self.replace && dst_db.check_exist(&self.destination).await {
+            return Ok(Frame::Integer(0));
+        }
+        dst_db.insert_entry(self.destination.clone(), entry).await;
+        inner.serve_blocked(dst_index, &self.destination).await;
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Copy {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'copy' command")
+        }
+        let mut copy = Copy {
+            source: bytes_to_string(bulks[1].clone())?,
+            destination: bytes_to_string(bulks[2].clone())?,
+            db: None,
+            replace: false,
+        };
+
+        let mut iter = bulks.into_iter().skip(3);
+        while let Some(arg) = iter.next() {
+            match arg.to_ascii_lowercase().as_slice() {
+                b"replace" => copy.replace = true,
+                b"db" => match iter.next() {
+                    Some(index) => copy.db = Some(parse_db_index(index)?),
+                    None => bail!("ERR syntax error"),
+                },
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        Ok(copy)
+    }
+}
+
+// https://redis.io/commands/move/
+// MOVE key db
+// return(the key was moved): :1\r\n
+// return(the key doesn't exist, or already exists in the target database): :0\r\n
+pub struct Move {
+    pub key: String,
+    pub db: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Move {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'MOVE'");
+        let mut inner = db.lock().await;
+        if self.db >= inner.string_dbs.len() {
+            bail!("ERR DB index is out of range")
+        }
+        if self.db == db.index {
+            bail!("ERR source and destination objects are the same")
+        }
+
+        if !inner.string_db().check_exist(&self.key).await
+            || inner.string_dbs[self.db].check_exist(&self.key).await
+        {
+            return Ok(Frame::Integer(0));
+        }
+        if let Some(entry) = inner.string_db().remove_entry(&self.key).await {
+            inner.string_dbs[self.db]
+                .insert_entry(self.key.clone(), entry)
+                .await;
+            inner.serve_blocked(self.db, &self.key).await;
+        }
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Move {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            bail!("ERR wrong number of arguments for 'move' command")
+        }
+        Ok(Move {
+            key: bytes_to_string(bulks[1].clone())?,
+            db: parse_db_index(bulks[2].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/select/
+// SELECT index
+// return: +OK\r\n
+pub struct Select {
+    pub index: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Select {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SELECT'");
+        if self.index >= db.lock().await.string_dbs.len() {
+            bail!("ERR DB index is out of range")
+        }
+        db.index = self.index;
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Select {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'select' command")
+        }
+        Ok(Select {
+            index: parse_db_index(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/swapdb/
+// SWAPDB index1 index2, connections that selected one of them see the other one from now on
+// return: +OK\r\n
+pub struct SwapDb {
+    pub index1: usize,
+    pub index2: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SwapDb {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SWAPDB'");
+        let mut inner = db.lock().await;
+        let len = inner.string_dbs.len();
+        if self.index1 >= len || self.index2 >= len {
+            bail!("ERR DB index is out of range")
+        }
+        // the keys stay watched in the database at the same index
+        let watched1 = inner.string_dbs[self.index1].take_watched().await;
+        let watched2 = inner.string_dbs[self.index2].take_watched().await;
+        inner.string_dbs.swap(self.index1, self.index2);
+        inner.string_dbs[self.index1]
+            .restore_watched(watched1)
+            .await;
+        inner.string_dbs[self.index2]
+            .restore_watched(watched2)
+            .await;
+        // the clients blocked in one of the databases may find their keys in the other one
+        for index in [self.index1, self.index2] {
+            for key in inner.blocked.keys(index) {
+                inner.serve_blocked(index, &key).await;
+            }
+        }
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SwapDb {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            bail!("ERR wrong number of arguments for 'swapdb' command")
+        }
+        let parse = |bulk: Bytes| {
+            bytes_to_i64(bulk)
+                .ok()
+                .and_then(|index| usize::try_from(index).ok())
+                .ok_or_else(|| anyhow!("ERR invalid DB index"))
+        };
+        Ok(SwapDb {
+            index1: parse(bulks[1].clone())?,
+            index2: parse(bulks[2].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/flushdb/
+// https://redis.io/commands/flushall/
+// FLUSHDB [ASYNC | SYNC]
+// return: +OK\r\n
+pub struct Flush {
+    // FLUSHALL empties every database instead of only the selected one
+    pub all: bool,
+    // free the removed keys in the background
+    pub lazy: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Flush {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.all { "FLUSHALL" } else { "FLUSHDB" }
+        );
+        let mut removed = Vec::new();
+        {
+            let mut inner = db.lock().await;
+            if self.all {
+                for string_db in inner.string_dbs.iter_mut() {
+                    removed.push(string_db.flush().await);
+                }
+            } else {
+                removed.push(inner.string_db().flush().await);
+            }
+        }
+
+        if self.lazy {
+            tokio::task::spawn_blocking(move || drop(removed));
+        }
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl Flush {
+    // `all` distinguishes FLUSHALL from FLUSHDB
+    pub fn parse(bulks: Vec<Bytes>, all: bool) -> Result<Self> {
+        let lazy = match bulks.len() {
+            1 => false,
+            2 => match bulks[1].to_ascii_lowercase().as_slice() {
+                b"async" => true,
+                b"sync" => false,
+                _ => bail!("ERR syntax error"),
+            },
+            _ => bail!("ERR syntax error"),
+        };
+        Ok(Flush { all, lazy })
+    }
+}
+
+pub fn parse_db_index(bulk: Bytes) -> Result<usize> {
+    match bytes_to_i64(bulk)? {
+        index if index >= 0 => Ok(index as usize),
+        _ => bail!("ERR DB index is out of range"),
+    }
+}
+
+// parse `<cmd> key [key ...]`
+fn parse_keys(bulks: Vec<Bytes>, cmd_name: &str) -> Result<Vec<String>> {
+    if bulks.len() < 2 {
+        bail!("ERR wrong number of arguments for '{cmd_name}' command")
+    }
+    bulks.into_iter().skip(1).map(bytes_to_string).collect()
+}
+
+#[cfg(test)]
+mod keys_test {
+    use super::*;
+    use crate::{
+        cmd::run,
+        db::{StringDb, StringDbManipulator},
+    };
+
+    fn new_db(dbnum: usize) -> Db {
+        Db::new(
+            (0..dbnum)
+                .map(|_| Box::new(StringDb::new()) as Box<dyn StringDbManipulator>)
+                .collect(),
+        )
+    }
+
+    async fn get(db: &mut Db, key: &str) -> Frame {
+        run(db, &["get", key]).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn select_should_fail_out_of_range() {
+        let mut db = new_db(2);
+        run(&mut db, &["select", "1"]).await.unwrap();
+        assert_eq!(1, db.index);
+        for index in ["2", "-1"] {
+            assert_eq!(
+                "ERR DB index is out of range",
+                run(&mut db, &["select", index])
+                    .await
+                    .unwrap_err()
+                    .to_string()
+            );
+        }
+        assert!(run(&mut db, &["select", "x"]).await.is_err());
+        assert_eq!(1, db.index);
+    }
+
+    #[tokio::test]
+    async fn swapdb_should_be_seen_by_every_connection() {
+        let mut db = new_db(2);
+        let mut other = db.clone();
+        run(&mut other, &["select", "1"]).await.unwrap();
+        run(&mut db, &["set", "k", "0"]).await.unwrap();
+        run(&mut other, &["set", "k", "1"]).await.unwrap();
+
+        run(&mut db, &["swapdb", "0", "1"]).await.unwrap();
+        assert_eq!(Frame::Bulk("1".into()), get(&mut db, "k").await);
+        assert_eq!(Frame::Bulk("0".into()), get(&mut other, "k").await);
+
+        assert_eq!(
+            "ERR DB index is out of range",
+            run(&mut db, &["swapdb", "0", "2"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "ERR invalid DB index",
+            run(&mut db, &["swapdb", "-1", "0"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn swapdb_should_serve_the_blocked_clients() {
+        let db = new_db(2);
+        let mut blocked = db.clone();
+        let handle = tokio::spawn(async move { run(&mut blocked, &["blpop", "list", "0"]).await });
+        while db.lock().await.blocked.is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let mut other = db.clone();
+        run(&mut other, &["select", "1"]).await.unwrap();
+        run(&mut other, &["rpush", "list", "x", "y"]).await.unwrap();
+        run(&mut other, &["swapdb", "1", "0"]).await.unwrap();
+
+        let res = tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("the client should be served")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Frame::Array(vec![Frame::Bulk("list".into()), Frame::Bulk("x".into())]),
+            res
+        );
+        assert!(db.lock().await.blocked.is_empty());
+        // the list that was popped from is the one now in db 0
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db.clone(), &["llen", "list"]).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn flushall_should_empty_every_db() {
+        for args in [
+            &["flushall", "async"][..],
+            &["flushall"],
+            &["flushall", "SYNC"],
+        ] {
+            let mut db = new_db(3);
+            for index in ["0", "2"] {
+                run(&mut db, &["select", index]).await.unwrap();
+                run(&mut db, &["set", "k", "v"]).await.unwrap();
+                run(&mut db, &["sadd", "s", "a"]).await.unwrap();
+            }
+            run(&mut db, args).await.unwrap();
+            for index in ["0", "1", "2"] {
+                run(&mut db, &["select", index]).await.unwrap();
+                assert_eq!(Frame::Integer(0), run(&mut db, &["dbsize"]).await.unwrap());
+            }
+        }
+
+        // while FLUSHDB only empties the selected one
+        let mut db = new_db(2);
+        run(&mut db, &["set", "k", "v"]).await.unwrap();
+        run(&mut db, &["select", "1"]).await.unwrap();
+        run(&mut db, &["set", "k", "v"]).await.unwrap();
+        run(&mut db, &["flushdb", "async"]).await.unwrap();
+        assert_eq!(Frame::Null, get(&mut db, "k").await);
+        run(&mut db, &["select", "0"]).await.unwrap();
+        assert_eq!(Frame::Bulk("v".into()), get(&mut db, "k").await);
+
+        assert!(run(&mut db, &["flushall", "now"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn del_and_unlink_should_count_the_removed_keys() {
+        let mut db = new_db(1);
+        run(&mut db, &["mset", "a", "1", "b", "2"]).await.unwrap();
+        // big enough to be freed in the background by UNLINK
+        let members: Vec<String> = (0..=LAZYFREE_THRESHOLD).map(|i| i.to_string()).collect();
+        let mut sadd = vec!["sadd", "big"];
+        sadd.extend(members.iter().map(String::as_str));
+        run(&mut db, &sadd).await.unwrap();
+        run(&mut db, &["rpush", "small", "x"]).await.unwrap();
+
+        // a key mentioned twice is removed once
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["del", "a", "a", "missing"]).await.unwrap()
+        );
+        assert_eq!(
+            Frame::Integer(3),
+            run(&mut db, &["unlink", "b", "big", "small", "a"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(Frame::Integer(0), run(&mut db, &["dbsize"]).await.unwrap());
+
+        assert!(run(&mut db, &["del"]).await.is_err());
+        assert!(run(&mut db, &["unlink"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn exists_and_touch_should_count_duplicates() {
+        let mut db = new_db(1);
+        run(&mut db, &["set", "a", "1"]).await.unwrap();
+        run(&mut db, &["hset", "h", "f", "v"]).await.unwrap();
+        run(&mut db, &["set", "old", "1", "PX", "1"]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        for cmd in ["exists", "touch"] {
+            assert_eq!(
+                Frame::Integer(3),
+                run(&mut db, &[cmd, "a", "h", "a", "missing", "old"])
+                    .await
+                    .unwrap()
+            );
+            assert!(run(&mut db, &[cmd]).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn type_should_name_every_type() {
+        let mut db = new_db(1);
+        let commands: [&[&str]; 6] = [
+            &["set", "string", "v"],
+            &["rpush", "list", "v"],
+            &["sadd", "set", "v"],
+            &["zadd", "zset", "1", "v"],
+            &["hset", "hash", "f", "v"],
+            &["xadd", "stream", "*", "f", "v"],
+        ];
+        for args in commands {
+            run(&mut db, args).await.unwrap();
+            assert_eq!(
+                Frame::Simple(args[1].to_string()),
+                run(&mut db, &["type", args[1]]).await.unwrap()
+            );
+        }
+        assert_eq!(
+            Frame::Simple("none".into()),
+            run(&mut db, &["type", "missing"]).await.unwrap()
+        );
+        assert!(run(&mut db, &["type", "a", "b"]).await.is_err());
+    }
+
+    async fn ttl(db: &mut Db, key: &str) -> Frame {
+        run(db, &["ttl", key]).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn rename_should_keep_the_ttl() {
+        let mut db = new_db(1);
+        run(&mut db, &["set", "a", "1", "EX", "100"]).await.unwrap();
+        run(&mut db, &["set", "b", "2"]).await.unwrap();
+
+        assert_eq!(
+            Frame::Simple("OK".into()),
+            run(&mut db, &["rename", "a", "b"]).await.unwrap()
+        );
+        assert_eq!(Frame::Bulk("1".into()), get(&mut db, "b").await);
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "b").await);
+        assert_eq!(Frame::Integer(-2), ttl(&mut db, "a").await);
+
+        // renaming a key to itself keeps it
+        assert_eq!(
+            Frame::Simple("OK".into()),
+            run(&mut db, &["rename", "b", "b"]).await.unwrap()
+        );
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "b").await);
+
+        assert_eq!(
+            "ERR no such key",
+            run(&mut db, &["rename", "a", "c"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(run(&mut db, &["rename", "b"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn renamenx_should_not_overwrite() {
+        let mut db = new_db(1);
+        run(&mut db, &["mset", "a", "1", "b", "2"]).await.unwrap();
+        for new_key in ["b", "a"] {
+            assert_eq!(
+                Frame::Integer(0),
+                run(&mut db, &["renamenx", "a", new_key]).await.unwrap()
+            );
+        }
+        assert_eq!(Frame::Bulk("2".into()), get(&mut db, "b").await);
+
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["renamenx", "a", "c"]).await.unwrap()
+        );
+        assert_eq!(Frame::Null, get(&mut db, "a").await);
+        assert_eq!(Frame::Bulk("1".into()), get(&mut db, "c").await);
+        assert!(run(&mut db, &["renamenx", "a", "d"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn copy_should_replace_only_when_asked() {
+        let mut db = new_db(2);
+        run(&mut db, &["rpush", "src", "x", "y"]).await.unwrap();
+        run(&mut db, &["expire", "src", "100"]).await.unwrap();
+        run(&mut db, &["set", "dst", "old"]).await.unwrap();
+
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["copy", "src", "dst"]).await.unwrap()
+        );
+        assert_eq!(Frame::Bulk("old".into()), get(&mut db, "dst").await);
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["copy", "src", "dst", "REPLACE"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "dst").await);
+
+        // the copy is independent of the source
+        run(&mut db, &["rpush", "dst", "z"]).await.unwrap();
+        assert_eq!(
+            Frame::Integer(2),
+            run(&mut db, &["llen", "src"]).await.unwrap()
+        );
+
+        // to another database, which the selected one doesn't see
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["copy", "src", "src", "DB", "1"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["copy", "dst", "src", "db", "1"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["copy", "dst", "src", "db", "1", "replace"])
+                .await
+                .unwrap()
+        );
+        run(&mut db, &["select", "1"]).await.unwrap();
+        assert_eq!(
+            Frame::Integer(3),
+            run(&mut db, &["llen", "src"]).await.unwrap()
+        );
+        assert_eq!(Frame::Integer(-2), ttl(&mut db, "dst").await);
+        run(&mut db, &["select", "0"]).await.unwrap();
+
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["copy", "missing", "new"]).await.unwrap()
+        );
+        for (args, err) in [
+            (
+                &["copy", "src", "src"][..],
+                "ERR source and destination objects are the same",
+            ),
+            (
+                &["copy", "src", "src", "DB", "0"],
+                "ERR source and destination objects are the same",
+            ),
+            (
+                &["copy", "src", "new", "DB", "2"],
+                "ERR DB index is out of range",
+            ),
+            (&["copy", "src", "new", "DB"], "ERR syntax error"),
+            (&["copy", "src", "new", "NX"], "ERR syntax error"),
+        ] {
+            assert_eq!(err, run(&mut db, args).await.unwrap_err().to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn move_should_not_overwrite() {
+        let mut db = new_db(2);
+        run(&mut db, &["set", "a", "0", "EX", "100"]).await.unwrap();
+        run(&mut db, &["set", "b", "0"]).await.unwrap();
+        run(&mut db, &["select", "1"]).await.unwrap();
+        run(&mut db, &["set", "b", "1"]).await.unwrap();
+        run(&mut db, &["select", "0"]).await.unwrap();
+
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["move", "a", "1"]).await.unwrap()
+        );
+        assert_eq!(Frame::Null, get(&mut db, "a").await);
+        for key in ["b", "missing"] {
+            assert_eq!(
+                Frame::Integer(0),
+                run(&mut db, &["move", key, "1"]).await.unwrap()
+            );
+        }
+        assert_eq!(Frame::Bulk("0".into()), get(&mut db, "b").await);
+
+        run(&mut db, &["select", "1"]).await.unwrap();
+        assert_eq!(Frame::Bulk("0".into()), get(&mut db, "a").await);
+        assert_eq!(Frame::Integer(100), ttl(&mut db, "a").await);
+        assert_eq!(Frame::Bulk("1".into()), get(&mut db, "b").await);
+
+        for (args, err) in [
+            (
+                &["move", "a", "1"][..],
+                "ERR source and destination objects are the same",
+            ),
+            (&["move", "a", "2"], "ERR DB index is out of range"),
+            (&["move", "a", "-1"], "ERR DB index is out of range"),
+        ] {
+            assert_eq!(err, run(&mut db, args).await.unwrap_err().to_string());
+        }
+    }
+}
@@ -0,0 +1,1013 @@
+use super::CmdExecutor;
+use crate::{
+    db::{list_or_create, pop, BlockedOp, Db, DbGuard, DbInner, Direction, StringDbManipulator},
+    frame::Frame,
+    util::{bytes_to_i64, bytes_to_string},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use bytes::Bytes;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+impl TryFrom<Bytes> for Direction {
+    type Error = Error;
+
+    fn try_from(value: Bytes) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_slice() {
+            b"left" => Ok(Direction::Left),
+            b"right" => Ok(Direction::Right),
+            _ => Err(anyhow!("ERR syntax error")),
+        }
+    }
+}
+
+// turn a possibly negative index (counting from the tail) into an index from the head, None if
+// it's out of range
+fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+// turn a range of possibly negative indexes into an inclusive range of indexes from the head,
+// None if the range is empty
+fn normalize_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 { end + len } else { end.min(len - 1) };
+    (start <= end && start < len).then_some((start as usize, end as usize))
+}
+
+pub fn wrong_args(cmd_name: &str) -> Error {
+    anyhow!("ERR wrong number of arguments for '{cmd_name}' command")
+}
+
+// https://redis.io/commands/lpush/
+// https://redis.io/commands/rpush/
+// https://redis.io/commands/lpushx/
+// https://redis.io/commands/rpushx/
+// LPUSH key element [element ...]
+// return: :<the length of the list after the push>\r\n
+pub struct Push {
+    pub key: String,
+    pub elements: Vec<Bytes>,
+    pub direction: Direction,
+    // LPUSHX and RPUSHX only push to an existing list
+    pub only_existing: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Push {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PUSH'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        if self.only_existing && !string_db.check_exist(&self.key).await {
+            return Ok(Frame::Integer(0));
+        }
+
+        let list = list_or_create(string_db, &self.key).await?;
+        for element in self.elements {
+            list.push(self.direction, element);
+        }
+        let len = list.len();
+        string_db.signal_modified(&self.key).await;
+        inner.serve_blocked(db.index, &self.key).await;
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl Push {
+    pub fn parse(bulks: Vec<Bytes>, direction: Direction, only_existing: bool) -> Result<Self> {
+        if bulks.len() < 3 {
+            let cmd_name = match (direction, only_existing) {
+                (Direction::Left, false) => "lpush",
+                (Direction::Right, false) => "rpush",
+                (Direction::Left, true) => "lpushx",
+                (Direction::Right, true) => "rpushx",
+            };
+            return Err(wrong_args(cmd_name));
+        }
+        Ok(Push {
+            key: bytes_to_string(bulks[1].clone())?,
+            elements: bulks[2..].to_vec(),
+            direction,
+            only_existing,
+        })
+    }
+}
+
+// https://redis.io/commands/lpop/
+// https://redis.io/commands/rpop/
+// LPOP key [count]
+// return(without count): $<len>\r\n<element>\r\n, or $-1\r\n if the key doesn't exist
+// return(with count): *<n>\r\n<element>..., or *-1\r\n if the key doesn't exist
+pub struct Pop {
+    pub key: String,
+    pub count: Option<usize>,
+    pub direction: Direction,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Pop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'POP'");
+        let mut inner = db.lock().await;
+        let elements = pop(
+            inner.string_db(),
+            &self.key,
+            self.count.unwrap_or(1),
+            self.direction,
+        )
+        .await?;
+
+        Ok(match (elements, self.count) {
+            (None, None) => Frame::Null,
+            (None, Some(_)) => Frame::NullArray,
+            (Some(mut elements), None) => elements.pop().map_or(Frame::Null, Frame::Bulk),
+            (Some(elements), Some(_)) => elements.into(),
+        })
+    }
+}
+
+impl Pop {
+    pub fn parse(bulks: Vec<Bytes>, direction: Direction) -> Result<Self> {
+        let cmd_name = match direction {
+            Direction::Left => "lpop",
+            Direction::Right => "rpop",
+        };
+        let count = match bulks.len() {
+            2 => None,
+            3 => Some(parse_count(bulks[2].clone())?),
+            _ => return Err(wrong_args(cmd_name)),
+        };
+        Ok(Pop {
+            key: bytes_to_string(bulks[1].clone())?,
+            count,
+            direction,
+        })
+    }
+}
+
+pub fn parse_count(bulk: Bytes) -> Result<usize> {
+    match bytes_to_i64(bulk)? {
+        count if count >= 0 => Ok(count as usize),
+        _ => bail!("ERR value is out of range, must be positive"),
+    }
+}
+
+// https://redis.io/commands/lrange/
+// LRANGE key start stop
+// return: *<n>\r\n<element>...
+pub struct LRange {
+    pub key: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LRange {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LRANGE'");
+        let mut inner = db.lock().await;
+        let list = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_list()?,
+            None => return Ok(Frame::Array(vec![])),
+        };
+
+        Ok(match normalize_range(self.start, self.end, list.len()) {
+            Some((start, end)) => Frame::Array(
+                list.iter_from(start)
+                    .take(end - start + 1)
+                    .map(|element| Frame::Bulk(element.clone()))
+                    .collect(),
+            ),
+            None => Frame::Array(vec![]),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LRange {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            return Err(wrong_args("lrange"));
+        }
+        Ok(LRange {
+            key: bytes_to_string(bulks[1].clone())?,
+            start: bytes_to_i64(bulks[2].clone())?,
+            end: bytes_to_i64(bulks[3].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/llen/
+// return: :<the length of the list>\r\n, :0\r\n if the key doesn't exist
+pub struct LLen {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LLen {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LLEN'");
+        let mut inner = db.lock().await;
+        let len = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_list()?.len(),
+            None => 0,
+        };
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LLen {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            return Err(wrong_args("llen"));
+        }
+        Ok(LLen {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/lindex/
+// LINDEX key index, a negative index counts from the tail
+// return: $<len>\r\n<element>\r\n, or $-1\r\n if the index is out of range
+pub struct LIndex {
+    pub key: String,
+    pub index: i64,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LIndex {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LINDEX'");
+        let mut inner = db.lock().await;
+        let list = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_list()?,
+            None => return Ok(Frame::Null),
+        };
+        Ok(normalize_index(self.index, list.len())
+            .and_then(|index| list.get(index))
+            .map_or(Frame::Null, |element| Frame::Bulk(element.clone())))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LIndex {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            return Err(wrong_args("lindex"));
+        }
+        Ok(LIndex {
+            key: bytes_to_string(bulks[1].clone())?,
+            index: bytes_to_i64(bulks[2].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/lset/
+// LSET key index element
+// return: +OK\r\n
+pub struct LSet {
+    pub key: String,
+    pub index: i64,
+    pub element: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LSet {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LSET'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let list = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_list_mut()?,
+            None => bail!("ERR no such key"),
+        };
+        match normalize_index(self.index, list.len()) {
+            Some(index) => list.set(index, self.element),
+            None => bail!("ERR index out of range"),
+        };
+        string_db.signal_modified(&self.key).await;
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LSet {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            return Err(wrong_args("lset"));
+        }
+        Ok(LSet {
+            key: bytes_to_string(bulks[1].clone())?,
+            index: bytes_to_i64(bulks[2].clone())?,
+            element: bulks[3].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/linsert/
+// LINSERT key <BEFORE | AFTER> pivot element
+// return: :<the length of the list after the insert>\r\n
+// return(the pivot wasn't found): :-1\r\n
+// return(the key doesn't exist): :0\r\n
+pub struct LInsert {
+    pub key: String,
+    pub before: bool,
+    pub pivot: Bytes,
+    pub element: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LInsert {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LINSERT'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let list = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_list_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+        let pivot = match list.iter().position(|element| *element == self.pivot) {
+            Some(pivot) => pivot,
+            None => return Ok(Frame::Integer(-1)),
+        };
+
+        let index = if self.before { pivot } else { pivot + 1 };
+        list.insert(index, self.element);
+        let len = list.len();
+        string_db.signal_modified(&self.key).await;
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LInsert {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 5 {
+            return Err(wrong_args("linsert"));
+        }
+        let before = match bulks[2].to_ascii_lowercase().as_slice() {
+            b"before" => true,
+            b"after" => false,
+            _ => bail!("ERR syntax error"),
+        };
+        Ok(LInsert {
+            key: bytes_to_string(bulks[1].clone())?,
+            before,
+            pivot: bulks[3].clone(),
+            element: bulks[4].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/lrem/
+// LREM key count element, removes the first count occurrences (from the tail if count is
+// negative, all of them if count is 0)
+// return: :<the number of removed elements>\r\n
+pub struct LRem {
+    pub key: String,
+    pub count: i64,
+    pub element: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LRem {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LREM'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let list = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_list_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+
+        let removed = list.remove_matches(
+            &self.element,
+            self.count.unsigned_abs() as usize,
+            self.count < 0,
+        );
+        if list.is_empty() {
+            string_db.del(&self.key).await;
+        } else if removed > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LRem {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            return Err(wrong_args("lrem"));
+        }
+        Ok(LRem {
+            key: bytes_to_string(bulks[1].clone())?,
+            count: bytes_to_i64(bulks[2].clone())?,
+            element: bulks[3].clone(),
+        })
+    }
+}
+
+// https://redis.io/commands/ltrim/
+// LTRIM key start stop
+// return: +OK\r\n
+pub struct LTrim {
+    pub key: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LTrim {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LTRIM'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let list = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_list_mut()?,
+            None => return Ok(Frame::Simple("OK".to_string())),
+        };
+
+        match normalize_range(self.start, self.end, list.len()) {
+            Some((start, end)) => list.trim(start, end),
+            None => list.trim(1, 0),
+        }
+        if list.is_empty() {
+            string_db.del(&self.key).await;
+        } else {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LTrim {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            return Err(wrong_args("ltrim"));
+        }
+        Ok(LTrim {
+            key: bytes_to_string(bulks[1].clone())?,
+            start: bytes_to_i64(bulks[2].clone())?,
+            end: bytes_to_i64(bulks[3].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/lpos/
+// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+// return(without COUNT): :<the index of the match>\r\n, or $-1\r\n if there is no match
+// return(with COUNT): *<n>\r\n:<index>\r\n...
+pub struct LPos {
+    pub key: String,
+    pub element: Bytes,
+    // skip the first rank-1 matches, search from the tail if negative
+    pub rank: i64,
+    // return up to count matches (all of them if 0) as an array
+    pub count: Option<usize>,
+    // compare at most maxlen elements (all of them if 0)
+    pub maxlen: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LPos {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LPOS'");
+        let mut inner = db.lock().await;
+        let list = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_list()?,
+            None if self.count.is_some() => return Ok(Frame::Array(vec![])),
+            None => return Ok(Frame::Null),
+        };
+
+        let len = list.len();
+        let limit = match self.count {
+            Some(0) => usize::MAX,
+            Some(count) => count,
+            None => 1,
+        };
+        let maxlen = if self.maxlen == 0 { len } else { self.maxlen };
+        let iter: Box<dyn Iterator<Item = (usize, &Bytes)>> = if self.rank > 0 {
+            Box::new(list.iter().enumerate())
+        } else {
+            Box::new(list.iter().rev().enumerate().map(|(i, e)| (len - 1 - i, e)))
+        };
+
+        let mut skip = self.rank.unsigned_abs() - 1;
+        let mut matches = vec![];
+        for (index, element) in iter.take(maxlen) {
+            if *element != self.element {
+                continue;
+            }
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            matches.push(index);
+            if matches.len() == limit {
+                break;
+            }
+        }
+
+        Ok(match self.count {
+            Some(_) => Frame::Array(
+                matches
+                    .into_iter()
+                    .map(|index| Frame::Integer(index as i64))
+                    .collect(),
+            ),
+            None => matches
+                .first()
+                .map_or(Frame::Null, |index| Frame::Integer(*index as i64)),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LPos {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            return Err(wrong_args("lpos"));
+        }
+        let mut lpos = LPos {
+            key: bytes_to_string(bulks[1].clone())?,
+            element: bulks[2].clone(),
+            rank: 1,
+            count: None,
+            maxlen: 0,
+        };
+
+        let mut iter = bulks.into_iter().skip(3);
+        while let Some(arg) = iter.next() {
+            let value = match iter.next() {
+                Some(value) => bytes_to_i64(value)?,
+                None => bail!("ERR syntax error"),
+            };
+            match arg.to_ascii_lowercase().as_slice() {
+                b"rank" => {
+                    if value == 0 || value == i64::MIN {
+                        bail!("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list")
+                    }
+                    lpos.rank = value;
+                }
+                b"count" if value < 0 => bail!("ERR COUNT can't be negative"),
+                b"count" => lpos.count = Some(value as usize),
+                b"maxlen" if value < 0 => bail!("ERR MAXLEN can't be negative"),
+                b"maxlen" => lpos.maxlen = value as usize,
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        Ok(lpos)
+    }
+}
+
+// https://redis.io/commands/lmove/
+// https://redis.io/commands/rpoplpush/
+// LMOVE source destination <LEFT | RIGHT> <LEFT | RIGHT>
+// return: $<len>\r\n<element>\r\n, or $-1\r\n if source doesn't exist
+pub struct LMove {
+    pub source: String,
+    pub destination: String,
+    pub from: Direction,
+    pub to: Direction,
+}
+
+impl LMove {
+    // pop an element from source and push it to destination in a single step. Return None if
+    // source doesn't exist
+    pub async fn apply(
+        &self,
+        string_db: &mut Box<dyn StringDbManipulator>,
+    ) -> Result<Option<Bytes>> {
+        // like redis, a missing source replies nil whatever destination holds, while the type of
+        // destination is checked before touching source
+        match string_db.get_value(&self.source).await {
+            Some(value) if !value.as_list()?.is_empty() => {}
+            _ => return Ok(None),
+        }
+        if let Some(value) = string_db.get_value(&self.destination).await {
+            value.as_list()?;
+        }
+
+        let element = match pop(string_db, &self.source, 1, self.from).await? {
+            Some(mut elements) => elements.pop(),
+            None => None,
+        };
+        if let Some(element) = element.clone() {
+            let list = list_or_create(string_db, &self.destination).await?;
+            list.push(self.to, element);
+            string_db.signal_modified(&self.destination).await;
+        }
+        Ok(element)
+    }
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LMove {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LMOVE'");
+        let mut inner = db.lock().await;
+        Ok(match self.apply(inner.string_db()).await? {
+            Some(element) => {
+                inner.serve_blocked(db.index, &self.destination).await;
+                Frame::Bulk(element)
+            }
+            None => Frame::Null,
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for LMove {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 5 {
+            return Err(wrong_args("lmove"));
+        }
+        Ok(LMove {
+            source: bytes_to_string(bulks[1].clone())?,
+            destination: bytes_to_string(bulks[2].clone())?,
+            from: bulks[3].clone().try_into()?,
+            to: bulks[4].clone().try_into()?,
+        })
+    }
+}
+
+impl LMove {
+    // RPOPLPUSH source destination, the same as LMOVE source destination RIGHT LEFT
+    pub fn parse_rpoplpush(bulks: Vec<Bytes>) -> Result<Self> {
+        if bulks.len() != 3 {
+            return Err(wrong_args("rpoplpush"));
+        }
+        Ok(LMove {
+            source: bytes_to_string(bulks[1].clone())?,
+            destination: bytes_to_string(bulks[2].clone())?,
+            from: Direction::Right,
+            to: Direction::Left,
+        })
+    }
+}
+
+// parse the timeout in seconds of a blocking command, None means blocking forever
+pub fn parse_timeout(bulk: Bytes) -> Result<Option<Duration>> {
+    let timeout = std::str::from_utf8(&bulk)
+        .ok()
+        .and_then(|timeout| timeout.parse::<f64>().ok())
+        .filter(|timeout| timeout.is_finite())
+        .ok_or_else(|| anyhow!("ERR timeout is not a float or out of range"))?;
+    if timeout < 0.0 {
+        bail!("ERR timeout is negative")
+    }
+    if timeout == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(timeout)
+        .map(Some)
+        .map_err(|_| anyhow!("ERR timeout is out of range"))
+}
+
+// Unblocks the client when block_on is dropped while it waits, i.e. the client disconnected.
+struct BlockedGuard {
+    inner: Arc<Mutex<DbInner>>,
+    // None once the client isn't blocked anymore
+    id: Option<u64>,
+}
+
+impl Drop for BlockedGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let inner = self.inner.clone();
+            tokio::spawn(async move {
+                inner.lock().await.blocked.unblock(id);
+            });
+        }
+    }
+}
+
+// Block the client on `keys` until a command pushing to one of them serves it, or until the
+// timeout expires. The lock is released while waiting. Return the key the client was served from
+// and the popped elements, None on timeout. A transaction doesn't block, it times out right away
+pub async fn block_on(
+    db: &Db,
+    mut inner: DbGuard<'_>,
+    keys: Vec<String>,
+    op: BlockedOp,
+    timeout: Option<Duration>,
+) -> Result<Option<(String, Vec<Bytes>)>> {
+    if db.is_held() {
+        return Ok(None);
+    }
+    let (id, mut receiver) = inner.blocked.block(db.index, keys, op);
+    drop(inner);
+    let mut guard = BlockedGuard {
+        inner: db.inner.clone(),
+        id: Some(id),
+    };
+
+    let served = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, &mut receiver).await.ok(),
+        None => Some((&mut receiver).await),
+    };
+    if let Some(Ok(served)) = served {
+        guard.id = None;
+        return served.map(Some);
+    }
+
+    // a push may have served the client right before it took the lock back
+    db.lock().await.blocked.unblock(id);
+    guard.id = None;
+    match receiver.try_recv() {
+        Ok(served) => served.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+// https://redis.io/commands/blpop/
+// https://redis.io/commands/brpop/
+// BLPOP key [key ...] timeout
+// return: *2\r\n$<len>\r\n<key>\r\n$<len>\r\n<element>\r\n, or *-1\r\n on timeout
+pub struct BPop {
+    pub keys: Vec<String>,
+    pub direction: Direction,
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BPop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'BPOP'");
+        let mut inner = db.lock().await;
+        for key in self.keys.iter() {
+            if let Some(element) = pop(inner.string_db(), key, 1, self.direction)
+                .await?
+                .and_then(|mut elements| elements.pop())
+            {
+                return Ok(vec![Bytes::from(key.clone()), element].into());
+            }
+        }
+
+        let op = BlockedOp::Pop {
+            direction: self.direction,
+            count: 1,
+        };
+        Ok(
+            match block_on(db, inner, self.keys, op, self.timeout).await? {
+                Some((key, mut elements)) => match elements.pop() {
+                    Some(element) => vec![Bytes::from(key), element].into(),
+                    None => Frame::NullArray,
+                },
+                None => Frame::NullArray,
+            },
+        )
+    }
+
+    fn may_block(&self) -> bool {
+        true
+    }
+}
+
+impl BPop {
+    pub fn parse(bulks: Vec<Bytes>, direction: Direction) -> Result<Self> {
+        if bulks.len() < 3 {
+            return Err(wrong_args(match direction {
+                Direction::Left => "blpop",
+                Direction::Right => "brpop",
+            }));
+        }
+        let timeout = parse_timeout(bulks[bulks.len() - 1].clone())?;
+        Ok(BPop {
+            keys: bulks[1..bulks.len() - 1]
+                .iter()
+                .map(|key| bytes_to_string(key.clone()))
+                .collect::<Result<_>>()?,
+            direction,
+            timeout,
+        })
+    }
+}
+
+// https://redis.io/commands/lmpop/
+// https://redis.io/commands/blmpop/
+// LMPOP numkeys key [key ...] <LEFT | RIGHT> [COUNT count]
+// BLMPOP timeout numkeys key [key ...] <LEFT | RIGHT> [COUNT count]
+// return: *2\r\n$<len>\r\n<key>\r\n*<n>\r\n<element>..., or *-1\r\n if every list is empty
+pub struct LMPop {
+    pub keys: Vec<String>,
+    pub direction: Direction,
+    pub count: usize,
+    // BLMPOP blocks, None meaning forever
+    pub block: Option<Option<Duration>>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for LMPop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'LMPOP'");
+        let mut inner = db.lock().await;
+        for key in self.keys.iter() {
+            if let Some(elements) = pop(inner.string_db(), key, self.count, self.direction).await? {
+                return Ok(Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(key.clone())),
+                    elements.into(),
+                ]));
+            }
+        }
+        let timeout = match self.block {
+            Some(timeout) => timeout,
+            None => return Ok(Frame::NullArray),
+        };
+
+        let op = BlockedOp::Pop {
+            direction: self.direction,
+            count: self.count,
+        };
+        Ok(match block_on(db, inner, self.keys, op, timeout).await? {
+            Some((key, elements)) => {
+                Frame::Array(vec![Frame::Bulk(Bytes::from(key)), elements.into()])
+            }
+            None => Frame::NullArray,
+        })
+    }
+
+    fn may_block(&self) -> bool {
+        self.block.is_some()
+    }
+}
+
+impl LMPop {
+    // `block` distinguishes BLMPOP, which takes a timeout first, from LMPOP
+    pub fn parse(bulks: Vec<Bytes>, block: bool) -> Result<Self> {
+        let cmd_name = if block { "blmpop" } else { "lmpop" };
+        let mut iter = bulks.into_iter().skip(1);
+        let timeout = match block {
+            true => Some(parse_timeout(
+                iter.next().ok_or_else(|| wrong_args(cmd_name))?,
+            )?),
+            false => None,
+        };
+
+        let numkeys = bytes_to_i64(iter.next().ok_or_else(|| wrong_args(cmd_name))?)
+            .ok()
+            .filter(|numkeys| *numkeys > 0)
+            .ok_or_else(|| anyhow!("ERR numkeys should be greater than 0"))?;
+        let keys = (&mut iter)
+            .take(numkeys as usize)
+            .map(bytes_to_string)
+            .collect::<Result<Vec<_>>>()?;
+        if keys.len() < numkeys as usize {
+            return Err(wrong_args(cmd_name));
+        }
+        let direction = iter
+            .next()
+            .ok_or_else(|| wrong_args(cmd_name))?
+            .try_into()?;
+
+        let mut count = 1;
+        while let Some(arg) = iter.next() {
+            match (arg.to_ascii_lowercase().as_slice(), iter.next()) {
+                (b"count", Some(value)) => {
+                    count = bytes_to_i64(value)
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| anyhow!("ERR count should be greater than 0"))?
+                        as usize
+                }
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        Ok(LMPop {
+            keys,
+            direction,
+            count,
+            block: timeout,
+        })
+    }
+}
+
+// https://redis.io/commands/blmove/
+// https://redis.io/commands/brpoplpush/
+// BLMOVE source destination <LEFT | RIGHT> <LEFT | RIGHT> timeout
+// return: $<len>\r\n<element>\r\n, or $-1\r\n on timeout
+pub struct BLMove {
+    pub lmove: LMove,
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BLMove {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'BLMOVE'");
+        let mut inner = db.lock().await;
+        if let Some(element) = self.lmove.apply(inner.string_db()).await? {
+            inner.serve_blocked(db.index, &self.lmove.destination).await;
+            return Ok(Frame::Bulk(element));
+        }
+
+        let LMove {
+            source,
+            destination,
+            from,
+            to,
+        } = self.lmove;
+        let op = BlockedOp::Move {
+            from,
+            destination,
+            to,
+        };
+        Ok(
+            match block_on(db, inner, vec![source], op, self.timeout).await? {
+                Some((_, mut elements)) => elements.pop().map_or(Frame::Null, Frame::Bulk),
+                None => Frame::Null,
+            },
+        )
+    }
+
+    fn may_block(&self) -> bool {
+        true
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for BLMove {
+    type Error = Error;
+
+    fn try_from(mut bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 6 {
+            return Err(wrong_args("blmove"));
+        }
+        let timeout = parse_timeout(bulks.pop().expect("the timeout should exist"))?;
+        bulks[0] = Bytes::from("lmove");
+        Ok(BLMove {
+            lmove: bulks.try_into()?,
+            timeout,
+        })
+    }
+}
+
+impl BLMove {
+    // BRPOPLPUSH source destination timeout, the same as BLMOVE source destination RIGHT LEFT
+    pub fn parse_brpoplpush(mut bulks: Vec<Bytes>) -> Result<Self> {
+        if bulks.len() != 4 {
+            return Err(wrong_args("brpoplpush"));
+        }
+        let timeout = parse_timeout(bulks.pop().expect("the timeout should exist"))?;
+        Ok(BLMove {
+            lmove: LMove::parse_rpoplpush(bulks)?,
+            timeout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod list_test {
+    use super::*;
+    use crate::{cmd::run, db::StringDb};
+
+    #[tokio::test]
+    async fn blocked_clients_should_be_forgotten() {
+        let db = Db::new(vec![
+            Box::new(StringDb::new()) as Box<dyn StringDbManipulator>
+        ]);
+
+        // on timeout
+        let res = run(&mut db.clone(), &["blpop", "x", "0.01"]).await.unwrap();
+        assert_eq!(Frame::NullArray, res);
+        assert!(db.lock().await.blocked.is_empty());
+
+        // once the client disconnects, which drops the command
+        let mut blocked = db.clone();
+        let handle = tokio::spawn(async move { run(&mut blocked, &["blpop", "x", "0"]).await });
+        while db.lock().await.blocked.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        let unblocked = async {
+            while !db.lock().await.blocked.is_empty() {
+                tokio::task::yield_now().await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(1), unblocked)
+            .await
+            .expect("the client should be unblocked");
+        assert!(db.lock().await.blocked.keys(0).is_empty());
+    }
+}
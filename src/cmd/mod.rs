@@ -1,12 +1,52 @@
+mod bitmap;
 mod command;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod keys;
+mod list;
+mod persistence;
+mod pubsub;
 mod replication;
+mod set;
+mod stream;
+mod transaction;
+mod zset;
 
 use crate::db::Db;
 use crate::frame::Frame;
+pub use bitmap::*;
 pub use command::*;
+pub use geo::*;
+pub use hash::*;
+pub use hyperloglog::*;
+pub use keys::*;
+pub use list::*;
+pub use persistence::*;
+pub use pubsub::*;
 pub use replication::*;
+pub use set::*;
+pub use stream::*;
+pub use transaction::*;
+pub use zset::*;
 
 #[async_trait::async_trait]
 pub trait CmdExecutor: Send {
     async fn execute(self: Box<Self>, db: &mut Db) -> anyhow::Result<Frame>;
+
+    // whether the command may wait for another client, e.g. BLPOP. The connection stops waiting
+    // for it once the client closes the connection
+    fn may_block(&self) -> bool {
+        false
+    }
+}
+
+// run a command as if a client sent it, for the tests of the commands
+#[cfg(test)]
+pub async fn run(db: &mut Db, args: &[&str]) -> anyhow::Result<Frame> {
+    let bulks: Vec<bytes::Bytes> = args
+        .iter()
+        .map(|arg| bytes::Bytes::copy_from_slice(arg.as_bytes()))
+        .collect();
+    Frame::from(bulks).parse_cmd()?.execute(db).await
 }
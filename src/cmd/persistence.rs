@@ -0,0 +1,83 @@
+use super::{wrong_args, CmdExecutor};
+use crate::{
+    db::{encode_rdb, snapshot, Db},
+    frame::Frame,
+    CONFIG,
+};
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tracing::{debug, error};
+
+// whether a BGSAVE is writing the RDB file
+static SAVING: AtomicBool = AtomicBool::new(false);
+
+// https://redis.io/commands/save/
+// https://redis.io/commands/bgsave/
+// SAVE, write every database to the RDB file at --dir/--dbfilename, which is loaded on startup
+// BGSAVE, the same in the background, replying once the databases are copied
+// return(SAVE): +OK\r\n
+// return(BGSAVE): +Background saving started\r\n
+pub struct Save {
+    pub background: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Save {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.background { "BGSAVE" } else { "SAVE" }
+        );
+        if SAVING.swap(true, Ordering::SeqCst) {
+            bail!("ERR Background save already in progress");
+        }
+        let dbs = snapshot(&mut *db.lock().await).await;
+
+        if self.background {
+            tokio::spawn(async move {
+                let buf = tokio::task::spawn_blocking(move || encode_rdb(&dbs))
+                    .await
+                    .expect("the encoding doesn't panic");
+                if let Err(e) = write_rdb(&buf).await {
+                    error!("background saving failed: {e}");
+                }
+                SAVING.store(false, Ordering::SeqCst);
+            });
+            return Ok(Frame::Simple("Background saving started".to_string()));
+        }
+
+        let res = write_rdb(&encode_rdb(&dbs)).await;
+        SAVING.store(false, Ordering::SeqCst);
+        if let Err(e) = res {
+            error!("saving failed: {e}");
+            bail!("ERR Failed to save the RDB file");
+        }
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl Save {
+    // `background` distinguishes BGSAVE from SAVE
+    pub fn parse(bulks: Vec<Bytes>, background: bool) -> Result<Self> {
+        match bulks.len() {
+            1 => {}
+            _ if background => bail!("ERR syntax error"),
+            _ => return Err(wrong_args("save")),
+        }
+        Ok(Save { background })
+    }
+}
+
+// write the file next to the current one then rename it, so that the current one is replaced at
+// once and kept if writing fails
+async fn write_rdb(buf: &[u8]) -> Result<()> {
+    let path = Path::new(&CONFIG.dir).join(&CONFIG.dbfilename);
+    let temp = Path::new(&CONFIG.dir).join(format!("temp-{}.rdb", std::process::id()));
+    tokio::fs::write(&temp, buf).await?;
+    tokio::fs::rename(&temp, &path).await?;
+    Ok(())
+}
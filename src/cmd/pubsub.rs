@@ -0,0 +1,110 @@
+use super::{wrong_args, CmdExecutor};
+use crate::{db::Db, frame::Frame, util::bytes_to_string};
+use anyhow::{bail, Error, Result};
+use bytes::Bytes;
+use tracing::debug;
+
+// https://redis.io/commands/publish/
+// PUBLISH channel message
+// return: :<the number of clients that received the message>\r\n
+pub struct Publish {
+    pub channel: Bytes,
+    pub message: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for Publish {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PUBLISH'");
+        let receivers = db.lock().await.pubsub.publish(&self.channel, &self.message);
+        Ok(Frame::Integer(receivers as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Publish {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 3 {
+            return Err(wrong_args("publish"));
+        }
+        Ok(Publish {
+            channel: bulks[1].clone(),
+            message: bulks[2].clone(),
+        })
+    }
+}
+
+// PUBSUB subcommands
+#[derive(Debug, Clone, PartialEq)]
+pub enum PubSubOp {
+    Channels(Option<Bytes>),
+    NumSub(Vec<Bytes>),
+    NumPat,
+}
+
+// https://redis.io/commands/pubsub-channels/
+// https://redis.io/commands/pubsub-numsub/
+// https://redis.io/commands/pubsub-numpat/
+// PUBSUB CHANNELS [pattern]
+// PUBSUB NUMSUB [channel [channel ...]]
+// PUBSUB NUMPAT
+// The pattern subscriptions are only counted by NUMPAT.
+// return(CHANNELS): *<n>\r\n$<len>\r\n<channel>\r\n...
+// return(NUMSUB): *<2n>\r\n$<len>\r\n<channel>\r\n:<subscribers>\r\n...
+// return(NUMPAT): :<the number of patterns>\r\n
+pub struct PubSubCmd {
+    pub op: PubSubOp,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for PubSubCmd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'PUBSUB'");
+        let inner = db.lock().await;
+        let pubsub = &inner.pubsub;
+        Ok(match self.op {
+            PubSubOp::Channels(pattern) => Frame::Array(
+                pubsub
+                    .active_channels(pattern.as_deref())
+                    .into_iter()
+                    .map(Frame::Bulk)
+                    .collect(),
+            ),
+            PubSubOp::NumSub(channels) => {
+                let mut frames = Vec::with_capacity(channels.len() * 2);
+                for channel in channels {
+                    let subscribers = pubsub.numsub(&channel);
+                    frames.push(Frame::Bulk(channel));
+                    frames.push(Frame::Integer(subscribers as i64));
+                }
+                Frame::Array(frames)
+            }
+            PubSubOp::NumPat => Frame::Integer(pubsub.numpat() as i64),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for PubSubCmd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("pubsub"));
+        }
+        let subcommand = bytes_to_string(bulks[1].clone())?.to_lowercase();
+        let wrong_args = || wrong_args(&format!("pubsub|{subcommand}"));
+        let len = bulks.len();
+        let op = match subcommand.as_str() {
+            "channels" if len <= 3 => PubSubOp::Channels(bulks.get(2).cloned()),
+            "numsub" => PubSubOp::NumSub(bulks[2..].to_vec()),
+            "numpat" if len == 2 => PubSubOp::NumPat,
+            "channels" | "numpat" => return Err(wrong_args()),
+            _ => bail!(
+                "ERR unknown subcommand '{}'. Try PUBSUB HELP.",
+                String::from_utf8_lossy(&bulks[1])
+            ),
+        };
+        Ok(PubSubCmd { op })
+    }
+}
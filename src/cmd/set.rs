@@ -0,0 +1,627 @@
+use super::{parse_count, parse_cursor, CmdExecutor, ScanOptions};
+use crate::{
+    db::{set_or_create, Db, Set, StringDbManipulator, Value},
+    frame::Frame,
+    util::{bytes_to_i64, bytes_to_string},
+};
+use anyhow::{bail, Error, Result};
+use bytes::Bytes;
+use tracing::debug;
+
+// https://redis.io/commands/sadd/
+// SADD key member [member ...]
+// return: :<the number of members that were added>\r\n
+pub struct SAdd {
+    pub key: String,
+    pub members: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SAdd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SADD'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let set = set_or_create(string_db, &self.key).await?;
+        let added = self
+            .members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count();
+        if added > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(added as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SAdd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'sadd' command")
+        }
+        Ok(SAdd {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: bulks[2..].to_vec(),
+        })
+    }
+}
+
+// https://redis.io/commands/srem/
+// SREM key member [member ...], the key is deleted along with its last member
+// return: :<the number of members that were removed>\r\n
+pub struct SRem {
+    pub key: String,
+    pub members: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SRem {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SREM'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let set = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_set_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+
+        let removed = self
+            .members
+            .iter()
+            .filter(|member| set.remove(member))
+            .count();
+        if set.is_empty() {
+            string_db.del(&self.key).await;
+        } else if removed > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SRem {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'srem' command")
+        }
+        Ok(SRem {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: bulks[2..].to_vec(),
+        })
+    }
+}
+
+// https://redis.io/commands/smembers/
+// return: *<n>\r\n$<len>\r\n<member>\r\n...
+pub struct SMembers {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SMembers {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SMEMBERS'");
+        let mut inner = db.lock().await;
+        Ok(match inner.string_db().get_value(&self.key).await {
+            Some(value) => Frame::Array(value.as_set()?.iter().map(Frame::Bulk).collect()),
+            None => Frame::Array(vec![]),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SMembers {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'smembers' command")
+        }
+        Ok(SMembers {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/sismember/
+// https://redis.io/commands/smismember/
+// SMISMEMBER key member [member ...]
+// return: :1\r\n if the member exists, :0\r\n otherwise
+// return(SMISMEMBER): *<n>\r\n:<1 or 0>\r\n...
+pub struct SIsMember {
+    pub key: String,
+    pub members: Vec<Bytes>,
+    // SMISMEMBER replies an array, even for a single member
+    pub multiple: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SIsMember {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.multiple {
+                "SMISMEMBER"
+            } else {
+                "SISMEMBER"
+            }
+        );
+        let mut inner = db.lock().await;
+        let set = match inner.string_db().get_value(&self.key).await {
+            Some(value) => Some(value.as_set()?),
+            None => None,
+        };
+
+        let mut res: Vec<Frame> = self
+            .members
+            .iter()
+            .map(|member| Frame::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+            .collect();
+        Ok(match self.multiple {
+            true => Frame::Array(res),
+            false => res.pop().unwrap_or(Frame::Integer(0)),
+        })
+    }
+}
+
+impl SIsMember {
+    // `multiple` distinguishes SMISMEMBER from SISMEMBER
+    pub fn parse(bulks: Vec<Bytes>, multiple: bool) -> Result<Self> {
+        if bulks.len() < 3 || (!multiple && bulks.len() != 3) {
+            let cmd_name = if multiple { "smismember" } else { "sismember" };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(SIsMember {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: bulks[2..].to_vec(),
+            multiple,
+        })
+    }
+}
+
+// https://redis.io/commands/scard/
+// return: :<the number of members>\r\n, :0\r\n if the key doesn't exist
+pub struct SCard {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SCard {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SCARD'");
+        let mut inner = db.lock().await;
+        let len = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_set()?.len(),
+            None => 0,
+        };
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SCard {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'scard' command")
+        }
+        Ok(SCard {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/spop/
+// SPOP key [count]
+// return(without count): $<len>\r\n<member>\r\n, or $-1\r\n if the key doesn't exist
+// return(with count): *<n>\r\n$<len>\r\n<member>\r\n...
+pub struct SPop {
+    pub key: String,
+    pub count: Option<usize>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SPop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SPOP'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let set = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_set_mut()?,
+            None if self.count.is_some() => return Ok(Frame::Array(vec![])),
+            None => return Ok(Frame::Null),
+        };
+
+        let mut members = set.pop(self.count.unwrap_or(1));
+        if set.is_empty() {
+            string_db.del(&self.key).await;
+        } else if !members.is_empty() {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(match self.count {
+            Some(_) => members.into(),
+            None => members.pop().map_or(Frame::Null, Frame::Bulk),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SPop {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let count = match bulks.len() {
+            2 => None,
+            3 => Some(parse_count(bulks[2].clone())?),
+            _ => bail!("ERR wrong number of arguments for 'spop' command"),
+        };
+        Ok(SPop {
+            key: bytes_to_string(bulks[1].clone())?,
+            count,
+        })
+    }
+}
+
+// https://redis.io/commands/srandmember/
+// SRANDMEMBER key [count], a negative count allows the same member to be returned several times
+// return(without count): $<len>\r\n<member>\r\n, or $-1\r\n if the key doesn't exist
+// return(with count): *<n>\r\n$<len>\r\n<member>\r\n...
+pub struct SRandMember {
+    pub key: String,
+    pub count: Option<i64>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SRandMember {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SRANDMEMBER'");
+        let mut inner = db.lock().await;
+        let set = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_set()?,
+            None if self.count.is_some() => return Ok(Frame::Array(vec![])),
+            None => return Ok(Frame::Null),
+        };
+
+        Ok(match self.count {
+            Some(count) => set
+                .random_members(count.unsigned_abs() as usize, count >= 0)
+                .into(),
+            None => set.random_member().map_or(Frame::Null, Frame::Bulk),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SRandMember {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let count = match bulks.len() {
+            2 => None,
+            3 => Some(bytes_to_i64(bulks[2].clone())?),
+            _ => bail!("ERR wrong number of arguments for 'srandmember' command"),
+        };
+        Ok(SRandMember {
+            key: bytes_to_string(bulks[1].clone())?,
+            count,
+        })
+    }
+}
+
+// https://redis.io/commands/smove/
+// SMOVE source destination member
+// return(the member was moved): :1\r\n
+// return(the member isn't in source): :0\r\n
+pub struct SMove {
+    pub source: String,
+    pub destination: String,
+    pub member: Bytes,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SMove {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SMOVE'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        // check the type of destination before touching source
+        if let Some(value) = string_db.get_value(&self.destination).await {
+            value.as_set()?;
+        }
+        let source = match string_db.get_value_mut(&self.source).await {
+            Some(value) => value.as_set_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+        if self.source == self.destination {
+            return Ok(Frame::Integer(source.contains(&self.member) as i64));
+        }
+
+        if !source.remove(&self.member) {
+            return Ok(Frame::Integer(0));
+        }
+        if source.is_empty() {
+            string_db.del(&self.source).await;
+        } else {
+            string_db.signal_modified(&self.source).await;
+        }
+        if set_or_create(string_db, &self.destination)
+            .await?
+            .insert(self.member)
+        {
+            string_db.signal_modified(&self.destination).await;
+        }
+        Ok(Frame::Integer(1))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SMove {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for 'smove' command")
+        }
+        Ok(SMove {
+            source: bytes_to_string(bulks[1].clone())?,
+            destination: bytes_to_string(bulks[2].clone())?,
+            member: bulks[3].clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SetOp {
+    fn cmd_name(self, store: bool) -> &'static str {
+        match (self, store) {
+            (SetOp::Inter, false) => "SINTER",
+            (SetOp::Union, false) => "SUNION",
+            (SetOp::Diff, false) => "SDIFF",
+            (SetOp::Inter, true) => "SINTERSTORE",
+            (SetOp::Union, true) => "SUNIONSTORE",
+            (SetOp::Diff, true) => "SDIFFSTORE",
+        }
+    }
+}
+
+// Combine the sets stored at `keys`, a missing key counting as an empty set. The difference is
+// the members of the first set that aren't in the others.
+async fn combine(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    keys: &[String],
+    op: SetOp,
+) -> Result<Set> {
+    // check the types first, so that a key of another type fails even after a missing key
+    let mut lens = Vec::with_capacity(keys.len());
+    for key in keys.iter() {
+        lens.push(match string_db.get_value(key).await {
+            Some(value) => value.as_set()?.len(),
+            None => 0,
+        });
+    }
+
+    let mut members: Vec<Bytes> = vec![];
+    match op {
+        SetOp::Union => {
+            let mut res = Set::new();
+            for key in keys.iter() {
+                if let Some(Value::Set(set)) = string_db.get_value(key).await {
+                    for member in set.iter() {
+                        res.insert(member);
+                    }
+                }
+            }
+            return Ok(res);
+        }
+        SetOp::Inter if lens.contains(&0) => return Ok(Set::new()),
+        SetOp::Inter => {
+            // start from the smallest set, each member is then looked up in the others
+            let mut order: Vec<usize> = (0..keys.len()).collect();
+            order.sort_by_key(|i| lens[*i]);
+            for (n, i) in order.into_iter().enumerate() {
+                let set = match string_db.get_value(&keys[i]).await {
+                    Some(Value::Set(set)) => set,
+                    _ => unreachable!("the key was checked"),
+                };
+                if n == 0 {
+                    members = set.iter().collect();
+                } else {
+                    members.retain(|member| set.contains(member));
+                }
+            }
+        }
+        SetOp::Diff => {
+            for (i, key) in keys.iter().enumerate() {
+                let set = match string_db.get_value(key).await {
+                    Some(Value::Set(set)) => set,
+                    _ if i == 0 => break,
+                    _ => continue,
+                };
+                if i == 0 {
+                    members = set.iter().collect();
+                } else {
+                    members.retain(|member| !set.contains(member));
+                }
+                if members.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(members.into_iter().collect())
+}
+
+// https://redis.io/commands/sinter/
+// https://redis.io/commands/sunion/
+// https://redis.io/commands/sdiff/
+// https://redis.io/commands/sinterstore/
+// https://redis.io/commands/sunionstore/
+// https://redis.io/commands/sdiffstore/
+// SINTER key [key ...]
+// SINTERSTORE destination key [key ...], an empty result deletes destination
+// return: *<n>\r\n$<len>\r\n<member>\r\n...
+// return(STORE): :<the number of members of the result>\r\n
+pub struct SCombine {
+    pub keys: Vec<String>,
+    pub op: SetOp,
+    // the STORE variants store the result instead of replying it
+    pub destination: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SCombine {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            self.op.cmd_name(self.destination.is_some())
+        );
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let res = combine(string_db, &self.keys, self.op).await?;
+
+        let destination = match self.destination {
+            Some(destination) => destination,
+            None => return Ok(Frame::Array(res.iter().map(Frame::Bulk).collect())),
+        };
+        let len = res.len();
+        if res.is_empty() {
+            string_db.del(&destination).await;
+        } else {
+            string_db.insert_value(destination, Value::Set(res)).await;
+        }
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl SCombine {
+    // `store` distinguishes the STORE variants, which take the destination first
+    pub fn parse(bulks: Vec<Bytes>, op: SetOp, store: bool) -> Result<Self> {
+        if bulks.len() < 2 + store as usize {
+            let cmd_name = op.cmd_name(store).to_lowercase();
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+
+        let mut names = bulks
+            .into_iter()
+            .skip(1)
+            .map(bytes_to_string)
+            .collect::<Result<Vec<_>>>()?;
+        let destination = store.then(|| names.remove(0));
+        Ok(SCombine {
+            keys: names,
+            op,
+            destination,
+        })
+    }
+}
+
+// https://redis.io/commands/sintercard/
+// SINTERCARD numkeys key [key ...] [LIMIT limit], stops counting once the limit is reached
+// return: :<the number of members of the intersection>\r\n
+pub struct SInterCard {
+    pub keys: Vec<String>,
+    // 0 means no limit
+    pub limit: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SInterCard {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SINTERCARD'");
+        let mut inner = db.lock().await;
+        let len = combine(inner.string_db(), &self.keys, SetOp::Inter)
+            .await?
+            .len();
+        Ok(Frame::Integer(match self.limit {
+            0 => len,
+            limit => len.min(limit),
+        } as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SInterCard {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'sintercard' command")
+        }
+        let numkeys = match bytes_to_i64(bulks[1].clone()) {
+            Ok(numkeys) if numkeys > 0 => numkeys as usize,
+            _ => bail!("ERR numkeys should be greater than 0"),
+        };
+        if numkeys > bulks.len() - 2 {
+            bail!("ERR Number of keys can't be greater than number of args")
+        }
+
+        let keys = bulks[2..2 + numkeys]
+            .iter()
+            .map(|key| bytes_to_string(key.clone()))
+            .collect::<Result<_>>()?;
+        let limit = match &bulks[2 + numkeys..] {
+            [] => 0,
+            [arg, limit] if arg.eq_ignore_ascii_case(b"limit") => {
+                match bytes_to_i64(limit.clone()) {
+                    Ok(limit) if limit >= 0 => limit as usize,
+                    _ => bail!("ERR LIMIT can't be negative"),
+                }
+            }
+            _ => bail!("ERR syntax error"),
+        };
+        Ok(SInterCard { keys, limit })
+    }
+}
+
+// https://redis.io/commands/sscan/
+// SSCAN key cursor [MATCH pattern] [COUNT count]
+// return: *2\r\n$<len>\r\n<next cursor>\r\n*<n>\r\n$<len>\r\n<member>\r\n...
+pub struct SScan {
+    pub key: String,
+    pub cursor: u64,
+    pub options: ScanOptions,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for SScan {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'SSCAN'");
+        let mut inner = db.lock().await;
+        let (cursor, members) = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_set()?.scan(self.cursor, self.options.count),
+            None => (0, vec![]),
+        };
+
+        Ok(Frame::Array(vec![
+            Frame::Bulk(cursor.to_string().into()),
+            Frame::Array(
+                members
+                    .into_iter()
+                    .filter(|member| self.options.matches(member))
+                    .map(Frame::Bulk)
+                    .collect(),
+            ),
+        ]))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for SScan {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'sscan' command")
+        }
+        Ok(SScan {
+            key: bytes_to_string(bulks[1].clone())?,
+            cursor: parse_cursor(bulks[2].clone())?,
+            options: ScanOptions::parse(&bulks[3..], false, false)?,
+        })
+    }
+}
@@ -0,0 +1,1834 @@
+use super::{block_on, wrong_args, CmdExecutor};
+use crate::{
+    db::{
+        stream_or_create, BlockedOp, ConsumerGroup, Db, Stream, StreamId, StringDbManipulator, Trim,
+    },
+    frame::Frame,
+    util::{bytes_to_i64, bytes_to_string, to_unix_millis},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use bytes::Bytes;
+use std::{
+    ops::RangeInclusive,
+    time::{Duration, SystemTime},
+};
+use tokio::time::Instant;
+use tracing::debug;
+
+fn invalid_id() -> Error {
+    anyhow!("ERR Invalid stream ID specified as stream command argument")
+}
+
+// parse an ID like `<ms>-<seq>`, or `<ms>` alone whose sequence number is then `missing_seq`
+pub fn parse_id(bulk: &[u8], missing_seq: u64) -> Result<StreamId> {
+    let id = std::str::from_utf8(bulk).map_err(|_| invalid_id())?;
+    let (ms, seq) = match id.split_once('-') {
+        Some((ms, seq)) => (ms, seq.parse().map_err(|_| invalid_id())?),
+        None => (id, missing_seq),
+    };
+    Ok(StreamId::new(ms.parse().map_err(|_| invalid_id())?, seq))
+}
+
+// parse a bound of XRANGE like `-`, `+`, `<ms>`, `<ms>-<seq>` or `(<ms>-<seq>` for an exclusive
+// bound. A missing sequence number means the whole millisecond
+pub fn parse_range_bound(bulk: &[u8], start: bool) -> Result<StreamId> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    match bulk {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => {
+            let id = parse_id(id, missing_seq)?;
+            match start {
+                true => id
+                    .next()
+                    .ok_or_else(|| anyhow!("ERR invalid start ID for the interval")),
+                false => id
+                    .prev()
+                    .ok_or_else(|| anyhow!("ERR invalid end ID for the interval")),
+            }
+        }
+        _ => parse_id(bulk, missing_seq),
+    }
+}
+
+// an entry is replied as its ID followed by its fields and values
+pub fn entry_frame(id: StreamId, fields: Vec<Bytes>) -> Frame {
+    Frame::Array(vec![Frame::Bulk(id.to_string().into()), fields.into()])
+}
+
+// MAXLEN or MINID, with `~` for an approximate trimming, and LIMIT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrimOptions {
+    pub trim: Trim,
+    pub approx: bool,
+    pub limit: Option<usize>,
+}
+
+impl TrimOptions {
+    // Parse the trimming option at `bulks[*i]` and advance `*i` past it. Return false, without
+    // advancing, if there is no trimming option there.
+    fn parse_at(options: &mut Option<TrimOptions>, bulks: &[Bytes], i: &mut usize) -> Result<bool> {
+        let arg = bulks[*i].to_ascii_lowercase();
+        if arg == b"limit" {
+            let limit = bulks
+                .get(*i + 1)
+                .ok_or_else(|| anyhow!("ERR syntax error"))?;
+            let limit = match bytes_to_i64(limit.clone())? {
+                limit if limit >= 0 => limit as usize,
+                _ => bail!("ERR The LIMIT argument must be >= 0."),
+            };
+            // the strategy may come after LIMIT
+            match options {
+                Some(options) => options.limit = Some(limit),
+                None => {
+                    *options = Some(TrimOptions {
+                        trim: Trim::MaxLen(usize::MAX),
+                        approx: false,
+                        limit: Some(limit),
+                    })
+                }
+            }
+            *i += 2;
+            return Ok(true);
+        }
+        if arg != b"maxlen" && arg != b"minid" {
+            return Ok(false);
+        }
+
+        let mut j = *i + 1;
+        let approx = match bulks.get(j).map(|bulk| bulk.as_ref()) {
+            Some(b"~") => true,
+            Some(b"=") => false,
+            _ => {
+                j -= 1;
+                false
+            }
+        };
+        j += 1;
+        let threshold = bulks.get(j).ok_or_else(|| anyhow!("ERR syntax error"))?;
+        let trim = match arg.as_slice() {
+            b"maxlen" => match bytes_to_i64(threshold.clone())? {
+                maxlen if maxlen >= 0 => Trim::MaxLen(maxlen as usize),
+                _ => bail!("ERR The MAXLEN argument must be >= 0."),
+            },
+            _ => Trim::MinId(parse_id(threshold, 0)?),
+        };
+        let limit = options.and_then(|options| options.limit);
+        *options = Some(TrimOptions {
+            trim,
+            approx,
+            limit,
+        });
+        *i = j + 1;
+        Ok(true)
+    }
+
+    // check the parsed options, LIMIT only makes sense with `~`
+    fn check(options: Option<TrimOptions>) -> Result<Option<TrimOptions>> {
+        if let Some(options) = options {
+            if options.limit.is_some() && !options.approx {
+                bail!("ERR syntax error, LIMIT cannot be used without the special ~ option")
+            }
+        }
+        Ok(options)
+    }
+
+    fn apply(&self, stream: &mut Stream) -> usize {
+        stream.trim(self.trim, self.approx, self.limit)
+    }
+}
+
+// the ID argument of XADD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAddId {
+    // `*`
+    Auto,
+    // `<ms>-*`
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+// https://redis.io/commands/xadd/
+// XADD key [NOMKSTREAM] [<MAXLEN | MINID> [= | ~] threshold [LIMIT count]] <* | id> field value
+//   [field value ...]
+// return: $<len>\r\n<the ID of the entry>\r\n, or $-1\r\n with NOMKSTREAM if the key doesn't
+//   exist
+pub struct XAdd {
+    pub key: String,
+    pub id: XAddId,
+    pub fields: Vec<Bytes>,
+    // don't create the stream if it doesn't exist
+    pub no_mkstream: bool,
+    pub trim: Option<TrimOptions>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XAdd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XADD'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        // validate the ID before creating the stream
+        let id = match string_db.get_value(&self.key).await {
+            Some(value) => self.next_id(value.as_stream()?)?,
+            None if self.no_mkstream => return Ok(Frame::Null),
+            None => self.next_id(&Stream::new())?,
+        };
+
+        let stream = stream_or_create(string_db, &self.key).await?;
+        stream.add(id, self.fields);
+        if let Some(trim) = self.trim {
+            trim.apply(stream);
+        }
+        string_db.signal_modified(&self.key).await;
+        inner.serve_blocked(db.index, &self.key).await;
+        Ok(Frame::Bulk(id.to_string().into()))
+    }
+}
+
+impl XAdd {
+    // the ID of the entry to add to `stream`
+    fn next_id(&self, stream: &Stream) -> Result<StreamId> {
+        let now_ms = now_ms();
+        let too_small = || {
+            anyhow!(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+            )
+        };
+        match self.id {
+            XAddId::Explicit(StreamId::MIN) => {
+                bail!("ERR The ID specified in XADD must be greater than 0-0")
+            }
+            XAddId::Explicit(id) if id > stream.last_id() => Ok(id),
+            XAddId::Explicit(_) => Err(too_small()),
+            XAddId::AutoSeq(ms) => stream.next_id(now_ms, Some(ms)).ok_or_else(too_small),
+            XAddId::Auto => stream.next_id(now_ms, None).ok_or_else(|| {
+                anyhow!(
+                    "ERR The stream has exhausted the last possible ID, unable to add more items"
+                )
+            }),
+        }
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XAdd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 5 {
+            return Err(wrong_args("xadd"));
+        }
+        let mut no_mkstream = false;
+        let mut trim = None;
+        let mut i = 2;
+        while i < bulks.len() {
+            if bulks[i].eq_ignore_ascii_case(b"nomkstream") {
+                no_mkstream = true;
+                i += 1;
+            } else if !TrimOptions::parse_at(&mut trim, &bulks, &mut i)? {
+                break;
+            }
+        }
+        let trim = TrimOptions::check(trim)?;
+
+        let fields = bulks.get(i + 1..).unwrap_or_default();
+        if fields.is_empty() || !fields.len().is_multiple_of(2) {
+            return Err(wrong_args("xadd"));
+        }
+        let id = match bulks[i].as_ref() {
+            b"*" => XAddId::Auto,
+            [ms @ .., b'-', b'*'] => XAddId::AutoSeq(
+                std::str::from_utf8(ms)
+                    .ok()
+                    .and_then(|ms| ms.parse().ok())
+                    .ok_or_else(invalid_id)?,
+            ),
+            id => XAddId::Explicit(parse_id(id, 0)?),
+        };
+        Ok(XAdd {
+            key: bytes_to_string(bulks[1].clone())?,
+            id,
+            fields: fields.to_vec(),
+            no_mkstream,
+            trim,
+        })
+    }
+}
+
+// https://redis.io/commands/xtrim/
+// XTRIM key <MAXLEN | MINID> [= | ~] threshold [LIMIT count]
+// return: :<the number of entries removed>\r\n
+pub struct XTrim {
+    pub key: String,
+    pub trim: TrimOptions,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XTrim {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XTRIM'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let removed = match string_db.get_value_mut(&self.key).await {
+            Some(value) => self.trim.apply(value.as_stream_mut()?),
+            None => 0,
+        };
+        if removed > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XTrim {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 4 {
+            return Err(wrong_args("xtrim"));
+        }
+        let mut trim = None;
+        let mut i = 2;
+        while i < bulks.len() {
+            if !TrimOptions::parse_at(&mut trim, &bulks, &mut i)? {
+                bail!("ERR syntax error")
+            }
+        }
+        match TrimOptions::check(trim)? {
+            // LIMIT alone doesn't say how to trim
+            Some(trim) if trim.trim != Trim::MaxLen(usize::MAX) => Ok(XTrim {
+                key: bytes_to_string(bulks[1].clone())?,
+                trim,
+            }),
+            _ => bail!("ERR syntax error"),
+        }
+    }
+}
+
+// https://redis.io/commands/xdel/
+// XDEL key id [id ...], the stream is kept even once it's empty
+// return: :<the number of entries deleted>\r\n
+pub struct XDel {
+    pub key: String,
+    pub ids: Vec<StreamId>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XDel {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XDEL'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let stream = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_stream_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+        let deleted = self.ids.iter().filter(|id| stream.remove(**id)).count();
+        if deleted > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(deleted as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XDel {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            return Err(wrong_args("xdel"));
+        }
+        Ok(XDel {
+            key: bytes_to_string(bulks[1].clone())?,
+            ids: bulks[2..]
+                .iter()
+                .map(|id| parse_id(id, 0))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+// https://redis.io/commands/xlen/
+// return: :<the number of entries>\r\n, :0\r\n if the key doesn't exist
+pub struct XLen {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XLen {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XLEN'");
+        let mut inner = db.lock().await;
+        let len = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_stream()?.len(),
+            None => 0,
+        };
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XLen {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            return Err(wrong_args("xlen"));
+        }
+        Ok(XLen {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/xrange/
+// https://redis.io/commands/xrevrange/
+// XRANGE key start end [COUNT count]
+// XREVRANGE key end start [COUNT count]
+// return: *<n>\r\n*2\r\n$<len>\r\n<id>\r\n*<2m>\r\n$<len>\r\n<field>\r\n$<len>\r\n<value>\r\n...
+pub struct XRange {
+    pub key: String,
+    pub range: RangeInclusive<StreamId>,
+    pub count: Option<usize>,
+    // from the greatest ID, like XREVRANGE
+    pub rev: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XRange {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.rev { "XREVRANGE" } else { "XRANGE" }
+        );
+        let mut inner = db.lock().await;
+        let entries = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_stream()?.range(self.range, self.count, self.rev),
+            None => vec![],
+        };
+        Ok(Frame::Array(
+            entries
+                .into_iter()
+                .map(|(id, fields)| entry_frame(id, fields))
+                .collect(),
+        ))
+    }
+}
+
+impl XRange {
+    // `rev` distinguishes XREVRANGE, which takes the end first, from XRANGE
+    pub fn parse(bulks: Vec<Bytes>, rev: bool) -> Result<Self> {
+        let cmd_name = if rev { "xrevrange" } else { "xrange" };
+        let count = match bulks.len() {
+            4 => None,
+            6 if bulks[4].eq_ignore_ascii_case(b"count") => {
+                // a negative count is the same as 0
+                Some(bytes_to_i64(bulks[5].clone())?.max(0) as usize)
+            }
+            6 => bail!("ERR syntax error"),
+            _ => return Err(wrong_args(cmd_name)),
+        };
+        let (start, end) = match rev {
+            true => (&bulks[3], &bulks[2]),
+            false => (&bulks[2], &bulks[3]),
+        };
+        Ok(XRange {
+            key: bytes_to_string(bulks[1].clone())?,
+            range: parse_range_bound(start, true)?..=parse_range_bound(end, false)?,
+            count,
+            rev,
+        })
+    }
+}
+
+// the ID argument of XREAD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XReadId {
+    // `$`, the last ID of the stream when the command is called
+    Last,
+    // `+`, the last entry of the stream
+    LastEntry,
+    // read the entries after this ID
+    After(StreamId),
+}
+
+// the entries after `after` of each stream, for the streams that have some
+async fn read_streams(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    keys: &[String],
+    after: &[StreamId],
+    count: Option<usize>,
+) -> Result<Vec<Frame>> {
+    let mut res = vec![];
+    for (key, after) in keys.iter().zip(after.iter()) {
+        let stream = match string_db.get_value(key).await {
+            Some(value) => value.as_stream()?,
+            None => continue,
+        };
+        let entries = match after.next() {
+            Some(start) => stream.range(start..=StreamId::MAX, count, false),
+            None => continue,
+        };
+        if entries.is_empty() {
+            continue;
+        }
+        res.push(Frame::Array(vec![
+            Frame::Bulk(Bytes::from(key.clone())),
+            Frame::Array(
+                entries
+                    .into_iter()
+                    .map(|(id, fields)| entry_frame(id, fields))
+                    .collect(),
+            ),
+        ]));
+    }
+    Ok(res)
+}
+
+// https://redis.io/commands/xread/
+// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+// With BLOCK, wait for an entry to be added to one of the streams, 0 meaning forever. The client
+// is woken up by XADD and reads the streams again.
+// return: *<n>\r\n*2\r\n$<len>\r\n<key>\r\n*<m>\r\n<entry>..., or *-1\r\n if there is no entry
+pub struct XRead {
+    pub keys: Vec<String>,
+    pub ids: Vec<XReadId>,
+    // the maximum number of entries per stream
+    pub count: Option<usize>,
+    // None if the command doesn't block, Some(None) if it blocks forever
+    pub block: Option<Option<Duration>>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XRead {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XREAD'");
+        let deadline = self.block.flatten().map(|timeout| Instant::now() + timeout);
+        let mut inner = db.lock().await;
+
+        // `$` and `+` are resolved once, so that the entries added while blocked are read
+        let mut after = Vec::with_capacity(self.keys.len());
+        for (key, id) in self.keys.iter().zip(self.ids.iter()) {
+            let stream = match inner.string_db().get_value(key).await {
+                Some(value) => Some(value.as_stream()?),
+                None => None,
+            };
+            let last_id = stream.map_or(StreamId::MIN, |stream| stream.last_id());
+            after.push(match id {
+                XReadId::After(id) => *id,
+                XReadId::Last => last_id,
+                XReadId::LastEntry => match stream.and_then(|stream| stream.last_entry_id()) {
+                    Some(id) => id.prev().unwrap_or(StreamId::MIN),
+                    None => last_id,
+                },
+            });
+        }
+
+        loop {
+            let res = read_streams(inner.string_db(), &self.keys, &after, self.count).await?;
+            if !res.is_empty() {
+                return Ok(Frame::Array(res));
+            }
+            let timeout = match self.block {
+                Some(_) => {
+                    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+                }
+                None => return Ok(Frame::NullArray),
+            };
+
+            let op = BlockedOp::XRead {
+                after: self
+                    .keys
+                    .iter()
+                    .cloned()
+                    .zip(after.iter().copied())
+                    .collect(),
+            };
+            if block_on(db, inner, self.keys.clone(), op, timeout)
+                .await?
+                .is_none()
+            {
+                return Ok(Frame::NullArray);
+            }
+            inner = db.lock().await;
+        }
+    }
+
+    fn may_block(&self) -> bool {
+        self.block.is_some()
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XRead {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let options = ReadOptions::parse(bulks, false)?;
+        Ok(XRead {
+            keys: options.keys,
+            ids: options
+                .ids
+                .iter()
+                .map(|id| match id.as_ref() {
+                    b"$" => Ok(XReadId::Last),
+                    b"+" => Ok(XReadId::LastEntry),
+                    b">" => bail!("ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option."),
+                    id => Ok(XReadId::After(parse_id(id, 0)?)),
+                })
+                .collect::<Result<_>>()?,
+            count: options.count,
+            block: options.block,
+        })
+    }
+}
+
+// the arguments shared by XREAD and XREADGROUP
+struct ReadOptions {
+    // the group and the consumer of XREADGROUP
+    group: Option<(Bytes, Bytes)>,
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+    no_ack: bool,
+    keys: Vec<String>,
+    ids: Vec<Bytes>,
+}
+
+impl ReadOptions {
+    // `group` distinguishes XREADGROUP from XREAD
+    fn parse(bulks: Vec<Bytes>, group: bool) -> Result<Self> {
+        let cmd_name = if group { "xreadgroup" } else { "xread" };
+        let mut options = ReadOptions {
+            group: None,
+            count: None,
+            block: None,
+            no_ack: false,
+            keys: vec![],
+            ids: vec![],
+        };
+        let mut i = 1;
+        loop {
+            let arg = bulks
+                .get(i)
+                .ok_or_else(|| wrong_args(cmd_name))?
+                .to_ascii_lowercase();
+            match arg.as_slice() {
+                b"streams" => break,
+                b"noack" if group => {
+                    options.no_ack = true;
+                    i += 1;
+                    continue;
+                }
+                b"count" | b"block" | b"group" => {}
+                _ => bail!("ERR syntax error"),
+            }
+            let value = bulks
+                .get(i + 1)
+                .ok_or_else(|| anyhow!("ERR syntax error"))?
+                .clone();
+            match arg.as_slice() {
+                // 0 (or less) means no limit
+                b"count" => {
+                    options.count = Some(bytes_to_i64(value)?)
+                        .filter(|count| *count > 0)
+                        .map(|count| count as usize)
+                }
+                b"block" => {
+                    let timeout = bytes_to_i64(value)
+                        .map_err(|_| anyhow!("ERR timeout is not an integer or out of range"))?;
+                    if timeout < 0 {
+                        bail!("ERR timeout is negative")
+                    }
+                    options.block =
+                        Some((timeout > 0).then(|| Duration::from_millis(timeout as u64)));
+                }
+                _ if !group => bail!(
+                    "ERR The GROUP option is only supported by XREADGROUP. You called XREAD instead."
+                ),
+                _ => {
+                    let consumer = bulks
+                        .get(i + 2)
+                        .ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    options.group = Some((value, consumer.clone()));
+                    i += 1;
+                }
+            }
+            i += 2;
+        }
+        if group && options.group.is_none() {
+            bail!("ERR Missing GROUP option for XREADGROUP")
+        }
+
+        let streams = &bulks[i + 1..];
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            bail!("ERR Unbalanced '{cmd_name}' list of streams: for each stream key an ID or '$' must be specified.")
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        options.keys = keys
+            .iter()
+            .map(|key| bytes_to_string(key.clone()))
+            .collect::<Result<_>>()?;
+        options.ids = ids.to_vec();
+        Ok(options)
+    }
+}
+
+fn now_ms() -> u64 {
+    to_unix_millis(SystemTime::now()).max(0) as u64
+}
+
+// the error of the commands reading or claiming the entries of a missing group
+fn no_group(key: &str, group: &[u8]) -> Error {
+    anyhow!(
+        "NOGROUP No such key '{key}' or consumer group '{}'",
+        String::from_utf8_lossy(group)
+    )
+}
+
+// parse the ID of a group like `<ms>-<seq>`, or `$` for the last ID of the stream, which is None
+fn parse_group_id(bulk: &[u8]) -> Result<Option<StreamId>> {
+    match bulk {
+        b"$" => Ok(None),
+        id => Ok(Some(parse_id(id, 0)?)),
+    }
+}
+
+// XGROUP subcommands
+#[derive(Debug, Clone, PartialEq)]
+pub enum XGroupOp {
+    // the group starts after `id`, the last ID of the stream if None
+    Create {
+        id: Option<StreamId>,
+        // create the stream if it doesn't exist
+        mkstream: bool,
+        entries_read: Option<u64>,
+    },
+    SetId {
+        id: Option<StreamId>,
+        entries_read: Option<u64>,
+    },
+    Destroy,
+    CreateConsumer(Bytes),
+    DelConsumer(Bytes),
+}
+
+// https://redis.io/commands/xgroup-create/
+// https://redis.io/commands/xgroup-setid/
+// https://redis.io/commands/xgroup-destroy/
+// https://redis.io/commands/xgroup-createconsumer/
+// https://redis.io/commands/xgroup-delconsumer/
+// XGROUP CREATE key group <id | $> [MKSTREAM] [ENTRIESREAD entries-read]
+// XGROUP SETID key group <id | $> [ENTRIESREAD entries-read]
+// XGROUP DESTROY key group
+// XGROUP CREATECONSUMER key group consumer
+// XGROUP DELCONSUMER key group consumer
+// return(CREATE, SETID): +OK\r\n
+// return(DESTROY, CREATECONSUMER): :1\r\n, or :0\r\n if the group or the consumer didn't exist
+//   or already existed
+// return(DELCONSUMER): :<the number of entries that were pending for the consumer>\r\n
+pub struct XGroup {
+    pub key: String,
+    pub group: Bytes,
+    pub op: XGroupOp,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XGroup {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XGROUP'");
+        // like redis, the consumer groups changing doesn't count as a modification of the key for
+        // WATCH, whether it's done by XGROUP, XREADGROUP, XACK or XCLAIM
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let mkstream = matches!(self.op, XGroupOp::Create { mkstream: true, .. });
+        if !mkstream && !string_db.check_exist(&self.key).await {
+            bail!("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")
+        }
+        let stream = stream_or_create(string_db, &self.key).await?;
+        let last_id = stream.last_id();
+        if let XGroupOp::Create {
+            id, entries_read, ..
+        } = self.op
+        {
+            let group = ConsumerGroup::new(id.unwrap_or(last_id), entries_read);
+            if !stream.create_group(self.group, group) {
+                bail!("BUSYGROUP Consumer Group name already exists")
+            }
+            return Ok(Frame::Simple("OK".to_string()));
+        }
+        if self.op == XGroupOp::Destroy {
+            let destroyed = stream.destroy_group(&self.group);
+            if destroyed {
+                // the clients blocked on the group fail
+                inner.serve_blocked(db.index, &self.key).await;
+            }
+            return Ok(Frame::Integer(destroyed as i64));
+        }
+
+        let group = stream.group_mut(&self.group).ok_or_else(|| {
+            anyhow!(
+                "NOGROUP No such consumer group '{}' for key name '{}'",
+                String::from_utf8_lossy(&self.group),
+                self.key
+            )
+        })?;
+        match self.op {
+            XGroupOp::SetId { id, entries_read } => {
+                group.last_id = id.unwrap_or(last_id);
+                group.entries_read = entries_read;
+                // the group may have entries to read again
+                inner.serve_blocked(db.index, &self.key).await;
+                Ok(Frame::Simple("OK".to_string()))
+            }
+            XGroupOp::CreateConsumer(consumer) => {
+                let created = !group.consumers.contains_key(&consumer);
+                group.consumer(&consumer, now_ms());
+                Ok(Frame::Integer(created as i64))
+            }
+            XGroupOp::DelConsumer(consumer) => Ok(Frame::Integer(
+                group.remove_consumer(&consumer).unwrap_or(0) as i64,
+            )),
+            XGroupOp::Create { .. } | XGroupOp::Destroy => unreachable!(),
+        }
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XGroup {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("xgroup"));
+        }
+        let subcommand = bytes_to_string(bulks[1].clone())?.to_lowercase();
+        let wrong_args = || wrong_args(&format!("xgroup|{subcommand}"));
+        let len = bulks.len();
+        let op = match subcommand.as_str() {
+            "create" | "setid" if len >= 5 => {
+                let id = parse_group_id(&bulks[4])?;
+                let mut mkstream = false;
+                let mut entries_read = None;
+                let mut i = 5;
+                while i < len {
+                    let arg = bulks[i].to_ascii_lowercase();
+                    if arg == b"mkstream" && subcommand == "create" {
+                        mkstream = true;
+                        i += 1;
+                    } else if arg == b"entriesread" && i + 1 < len {
+                        entries_read = match bytes_to_i64(bulks[i + 1].clone())? {
+                            -1 => None,
+                            n if n >= 0 => Some(n as u64),
+                            _ => bail!("ERR value for ENTRIESREAD must be positive or -1"),
+                        };
+                        i += 2;
+                    } else {
+                        bail!("ERR syntax error")
+                    }
+                }
+                match subcommand.as_str() {
+                    "create" => XGroupOp::Create {
+                        id,
+                        mkstream,
+                        entries_read,
+                    },
+                    _ => XGroupOp::SetId { id, entries_read },
+                }
+            }
+            "destroy" if len == 4 => XGroupOp::Destroy,
+            "createconsumer" if len == 5 => XGroupOp::CreateConsumer(bulks[4].clone()),
+            "delconsumer" if len == 5 => XGroupOp::DelConsumer(bulks[4].clone()),
+            "create" | "setid" | "destroy" | "createconsumer" | "delconsumer" => {
+                return Err(wrong_args())
+            }
+            _ => bail!(
+                "ERR unknown subcommand '{}'. Try XGROUP HELP.",
+                String::from_utf8_lossy(&bulks[1])
+            ),
+        };
+        Ok(XGroup {
+            key: bytes_to_string(bulks[2].clone())?,
+            group: bulks[3].clone(),
+            op,
+        })
+    }
+}
+
+// https://redis.io/commands/xreadgroup/
+// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...]
+//   id [id ...]
+// `>` reads the entries never delivered to the group, which become pending for the consumer
+// unless NOACK is given, only these block. Another ID reads the pending entries of the consumer
+// after it again.
+// return: *<n>\r\n*2\r\n$<len>\r\n<key>\r\n*<m>\r\n<entry>..., or *-1\r\n if there is no entry
+pub struct XReadGroup {
+    pub group: Bytes,
+    pub consumer: Bytes,
+    pub keys: Vec<String>,
+    // None for `>`
+    pub ids: Vec<Option<StreamId>>,
+    pub count: Option<usize>,
+    // None if the command doesn't block, Some(None) if it blocks forever
+    pub block: Option<Option<Duration>>,
+    pub no_ack: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XReadGroup {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XREADGROUP'");
+        let deadline = self.block.flatten().map(|timeout| Instant::now() + timeout);
+        let mut inner = db.lock().await;
+        loop {
+            let string_db = inner.string_db();
+            for key in self.keys.iter() {
+                let exists = match string_db.get_value(key).await {
+                    Some(value) => value.as_stream()?.group(&self.group).is_some(),
+                    None => false,
+                };
+                if !exists {
+                    return Err(anyhow!(
+                        "{} in XREADGROUP with GROUP option",
+                        no_group(key, &self.group)
+                    ));
+                }
+            }
+
+            let now = now_ms();
+            let mut res = vec![];
+            for (key, id) in self.keys.iter().zip(self.ids.iter()) {
+                let stream = string_db
+                    .get_value_mut(key)
+                    .await
+                    .expect("the stream should exist")
+                    .as_stream_mut()?;
+                let entries: Vec<Frame> = match id {
+                    None => stream
+                        .read_group(&self.group, &self.consumer, self.count, self.no_ack, now)
+                        .into_iter()
+                        .map(|(id, fields)| entry_frame(id, fields))
+                        .collect(),
+                    Some(after) => stream
+                        .read_pending(&self.group, &self.consumer, *after, self.count, now)
+                        .into_iter()
+                        .map(|(id, fields)| match fields {
+                            Some(fields) => entry_frame(id, fields),
+                            // deleted since it was delivered
+                            None => Frame::Array(vec![
+                                Frame::Bulk(id.to_string().into()),
+                                Frame::NullArray,
+                            ]),
+                        })
+                        .collect(),
+                };
+                // the pending entries are replied even if there is none
+                if id.is_some() || !entries.is_empty() {
+                    res.push(Frame::Array(vec![
+                        Frame::Bulk(Bytes::from(key.clone())),
+                        Frame::Array(entries),
+                    ]));
+                }
+            }
+            if !res.is_empty() {
+                return Ok(Frame::Array(res));
+            }
+            let timeout = match self.block {
+                Some(_) => {
+                    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+                }
+                None => return Ok(Frame::NullArray),
+            };
+
+            let op = BlockedOp::XReadGroup {
+                group: self.group.clone(),
+            };
+            if block_on(db, inner, self.keys.clone(), op, timeout)
+                .await?
+                .is_none()
+            {
+                return Ok(Frame::NullArray);
+            }
+            inner = db.lock().await;
+        }
+    }
+
+    fn may_block(&self) -> bool {
+        self.block.is_some()
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XReadGroup {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let options = ReadOptions::parse(bulks, true)?;
+        let (group, consumer) = options.group.expect("the group should be parsed");
+        Ok(XReadGroup {
+            group,
+            consumer,
+            keys: options.keys,
+            ids: options
+                .ids
+                .iter()
+                .map(|id| match id.as_ref() {
+                    b">" => Ok(None),
+                    b"$" => bail!("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."),
+                    id => Ok(Some(parse_id(id, 0)?)),
+                })
+                .collect::<Result<_>>()?,
+            count: options.count,
+            block: options.block,
+            no_ack: options.no_ack,
+        })
+    }
+}
+
+// https://redis.io/commands/xack/
+// XACK key group id [id ...]
+// return: :<the number of entries acknowledged>\r\n
+pub struct XAck {
+    pub key: String,
+    pub group: Bytes,
+    pub ids: Vec<StreamId>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XAck {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XACK'");
+        let mut inner = db.lock().await;
+        let group = match inner.string_db().get_value_mut(&self.key).await {
+            Some(value) => value.as_stream_mut()?.group_mut(&self.group),
+            None => None,
+        };
+        let acked = match group {
+            Some(group) => self.ids.iter().filter(|id| group.ack(**id)).count(),
+            None => 0,
+        };
+        Ok(Frame::Integer(acked as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XAck {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 4 {
+            return Err(wrong_args("xack"));
+        }
+        Ok(XAck {
+            key: bytes_to_string(bulks[1].clone())?,
+            group: bulks[2].clone(),
+            ids: bulks[3..]
+                .iter()
+                .map(|id| parse_id(id, 0))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+// the extended form of XPENDING
+#[derive(Debug, Clone, PartialEq)]
+pub struct XPendingRange {
+    // in milliseconds
+    pub min_idle: u64,
+    pub range: RangeInclusive<StreamId>,
+    pub count: usize,
+    pub consumer: Option<Bytes>,
+}
+
+// https://redis.io/commands/xpending/
+// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+// return(summary): *4\r\n:<count>\r\n$<len>\r\n<smallest id>\r\n$<len>\r\n<greatest id>\r\n
+//   *<n>\r\n*2\r\n$<len>\r\n<consumer>\r\n$<len>\r\n<count>\r\n...
+// return(extended): *<n>\r\n*4\r\n$<len>\r\n<id>\r\n$<len>\r\n<consumer>\r\n
+//   :<milliseconds since the last delivery>\r\n:<delivery count>\r\n...
+pub struct XPending {
+    pub key: String,
+    pub group: Bytes,
+    pub range: Option<XPendingRange>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XPending {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XPENDING'");
+        let mut inner = db.lock().await;
+        let group = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_stream()?.group(&self.group),
+            None => None,
+        };
+        let group = group.ok_or_else(|| no_group(&self.key, &self.group))?;
+
+        let range = match self.range {
+            Some(range) => range,
+            None => {
+                let (first, last) = match (group.pending.keys().next(), group.pending.keys().last())
+                {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => {
+                        return Ok(Frame::Array(vec![
+                            Frame::Integer(0),
+                            Frame::Null,
+                            Frame::Null,
+                            Frame::NullArray,
+                        ]))
+                    }
+                };
+                let consumers = group
+                    .consumers
+                    .iter()
+                    .filter(|(_, consumer)| !consumer.pending.is_empty())
+                    .map(|(name, consumer)| {
+                        vec![name.clone(), consumer.pending.len().to_string().into()].into()
+                    })
+                    .collect();
+                return Ok(Frame::Array(vec![
+                    Frame::Integer(group.pending.len() as i64),
+                    Frame::Bulk(first.to_string().into()),
+                    Frame::Bulk(last.to_string().into()),
+                    Frame::Array(consumers),
+                ]));
+            }
+        };
+
+        if range.range.is_empty() {
+            return Ok(Frame::Array(vec![]));
+        }
+        let now = now_ms();
+        Ok(Frame::Array(
+            group
+                .pending
+                .range(range.range)
+                .filter(|(_, entry)| {
+                    range
+                        .consumer
+                        .as_ref()
+                        .is_none_or(|consumer| entry.consumer == consumer)
+                })
+                .map(|(id, entry)| (id, entry, now.saturating_sub(entry.delivery_time)))
+                .filter(|(_, _, idle)| *idle >= range.min_idle)
+                .take(range.count)
+                .map(|(id, entry, idle)| {
+                    Frame::Array(vec![
+                        Frame::Bulk(id.to_string().into()),
+                        Frame::Bulk(entry.consumer.clone()),
+                        Frame::Integer(idle as i64),
+                        Frame::Integer(entry.delivery_count as i64),
+                    ])
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XPending {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            return Err(wrong_args("xpending"));
+        }
+        let range = match bulks.len() {
+            3 => None,
+            len => {
+                let mut i = 3;
+                let mut min_idle = 0;
+                if bulks[i].eq_ignore_ascii_case(b"idle") && len >= 5 {
+                    min_idle = bytes_to_i64(bulks[i + 1].clone())?.max(0) as u64;
+                    i += 2;
+                }
+                if len - i != 3 && len - i != 4 {
+                    bail!("ERR syntax error")
+                }
+                Some(XPendingRange {
+                    min_idle,
+                    range: parse_range_bound(&bulks[i], true)?
+                        ..=parse_range_bound(&bulks[i + 1], false)?,
+                    // a negative count is the same as 0
+                    count: bytes_to_i64(bulks[i + 2].clone())?.max(0) as usize,
+                    consumer: bulks.get(i + 3).cloned(),
+                })
+            }
+        };
+        Ok(XPending {
+            key: bytes_to_string(bulks[1].clone())?,
+            group: bulks[2].clone(),
+            range,
+        })
+    }
+}
+
+// when XCLAIM sets the entries as delivered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClaimTime {
+    // IDLE, this many milliseconds ago
+    Idle(i64),
+    // TIME, at this unix time in milliseconds
+    At(i64),
+}
+
+// https://redis.io/commands/xclaim/
+// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+//   [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+// Give the pending entries idle for at least min-idle-time to the consumer. The entries deleted
+// from the stream are removed from the pending entries instead.
+// return: *<n>\r\n<entry>..., or *<n>\r\n$<len>\r\n<id>\r\n... with JUSTID
+pub struct XClaim {
+    pub key: String,
+    pub group: Bytes,
+    pub consumer: Bytes,
+    // in milliseconds
+    pub min_idle: u64,
+    pub ids: Vec<StreamId>,
+    pub time: Option<ClaimTime>,
+    pub retry_count: Option<u64>,
+    // claim the entries that aren't pending too
+    pub force: bool,
+    // reply the IDs only, and don't increment the delivery counts
+    pub just_id: bool,
+    pub last_id: Option<StreamId>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XClaim {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XCLAIM'");
+        let mut inner = db.lock().await;
+        let stream = match inner.string_db().get_value_mut(&self.key).await {
+            Some(value) => value.as_stream_mut()?,
+            None => return Err(no_group(&self.key, &self.group)),
+        };
+        let now = now_ms();
+        let group = stream
+            .group_mut(&self.group)
+            .ok_or_else(|| no_group(&self.key, &self.group))?;
+        if let Some(last_id) = self.last_id {
+            group.last_id = group.last_id.max(last_id);
+        }
+        group.consumer(&self.consumer, now);
+        let delivery_time = match self.time {
+            Some(ClaimTime::Idle(idle)) => now as i64 - idle,
+            Some(ClaimTime::At(time)) => time,
+            None => now as i64,
+        };
+        let delivery_time = match delivery_time {
+            time if time < 0 || time > now as i64 => now,
+            time => time as u64,
+        };
+
+        let mut res = vec![];
+        for id in self.ids {
+            let exists = stream.entry(id).is_some();
+            let group = stream
+                .group_mut(&self.group)
+                .expect("the group should exist");
+            if !exists {
+                group.ack(id);
+                continue;
+            }
+            match group.pending.get(&id) {
+                None if !self.force => continue,
+                Some(entry) if now.saturating_sub(entry.delivery_time) < self.min_idle => continue,
+                _ => {}
+            }
+            group.claim(
+                id,
+                &self.consumer,
+                delivery_time,
+                self.retry_count,
+                !self.just_id,
+            );
+            group.consumer(&self.consumer, now).active_time = Some(now);
+            res.push(match self.just_id {
+                true => Frame::Bulk(id.to_string().into()),
+                false => entry_frame(id, stream.entry(id).expect("the entry exists").clone()),
+            });
+        }
+        Ok(Frame::Array(res))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XClaim {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 6 {
+            return Err(wrong_args("xclaim"));
+        }
+        let min_idle = bytes_to_i64(bulks[4].clone())
+            .map_err(|_| anyhow!("ERR Invalid min-idle-time argument for XCLAIM"))?;
+        let mut claim = XClaim {
+            key: bytes_to_string(bulks[1].clone())?,
+            group: bulks[2].clone(),
+            consumer: bulks[3].clone(),
+            min_idle: min_idle.max(0) as u64,
+            ids: vec![],
+            time: None,
+            retry_count: None,
+            force: false,
+            just_id: false,
+            last_id: None,
+        };
+        // the IDs go on until the first option
+        let mut i = 5;
+        while let Some(id) = bulks.get(i).and_then(|id| parse_id(id, 0).ok()) {
+            claim.ids.push(id);
+            i += 1;
+        }
+
+        while i < bulks.len() {
+            let arg = bulks[i].to_ascii_lowercase();
+            match arg.as_slice() {
+                b"force" => claim.force = true,
+                b"justid" => claim.just_id = true,
+                b"idle" | b"time" | b"retrycount" | b"lastid" if i + 1 < bulks.len() => {
+                    i += 1;
+                    let value = bulks[i].clone();
+                    let invalid =
+                        |option: &str| anyhow!("ERR Invalid {option} option argument for XCLAIM");
+                    match arg.as_slice() {
+                        b"idle" => {
+                            claim.time = Some(ClaimTime::Idle(
+                                bytes_to_i64(value).map_err(|_| invalid("IDLE"))?,
+                            ))
+                        }
+                        b"time" => {
+                            claim.time = Some(ClaimTime::At(
+                                bytes_to_i64(value).map_err(|_| invalid("TIME"))?,
+                            ))
+                        }
+                        b"retrycount" => {
+                            claim.retry_count = Some(
+                                u64::try_from(bytes_to_i64(value)?)
+                                    .map_err(|_| invalid("RETRYCOUNT"))?,
+                            )
+                        }
+                        _ => claim.last_id = Some(parse_id(&value, 0)?),
+                    }
+                }
+                _ => bail!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(&bulks[i])
+                ),
+            }
+            i += 1;
+        }
+        Ok(claim)
+    }
+}
+
+// https://redis.io/commands/xautoclaim/
+// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+// Like XCLAIM on the pending entries from `start`, scanning at most 10 times `count` of them.
+// return: *3\r\n$<len>\r\n<the ID to continue from, 0-0 once done>\r\n*<n>\r\n<entry>...
+//   *<m>\r\n$<len>\r\n<the ID of an entry deleted from the stream>\r\n...
+pub struct XAutoClaim {
+    pub key: String,
+    pub group: Bytes,
+    pub consumer: Bytes,
+    // in milliseconds
+    pub min_idle: u64,
+    pub start: StreamId,
+    pub count: usize,
+    // reply the IDs only, and don't increment the delivery counts
+    pub just_id: bool,
+}
+
+// the number of pending entries XAUTOCLAIM scans for each one it may claim
+const XAUTOCLAIM_ATTEMPTS_FACTOR: usize = 10;
+
+#[async_trait::async_trait]
+impl CmdExecutor for XAutoClaim {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XAUTOCLAIM'");
+        let mut inner = db.lock().await;
+        let stream = match inner.string_db().get_value_mut(&self.key).await {
+            Some(value) => value.as_stream_mut()?,
+            None => return Err(no_group(&self.key, &self.group)),
+        };
+        let now = now_ms();
+        let group = stream
+            .group_mut(&self.group)
+            .ok_or_else(|| no_group(&self.key, &self.group))?;
+        group.consumer(&self.consumer, now);
+        let attempts = self.count * XAUTOCLAIM_ATTEMPTS_FACTOR;
+        // one more to know where the next call continues from
+        let ids: Vec<StreamId> = group
+            .pending
+            .range(self.start..)
+            .map(|(id, _)| *id)
+            .take(attempts + 1)
+            .collect();
+
+        let (mut claimed, mut deleted) = (vec![], vec![]);
+        let mut count = self.count;
+        let mut scanned = 0;
+        while scanned < attempts.min(ids.len()) && count > 0 {
+            let id = ids[scanned];
+            scanned += 1;
+            let exists = stream.entry(id).is_some();
+            let group = stream
+                .group_mut(&self.group)
+                .expect("the group should exist");
+            if !exists {
+                group.ack(id);
+                deleted.push(Frame::Bulk(id.to_string().into()));
+                count -= 1;
+                continue;
+            }
+            if now.saturating_sub(group.pending[&id].delivery_time) < self.min_idle {
+                continue;
+            }
+            group.claim(id, &self.consumer, now, None, !self.just_id);
+            group.consumer(&self.consumer, now).active_time = Some(now);
+            claimed.push(match self.just_id {
+                true => Frame::Bulk(id.to_string().into()),
+                false => entry_frame(id, stream.entry(id).expect("the entry exists").clone()),
+            });
+            count -= 1;
+        }
+
+        let cursor = ids.get(scanned).copied().unwrap_or(StreamId::MIN);
+        Ok(Frame::Array(vec![
+            Frame::Bulk(cursor.to_string().into()),
+            Frame::Array(claimed),
+            Frame::Array(deleted),
+        ]))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XAutoClaim {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 6 {
+            return Err(wrong_args("xautoclaim"));
+        }
+        let min_idle = bytes_to_i64(bulks[4].clone())
+            .map_err(|_| anyhow!("ERR Invalid min-idle-time argument for XAUTOCLAIM"))?;
+        let mut claim = XAutoClaim {
+            key: bytes_to_string(bulks[1].clone())?,
+            group: bulks[2].clone(),
+            consumer: bulks[3].clone(),
+            min_idle: min_idle.max(0) as u64,
+            start: parse_range_bound(&bulks[5], true)?,
+            count: 100,
+            just_id: false,
+        };
+        let mut i = 6;
+        while i < bulks.len() {
+            let arg = bulks[i].to_ascii_lowercase();
+            match arg.as_slice() {
+                b"justid" => claim.just_id = true,
+                b"count" if i + 1 < bulks.len() => {
+                    i += 1;
+                    claim.count = match bytes_to_i64(bulks[i].clone())? {
+                        count
+                            if count < 1
+                                || count as usize > usize::MAX / XAUTOCLAIM_ATTEMPTS_FACTOR =>
+                        {
+                            bail!("ERR COUNT must be > 0")
+                        }
+                        count => count as usize,
+                    }
+                }
+                _ => bail!("ERR syntax error"),
+            }
+            i += 1;
+        }
+        Ok(claim)
+    }
+}
+
+// XINFO subcommands
+#[derive(Debug, Clone, PartialEq)]
+pub enum XInfoOp {
+    // FULL lists the entries, groups and consumers, along with up to `count` entries and pending
+    // entries of each of them, 0 meaning all
+    Stream { full: bool, count: usize },
+    Groups,
+    Consumers(Bytes),
+}
+
+// https://redis.io/commands/xinfo-stream/
+// https://redis.io/commands/xinfo-groups/
+// https://redis.io/commands/xinfo-consumers/
+// XINFO STREAM key [FULL [COUNT count]]
+// XINFO GROUPS key
+// XINFO CONSUMERS key group
+// return: *<2n>\r\n$<len>\r\n<name>\r\n<value>..., or an array of them for GROUPS and CONSUMERS
+pub struct XInfo {
+    pub key: String,
+    pub op: XInfoOp,
+}
+
+fn info_frame(fields: Vec<(&'static str, Frame)>) -> Frame {
+    let mut frames = Vec::with_capacity(fields.len() * 2);
+    for (name, value) in fields {
+        frames.push(Frame::Bulk(Bytes::from_static(name.as_bytes())));
+        frames.push(value);
+    }
+    Frame::Array(frames)
+}
+
+fn id_frame(id: StreamId) -> Frame {
+    Frame::Bulk(id.to_string().into())
+}
+
+fn optional_frame(value: Option<u64>) -> Frame {
+    value.map_or(Frame::Null, |value| Frame::Integer(value as i64))
+}
+
+impl XInfo {
+    fn stream_frame(stream: &Stream, full: bool, count: usize) -> Frame {
+        let count = if count == 0 { usize::MAX } else { count };
+        let optional_entry = |entry: Option<(StreamId, &Vec<Bytes>)>| match entry {
+            Some((id, fields)) => entry_frame(id, fields.clone()),
+            None => Frame::Null,
+        };
+        let mut fields = vec![
+            ("length", Frame::Integer(stream.len() as i64)),
+            // redis stores the listpacks in a radix tree, whose root is a node too
+            ("radix-tree-keys", Frame::Integer(stream.nodes() as i64)),
+            (
+                "radix-tree-nodes",
+                Frame::Integer(stream.nodes() as i64 + 1),
+            ),
+            ("last-generated-id", id_frame(stream.last_id())),
+            ("max-deleted-entry-id", id_frame(stream.max_deleted_id())),
+            (
+                "entries-added",
+                Frame::Integer(stream.entries_added() as i64),
+            ),
+            (
+                "recorded-first-entry-id",
+                id_frame(stream.first_entry().map_or(StreamId::MIN, |(id, _)| id)),
+            ),
+        ];
+        if !full {
+            fields.push(("groups", Frame::Integer(stream.groups().count() as i64)));
+            fields.push(("first-entry", optional_entry(stream.first_entry())));
+            fields.push(("last-entry", optional_entry(stream.last_entry())));
+            return info_frame(fields);
+        }
+
+        let entries = stream.range(StreamId::MIN..=StreamId::MAX, Some(count), false);
+        fields.push((
+            "entries",
+            Frame::Array(
+                entries
+                    .into_iter()
+                    .map(|(id, fields)| entry_frame(id, fields))
+                    .collect(),
+            ),
+        ));
+        let groups = stream.groups().map(|(name, group)| {
+            let pending = group.pending.iter().take(count).map(|(id, entry)| {
+                Frame::Array(vec![
+                    id_frame(*id),
+                    Frame::Bulk(entry.consumer.clone()),
+                    Frame::Integer(entry.delivery_time as i64),
+                    Frame::Integer(entry.delivery_count as i64),
+                ])
+            });
+            let consumers = group.consumers.iter().map(|(name, consumer)| {
+                let pending = consumer.pending.iter().take(count).map(|id| {
+                    let entry = &group.pending[id];
+                    Frame::Array(vec![
+                        id_frame(*id),
+                        Frame::Integer(entry.delivery_time as i64),
+                        Frame::Integer(entry.delivery_count as i64),
+                    ])
+                });
+                info_frame(vec![
+                    ("name", Frame::Bulk(name.clone())),
+                    ("seen-time", Frame::Integer(consumer.seen_time as i64)),
+                    (
+                        "active-time",
+                        consumer
+                            .active_time
+                            .map_or(Frame::Integer(-1), |time| Frame::Integer(time as i64)),
+                    ),
+                    ("pel-count", Frame::Integer(consumer.pending.len() as i64)),
+                    ("pending", Frame::Array(pending.collect())),
+                ])
+            });
+            info_frame(vec![
+                ("name", Frame::Bulk(name.clone())),
+                ("last-delivered-id", id_frame(group.last_id)),
+                ("entries-read", optional_frame(group.entries_read)),
+                ("lag", optional_frame(stream.lag(group))),
+                ("pel-count", Frame::Integer(group.pending.len() as i64)),
+                ("pending", Frame::Array(pending.collect())),
+                ("consumers", Frame::Array(consumers.collect())),
+            ])
+        });
+        fields.push(("groups", Frame::Array(groups.collect())));
+        info_frame(fields)
+    }
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for XInfo {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'XINFO'");
+        let mut inner = db.lock().await;
+        let stream = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_stream()?,
+            None => bail!("ERR no such key"),
+        };
+        let now = now_ms();
+        match self.op {
+            XInfoOp::Stream { full, count } => Ok(XInfo::stream_frame(stream, full, count)),
+            XInfoOp::Groups => Ok(Frame::Array(
+                stream
+                    .groups()
+                    .map(|(name, group)| {
+                        info_frame(vec![
+                            ("name", Frame::Bulk(name.clone())),
+                            ("consumers", Frame::Integer(group.consumers.len() as i64)),
+                            ("pending", Frame::Integer(group.pending.len() as i64)),
+                            ("last-delivered-id", id_frame(group.last_id)),
+                            ("entries-read", optional_frame(group.entries_read)),
+                            ("lag", optional_frame(stream.lag(group))),
+                        ])
+                    })
+                    .collect(),
+            )),
+            XInfoOp::Consumers(group) => {
+                let consumers = &stream
+                    .group(&group)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "NOGROUP No such consumer group '{}' for key name '{}'",
+                            String::from_utf8_lossy(&group),
+                            self.key
+                        )
+                    })?
+                    .consumers;
+                Ok(Frame::Array(
+                    consumers
+                        .iter()
+                        .map(|(name, consumer)| {
+                            let inactive = consumer
+                                .active_time
+                                .map_or(-1, |time| now.saturating_sub(time) as i64);
+                            info_frame(vec![
+                                ("name", Frame::Bulk(name.clone())),
+                                ("pending", Frame::Integer(consumer.pending.len() as i64)),
+                                (
+                                    "idle",
+                                    Frame::Integer(now.saturating_sub(consumer.seen_time) as i64),
+                                ),
+                                ("inactive", Frame::Integer(inactive)),
+                            ])
+                        })
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for XInfo {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("xinfo"));
+        }
+        let subcommand = bytes_to_string(bulks[1].clone())?.to_lowercase();
+        let op = match (subcommand.as_str(), bulks.len()) {
+            ("stream", 3) => XInfoOp::Stream {
+                full: false,
+                count: 0,
+            },
+            ("stream", 4) if bulks[3].eq_ignore_ascii_case(b"full") => XInfoOp::Stream {
+                full: true,
+                count: 10,
+            },
+            ("stream", 6)
+                if bulks[3].eq_ignore_ascii_case(b"full")
+                    && bulks[4].eq_ignore_ascii_case(b"count") =>
+            {
+                XInfoOp::Stream {
+                    full: true,
+                    // a negative count is the same as 0
+                    count: bytes_to_i64(bulks[5].clone())?.max(0) as usize,
+                }
+            }
+            ("stream", len) if len > 3 => bail!("ERR syntax error"),
+            ("groups", 3) => XInfoOp::Groups,
+            ("consumers", 4) => XInfoOp::Consumers(bulks[3].clone()),
+            ("stream" | "groups" | "consumers", _) => {
+                return Err(wrong_args(&format!("xinfo|{subcommand}")))
+            }
+            _ => bail!(
+                "ERR unknown subcommand '{}'. Try XINFO HELP.",
+                String::from_utf8_lossy(&bulks[1])
+            ),
+        };
+        Ok(XInfo {
+            key: bytes_to_string(bulks[2].clone())?,
+            op,
+        })
+    }
+}
+
+#[cfg(test)]
+mod stream_test {
+    use super::*;
+    use crate::{cmd::run, db::StringDb};
+    use tokio::task::JoinHandle;
+
+    fn new_db() -> Db {
+        Db::new(vec![
+            Box::new(StringDb::new()) as Box<dyn StringDbManipulator>
+        ])
+    }
+
+    // the reply of XREAD for a single stream whose entries have a single field `f`
+    fn read_reply(key: &str, entries: &[(&str, &str)]) -> Frame {
+        Frame::Array(vec![Frame::Array(vec![
+            Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+            Frame::Array(
+                entries
+                    .iter()
+                    .map(|(id, value)| {
+                        entry_frame(
+                            parse_id(id.as_bytes(), 0).unwrap(),
+                            vec!["f".into(), Bytes::copy_from_slice(value.as_bytes())],
+                        )
+                    })
+                    .collect(),
+            ),
+        ])])
+    }
+
+    // run a blocking command in the background, once it's blocked
+    async fn spawn_blocked(db: &Db, args: &'static [&'static str]) -> JoinHandle<Result<Frame>> {
+        let mut blocked = db.clone();
+        let handle = tokio::spawn(async move { run(&mut blocked, args).await });
+        while db.lock().await.blocked.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        handle
+    }
+
+    async fn served(handle: JoinHandle<Result<Frame>>) -> Frame {
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("the client should be served")
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn xread_block_should_be_woken_by_xadd() {
+        let db = new_db();
+        run(&mut db.clone(), &["xadd", "s", "1-0", "f", "a"])
+            .await
+            .unwrap();
+        let handle = spawn_blocked(
+            &db,
+            &["xread", "BLOCK", "0", "STREAMS", "missing", "s", "0", "1-0"],
+        )
+        .await;
+
+        // an entry trimmed right away doesn't wake the client up
+        run(
+            &mut db.clone(),
+            &["xadd", "s", "MAXLEN", "0", "1-1", "f", "b"],
+        )
+        .await
+        .unwrap();
+        assert!(!handle.is_finished());
+        assert!(!db.lock().await.blocked.is_empty());
+
+        run(&mut db.clone(), &["xadd", "s", "2-0", "f", "c"])
+            .await
+            .unwrap();
+        assert_eq!(read_reply("s", &[("2-0", "c")]), served(handle).await);
+        assert!(db.lock().await.blocked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn xread_block_should_resolve_the_last_id_once() {
+        let db = new_db();
+        run(&mut db.clone(), &["xadd", "s", "1-0", "f", "a"])
+            .await
+            .unwrap();
+        let handle = spawn_blocked(&db, &["xread", "BLOCK", "0", "STREAMS", "s", "$"]).await;
+
+        // both entries are read although the last ID changed before the client is woken up
+        let mut transaction = db.clone();
+        transaction.hold().await;
+        for id in ["2-0", "3-0"] {
+            run(&mut transaction, &["xadd", "s", id, "f", id])
+                .await
+                .unwrap();
+        }
+        transaction.release().await;
+        assert_eq!(
+            read_reply("s", &[("2-0", "2-0"), ("3-0", "3-0")]),
+            served(handle).await
+        );
+
+        // `$` of a missing stream is 0-0
+        let handle = spawn_blocked(
+            &db,
+            &["xread", "COUNT", "1", "BLOCK", "0", "STREAMS", "new", "$"],
+        )
+        .await;
+        let mut transaction = db.clone();
+        transaction.hold().await;
+        for id in ["0-1", "0-2"] {
+            run(&mut transaction, &["xadd", "new", id, "f", "v"])
+                .await
+                .unwrap();
+        }
+        transaction.release().await;
+        assert_eq!(read_reply("new", &[("0-1", "v")]), served(handle).await);
+    }
+
+    #[tokio::test]
+    async fn xread_block_should_time_out() {
+        let db = new_db();
+        run(&mut db.clone(), &["xadd", "s", "1-0", "f", "a"])
+            .await
+            .unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            Frame::NullArray,
+            run(
+                &mut db.clone(),
+                &["xread", "BLOCK", "20", "STREAMS", "s", "$"]
+            )
+            .await
+            .unwrap()
+        );
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(db.lock().await.blocked.is_empty());
+
+        // without BLOCK, or when entries are available, the reply comes right away
+        assert_eq!(
+            Frame::NullArray,
+            run(&mut db.clone(), &["xread", "STREAMS", "s", "$"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            read_reply("s", &[("1-0", "a")]),
+            run(
+                &mut db.clone(),
+                &["xread", "BLOCK", "0", "STREAMS", "s", "0"]
+            )
+            .await
+            .unwrap()
+        );
+        assert!(run(
+            &mut db.clone(),
+            &["xread", "BLOCK", "-1", "STREAMS", "s", "$"]
+        )
+        .await
+        .is_err());
+    }
+}
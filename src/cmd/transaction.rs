@@ -0,0 +1,260 @@
+use super::{wrong_args, CmdExecutor};
+use crate::{db::Db, frame::Frame, util::bytes_to_string};
+use anyhow::{bail, Error, Result};
+use bytes::Bytes;
+use tracing::debug;
+
+// The commands a connection queued since MULTI.
+#[derive(Default)]
+pub struct Transaction {
+    commands: Vec<Queued>,
+    // a command couldn't be queued, EXEC discards the transaction
+    aborted: bool,
+}
+
+enum Queued {
+    Command(Box<dyn CmdExecutor>),
+    // a command changing the state of the connection, e.g. SUBSCRIBE or HELLO
+    Connection(Frame),
+}
+
+// The connection that runs a transaction, for the commands changing its state.
+#[async_trait::async_trait]
+pub trait Connection: Send {
+    async fn execute(&mut self, db: &mut Db, frame: Frame) -> Result<Frame>;
+}
+
+impl Transaction {
+    // Parse and queue a command, replying QUEUED. A command that fails to parse, e.g. an unknown
+    // command or a wrong number of arguments, aborts the transaction.
+    pub fn queue(&mut self, frame: Frame) -> Result<Frame> {
+        match frame.parse_cmd() {
+            Ok(cmd) => {
+                self.commands.push(Queued::Command(cmd));
+                Ok(Frame::Simple("QUEUED".to_string()))
+            }
+            Err(e) => {
+                self.abort();
+                Err(e)
+            }
+        }
+    }
+
+    // queue a command for the connection to run, see Connection
+    pub fn queue_connection(&mut self, frame: Frame) -> Frame {
+        self.commands.push(Queued::Connection(frame));
+        Frame::Simple("QUEUED".to_string())
+    }
+
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+// The keys a connection watches, along with their version when they were watched. They must be
+// cleared once the connection doesn't watch them anymore, for the databases to forget them.
+#[derive(Default)]
+pub struct Watched {
+    // (the index of the database, the key, its version)
+    keys: Vec<(usize, String, u64)>,
+}
+
+impl Watched {
+    pub async fn watch(&mut self, db: &Db, keys: Vec<String>) {
+        let mut inner = db.lock().await;
+        for key in keys {
+            if self
+                .keys
+                .iter()
+                .any(|(index, k, _)| *index == db.index && *k == key)
+            {
+                continue;
+            }
+            let version = inner.string_db().watch(&key).await;
+            self.keys.push((db.index, key, version));
+        }
+    }
+
+    pub async fn clear(&mut self, db: &Db) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let mut inner = db.lock().await;
+        for (index, key, _) in self.keys.drain(..) {
+            inner.string_dbs[index].unwatch(&key).await;
+        }
+    }
+
+    // whether a watched key was written, expired or deleted since it was watched
+    async fn touched(&self, db: &Db) -> bool {
+        let mut inner = db.lock().await;
+        for (index, key, version) in self.keys.iter() {
+            if inner.string_dbs[*index].version(key).await != Some(*version) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// https://redis.io/commands/watch/
+// https://redis.io/commands/unwatch/
+// WATCH key [key ...], EXEC fails if one of the keys is modified before it runs
+// UNWATCH, forget the watched keys. EXEC and DISCARD forget them as well
+// Both are handled by the connection, the command is only left to reply to an UNWATCH queued by
+// MULTI
+// return: +OK\r\n
+pub struct Watch {
+    pub keys: Vec<String>,
+}
+
+impl TryFrom<Vec<Bytes>> for Watch {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 2 {
+            return Err(wrong_args("watch"));
+        }
+        Ok(Watch {
+            keys: bulks[1..]
+                .iter()
+                .map(|bulk| bytes_to_string(bulk.clone()))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+pub struct Unwatch;
+
+#[async_trait::async_trait]
+impl CmdExecutor for Unwatch {
+    async fn execute(self: Box<Self>, _db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'UNWATCH'");
+        Ok(Frame::Simple("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for Unwatch {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 1 {
+            return Err(wrong_args("unwatch"));
+        }
+        Ok(Unwatch)
+    }
+}
+
+// https://redis.io/commands/multi/
+// https://redis.io/commands/exec/
+// https://redis.io/commands/discard/
+// MULTI, the following commands are queued until EXEC or DISCARD
+// EXEC, run the queued commands without any other client running in between. A command failing
+//   doesn't stop the others
+// return(MULTI, DISCARD): +OK\r\n
+// return(EXEC): *<n>\r\n<the reply of each command>..., -EXECABORT if a command couldn't be
+//   queued, or *-1\r\n if a watched key was modified
+pub struct Exec {
+    pub transaction: Transaction,
+    pub watched: Watched,
+}
+
+impl Exec {
+    pub async fn execute(self, db: &mut Db, connection: &mut dyn Connection) -> Result<Frame> {
+        debug!("executing command 'EXEC'");
+        let Exec {
+            transaction,
+            mut watched,
+        } = self;
+        if transaction.aborted {
+            watched.clear(db).await;
+            bail!("EXECABORT Transaction discarded because of previous errors.")
+        }
+
+        db.hold().await;
+        let touched = watched.touched(db).await;
+        watched.clear(db).await;
+        if touched {
+            db.release().await;
+            return Ok(Frame::NullArray);
+        }
+        let mut res = Vec::with_capacity(transaction.commands.len());
+        for cmd in transaction.commands {
+            let frame = match cmd {
+                Queued::Command(cmd) => cmd.execute(db).await,
+                Queued::Connection(frame) => connection.execute(db, frame).await,
+            };
+            res.push(frame.unwrap_or_else(|e| Frame::Error(e.to_string())));
+        }
+        db.release().await;
+        Ok(Frame::Array(res))
+    }
+}
+
+#[cfg(test)]
+mod transaction_test {
+    use super::*;
+    use crate::{
+        cmd::run,
+        db::{StringDb, StringDbManipulator},
+    };
+
+    struct NoConnection;
+
+    #[async_trait::async_trait]
+    impl Connection for NoConnection {
+        async fn execute(&mut self, _db: &mut Db, _frame: Frame) -> Result<Frame> {
+            unreachable!()
+        }
+    }
+
+    fn new_db() -> Db {
+        Db::new(vec![
+            Box::new(StringDb::new()) as Box<dyn StringDbManipulator>
+        ])
+    }
+
+    // watch `key`, let another client run `commands`, then EXEC a transaction
+    async fn exec_after(db: &mut Db, key: &str, commands: &[&[&str]]) -> Frame {
+        let mut watched = Watched::default();
+        watched.watch(db, vec![key.to_string()]).await;
+        for args in commands {
+            run(&mut db.clone(), args).await.unwrap();
+        }
+        let mut transaction = Transaction::default();
+        transaction
+            .queue(Frame::from(vec![Bytes::from("ping")]))
+            .unwrap();
+        Exec {
+            transaction,
+            watched,
+        }
+        .execute(db, &mut NoConnection)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn exec_should_fail_once_a_missing_key_is_set_then_deleted() {
+        let mut db = new_db();
+        let res = exec_after(&mut db, "x", &[&["set", "x", "1"], &["del", "x"]]).await;
+        assert_eq!(Frame::NullArray, res);
+        // the key isn't watched anymore
+        assert_eq!(None, db.lock().await.string_db().version("x").await);
+    }
+
+    #[tokio::test]
+    async fn exec_should_ignore_commands_modifying_nothing() {
+        let mut db = new_db();
+        run(&mut db, &["hset", "h", "a", "1"]).await.unwrap();
+        run(&mut db, &["sadd", "s", "a"]).await.unwrap();
+
+        let res = exec_after(&mut db, "h", &[&["hdel", "h", "b"]]).await;
+        assert_eq!(Frame::Array(vec![Frame::Simple("PONG".into())]), res);
+        let res = exec_after(&mut db, "s", &[&["srem", "s", "b"], &["sadd", "s", "a"]]).await;
+        assert_eq!(Frame::Array(vec![Frame::Simple("PONG".into())]), res);
+
+        let res = exec_after(&mut db, "s", &[&["srem", "s", "a"]]).await;
+        assert_eq!(Frame::NullArray, res);
+    }
+}
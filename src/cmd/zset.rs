@@ -0,0 +1,1585 @@
+use super::{
+    block_on, parse_count, parse_cursor, parse_timeout, wrong_args, CmdExecutor, ScanOptions,
+};
+use crate::{
+    db::{
+        wrong_type, zset_or_create, BlockedOp, Db, LexBound, ScoreBound, StringDbManipulator,
+        Value, ZRangeSpec, ZSet,
+    },
+    frame::Frame,
+    util::{bytes_to_f64, bytes_to_i64, bytes_to_string},
+};
+use anyhow::{anyhow, bail, Error, Result};
+use bytes::Bytes;
+use std::{collections::HashMap, ops::Range, time::Duration};
+use tracing::debug;
+
+// scores are replied as bulk strings
+pub fn score_frame(score: f64) -> Frame {
+    Frame::Bulk(score.to_string().into())
+}
+
+// flatten elements into member, score, member, score... or just the members
+pub fn elements_frame(elements: Vec<(Bytes, f64)>, with_scores: bool) -> Frame {
+    let mut res = Vec::with_capacity(elements.len() * (1 + with_scores as usize));
+    for (member, score) in elements {
+        res.push(Frame::Bulk(member));
+        if with_scores {
+            res.push(score_frame(score));
+        }
+    }
+    Frame::Array(res)
+}
+
+// parse a score bound like `1`, `(1`, `-inf` or `+inf`
+pub fn parse_score_bound(bulk: &Bytes) -> Result<ScoreBound> {
+    let (score, exclusive) = match bulk.strip_prefix(b"(") {
+        Some(score) => (score, true),
+        None => (bulk.as_ref(), false),
+    };
+    let score = bytes_to_f64(Bytes::copy_from_slice(score))
+        .map_err(|_| anyhow!("ERR min or max is not a float"))?;
+    Ok(ScoreBound { score, exclusive })
+}
+
+// parse a lexicographical bound like `[a`, `(a`, `-` or `+`
+pub fn parse_lex_bound(bulk: &Bytes) -> Result<LexBound> {
+    match bulk.first() {
+        Some(b'-') if bulk.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bulk.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(bulk.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(bulk.slice(1..))),
+        _ => bail!("ERR min or max not valid string range item"),
+    }
+}
+
+// https://redis.io/commands/zadd/
+// https://redis.io/commands/zincrby/
+// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+// ZINCRBY key increment member, the same as ZADD key INCR increment member
+// return: :<the number of members added, or added and updated with CH>\r\n
+// return(INCR): $<len>\r\n<the new score>\r\n, or $-1\r\n if the update was prevented
+pub struct ZAdd {
+    pub key: String,
+    // only update existing members
+    pub xx: bool,
+    // only add new members
+    pub nx: bool,
+    // only update existing members if the new score is greater, or less with `lt`
+    pub gt: bool,
+    pub lt: bool,
+    // count the updated members too
+    pub ch: bool,
+    // increment the score of the single member, like ZINCRBY
+    pub incr: bool,
+    pub elements: Vec<(f64, Bytes)>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZAdd {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZADD'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let zset = match string_db.get_value(&self.key).await {
+            Some(value) => Some(value.as_zset()?),
+            None => None,
+        };
+        // fail before creating the key, only an increment can produce NaN
+        if let (true, Some(zset), Some((increment, member))) =
+            (self.incr, zset, self.elements.first())
+        {
+            if zset
+                .score(member)
+                .is_some_and(|current| (current + increment).is_nan())
+            {
+                bail!("ERR resulting score is not a number (NaN)")
+            }
+        }
+        // don't create the key when nothing can be added
+        if zset.is_none() && self.xx {
+            return Ok(if self.incr {
+                Frame::Null
+            } else {
+                Frame::Integer(0)
+            });
+        }
+
+        let zset = zset_or_create(string_db, &self.key).await?;
+        let mut added = 0;
+        let mut updated = 0;
+        let mut incr_score = None;
+        for (score, member) in self.elements.iter().cloned() {
+            let current = zset.score(&member);
+            let score = match current {
+                Some(current) if self.incr => current + score,
+                _ => score,
+            };
+
+            match current {
+                None if self.xx => continue,
+                None => {
+                    zset.insert(member, score);
+                    added += 1;
+                }
+                Some(_) if self.nx => continue,
+                Some(current) if (self.gt && score <= current) || (self.lt && score >= current) => {
+                    continue
+                }
+                Some(current) => {
+                    if score != current {
+                        zset.insert(member, score);
+                        updated += 1;
+                    }
+                }
+            }
+            incr_score = Some(score);
+        }
+
+        if zset.is_empty() {
+            string_db.del(&self.key).await;
+        } else if added + updated > 0 {
+            string_db.signal_modified(&self.key).await;
+            if added > 0 {
+                inner.serve_blocked(db.index, &self.key).await;
+            }
+        }
+        Ok(match self.incr {
+            true => incr_score.map_or(Frame::Null, score_frame),
+            false if self.ch => Frame::Integer(added + updated),
+            false => Frame::Integer(added),
+        })
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZAdd {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 4 {
+            bail!("ERR wrong number of arguments for 'zadd' command")
+        }
+        let mut zadd = ZAdd {
+            key: bytes_to_string(bulks[1].clone())?,
+            xx: false,
+            nx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: false,
+            elements: vec![],
+        };
+
+        let mut i = 2;
+        while i < bulks.len() {
+            match bulks[i].to_ascii_lowercase().as_slice() {
+                b"xx" => zadd.xx = true,
+                b"nx" => zadd.nx = true,
+                b"gt" => zadd.gt = true,
+                b"lt" => zadd.lt = true,
+                b"ch" => zadd.ch = true,
+                b"incr" => zadd.incr = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let pairs = &bulks[i..];
+        if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+            bail!("ERR syntax error")
+        }
+        if zadd.nx && zadd.xx {
+            bail!("ERR XX and NX options at the same time are not compatible")
+        }
+        if (zadd.nx && (zadd.gt || zadd.lt)) || (zadd.gt && zadd.lt) {
+            bail!("ERR GT, LT, and/or NX options at the same time are not compatible")
+        }
+        if zadd.incr && pairs.len() > 2 {
+            bail!("ERR INCR option supports a single increment-element pair")
+        }
+        for pair in pairs.chunks(2) {
+            zadd.elements
+                .push((bytes_to_f64(pair[0].clone())?, pair[1].clone()));
+        }
+        Ok(zadd)
+    }
+}
+
+pub struct ZIncrBy(ZAdd);
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZIncrBy {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZINCRBY'");
+        Box::new(self.0).execute(db).await
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZIncrBy {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for 'zincrby' command")
+        }
+        Ok(ZIncrBy(ZAdd {
+            key: bytes_to_string(bulks[1].clone())?,
+            xx: false,
+            nx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: true,
+            elements: vec![(bytes_to_f64(bulks[2].clone())?, bulks[3].clone())],
+        }))
+    }
+}
+
+// https://redis.io/commands/zrem/
+// ZREM key member [member ...], the key is deleted along with its last member
+// return: :<the number of members that were removed>\r\n
+pub struct ZRem {
+    pub key: String,
+    pub members: Vec<Bytes>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZRem {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZREM'");
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let zset = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_zset_mut()?,
+            None => return Ok(Frame::Integer(0)),
+        };
+
+        let removed = self
+            .members
+            .iter()
+            .filter(|member| zset.remove(member))
+            .count();
+        if zset.is_empty() {
+            string_db.del(&self.key).await;
+        } else if removed > 0 {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZRem {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'zrem' command")
+        }
+        Ok(ZRem {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: bulks[2..].to_vec(),
+        })
+    }
+}
+
+// https://redis.io/commands/zscore/
+// https://redis.io/commands/zmscore/
+// ZMSCORE key member [member ...]
+// return: $<len>\r\n<score>\r\n, or $-1\r\n if the member doesn't exist
+// return(ZMSCORE): *<n>\r\n$<len>\r\n<score>\r\n...
+pub struct ZScore {
+    pub key: String,
+    pub members: Vec<Bytes>,
+    // ZMSCORE replies an array, even for a single member
+    pub multiple: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZScore {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.multiple { "ZMSCORE" } else { "ZSCORE" }
+        );
+        let mut inner = db.lock().await;
+        let zset = match inner.string_db().get_value(&self.key).await {
+            Some(value) => Some(value.as_zset()?),
+            None => None,
+        };
+
+        let mut res: Vec<Frame> = self
+            .members
+            .iter()
+            .map(|member| {
+                zset.and_then(|zset| zset.score(member))
+                    .map_or(Frame::Null, score_frame)
+            })
+            .collect();
+        Ok(match self.multiple {
+            true => Frame::Array(res),
+            false => res.pop().unwrap_or(Frame::Null),
+        })
+    }
+}
+
+impl ZScore {
+    // `multiple` distinguishes ZMSCORE from ZSCORE
+    pub fn parse(bulks: Vec<Bytes>, multiple: bool) -> Result<Self> {
+        if bulks.len() < 3 || (!multiple && bulks.len() != 3) {
+            let cmd_name = if multiple { "zmscore" } else { "zscore" };
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        Ok(ZScore {
+            key: bytes_to_string(bulks[1].clone())?,
+            members: bulks[2..].to_vec(),
+            multiple,
+        })
+    }
+}
+
+// https://redis.io/commands/zcard/
+// return: :<the number of members>\r\n, :0\r\n if the key doesn't exist
+pub struct ZCard {
+    pub key: String,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZCard {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZCARD'");
+        let mut inner = db.lock().await;
+        let len = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_zset()?.len(),
+            None => 0,
+        };
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZCard {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 2 {
+            bail!("ERR wrong number of arguments for 'zcard' command")
+        }
+        Ok(ZCard {
+            key: bytes_to_string(bulks[1].clone())?,
+        })
+    }
+}
+
+// https://redis.io/commands/zcount/
+// ZCOUNT key min max, the bounds being like `1`, `(1` or `-inf`
+// return: :<the number of members with a score between min and max>\r\n
+pub struct ZCount {
+    pub key: String,
+    pub range: ZRangeSpec,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZCount {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZCOUNT'");
+        let mut inner = db.lock().await;
+        let count = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_zset()?.ranks(&self.range).len(),
+            None => 0,
+        };
+        Ok(Frame::Integer(count as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZCount {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() != 4 {
+            bail!("ERR wrong number of arguments for 'zcount' command")
+        }
+        Ok(ZCount {
+            key: bytes_to_string(bulks[1].clone())?,
+            range: ZRangeSpec::Score(parse_score_bound(&bulks[2])?, parse_score_bound(&bulks[3])?),
+        })
+    }
+}
+
+// https://redis.io/commands/zrank/
+// https://redis.io/commands/zrevrank/
+// ZRANK key member [WITHSCORE]
+// return: :<the rank of the member>\r\n, or $-1\r\n if the member doesn't exist
+// return(WITHSCORE): *2\r\n:<rank>\r\n$<len>\r\n<score>\r\n, or *-1\r\n
+pub struct ZRank {
+    pub key: String,
+    pub member: Bytes,
+    pub with_score: bool,
+    // rank from the highest score, like ZREVRANK
+    pub rev: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZRank {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.rev { "ZREVRANK" } else { "ZRANK" }
+        );
+        let mut inner = db.lock().await;
+        let zset = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_zset()?,
+            None if self.with_score => return Ok(Frame::NullArray),
+            None => return Ok(Frame::Null),
+        };
+
+        let (rank, score) = match zset.rank(&self.member).zip(zset.score(&self.member)) {
+            Some(found) => found,
+            None if self.with_score => return Ok(Frame::NullArray),
+            None => return Ok(Frame::Null),
+        };
+        let rank = match self.rev {
+            true => zset.len() - 1 - rank,
+            false => rank,
+        };
+        Ok(match self.with_score {
+            true => Frame::Array(vec![Frame::Integer(rank as i64), score_frame(score)]),
+            false => Frame::Integer(rank as i64),
+        })
+    }
+}
+
+impl ZRank {
+    // `rev` distinguishes ZREVRANK from ZRANK
+    pub fn parse(bulks: Vec<Bytes>, rev: bool) -> Result<Self> {
+        let with_score = match bulks.len() {
+            3 => false,
+            4 if bulks[3].eq_ignore_ascii_case(b"withscore") => true,
+            4 => bail!("ERR syntax error"),
+            _ => {
+                let cmd_name = if rev { "zrevrank" } else { "zrank" };
+                bail!("ERR wrong number of arguments for '{cmd_name}' command")
+            }
+        };
+        Ok(ZRank {
+            key: bytes_to_string(bulks[1].clone())?,
+            member: bulks[2].clone(),
+            with_score,
+            rev,
+        })
+    }
+}
+
+// what a range selects, by rank or by a score or lexicographical range
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRangeBy {
+    Rank(i64, i64),
+    Spec(ZRangeSpec),
+}
+
+impl ZRangeBy {
+    // the ascending ranks selected in `zset`. The ranks given to REV count from the highest score,
+    // and a LIMIT applies to the selected elements in the order they are replied
+    pub fn ranks(&self, zset: &ZSet, rev: bool, limit: Option<(i64, i64)>) -> Range<usize> {
+        let len = zset.len() as i64;
+        let ranks = match self {
+            ZRangeBy::Rank(start, stop) => {
+                let start = if *start < 0 { start + len } else { *start }.max(0);
+                let stop = if *stop < 0 { stop + len } else { *stop }.min(len - 1);
+                if start > stop {
+                    return 0..0;
+                }
+                match rev {
+                    true => (len - 1 - stop) as usize..(len - start) as usize,
+                    false => start as usize..stop as usize + 1,
+                }
+            }
+            ZRangeBy::Spec(spec) => zset.ranks(spec),
+        };
+
+        let (offset, count) = match limit {
+            Some((offset, _)) if offset < 0 => return 0..0,
+            Some((offset, count)) if count < 0 => (offset as usize, ranks.len()),
+            Some((offset, count)) => (offset as usize, count as usize),
+            None => return ranks,
+        };
+        let len = ranks.len().saturating_sub(offset).min(count);
+        match rev {
+            true => {
+                let end = ranks.end.saturating_sub(offset).max(ranks.start);
+                end - len..end
+            }
+            false => {
+                let start = (ranks.start + offset).min(ranks.end);
+                start..start + len
+            }
+        }
+    }
+}
+
+// https://redis.io/commands/zrange/
+// https://redis.io/commands/zrangestore/
+// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+// ZRANGESTORE dst src min max [BYSCORE | BYLEX] [REV] [LIMIT offset count], an empty result
+//   deletes dst
+// With REV, start and stop are swapped for BYSCORE and BYLEX: the first bound is the maximum.
+// return: *<n>\r\n$<len>\r\n<member>\r\n...
+// return(WITHSCORES): *<2n>\r\n$<len>\r\n<member>\r\n$<len>\r\n<score>\r\n...
+// return(ZRANGESTORE): :<the number of members of the result>\r\n
+pub struct ZRange {
+    pub key: String,
+    pub by: ZRangeBy,
+    pub rev: bool,
+    // (offset, count), a negative count meaning all the remaining elements
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+    // ZRANGESTORE stores the result instead of replying it
+    pub destination: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZRange {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.destination.is_some() {
+                "ZRANGESTORE"
+            } else {
+                "ZRANGE"
+            }
+        );
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let elements = match string_db.get_value(&self.key).await {
+            Some(value) => {
+                let zset = value.as_zset()?;
+                zset.range(self.by.ranks(zset, self.rev, self.limit), self.rev)
+            }
+            None => vec![],
+        };
+
+        let destination = match self.destination {
+            Some(destination) => destination,
+            None => return Ok(elements_frame(elements, self.with_scores)),
+        };
+        let len = elements.len();
+        string_db.del(&destination).await;
+        if len > 0 {
+            let zset = zset_or_create(string_db, &destination).await?;
+            for (member, score) in elements {
+                zset.insert(member, score);
+            }
+            inner.serve_blocked(db.index, &destination).await;
+        }
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl ZRange {
+    // `store` distinguishes ZRANGESTORE, which takes the destination first
+    pub fn parse(bulks: Vec<Bytes>, store: bool) -> Result<Self> {
+        let mut bulks = bulks.into_iter();
+        let cmd_name = bytes_to_string(bulks.next().unwrap_or_default())?.to_lowercase();
+        let mut bulks: Vec<Bytes> = bulks.collect();
+        if bulks.len() < 3 + store as usize {
+            bail!("ERR wrong number of arguments for '{cmd_name}' command")
+        }
+        let destination = match store {
+            true => Some(bytes_to_string(bulks.remove(0))?),
+            false => None,
+        };
+
+        let (mut by_score, mut by_lex, mut rev, mut limit, mut with_scores) =
+            (false, false, false, None, false);
+        let mut i = 3;
+        while i < bulks.len() {
+            match bulks[i].to_ascii_lowercase().as_slice() {
+                b"byscore" => by_score = true,
+                b"bylex" => by_lex = true,
+                b"rev" => rev = true,
+                b"withscores" if !store => with_scores = true,
+                b"limit" if i + 2 < bulks.len() => {
+                    let offset = bytes_to_i64(bulks[i + 1].clone())?;
+                    let count = bytes_to_i64(bulks[i + 2].clone())?;
+                    limit = Some((offset, count));
+                    i += 2;
+                }
+                _ => bail!("ERR syntax error"),
+            }
+            i += 1;
+        }
+        if by_score && by_lex {
+            bail!("ERR syntax error")
+        }
+        if limit.is_some() && !by_score && !by_lex {
+            bail!("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")
+        }
+        if with_scores && by_lex {
+            bail!("ERR syntax error, WITHSCORES not supported in combination with BYLEX")
+        }
+
+        // with REV, the first bound is the maximum
+        let (min, max) = match rev {
+            true => (&bulks[2], &bulks[1]),
+            false => (&bulks[1], &bulks[2]),
+        };
+        let by = if by_score {
+            ZRangeBy::Spec(ZRangeSpec::Score(
+                parse_score_bound(min)?,
+                parse_score_bound(max)?,
+            ))
+        } else if by_lex {
+            ZRangeBy::Spec(ZRangeSpec::Lex(
+                parse_lex_bound(min)?,
+                parse_lex_bound(max)?,
+            ))
+        } else {
+            ZRangeBy::Rank(
+                bytes_to_i64(bulks[1].clone())?,
+                bytes_to_i64(bulks[2].clone())?,
+            )
+        };
+        Ok(ZRange {
+            key: bytes_to_string(bulks[0].clone())?,
+            by,
+            rev,
+            limit,
+            with_scores,
+            destination,
+        })
+    }
+}
+
+// https://redis.io/commands/zpopmin/
+// https://redis.io/commands/zpopmax/
+// ZPOPMIN key [count], the key is deleted along with its last member
+// return: *<2n>\r\n$<len>\r\n<member>\r\n$<len>\r\n<score>\r\n...
+pub struct ZPop {
+    pub key: String,
+    pub count: usize,
+    // pop the highest scores, like ZPOPMAX
+    pub max: bool,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZPop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.max { "ZPOPMAX" } else { "ZPOPMIN" }
+        );
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let zset = match string_db.get_value_mut(&self.key).await {
+            Some(value) => value.as_zset_mut()?,
+            None => return Ok(Frame::Array(vec![])),
+        };
+
+        let elements = zset.pop(self.count, self.max);
+        if zset.is_empty() {
+            string_db.del(&self.key).await;
+        } else if !elements.is_empty() {
+            string_db.signal_modified(&self.key).await;
+        }
+        Ok(elements_frame(elements, true))
+    }
+}
+
+impl ZPop {
+    // `max` distinguishes ZPOPMAX from ZPOPMIN
+    pub fn parse(bulks: Vec<Bytes>, max: bool) -> Result<Self> {
+        let count = match bulks.len() {
+            2 => 1,
+            3 => parse_count(bulks[2].clone())?,
+            _ => {
+                let cmd_name = if max { "zpopmax" } else { "zpopmin" };
+                bail!("ERR wrong number of arguments for '{cmd_name}' command")
+            }
+        };
+        Ok(ZPop {
+            key: bytes_to_string(bulks[1].clone())?,
+            count,
+            max,
+        })
+    }
+}
+
+// https://redis.io/commands/zscan/
+// ZSCAN key cursor [MATCH pattern] [COUNT count]
+// return: *2\r\n$<len>\r\n<next cursor>\r\n*<2n>\r\n$<len>\r\n<member>\r\n$<len>\r\n<score>\r\n...
+pub struct ZScan {
+    pub key: String,
+    pub cursor: u64,
+    pub options: ScanOptions,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZScan {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZSCAN'");
+        let mut inner = db.lock().await;
+        let (cursor, elements) = match inner.string_db().get_value(&self.key).await {
+            Some(value) => value.as_zset()?.scan(self.cursor, self.options.count),
+            None => (0, vec![]),
+        };
+
+        let elements = elements
+            .into_iter()
+            .filter(|(member, _)| self.options.matches(member))
+            .collect();
+        Ok(Frame::Array(vec![
+            Frame::Bulk(cursor.to_string().into()),
+            elements_frame(elements, true),
+        ]))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZScan {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        if bulks.len() < 3 {
+            bail!("ERR wrong number of arguments for 'zscan' command")
+        }
+        Ok(ZScan {
+            key: bytes_to_string(bulks[1].clone())?,
+            cursor: parse_cursor(bulks[2].clone())?,
+            options: ScanOptions::parse(&bulks[3..], false, false)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZSetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+impl ZSetOp {
+    fn cmd_name(self, store: bool) -> &'static str {
+        match (self, store) {
+            (ZSetOp::Union, false) => "ZUNION",
+            (ZSetOp::Inter, false) => "ZINTER",
+            (ZSetOp::Diff, false) => "ZDIFF",
+            (ZSetOp::Union, true) => "ZUNIONSTORE",
+            (ZSetOp::Inter, true) => "ZINTERSTORE",
+            (ZSetOp::Diff, true) => "ZDIFFSTORE",
+        }
+    }
+}
+
+// how the scores of a member found in several sets are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN, which redis turns into 0
+            Aggregate::Sum if (a + b).is_nan() => 0.0,
+            Aggregate::Sum => a + b,
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+// the elements of a sorted set, or of a set whose members all score 1, their scores multiplied by
+// `weight`
+fn weighted_elements(value: &Value, weight: f64) -> Result<HashMap<Bytes, f64>> {
+    // 0 * inf is NaN, which redis turns into 0
+    let weigh = |score: f64| match score * weight {
+        score if score.is_nan() => 0.0,
+        score => score,
+    };
+    match value {
+        Value::ZSet(zset) => Ok(zset
+            .range(0..zset.len(), false)
+            .into_iter()
+            .map(|(member, score)| (member, weigh(score)))
+            .collect()),
+        Value::Set(set) => Ok(set.iter().map(|member| (member, weigh(1.0))).collect()),
+        _ => Err(wrong_type()),
+    }
+}
+
+// Combine the sorted sets (or sets) stored at `keys`, a missing key counting as an empty set. The
+// difference keeps the scores of the first set.
+async fn zcombine(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    keys: &[String],
+    weights: Option<&[f64]>,
+    aggregate: Aggregate,
+    op: ZSetOp,
+) -> Result<ZSet> {
+    // check the types first, so that a key of another type fails even after a missing key
+    for key in keys.iter() {
+        if let Some(value) = string_db.get_value(key).await {
+            if !matches!(value, Value::ZSet(_) | Value::Set(_)) {
+                return Err(wrong_type());
+            }
+        }
+    }
+
+    let mut res: Option<HashMap<Bytes, f64>> = None;
+    for (i, key) in keys.iter().enumerate() {
+        let weight = weights.map_or(1.0, |weights| weights[i]);
+        let elements = match string_db.get_value(key).await {
+            Some(value) => weighted_elements(value, weight)?,
+            None => HashMap::new(),
+        };
+        let combined = match (res, op) {
+            (None, _) => elements,
+            (Some(mut acc), ZSetOp::Union) => {
+                for (member, score) in elements {
+                    acc.entry(member)
+                        .and_modify(|current| *current = aggregate.apply(*current, score))
+                        .or_insert(score);
+                }
+                acc
+            }
+            (Some(acc), ZSetOp::Inter) => acc
+                .into_iter()
+                .filter_map(|(member, current)| {
+                    let score = elements.get(&member)?;
+                    Some((member, aggregate.apply(current, *score)))
+                })
+                .collect(),
+            (Some(mut acc), ZSetOp::Diff) => {
+                acc.retain(|member, _| !elements.contains_key(member));
+                acc
+            }
+        };
+        // nothing can be added back to an empty intersection or difference
+        let done = op != ZSetOp::Union && combined.is_empty();
+        res = Some(combined);
+        if done {
+            break;
+        }
+    }
+
+    let mut zset = ZSet::new();
+    for (member, score) in res.unwrap_or_default() {
+        zset.insert(member, score);
+    }
+    Ok(zset)
+}
+
+// parse `numkeys key [key ...]` from the iterator
+fn parse_keys(bulks: &mut impl Iterator<Item = Bytes>, cmd_name: &str) -> Result<Vec<String>> {
+    let numkeys = bytes_to_i64(bulks.next().ok_or_else(|| wrong_args(cmd_name))?)?;
+    if numkeys <= 0 {
+        bail!("ERR numkeys should be greater than 0")
+    }
+    let keys = bulks
+        .take(numkeys as usize)
+        .map(bytes_to_string)
+        .collect::<Result<Vec<_>>>()?;
+    if keys.len() < numkeys as usize {
+        bail!("ERR Number of keys can't be greater than number of args")
+    }
+    Ok(keys)
+}
+
+// https://redis.io/commands/zunion/
+// https://redis.io/commands/zinter/
+// https://redis.io/commands/zdiff/
+// https://redis.io/commands/zunionstore/
+// https://redis.io/commands/zinterstore/
+// https://redis.io/commands/zdiffstore/
+// ZUNION numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE <SUM | MIN | MAX>]
+//   [WITHSCORES]
+// ZDIFF numkeys key [key ...] [WITHSCORES]
+// ZUNIONSTORE destination numkeys key [key ...] [WEIGHTS ...] [AGGREGATE ...], an empty result
+//   deletes destination
+// Sets are accepted as sorted sets whose members all score 1.
+// return: *<n>\r\n$<len>\r\n<member>\r\n...
+// return(STORE): :<the number of members of the result>\r\n
+pub struct ZCombine {
+    pub keys: Vec<String>,
+    pub op: ZSetOp,
+    // one weight per key, not supported by ZDIFF
+    pub weights: Option<Vec<f64>>,
+    pub aggregate: Aggregate,
+    pub with_scores: bool,
+    // the STORE variants store the result instead of replying it
+    pub destination: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZCombine {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            self.op.cmd_name(self.destination.is_some())
+        );
+        let mut inner = db.lock().await;
+        let string_db = inner.string_db();
+        let res = zcombine(
+            string_db,
+            &self.keys,
+            self.weights.as_deref(),
+            self.aggregate,
+            self.op,
+        )
+        .await?;
+
+        let destination = match self.destination {
+            Some(destination) => destination,
+            None => {
+                let elements = res.range(0..res.len(), false);
+                return Ok(elements_frame(elements, self.with_scores));
+            }
+        };
+        let len = res.len();
+        if res.is_empty() {
+            string_db.del(&destination).await;
+        } else {
+            string_db
+                .insert_value(destination.clone(), Value::ZSet(res))
+                .await;
+            inner.serve_blocked(db.index, &destination).await;
+        }
+        Ok(Frame::Integer(len as i64))
+    }
+}
+
+impl ZCombine {
+    // `store` distinguishes the STORE variants, which take the destination first
+    pub fn parse(bulks: Vec<Bytes>, op: ZSetOp, store: bool) -> Result<Self> {
+        let cmd_name = op.cmd_name(store).to_lowercase();
+        let mut iter = bulks.into_iter().skip(1);
+        let destination = match store {
+            true => Some(bytes_to_string(
+                iter.next().ok_or_else(|| wrong_args(&cmd_name))?,
+            )?),
+            false => None,
+        };
+        let keys = parse_keys(&mut iter, &cmd_name)?;
+
+        let mut zcombine = ZCombine {
+            keys,
+            op,
+            weights: None,
+            aggregate: Aggregate::Sum,
+            with_scores: false,
+            destination,
+        };
+        while let Some(arg) = iter.next() {
+            match arg.to_ascii_lowercase().as_slice() {
+                b"weights" if op != ZSetOp::Diff => {
+                    let weights = (&mut iter)
+                        .take(zcombine.keys.len())
+                        .map(|weight| {
+                            bytes_to_f64(weight)
+                                .map_err(|_| anyhow!("ERR weight value is not a float"))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    if weights.len() < zcombine.keys.len() {
+                        bail!("ERR syntax error")
+                    }
+                    zcombine.weights = Some(weights);
+                }
+                b"aggregate" if op != ZSetOp::Diff => {
+                    let aggregate = iter.next().ok_or_else(|| anyhow!("ERR syntax error"))?;
+                    zcombine.aggregate = match aggregate.to_ascii_lowercase().as_slice() {
+                        b"sum" => Aggregate::Sum,
+                        b"min" => Aggregate::Min,
+                        b"max" => Aggregate::Max,
+                        _ => bail!("ERR syntax error"),
+                    };
+                }
+                b"withscores" if !store => zcombine.with_scores = true,
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        Ok(zcombine)
+    }
+}
+
+// https://redis.io/commands/zintercard/
+// ZINTERCARD numkeys key [key ...] [LIMIT limit], stops counting once the limit is reached
+// return: :<the number of members of the intersection>\r\n
+pub struct ZInterCard {
+    pub keys: Vec<String>,
+    // 0 means no limit
+    pub limit: usize,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZInterCard {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!("executing command 'ZINTERCARD'");
+        let mut inner = db.lock().await;
+        let len = zcombine(
+            inner.string_db(),
+            &self.keys,
+            None,
+            Aggregate::Sum,
+            ZSetOp::Inter,
+        )
+        .await?
+        .len();
+        Ok(Frame::Integer(match self.limit {
+            0 => len,
+            limit => len.min(limit),
+        } as i64))
+    }
+}
+
+impl TryFrom<Vec<Bytes>> for ZInterCard {
+    type Error = Error;
+
+    fn try_from(bulks: Vec<Bytes>) -> Result<Self, Self::Error> {
+        let mut iter = bulks.into_iter().skip(1);
+        let keys = parse_keys(&mut iter, "zintercard")?;
+        let limit = match (iter.next(), iter.next(), iter.next()) {
+            (None, _, _) => 0,
+            (Some(arg), Some(limit), None) if arg.eq_ignore_ascii_case(b"limit") => {
+                match bytes_to_i64(limit) {
+                    Ok(limit) if limit >= 0 => limit as usize,
+                    _ => bail!("ERR LIMIT can't be negative"),
+                }
+            }
+            _ => bail!("ERR syntax error"),
+        };
+        Ok(ZInterCard { keys, limit })
+    }
+}
+
+// pop up to `count` elements of the first non-empty sorted set among `keys`, along with its key
+async fn pop_first(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    keys: &[String],
+    count: usize,
+    max: bool,
+) -> Result<Option<(String, Vec<(Bytes, f64)>)>> {
+    for key in keys.iter() {
+        let zset = match string_db.get_value_mut(key).await {
+            Some(value) => value.as_zset_mut()?,
+            None => continue,
+        };
+        let elements = zset.pop(count, max);
+        if zset.is_empty() {
+            string_db.del(key).await;
+        } else if !elements.is_empty() {
+            string_db.signal_modified(key).await;
+        }
+        return Ok(Some((key.clone(), elements)));
+    }
+    Ok(None)
+}
+
+// the elements served to a blocked client, members and scores being interleaved
+fn served_elements(elements: Vec<Bytes>) -> Vec<(Bytes, f64)> {
+    elements
+        .chunks(2)
+        .map(|pair| {
+            let score = std::str::from_utf8(&pair[1]).unwrap_or("").parse();
+            (pair[0].clone(), score.unwrap_or_default())
+        })
+        .collect()
+}
+
+// https://redis.io/commands/bzpopmin/
+// https://redis.io/commands/bzpopmax/
+// BZPOPMIN key [key ...] timeout
+// return: *3\r\n$<len>\r\n<key>\r\n$<len>\r\n<member>\r\n$<len>\r\n<score>\r\n, or *-1\r\n on
+//   timeout
+pub struct BZPop {
+    pub keys: Vec<String>,
+    // pop the highest score, like BZPOPMAX
+    pub max: bool,
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for BZPop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.max { "BZPOPMAX" } else { "BZPOPMIN" }
+        );
+        let mut inner = db.lock().await;
+        let popped = match pop_first(inner.string_db(), &self.keys, 1, self.max).await? {
+            Some(popped) => Some(popped),
+            None => {
+                let op = BlockedOp::ZPop {
+                    max: self.max,
+                    count: 1,
+                };
+                block_on(db, inner, self.keys, op, self.timeout)
+                    .await?
+                    .map(|(key, elements)| (key, served_elements(elements)))
+            }
+        };
+
+        Ok(match popped {
+            Some((key, mut elements)) => match elements.pop() {
+                Some((member, score)) => Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(key)),
+                    Frame::Bulk(member),
+                    score_frame(score),
+                ]),
+                None => Frame::NullArray,
+            },
+            None => Frame::NullArray,
+        })
+    }
+
+    fn may_block(&self) -> bool {
+        true
+    }
+}
+
+impl BZPop {
+    // `max` distinguishes BZPOPMAX from BZPOPMIN
+    pub fn parse(mut bulks: Vec<Bytes>, max: bool) -> Result<Self> {
+        if bulks.len() < 3 {
+            return Err(wrong_args(if max { "bzpopmax" } else { "bzpopmin" }));
+        }
+        let timeout = parse_timeout(bulks.pop().expect("the timeout should exist"))?;
+        Ok(BZPop {
+            keys: bulks
+                .into_iter()
+                .skip(1)
+                .map(bytes_to_string)
+                .collect::<Result<_>>()?,
+            max,
+            timeout,
+        })
+    }
+}
+
+// https://redis.io/commands/zmpop/
+// https://redis.io/commands/bzmpop/
+// ZMPOP numkeys key [key ...] <MIN | MAX> [COUNT count]
+// BZMPOP timeout numkeys key [key ...] <MIN | MAX> [COUNT count]
+// return: *2\r\n$<len>\r\n<key>\r\n*<n>\r\n*2\r\n$<len>\r\n<member>\r\n$<len>\r\n<score>\r\n...,
+//   or *-1\r\n if every sorted set is empty
+pub struct ZMPop {
+    pub keys: Vec<String>,
+    pub max: bool,
+    pub count: usize,
+    // BZMPOP blocks, None meaning forever
+    pub block: Option<Option<Duration>>,
+}
+
+#[async_trait::async_trait]
+impl CmdExecutor for ZMPop {
+    async fn execute(self: Box<Self>, db: &mut Db) -> Result<Frame> {
+        debug!(
+            "executing command '{}'",
+            if self.block.is_some() {
+                "BZMPOP"
+            } else {
+                "ZMPOP"
+            }
+        );
+        let mut inner = db.lock().await;
+        let popped = match pop_first(inner.string_db(), &self.keys, self.count, self.max).await? {
+            Some(popped) => Some(popped),
+            None => match self.block {
+                Some(timeout) => {
+                    let op = BlockedOp::ZPop {
+                        max: self.max,
+                        count: self.count,
+                    };
+                    block_on(db, inner, self.keys, op, timeout)
+                        .await?
+                        .map(|(key, elements)| (key, served_elements(elements)))
+                }
+                None => None,
+            },
+        };
+
+        Ok(match popped {
+            Some((key, elements)) => Frame::Array(vec![
+                Frame::Bulk(Bytes::from(key)),
+                Frame::Array(
+                    elements
+                        .into_iter()
+                        .map(|(member, score)| {
+                            Frame::Array(vec![Frame::Bulk(member), score_frame(score)])
+                        })
+                        .collect(),
+                ),
+            ]),
+            None => Frame::NullArray,
+        })
+    }
+
+    fn may_block(&self) -> bool {
+        self.block.is_some()
+    }
+}
+
+impl ZMPop {
+    // `block` distinguishes BZMPOP, which takes a timeout first, from ZMPOP
+    pub fn parse(bulks: Vec<Bytes>, block: bool) -> Result<Self> {
+        let cmd_name = if block { "bzmpop" } else { "zmpop" };
+        let mut iter = bulks.into_iter().skip(1);
+        let timeout = match block {
+            true => Some(parse_timeout(
+                iter.next().ok_or_else(|| wrong_args(cmd_name))?,
+            )?),
+            false => None,
+        };
+        let keys = parse_keys(&mut iter, cmd_name)?;
+        let max = match iter.next().ok_or_else(|| wrong_args(cmd_name))? {
+            arg if arg.eq_ignore_ascii_case(b"min") => false,
+            arg if arg.eq_ignore_ascii_case(b"max") => true,
+            _ => bail!("ERR syntax error"),
+        };
+
+        let mut count = 1;
+        while let Some(arg) = iter.next() {
+            match (arg.to_ascii_lowercase().as_slice(), iter.next()) {
+                (b"count", Some(value)) => {
+                    count = bytes_to_i64(value)
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| anyhow!("ERR count should be greater than 0"))?
+                        as usize
+                }
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        Ok(ZMPop {
+            keys,
+            max,
+            count,
+            block: timeout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod zset_test {
+    use super::*;
+    use crate::{cmd::run, db::StringDb};
+
+    fn new_db() -> Db {
+        Db::new(vec![
+            Box::new(StringDb::new()) as Box<dyn StringDbManipulator>
+        ])
+    }
+
+    fn bulks(values: &[&str]) -> Frame {
+        Frame::Array(
+            values
+                .iter()
+                .map(|value| Frame::Bulk(Bytes::copy_from_slice(value.as_bytes())))
+                .collect(),
+        )
+    }
+
+    // the reply of ZMPOP and BZMPOP
+    fn popped(key: &str, elements: &[(&str, &str)]) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+            Frame::Array(
+                elements
+                    .iter()
+                    .map(|(member, score)| bulks(&[member, score]))
+                    .collect(),
+            ),
+        ])
+    }
+
+    async fn new_db_with_zsets() -> Db {
+        let mut db = new_db();
+        run(&mut db, &["zadd", "a", "1", "x", "2", "y", "3", "z"])
+            .await
+            .unwrap();
+        run(&mut db, &["zadd", "b", "10", "y", "20", "z", "5", "w"])
+            .await
+            .unwrap();
+        run(&mut db, &["sadd", "s", "x"]).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn zunion_and_zinter_should_weigh_and_aggregate() {
+        let mut db = new_db_with_zsets().await;
+        let cases: [(&[&str], &[&str]); 6] = [
+            (
+                &["zunion", "2", "a", "b", "WEIGHTS", "2", "3", "WITHSCORES"],
+                &["x", "2", "w", "15", "y", "34", "z", "66"],
+            ),
+            (
+                &["zinter", "2", "a", "b", "aggregate", "sum", "withscores"],
+                &["y", "12", "z", "23"],
+            ),
+            (
+                &["zinter", "2", "a", "b", "AGGREGATE", "MIN", "WITHSCORES"],
+                &["y", "2", "z", "3"],
+            ),
+            (
+                &["zunion", "2", "a", "b", "AGGREGATE", "MAX", "WITHSCORES"],
+                &["x", "1", "w", "5", "y", "10", "z", "20"],
+            ),
+            (
+                &[
+                    "zinter",
+                    "2",
+                    "b",
+                    "a",
+                    "WEIGHTS",
+                    "1",
+                    "-1",
+                    "AGGREGATE",
+                    "MAX",
+                    "WITHSCORES",
+                ],
+                &["y", "10", "z", "20"],
+            ),
+            // a set scores 1
+            (
+                &["zunion", "2", "a", "s", "WEIGHTS", "1", "5", "WITHSCORES"],
+                &["y", "2", "z", "3", "x", "6"],
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(
+                bulks(expected),
+                run(&mut db, args).await.unwrap(),
+                "{args:?}"
+            );
+        }
+
+        assert_eq!(
+            Frame::Integer(3),
+            run(
+                &mut db,
+                &["zunionstore", "dst", "2", "a", "s", "AGGREGATE", "MIN"]
+            )
+            .await
+            .unwrap()
+        );
+        assert_eq!(
+            bulks(&["x", "1", "y", "2", "z", "3"]),
+            run(&mut db, &["zrange", "dst", "0", "-1", "WITHSCORES"])
+                .await
+                .unwrap()
+        );
+        // an empty result deletes the destination
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["zinterstore", "dst", "2", "a", "missing"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["exists", "dst"]).await.unwrap()
+        );
+
+        for args in [
+            &["zunion", "2", "a", "b", "WEIGHTS", "1"][..],
+            &["zunion", "2", "a", "b", "AGGREGATE", "AVG"],
+            &["zdiff", "2", "a", "b", "WEIGHTS", "1", "1"],
+            &["zunionstore", "dst", "1", "a", "WITHSCORES"],
+            &["zunion", "0", "a"],
+            &["zunion", "3", "a", "b"],
+        ] {
+            assert!(run(&mut db, args).await.is_err(), "{args:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn zsets_should_handle_inf_and_reject_nan() {
+        let mut db = new_db();
+        run(&mut db, &["zadd", "i", "inf", "m1", "-inf", "m2"])
+            .await
+            .unwrap();
+        run(&mut db, &["zadd", "j", "-inf", "m1", "+inf", "m2"])
+            .await
+            .unwrap();
+        assert_eq!(
+            Frame::Bulk("inf".into()),
+            run(&mut db, &["zscore", "i", "m1"]).await.unwrap()
+        );
+
+        // inf - inf and 0 * inf are NaN, which count as 0
+        assert_eq!(
+            bulks(&["m1", "0", "m2", "0"]),
+            run(&mut db, &["zunion", "2", "i", "j", "WITHSCORES"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            bulks(&["m1", "0", "m2", "0"]),
+            run(&mut db, &["zunion", "1", "i", "WEIGHTS", "0", "WITHSCORES"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            bulks(&["m1", "inf", "m2", "inf"]),
+            run(
+                &mut db,
+                &["zinter", "2", "i", "j", "AGGREGATE", "MAX", "WITHSCORES"]
+            )
+            .await
+            .unwrap()
+        );
+
+        assert_eq!(
+            "ERR value is not a valid float",
+            run(&mut db, &["zadd", "i", "nan", "m3"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "ERR weight value is not a float",
+            run(&mut db, &["zunion", "1", "i", "WEIGHTS", "nan"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "ERR resulting score is not a number (NaN)",
+            run(&mut db, &["zincrby", "i", "-inf", "m1"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            Frame::Bulk("inf".into()),
+            run(&mut db, &["zscore", "i", "m1"]).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn zintercard_should_stop_at_the_limit() {
+        let mut db = new_db_with_zsets().await;
+        for (limit, expected) in [(None, 2), (Some("0"), 2), (Some("1"), 1), (Some("5"), 2)] {
+            let mut args = vec!["zintercard", "2", "a", "b"];
+            if let Some(limit) = limit {
+                args.extend(["LIMIT", limit]);
+            }
+            assert_eq!(Frame::Integer(expected), run(&mut db, &args).await.unwrap());
+        }
+        assert_eq!(
+            Frame::Integer(1),
+            run(&mut db, &["zintercard", "2", "a", "s"]).await.unwrap()
+        );
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["zintercard", "2", "a", "missing"])
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            "ERR LIMIT can't be negative",
+            run(&mut db, &["zintercard", "1", "a", "LIMIT", "-1"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "ERR numkeys should be greater than 0",
+            run(&mut db, &["zintercard", "0", "a"])
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(run(&mut db, &["zintercard", "1", "a", "LIMIT"])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn zmpop_should_reply_the_key_and_the_elements() {
+        let mut db = new_db_with_zsets().await;
+        assert_eq!(
+            popped("a", &[("x", "1"), ("y", "2")]),
+            run(
+                &mut db,
+                &["zmpop", "2", "missing", "a", "MIN", "COUNT", "2"]
+            )
+            .await
+            .unwrap()
+        );
+        // a single element is still nested
+        assert_eq!(
+            popped("b", &[("z", "20")]),
+            run(&mut db, &["zmpop", "1", "b", "max"]).await.unwrap()
+        );
+        // the key is deleted along with its last member
+        assert_eq!(
+            popped("a", &[("z", "3")]),
+            run(&mut db, &["zmpop", "1", "a", "MAX", "COUNT", "10"])
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Frame::Integer(0),
+            run(&mut db, &["exists", "a"]).await.unwrap()
+        );
+        assert_eq!(
+            Frame::NullArray,
+            run(&mut db, &["zmpop", "1", "a", "MIN"]).await.unwrap()
+        );
+
+        for args in [
+            &["zmpop", "1", "b"][..],
+            &["zmpop", "1", "b", "MID"],
+            &["zmpop", "1", "b", "MIN", "COUNT", "0"],
+            &["zmpop", "1", "s", "MIN"],
+        ] {
+            assert!(run(&mut db, args).await.is_err(), "{args:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn bzmpop_should_reply_like_zmpop() {
+        let db = new_db();
+        assert_eq!(
+            Frame::NullArray,
+            run(&mut db.clone(), &["bzmpop", "0.01", "1", "q", "MIN"])
+                .await
+                .unwrap()
+        );
+
+        let mut blocked = db.clone();
+        let handle = tokio::spawn(async move {
+            run(
+                &mut blocked,
+                &["bzmpop", "0", "2", "p", "q", "MAX", "COUNT", "2"],
+            )
+            .await
+        });
+        while db.lock().await.blocked.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        run(
+            &mut db.clone(),
+            &["zadd", "q", "1", "a", "2", "b", "3", "c"],
+        )
+        .await
+        .unwrap();
+        let res = tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("the client should be served")
+            .unwrap()
+            .unwrap();
+        assert_eq!(popped("q", &[("c", "3"), ("b", "2")]), res);
+
+        // an element is available right away
+        assert_eq!(
+            popped("q", &[("a", "1")]),
+            run(
+                &mut db.clone(),
+                &["bzmpop", "0", "1", "q", "MIN", "COUNT", "5"]
+            )
+            .await
+            .unwrap()
+        );
+    }
+}
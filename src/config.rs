@@ -11,6 +11,10 @@ pub struct RedisConfig {
     pub replicaof: Option<String>,
     pub replid: String, // random 40 bytes
     pub repl_offset: u64,
+    pub hz: u32,
+    pub databases: usize,
+    pub dir: String,
+    pub dbfilename: String,
 }
 
 impl RedisConfig {
@@ -28,6 +32,10 @@ impl RedisConfig {
             replicaof: cli.replicaof.map(|addr| addr.to_string()),
             replid,
             repl_offset: 0,
+            hz: cli.hz,
+            databases: cli.databases as usize,
+            dir: cli.dir,
+            dbfilename: cli.dbfilename,
         }
     }
 
@@ -0,0 +1,305 @@
+// Bitmaps are plain strings, addressed bit by bit from the most significant bit of the first byte.
+use std::ops::RangeInclusive;
+
+// the number of bits set, counting a word at a time
+pub fn popcount(bytes: &[u8]) -> usize {
+    let mut chunks = bytes.chunks_exact(8);
+    let mut count: usize = chunks
+        .by_ref()
+        .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()).count_ones() as usize)
+        .sum();
+    for byte in chunks.remainder() {
+        count += byte.count_ones() as usize;
+    }
+    count
+}
+
+// the bits of a byte from the bit `offset` of the bitmap, the first one being bit 0
+fn bits_from(offset: usize) -> u8 {
+    0xff >> (offset % 8)
+}
+
+// the bits of a byte up to the bit `offset` of the bitmap included
+fn bits_until(offset: usize) -> u8 {
+    0xff << (7 - offset % 8)
+}
+
+// the number of bits set among the bits in `range`, which must be within the bitmap
+pub fn count_bits(bytes: &[u8], range: RangeInclusive<usize>) -> usize {
+    let (start, end) = (*range.start(), *range.end());
+    if start > end {
+        return 0;
+    }
+    let (first, last) = (start / 8, end / 8);
+    popcount(&bytes[first..=last])
+        - (bytes[first] & !bits_from(start)).count_ones() as usize
+        - (bytes[last] & !bits_until(end)).count_ones() as usize
+}
+
+// the position of the first bit equal to `bit` among the bits in `range`, which must be within
+// the bitmap
+pub fn bit_pos(bytes: &[u8], bit: bool, range: RangeInclusive<usize>) -> Option<usize> {
+    let (start, end) = (*range.start(), *range.end());
+    if start > end {
+        return None;
+    }
+    let (first, last) = (start / 8, end / 8);
+    // the bytes without any bit equal to `bit`
+    let skipped = if bit { 0 } else { 0xff };
+    let mut i = first;
+    while i <= last {
+        if i > first && i + 8 < last && bytes[i..i + 8] == [skipped; 8] {
+            i += 8;
+            continue;
+        }
+        // look for a 1
+        let mut byte = if bit { bytes[i] } else { !bytes[i] };
+        if i == first {
+            byte &= bits_from(start);
+        }
+        if i == last {
+            byte &= bits_until(end);
+        }
+        if byte != 0 {
+            return Some(i * 8 + byte.leading_zeros() as usize);
+        }
+        i += 1;
+    }
+    None
+}
+
+// the bit at `offset`, the bits past the end of the bitmap being 0
+pub fn get_bit(bytes: &[u8], offset: usize) -> bool {
+    match bytes.get(offset / 8) {
+        Some(byte) => byte & (0x80 >> (offset % 8)) != 0,
+        None => false,
+    }
+}
+
+// set the bit at `offset`, growing the bitmap with zeros as needed, and return its previous value
+pub fn set_bit(bytes: &mut Vec<u8>, offset: usize, bit: bool) -> bool {
+    if bytes.len() <= offset / 8 {
+        bytes.resize(offset / 8 + 1, 0);
+    }
+    let byte = &mut bytes[offset / 8];
+    let mask = 0x80 >> (offset % 8);
+    let previous = *byte & mask != 0;
+    if bit {
+        *byte |= mask;
+    } else {
+        *byte &= !mask;
+    }
+    previous
+}
+
+// the `bits` bits at `offset` as an unsigned integer, the bits past the end of the bitmap being 0
+fn get_bits(bytes: &[u8], offset: usize, bits: u32) -> u64 {
+    (0..bits as usize).fold(0, |value, i| {
+        (value << 1) | get_bit(bytes, offset + i) as u64
+    })
+}
+
+// set the `bits` bits at `offset` to the lowest bits of `value`, growing the bitmap as needed
+fn set_bits(bytes: &mut Vec<u8>, offset: usize, bits: u32, value: u64) {
+    for i in 0..bits as usize {
+        set_bit(bytes, offset + i, value >> (bits as usize - 1 - i) & 1 == 1);
+    }
+}
+
+// what BITFIELD does when SET or INCRBY overflows an integer
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    // wrap around, like the integers of most languages
+    #[default]
+    Wrap,
+    // saturate to the minimum or the maximum value
+    Sat,
+    // leave the integer as it is
+    Fail,
+}
+
+// an integer of BITFIELD, at most 64 bits if signed and 63 bits if unsigned so that it fits an i64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitField {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl BitField {
+    fn range(&self) -> (i128, i128) {
+        match self.signed {
+            true => (-(1 << (self.bits - 1)), (1 << (self.bits - 1)) - 1),
+            false => (0, (1 << self.bits) - 1),
+        }
+    }
+
+    pub fn get(&self, bytes: &[u8], offset: usize) -> i64 {
+        let value = get_bits(bytes, offset, self.bits);
+        match self.signed {
+            // extend the sign
+            true => ((value << (64 - self.bits)) as i64) >> (64 - self.bits),
+            false => value as i64,
+        }
+    }
+
+    pub fn set(&self, bytes: &mut Vec<u8>, offset: usize, value: i64) {
+        set_bits(bytes, offset, self.bits, value as u64);
+    }
+
+    // `value + incr` for this integer, None if it overflows with Overflow::Fail. The value of an
+    // unsigned integer is read as an u64, like redis does.
+    pub fn add(&self, value: i64, incr: i64, overflow: Overflow) -> Option<i64> {
+        let value = match self.signed {
+            true => value as i128,
+            false => value as u64 as i128,
+        };
+        let res = value + incr as i128;
+        let (min, max) = self.range();
+        if (min..=max).contains(&res) {
+            return Some(res as i64);
+        }
+        match overflow {
+            Overflow::Wrap => Some(((res - min).rem_euclid(1 << self.bits) + min) as i64),
+            Overflow::Sat => Some(res.clamp(min, max) as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+// the operations of BITOP
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+    // the bits of the first source set in none of the others
+    Diff,
+    // the bits of the first source set in at least one of the others
+    AndOr,
+    // the bits set in exactly one source
+    One,
+}
+
+impl BitOp {
+    // Combine the sources, the shorter ones being padded with zeros. The result is as long as the
+    // longest source.
+    pub fn apply(self, sources: &[&[u8]]) -> Vec<u8> {
+        let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+        let byte = |source: &[u8], i: usize| source.get(i).copied().unwrap_or(0);
+        let (first, others) = match sources.split_first() {
+            Some(split) => split,
+            None => return vec![],
+        };
+        (0..len)
+            .map(|i| {
+                let others = others.iter().map(|source| byte(source, i));
+                let first = byte(first, i);
+                match self {
+                    BitOp::And => others.fold(first, |res, byte| res & byte),
+                    BitOp::Or => others.fold(first, |res, byte| res | byte),
+                    BitOp::Xor => others.fold(first, |res, byte| res ^ byte),
+                    BitOp::Not => !first,
+                    BitOp::Diff => first & !others.fold(0, |res, byte| res | byte),
+                    BitOp::AndOr => first & others.fold(0, |res, byte| res | byte),
+                    // the bits set once so far, and the bits set more than once
+                    BitOp::One => {
+                        others
+                            .fold((first, 0), |(once, more), byte| {
+                                let more = more | (once & byte);
+                                ((once | byte) & !more, more)
+                            })
+                            .0
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod bitmap_test {
+    use super::*;
+
+    #[test]
+    fn count_bits_should_mask_the_range() {
+        let bytes = [0xff; 20];
+        assert_eq!(160, popcount(&bytes));
+        assert_eq!(1, count_bits(&bytes, 3..=3));
+        assert_eq!(150, count_bits(&bytes, 5..=154));
+        assert_eq!(4, count_bits(&[0b1010_1010, 0b0101_0101], 4..=11));
+    }
+
+    #[test]
+    fn bit_pos_should_skip_whole_words() {
+        let mut bytes = vec![0; 40];
+        assert_eq!(None, bit_pos(&bytes, true, 0..=319));
+        assert_eq!(Some(7), bit_pos(&bytes, false, 7..=319));
+        set_bit(&mut bytes, 300, true);
+        assert_eq!(Some(300), bit_pos(&bytes, true, 1..=319));
+        assert_eq!(None, bit_pos(&bytes, true, 1..=299));
+        assert_eq!(None, bit_pos(&bytes, true, 301..=319));
+
+        let bytes = vec![0xff; 40];
+        assert_eq!(None, bit_pos(&bytes, false, 0..=319));
+        assert_eq!(Some(12), bit_pos(&[0xff, 0xf7], false, 0..=15));
+    }
+
+    #[test]
+    fn set_bit_should_grow_the_bitmap() {
+        let mut bytes = vec![];
+        assert!(!set_bit(&mut bytes, 17, true));
+        assert_eq!(vec![0, 0, 0b0100_0000], bytes);
+        assert!(set_bit(&mut bytes, 17, false));
+        assert!(!get_bit(&bytes, 17));
+        assert!(!get_bit(&bytes, 1000));
+    }
+
+    #[test]
+    fn bit_fields_should_overflow() {
+        let (i8, u4) = (
+            BitField {
+                signed: true,
+                bits: 8,
+            },
+            BitField {
+                signed: false,
+                bits: 4,
+            },
+        );
+        assert_eq!(Some(-128), i8.add(127, 1, Overflow::Wrap));
+        assert_eq!(Some(127), i8.add(127, 1, Overflow::Sat));
+        assert_eq!(None, i8.add(-128, -1, Overflow::Fail));
+        assert_eq!(Some(1), u4.add(15, 2, Overflow::Wrap));
+        assert_eq!(Some(0), u4.add(3, -10, Overflow::Sat));
+        // a negative value is a huge unsigned one
+        assert_eq!(Some(15), u4.add(-1, 0, Overflow::Wrap));
+        assert_eq!(Some(15), u4.add(-1, 0, Overflow::Sat));
+
+        let i64 = BitField {
+            signed: true,
+            bits: 64,
+        };
+        assert_eq!(Some(i64::MIN), i64.add(i64::MAX, 1, Overflow::Wrap));
+
+        let mut bytes = vec![];
+        i8.set(&mut bytes, 4, -2);
+        assert_eq!(vec![0x0f, 0xe0], bytes);
+        assert_eq!(-2, i8.get(&bytes, 4));
+        assert_eq!(0xe, u4.get(&bytes, 8));
+        assert_eq!(0, u4.get(&bytes, 100));
+    }
+
+    #[test]
+    fn bit_ops_should_pad_with_zeros() {
+        let (a, b, c): (&[u8], &[u8], &[u8]) = (&[0b1100, 0xff], &[0b1010], &[0b0110]);
+        assert_eq!(vec![0b1000, 0], BitOp::And.apply(&[a, b]));
+        assert_eq!(vec![0b1110, 0xff], BitOp::Or.apply(&[a, b]));
+        assert_eq!(vec![0b0110, 0xff], BitOp::Xor.apply(&[a, b]));
+        assert_eq!(vec![!0b1100, 0], BitOp::Not.apply(&[a]));
+        assert_eq!(vec![0b0000, 0xff], BitOp::Diff.apply(&[a, b, c]));
+        assert_eq!(vec![0b1100, 0], BitOp::AndOr.apply(&[a, b, c]));
+        assert_eq!(vec![0b0000, 0xff], BitOp::One.apply(&[a, b, c]));
+        assert_eq!(vec![0b0110, 0xff], BitOp::One.apply(&[a, b]));
+    }
+}
@@ -0,0 +1,376 @@
+use super::{
+    list_or_create, pop, zset_or_create, DbInner, Direction, StreamId, StringDbManipulator, Value,
+};
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::oneshot;
+
+// what a blocked client does with the key it's woken up for
+#[derive(Debug, Clone)]
+pub enum BlockedOp {
+    // pop up to `count` elements
+    Pop {
+        direction: Direction,
+        count: usize,
+    },
+    // pop an element and push it to `destination`, in the same database
+    Move {
+        from: Direction,
+        destination: String,
+        to: Direction,
+    },
+    // pop up to `count` elements of a sorted set, the highest scores first if `max` is set
+    ZPop {
+        max: bool,
+        count: usize,
+    },
+    // read the entries of a stream after the given ID of each key, the client reads the stream
+    // again once woken up
+    XRead {
+        after: HashMap<String, StreamId>,
+    },
+    // read the entries a consumer group didn't read yet, the client reads the stream again once
+    // woken up, and fails if the group was destroyed meanwhile
+    XReadGroup {
+        group: Bytes,
+    },
+}
+
+impl BlockedOp {
+    // whether the operation can be applied to the value stored at `key`, a client blocked on a
+    // list isn't served by a sorted set and vice versa
+    fn is_ready(&self, key: &str, value: &Value) -> bool {
+        match (self, value) {
+            (BlockedOp::Pop { .. } | BlockedOp::Move { .. }, Value::List(list)) => !list.is_empty(),
+            (BlockedOp::ZPop { .. }, Value::ZSet(zset)) => !zset.is_empty(),
+            (BlockedOp::XRead { after }, Value::Stream(stream)) => {
+                stream.last_entry_id() > after.get(key).copied()
+            }
+            (BlockedOp::XReadGroup { group }, Value::Stream(stream)) => match stream.group(group) {
+                Some(group) => stream.last_entry_id() > Some(group.last_id),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+}
+
+// the key a blocked client was served from along with the popped elements (members and scores,
+// interleaved, for a sorted set), or the error to reply
+pub type Served = anyhow::Result<(String, Vec<Bytes>)>;
+
+#[derive(Debug)]
+pub struct BlockedClient {
+    // the database the client is blocked in
+    pub index: usize,
+    pub keys: Vec<String>,
+    pub op: BlockedOp,
+    pub sender: oneshot::Sender<Served>,
+}
+
+// The clients blocked on list, sorted set and stream keys. The clients blocked on a key are served
+// in the order they blocked, by the command that pushes to the key while it still holds the lock of
+// the databases.
+#[derive(Debug, Default)]
+pub struct BlockedClients {
+    next_id: u64,
+    clients: HashMap<u64, BlockedClient>,
+    // the ids of the clients blocked on a key of a database, oldest first
+    queues: HashMap<(usize, String), VecDeque<u64>>,
+    // the keys pushed to while a transaction runs, whose clients are served once it completes
+    deferred: Option<Vec<(usize, String)>>,
+}
+
+impl BlockedClients {
+    // block a client on `keys` and return its id, along with the receiver of the elements it will
+    // be served
+    pub fn block(
+        &mut self,
+        index: usize,
+        keys: Vec<String>,
+        op: BlockedOp,
+    ) -> (u64, oneshot::Receiver<Served>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in keys.iter() {
+            self.queues
+                .entry((index, key.clone()))
+                .or_default()
+                .push_back(id);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.clients.insert(
+            id,
+            BlockedClient {
+                index,
+                keys,
+                op,
+                sender,
+            },
+        );
+        (id, receiver)
+    }
+
+    // remove the client from the queues of all its keys, None if it isn't blocked anymore
+    pub fn unblock(&mut self, id: u64) -> Option<BlockedClient> {
+        let client = self.clients.remove(&id)?;
+        for key in client.keys.iter() {
+            let queue_key = (client.index, key.clone());
+            if let Some(queue) = self.queues.get_mut(&queue_key) {
+                queue.retain(|queued| *queued != id);
+                if queue.is_empty() {
+                    self.queues.remove(&queue_key);
+                }
+            }
+        }
+        Some(client)
+    }
+
+    // the clients blocked on the key, oldest first
+    pub fn queued(&self, index: usize, key: &str) -> Vec<u64> {
+        self.queues
+            .get(&(index, key.to_string()))
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // the keys of a database some clients are blocked on
+    pub fn keys(&self, index: usize) -> Vec<String> {
+        self.queues
+            .keys()
+            .filter(|(i, _)| *i == index)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    // serve the clients once `serve_deferred` is called, rather than by the pushing commands
+    pub fn defer(&mut self) {
+        self.deferred = Some(vec![]);
+    }
+}
+
+impl DbInner {
+    // Serve the clients blocked on `key` in the database `index`, oldest first, as long as the key
+    // holds a value they can be served from. The clients waiting for another type are skipped.
+    // Called by the commands that may add elements to a list, sorted set or stream, before they
+    // release the lock. A client served by BLMOVE pushes to its destination, whose blocked clients are
+    // served in turn.
+    pub async fn serve_blocked(&mut self, index: usize, key: &str) {
+        if self.blocked.is_empty() {
+            return;
+        }
+        if let Some(deferred) = self.blocked.deferred.as_mut() {
+            deferred.push((index, key.to_string()));
+            return;
+        }
+
+        let mut ready = VecDeque::from([key.to_string()]);
+        while let Some(key) = ready.pop_front() {
+            for id in self.blocked.queued(index, &key) {
+                let string_db = &mut self.string_dbs[index];
+                let value = match string_db.get_value(&key).await {
+                    Some(value) => value,
+                    None => break,
+                };
+                match self.blocked.clients.get(&id) {
+                    Some(client) if client.op.is_ready(&key, value) => {}
+                    _ => continue,
+                }
+
+                let client = self
+                    .blocked
+                    .unblock(id)
+                    .expect("the client should be blocked");
+                // the client went away while blocked
+                if client.sender.is_closed() {
+                    continue;
+                }
+                let served = serve(string_db, &key, &client.op).await;
+                if let (Ok(_), BlockedOp::Move { destination, .. }) = (&served, &client.op) {
+                    ready.push_back(destination.clone());
+                }
+
+                if let Err(Ok((_, elements))) = client.sender.send(served) {
+                    // the client went away since it was checked, put back what it was served
+                    put_back(string_db, &key, &client.op, elements).await;
+                }
+            }
+        }
+    }
+}
+
+impl DbInner {
+    // serve the clients blocked on the keys pushed to since `BlockedClients::defer`, in order
+    pub async fn serve_deferred(&mut self) {
+        for (index, key) in self.blocked.deferred.take().unwrap_or_default() {
+            self.serve_blocked(index, &key).await;
+        }
+    }
+}
+
+// undo the pop of a client that couldn't be served
+async fn put_back(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    key: &str,
+    op: &BlockedOp,
+    elements: Vec<Bytes>,
+) {
+    match op {
+        BlockedOp::Pop { direction, .. } => {
+            if let Ok(list) = list_or_create(string_db, key).await {
+                for element in elements.into_iter().rev() {
+                    list.push(*direction, element);
+                }
+            }
+            string_db.signal_modified(key).await;
+        }
+        BlockedOp::ZPop { .. } => {
+            if let Ok(zset) = zset_or_create(string_db, key).await {
+                for pair in elements.chunks(2) {
+                    if let Ok(score) = std::str::from_utf8(&pair[1]).unwrap_or("").parse() {
+                        zset.insert(pair[0].clone(), score);
+                    }
+                }
+            }
+            string_db.signal_modified(key).await;
+        }
+        // the element was pushed to the destination, which other clients may have seen already,
+        // and the readers of a stream weren't served anything
+        BlockedOp::Move { .. } | BlockedOp::XRead { .. } | BlockedOp::XReadGroup { .. } => {}
+    }
+}
+
+// apply the operation of a blocked client to the value stored at `key`, which is ready for it
+async fn serve(string_db: &mut Box<dyn StringDbManipulator>, key: &str, op: &BlockedOp) -> Served {
+    match op {
+        BlockedOp::Pop { direction, count } => {
+            let elements = pop(string_db, key, *count, *direction).await?;
+            Ok((key.to_string(), elements.unwrap_or_default()))
+        }
+        BlockedOp::Move {
+            from,
+            destination,
+            to,
+        } => {
+            if let Some(value) = string_db.get_value(destination).await {
+                value.as_list()?;
+            }
+            let elements = pop(string_db, key, 1, *from).await?.unwrap_or_default();
+            let list = list_or_create(string_db, destination).await?;
+            for element in elements.iter() {
+                list.push(*to, element.clone());
+            }
+            string_db.signal_modified(destination).await;
+            Ok((key.to_string(), elements))
+        }
+        BlockedOp::ZPop { max, count } => {
+            let zset = string_db
+                .get_value_mut(key)
+                .await
+                .expect("the sorted set should exist")
+                .as_zset_mut()?;
+            let popped = zset.pop(*count, *max);
+            let mut elements = Vec::with_capacity(popped.len() * 2);
+            for (member, score) in popped {
+                elements.push(member);
+                elements.push(score.to_string().into());
+            }
+            if zset.is_empty() {
+                string_db.del(key).await;
+            } else {
+                string_db.signal_modified(key).await;
+            }
+            Ok((key.to_string(), elements))
+        }
+        BlockedOp::XRead { .. } | BlockedOp::XReadGroup { .. } => Ok((key.to_string(), vec![])),
+    }
+}
+
+#[cfg(test)]
+mod blocking_test {
+    use super::*;
+    use crate::db::{stream_or_create, Db, StringDb};
+
+    fn pop_op() -> BlockedOp {
+        BlockedOp::Pop {
+            direction: Direction::Left,
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn clients_should_be_served_in_order() {
+        let mut blocked = BlockedClients::default();
+        let (a, _) = blocked.block(0, vec!["x".into(), "y".into()], pop_op());
+        let (b, _) = blocked.block(0, vec!["y".into()], pop_op());
+        let (c, _) = blocked.block(1, vec!["y".into()], pop_op());
+
+        assert_eq!(vec![a, b], blocked.queued(0, "y"));
+        assert_eq!(vec![c], blocked.queued(1, "y"));
+        assert!(blocked.unblock(a).is_some());
+        assert!(blocked.unblock(a).is_none());
+        assert!(blocked.queued(0, "x").is_empty());
+        assert_eq!(vec![b], blocked.queued(0, "y"));
+
+        blocked.unblock(b);
+        blocked.unblock(c);
+        assert!(blocked.is_empty());
+        assert!(blocked.queues.is_empty());
+    }
+
+    #[tokio::test]
+    async fn clients_should_be_served_after_a_transaction() {
+        let mut db = Db::new(vec![Box::new(StringDb::new())]);
+        let (_, mut receiver) = db.lock().await.blocked.block(0, vec!["x".into()], pop_op());
+        db.hold().await;
+        {
+            let mut inner = db.lock().await;
+            let list = list_or_create(inner.string_db(), "x").await.unwrap();
+            list.push(Direction::Right, "a".into());
+            inner.serve_blocked(0, "x").await;
+        }
+        assert!(receiver.try_recv().is_err());
+        // the other connections wait for the transaction
+        assert!(db.inner.try_lock().is_err());
+
+        db.release().await;
+        let (key, elements) = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(("x", vec![Bytes::from("a")]), (key.as_str(), elements));
+        assert!(db.inner.try_lock().is_ok());
+    }
+
+    #[tokio::test]
+    async fn xread_clients_should_wait_for_a_newer_entry() {
+        let db = Db::new(vec![Box::new(StringDb::new())]);
+        let mut inner = db.lock().await;
+        let after = HashMap::from([("s".to_string(), StreamId::new(5, 0))]);
+        let (_, mut receiver) =
+            inner
+                .blocked
+                .block(0, vec!["s".into()], BlockedOp::XRead { after });
+
+        let stream = stream_or_create(inner.string_db(), "s").await.unwrap();
+        stream.add(StreamId::new(5, 0), vec![]);
+        stream.add(StreamId::new(6, 0), vec![]);
+        // the entry added last was deleted
+        stream.remove(StreamId::new(6, 0));
+        inner.serve_blocked(0, "s").await;
+        assert!(receiver.try_recv().is_err());
+
+        let stream = stream_or_create(inner.string_db(), "s").await.unwrap();
+        stream.add(StreamId::new(7, 0), vec!["f".into(), "v".into()]);
+        inner.serve_blocked(0, "s").await;
+        // the client reads the stream itself
+        let (key, elements) = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(("s", vec![]), (key.as_str(), elements));
+        assert!(inner.blocked.is_empty());
+        // reading doesn't consume the entries
+        let stream = stream_or_create(inner.string_db(), "s").await.unwrap();
+        assert_eq!(2, stream.len());
+    }
+}
@@ -0,0 +1,473 @@
+use rand::Rng;
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+};
+
+// the smallest number of buckets of a non-empty table
+const MIN_SIZE: usize = 4;
+// shrink the table once fewer than 1/SHRINK_RATIO of its buckets would be used
+const SHRINK_RATIO: usize = 8;
+// the empty buckets a rehash step may skip before giving up until the next one
+const REHASH_EMPTY_VISITS: usize = 10;
+
+// A chained hash table whose number of buckets is always a power of two, like the dict of redis.
+// Unlike std's HashMap it exposes its buckets, which makes it possible to sample random entries.
+//
+// Like redis, the table is resized incrementally so that no single operation has to move all the
+// entries: a resize allocates a second table, then every operation modifying the dict moves a
+// bucket of the first table to it, until the first table is empty and the second one takes its
+// place. The entries may be in either table in the meantime, and new ones go to the second one.
+#[derive(Debug, Clone)]
+pub struct Dict<K, V> {
+    tables: [Vec<Vec<(K, V)>>; 2],
+    // the next bucket of the first table to move to the second one, None unless resizing
+    rehash_idx: Option<usize>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tables: [Vec::new(), Vec::new()],
+            rehash_idx: None,
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, bucket, pos) = self.find(key)?;
+        Some(&self.tables[table][bucket][pos].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (table, bucket, pos) = self.find(key)?;
+        Some(&mut self.tables[table][bucket][pos].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    // return the old value if the key was already present
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+
+        if self.rehash_idx.is_none() && self.len >= self.tables[0].len() {
+            self.resize((self.tables[0].len() * 2).max(MIN_SIZE));
+        }
+        // the second table while resizing
+        let table = self.rehash_idx.is_some() as usize;
+        let bucket = self.bucket_of(table, &key);
+        self.tables[table][bucket].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash_step();
+        let (table, bucket, pos) = self.find(key)?;
+        let entry = self.tables[table][bucket].swap_remove(pos);
+        self.len -= 1;
+        self.shrink_if_needed();
+        Some(entry)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tables
+            .iter()
+            .flatten()
+            .flat_map(|bucket| bucket.iter().map(|(k, v)| (k, v)))
+    }
+
+    // Visit the entries of the bucket at `cursor` and return the cursor of the next bucket, or 0
+    // once the whole table has been visited. Start with cursor 0.
+    //
+    // The cursor is incremented in reverse binary order (most significant bit of the mask first),
+    // as redis does. Since the number of buckets is a power of two, the entries of a bucket of a
+    // smaller table land in buckets whose index keeps the same low bits in a bigger table (and
+    // the other way around), so every entry present during the whole iteration is visited at
+    // least once even if the table grows or shrinks between two calls. Some entries may be
+    // visited more than once.
+    //
+    // While resizing, the bucket of the smaller table is visited along with all the buckets of
+    // the bigger table its entries may move to.
+    pub fn scan(&self, cursor: u64, mut f: impl FnMut(&K, &V)) -> u64 {
+        let mut visit = |bucket: &Vec<(K, V)>| {
+            for (k, v) in bucket.iter() {
+                f(k, v);
+            }
+        };
+        if self.rehash_idx.is_none() {
+            if self.tables[0].is_empty() {
+                return 0;
+            }
+            let mask = (self.tables[0].len() - 1) as u64;
+            visit(&self.tables[0][(cursor & mask) as usize]);
+            return next_cursor(cursor, mask);
+        }
+
+        let (small, big) = if self.tables[0].len() <= self.tables[1].len() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        let small_mask = (small.len() - 1) as u64;
+        let big_mask = (big.len() - 1) as u64;
+        visit(&small[(cursor & small_mask) as usize]);
+        let mut cursor = cursor;
+        loop {
+            visit(&big[(cursor & big_mask) as usize]);
+            cursor = next_cursor(cursor, big_mask);
+            // until the bits the bigger mask adds wrap around
+            if cursor & (small_mask ^ big_mask) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    // Pick a random entry: first a random non-empty bucket, then a random entry of its chain.
+    // Entries of long chains are slightly less likely to be picked, which is fine for sampling.
+    pub fn random_entry(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        // the buckets of the first table already moved are empty, they're skipped
+        let start = self.rehash_idx.unwrap_or(0);
+        let size = self.tables[0].len() + self.tables[1].len();
+        // the table is at least 1/SHRINK_RATIO full, so this takes a few tries on average
+        loop {
+            let index = rng.gen_range(start..size);
+            let bucket = match index.checked_sub(self.tables[0].len()) {
+                Some(index) => &self.tables[1][index],
+                None => &self.tables[0][index],
+            };
+            if !bucket.is_empty() {
+                let (k, v) = &bucket[rng.gen_range(0..bucket.len())];
+                return Some((k, v));
+            }
+        }
+    }
+
+    // the table, bucket and position in the bucket of the entry of `key`
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let tables = if self.rehash_idx.is_some() { 2 } else { 1 };
+        for table in 0..tables {
+            if self.tables[table].is_empty() {
+                continue;
+            }
+            let bucket = self.bucket_of(table, key);
+            let pos = self.tables[table][bucket]
+                .iter()
+                .position(|(k, _)| k.borrow() == key);
+            if let Some(pos) = pos {
+                return Some((table, bucket, pos));
+            }
+        }
+        None
+    }
+
+    fn bucket_of<Q>(&self, table: usize, key: &Q) -> usize
+    where
+        Q: Hash + ?Sized,
+    {
+        self.hasher.hash_one(key) as usize & (self.tables[table].len() - 1)
+    }
+
+    // start moving the entries to a table of `size` buckets
+    fn resize(&mut self, size: usize) {
+        if self.tables[0].is_empty() {
+            // nothing to move
+            self.tables[0] = (0..size).map(|_| Vec::new()).collect();
+            return;
+        }
+        self.tables[1] = (0..size).map(|_| Vec::new()).collect();
+        self.rehash_idx = Some(0);
+    }
+
+    fn shrink_if_needed(&mut self) {
+        let size = self.tables[0].len();
+        if self.rehash_idx.is_none() && size > MIN_SIZE && self.len * SHRINK_RATIO < size {
+            self.resize(self.len.next_power_of_two().max(MIN_SIZE));
+        }
+    }
+
+    // move a bucket to the second table when resizing, skipping up to REHASH_EMPTY_VISITS empty
+    // buckets
+    fn rehash_step(&mut self) {
+        let mut idx = match self.rehash_idx {
+            Some(idx) => idx,
+            None => return,
+        };
+        let mut empty_visits = REHASH_EMPTY_VISITS;
+        while idx < self.tables[0].len() && self.tables[0][idx].is_empty() {
+            idx += 1;
+            empty_visits -= 1;
+            if empty_visits == 0 {
+                self.rehash_idx = Some(idx);
+                return;
+            }
+        }
+        if idx < self.tables[0].len() {
+            for (k, v) in std::mem::take(&mut self.tables[0][idx]) {
+                let bucket = self.bucket_of(1, &k);
+                self.tables[1][bucket].push((k, v));
+            }
+            idx += 1;
+        }
+
+        if idx < self.tables[0].len() {
+            self.rehash_idx = Some(idx);
+        } else {
+            self.tables[0] = std::mem::take(&mut self.tables[1]);
+            self.rehash_idx = None;
+            // the entries removed in the meantime may call for a smaller table already
+            self.shrink_if_needed();
+        }
+    }
+}
+
+// the cursor following `cursor` for a table of `mask + 1` buckets
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    // set the unmasked bits so that incrementing the reversed cursor carries into the masked
+    // bits, then increment it
+    let cursor = cursor | !mask;
+    cursor.reverse_bits().wrapping_add(1).reverse_bits()
+}
+
+#[cfg(test)]
+mod dict_test {
+    use super::*;
+
+    #[test]
+    fn insert_get_remove_should_work() {
+        let mut dict = Dict::new();
+        assert_eq!(None, dict.get("foo"));
+
+        assert_eq!(None, dict.insert("foo".to_string(), 1));
+        assert_eq!(Some(1), dict.insert("foo".to_string(), 2));
+        assert_eq!(Some(&2), dict.get("foo"));
+        assert_eq!(1, dict.len());
+
+        assert_eq!(Some(2), dict.remove("foo"));
+        assert_eq!(None, dict.remove("foo"));
+        assert!(dict.is_empty());
+    }
+
+    // the number of buckets once the dict isn't resizing anymore
+    fn finish_resize<K: Hash + Eq, V>(dict: &mut Dict<K, V>) -> usize {
+        while dict.rehash_idx.is_some() {
+            dict.rehash_step();
+        }
+        dict.tables[0].len()
+    }
+
+    #[test]
+    fn resize_should_keep_entries() {
+        let mut dict = Dict::new();
+        for i in 0..1000 {
+            dict.insert(i, i * 2);
+        }
+        assert_eq!(1000, dict.len());
+        assert!(finish_resize(&mut dict) >= 1000);
+        for i in 0..1000 {
+            assert_eq!(Some(&(i * 2)), dict.get(&i));
+        }
+
+        // shrink
+        for i in 0..990 {
+            dict.remove(&i);
+        }
+        assert!(finish_resize(&mut dict) <= 10 * SHRINK_RATIO);
+        for i in 990..1000 {
+            assert_eq!(Some(&(i * 2)), dict.get(&i));
+        }
+        assert_eq!(10, dict.iter().count());
+    }
+
+    #[test]
+    fn resize_should_be_incremental() {
+        let mut dict = Dict::new();
+        for i in 0..64 {
+            dict.insert(i, ());
+        }
+        assert_eq!(None, dict.rehash_idx);
+        // grow: the entries are moved a bucket at a time
+        dict.insert(64, ());
+        assert_eq!(Some(0), dict.rehash_idx);
+        assert_eq!(128, dict.tables[1].len());
+        assert!(dict.tables[1].iter().all(|bucket| bucket.len() <= 1));
+        for i in 65..70 {
+            dict.insert(i, ());
+        }
+        assert!(dict.rehash_idx.is_some());
+        assert!(!dict.tables[0].is_empty());
+        // every entry is found in either table in the meantime
+        assert_eq!(70, dict.iter().count());
+        assert!((0..70).all(|i| dict.contains_key(&i)));
+        for _ in 0..100 {
+            assert!(*dict.random_entry().unwrap().0 < 70);
+        }
+        assert_eq!(Some(()), dict.remove(&0));
+        assert_eq!(None, dict.remove(&0));
+
+        // as many steps as buckets complete it at most
+        for _ in 0..64 {
+            dict.get_mut(&1);
+        }
+        assert_eq!(None, dict.rehash_idx);
+        assert_eq!(128, dict.tables[0].len());
+        assert!(dict.tables[1].is_empty());
+        assert!((1..70).all(|i| dict.contains_key(&i)));
+
+        // shrink
+        for i in 1..70 {
+            dict.remove(&i);
+        }
+        assert!(dict.is_empty());
+        assert!(finish_resize(&mut dict) <= MIN_SIZE * SHRINK_RATIO);
+    }
+
+    #[test]
+    fn random_entry_should_work() {
+        let mut dict = Dict::new();
+        assert_eq!(None, dict.random_entry());
+
+        for i in 0..10 {
+            dict.insert(i, ());
+        }
+        let mut seen = [false; 10];
+        for _ in 0..1000 {
+            let (k, _) = dict.random_entry().unwrap();
+            seen[*k] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn scan_should_visit_every_entry_while_resizing() {
+        let mut dict = Dict::new();
+        for i in 0..100 {
+            dict.insert(i, ());
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            cursor = dict.scan(cursor, |k, _| {
+                seen.insert(*k);
+            });
+            calls += 1;
+            // grow the table in the middle of the iteration, then shrink it
+            if calls == 10 {
+                for i in 100..1000 {
+                    dict.insert(i, ());
+                }
+            }
+            if calls == 100 {
+                for i in 100..1000 {
+                    dict.remove(&i);
+                }
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn scan_should_visit_every_entry_of_both_tables() {
+        for grow in [true, false] {
+            let mut dict = Dict::new();
+            let range = if grow { 0..64 } else { 0..1000 };
+            for i in range {
+                dict.insert(i, ());
+            }
+            finish_resize(&mut dict);
+            if grow {
+                dict.insert(64, ());
+            } else {
+                for i in 64..1000 {
+                    dict.remove(&i);
+                }
+            }
+            assert!(dict.rehash_idx.is_some());
+
+            // a scan started before the resize goes on during it, another one starts during it
+            let mut seen = std::collections::HashSet::new();
+            let mut cursor = 0;
+            for _ in 0..3 {
+                cursor = dict.scan(cursor, |k, _| {
+                    seen.insert(*k);
+                });
+            }
+            let mut seen_during = std::collections::HashSet::new();
+            let mut cursor_during = 0;
+            while cursor != 0 || cursor_during != 0 {
+                if cursor != 0 {
+                    cursor = dict.scan(cursor, |k, _| {
+                        seen.insert(*k);
+                    });
+                }
+                if cursor_during != 0 || seen_during.is_empty() {
+                    cursor_during = dict.scan(cursor_during, |k, _| {
+                        seen_during.insert(*k);
+                    });
+                }
+                dict.get_mut(&0);
+            }
+            assert!((0..64).all(|i| seen.contains(&i) && seen_during.contains(&i)));
+        }
+    }
+}
@@ -0,0 +1,502 @@
+// Geospatial indexes are sorted sets whose scores are 52 bits geohashes: the longitude and the
+// latitude, mapped to the web mercator ranges, interleaved bit by bit. The computations follow the
+// ones of redis so that scores, distances and search results are the same.
+use super::{ScoreBound, ZRangeSpec, ZSet};
+use bytes::Bytes;
+use std::f64::consts::PI;
+
+const STEP_MAX: u8 = 26;
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+
+// the characters of the standard geohash strings
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+fn deg_rad(angle: f64) -> f64 {
+    angle * (PI / 180.0)
+}
+
+fn rad_deg(angle: f64) -> f64 {
+    angle / (PI / 180.0)
+}
+
+// the bits of `v` moved to the even bits
+fn spread(v: u32) -> u64 {
+    let mut x = v as u64;
+    x = (x | (x << 16)) & 0x0000ffff0000ffff;
+    x = (x | (x << 8)) & 0x00ff00ff00ff00ff;
+    x = (x | (x << 4)) & 0x0f0f0f0f0f0f0f0f;
+    x = (x | (x << 2)) & 0x3333333333333333;
+    (x | (x << 1)) & 0x5555555555555555
+}
+
+// the even bits of `v`, packed
+fn squash(v: u64) -> u32 {
+    let mut x = v & 0x5555555555555555;
+    x = (x | (x >> 1)) & 0x3333333333333333;
+    x = (x | (x >> 2)) & 0x0f0f0f0f0f0f0f0f;
+    x = (x | (x >> 4)) & 0x00ff00ff00ff00ff;
+    x = (x | (x >> 8)) & 0x0000ffff0000ffff;
+    ((x | (x >> 16)) & 0x00000000ffffffff) as u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Range {
+    min: f64,
+    max: f64,
+}
+
+const LONGITUDE_RANGE: Range = Range {
+    min: LONGITUDE_MIN,
+    max: LONGITUDE_MAX,
+};
+const LATITUDE_RANGE: Range = Range {
+    min: LATITUDE_MIN,
+    max: LATITUDE_MAX,
+};
+
+// A geohash of `step` bits per coordinate, the latitude in the even bits and the longitude in the
+// odd ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GeoHash {
+    bits: u64,
+    step: u8,
+}
+
+// the area covered by a geohash
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    longitude: Range,
+    latitude: Range,
+}
+
+impl GeoHash {
+    fn encode(
+        longitude_range: Range,
+        latitude_range: Range,
+        longitude: f64,
+        latitude: f64,
+        step: u8,
+    ) -> Option<Self> {
+        if !valid_coordinates(longitude, latitude)
+            || !(longitude_range.min..=longitude_range.max).contains(&longitude)
+            || !(latitude_range.min..=latitude_range.max).contains(&latitude)
+        {
+            return None;
+        }
+        let scale = (1u64 << step) as f64;
+        let latitude_offset =
+            (latitude - latitude_range.min) / (latitude_range.max - latitude_range.min) * scale;
+        let longitude_offset =
+            (longitude - longitude_range.min) / (longitude_range.max - longitude_range.min) * scale;
+        Some(GeoHash {
+            bits: spread(latitude_offset as u32) | (spread(longitude_offset as u32) << 1),
+            step,
+        })
+    }
+
+    fn area(&self) -> Area {
+        let scale = (1u64 << self.step) as f64;
+        let latitude = squash(self.bits) as f64;
+        let longitude = squash(self.bits >> 1) as f64;
+        let range = |range: Range, offset: f64| Range {
+            min: range.min + (offset / scale) * (range.max - range.min),
+            max: range.min + ((offset + 1.0) / scale) * (range.max - range.min),
+        };
+        Area {
+            longitude: range(LONGITUDE_RANGE, longitude),
+            latitude: range(LATITUDE_RANGE, latitude),
+        }
+    }
+
+    // the hash moved by one box to the east (1) or west (-1), and to the north (1) or south (-1)
+    fn moved(&self, dx: i8, dy: i8) -> Self {
+        let shift = 64 - self.step as u32 * 2;
+        let mut x = self.bits & 0xaaaaaaaaaaaaaaaa;
+        let mut y = self.bits & 0x5555555555555555;
+        let move_bits = |bits: u64, d: i8, filler: u64, mask: u64| {
+            let zz = filler >> shift;
+            let bits = match d {
+                0 => return bits,
+                1.. => bits.wrapping_add(zz + 1),
+                _ => (bits | zz).wrapping_sub(zz + 1),
+            };
+            bits & (mask >> shift)
+        };
+        x = move_bits(x, dx, 0x5555555555555555, 0xaaaaaaaaaaaaaaaa);
+        y = move_bits(y, dy, 0xaaaaaaaaaaaaaaaa, 0x5555555555555555);
+        GeoHash {
+            bits: x | y,
+            step: self.step,
+        }
+    }
+
+    // the scores of the sorted set members in the box, the end excluded
+    fn scores(&self) -> (u64, u64) {
+        let shift = 52 - self.step as u32 * 2;
+        (self.bits << shift, (self.bits + 1) << shift)
+    }
+}
+
+pub fn valid_coordinates(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude)
+        && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+// the score of a position, None if it's out of the supported ranges
+pub fn encode_position(longitude: f64, latitude: f64) -> Option<u64> {
+    GeoHash::encode(
+        LONGITUDE_RANGE,
+        LATITUDE_RANGE,
+        longitude,
+        latitude,
+        STEP_MAX,
+    )
+    .map(|hash| hash.bits)
+}
+
+// the center of the box of a score, as (longitude, latitude)
+pub fn decode_position(score: f64) -> (f64, f64) {
+    let area = GeoHash {
+        bits: score as u64,
+        step: STEP_MAX,
+    }
+    .area();
+    let longitude =
+        ((area.longitude.min + area.longitude.max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude =
+        ((area.latitude.min + area.latitude.max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+    (longitude, latitude)
+}
+
+// the standard 11 characters geohash of a score, which uses the -90..90 latitude range
+pub fn geohash_string(score: f64) -> String {
+    let (longitude, latitude) = decode_position(score);
+    let full_range = Range {
+        min: -90.0,
+        max: 90.0,
+    };
+    let bits = GeoHash::encode(LONGITUDE_RANGE, full_range, longitude, latitude, STEP_MAX)
+        .map_or(0, |hash| hash.bits);
+    (0..11)
+        .map(|i| match i {
+            // 52 bits make 10 characters and a half, the last one is padded with zeros
+            10 => BASE32[0] as char,
+            _ => BASE32[((bits >> (52 - (i + 1) * 5)) & 0x1f) as usize] as char,
+        })
+        .collect()
+}
+
+fn latitude_distance(latitude1: f64, latitude2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (deg_rad(latitude2) - deg_rad(latitude1)).abs()
+}
+
+// the haversine distance in meters
+pub fn geo_distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let v = ((deg_rad(longitude2) - deg_rad(longitude1)) / 2.0).sin();
+    // the same meridian
+    if v == 0.0 {
+        return latitude_distance(latitude1, latitude2);
+    }
+    let (latitude1, latitude2) = (deg_rad(latitude1), deg_rad(latitude2));
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let a = u * u + latitude1.cos() * latitude2.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoArea {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+// The area searched by GEOSEARCH around a position. The sizes are in a unit worth `conversion`
+// meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoShape {
+    pub longitude: f64,
+    pub latitude: f64,
+    pub area: GeoArea,
+    pub conversion: f64,
+}
+
+// a member found by a search
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoPoint {
+    pub member: Bytes,
+    pub longitude: f64,
+    pub latitude: f64,
+    // the distance to the center of the search, in meters
+    pub distance: f64,
+    pub score: f64,
+}
+
+impl GeoShape {
+    // the smallest and greatest longitude and latitude of the area
+    fn bounding_box(&self) -> (Range, Range) {
+        let (width, height) = match self.area {
+            GeoArea::Radius(radius) => (radius, radius),
+            GeoArea::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+        let (width, height) = (self.conversion * width, self.conversion * height);
+        let latitude_delta = rad_deg(height / EARTH_RADIUS_IN_METERS);
+        // the area is wider on the side closer to the equator
+        let latitude = match self.latitude < 0.0 {
+            true => self.latitude - latitude_delta,
+            false => self.latitude + latitude_delta,
+        };
+        let longitude_delta = rad_deg(width / EARTH_RADIUS_IN_METERS / deg_rad(latitude).cos());
+        (
+            Range {
+                min: self.longitude - longitude_delta,
+                max: self.longitude + longitude_delta,
+            },
+            Range {
+                min: self.latitude - latitude_delta,
+                max: self.latitude + latitude_delta,
+            },
+        )
+    }
+
+    // the distance of a position in the area, in meters
+    fn distance_to(&self, longitude: f64, latitude: f64) -> Option<f64> {
+        match self.area {
+            GeoArea::Radius(radius) => {
+                let distance = geo_distance(self.longitude, self.latitude, longitude, latitude);
+                (distance <= radius * self.conversion).then_some(distance)
+            }
+            GeoArea::Box { width, height } => {
+                // the latitude distance is cheaper, check it first
+                if latitude_distance(latitude, self.latitude) > height * self.conversion / 2.0 {
+                    return None;
+                }
+                if geo_distance(longitude, latitude, self.longitude, latitude)
+                    > width * self.conversion / 2.0
+                {
+                    return None;
+                }
+                Some(geo_distance(
+                    self.longitude,
+                    self.latitude,
+                    longitude,
+                    latitude,
+                ))
+            }
+        }
+    }
+
+    // the geohash box of the center and its 8 neighbours, large enough to cover the area, without
+    // the neighbours outside of it
+    fn boxes(&self) -> [Option<GeoHash>; 9] {
+        let (longitude_bounds, latitude_bounds) = self.bounding_box();
+        let radius = match self.area {
+            GeoArea::Radius(radius) => radius,
+            // the distance from the center to a corner
+            GeoArea::Box { width, height } => {
+                ((width / 2.0) * (width / 2.0) + (height / 2.0) * (height / 2.0)).sqrt()
+            }
+        } * self.conversion;
+        let mut step = estimate_step(radius, self.latitude);
+
+        let neighbours = |step| {
+            let hash = GeoHash::encode(
+                LONGITUDE_RANGE,
+                LATITUDE_RANGE,
+                self.longitude,
+                self.latitude,
+                step,
+            )
+            .expect("the center should be valid");
+            // north, south, east, west, north east, north west, south east, south west
+            [
+                hash,
+                hash.moved(0, 1),
+                hash.moved(0, -1),
+                hash.moved(1, 0),
+                hash.moved(-1, 0),
+                hash.moved(1, 1),
+                hash.moved(-1, 1),
+                hash.moved(1, -1),
+                hash.moved(-1, -1),
+            ]
+        };
+        let mut hashes = neighbours(step);
+
+        // the step may be too coarse when the area is close to the edge of the center box
+        let too_small = hashes[1].area().latitude.max < latitude_bounds.max
+            || hashes[2].area().latitude.min > latitude_bounds.min
+            || hashes[3].area().longitude.max < longitude_bounds.max
+            || hashes[4].area().longitude.min > longitude_bounds.min;
+        if step > 1 && too_small {
+            step -= 1;
+            hashes = neighbours(step);
+        }
+
+        let mut boxes = hashes.map(Some);
+        if step >= 2 {
+            let area = hashes[0].area();
+            let mut exclude = |indexes: [usize; 3]| indexes.map(|i| boxes[i] = None);
+            if area.latitude.min < latitude_bounds.min {
+                exclude([2, 8, 7]);
+            }
+            if area.latitude.max > latitude_bounds.max {
+                exclude([1, 5, 6]);
+            }
+            if area.longitude.min < longitude_bounds.min {
+                exclude([4, 8, 6]);
+            }
+            if area.longitude.max > longitude_bounds.max {
+                exclude([3, 7, 5]);
+            }
+        }
+        boxes
+    }
+
+    // The members of the sorted set in the area, in the order of the boxes they were found in.
+    // The search stops once `limit` members are found, unless it's 0.
+    pub fn search(&self, zset: &ZSet, limit: usize) -> Vec<GeoPoint> {
+        let mut points = vec![];
+        // the last neighbour searched, the center box doesn't count like in redis
+        let mut last: Option<GeoHash> = None;
+        for (i, hash) in self.boxes().into_iter().enumerate() {
+            let Some(hash) = hash else { continue };
+            // the neighbours of a huge area may be the same box
+            if last == Some(hash) {
+                continue;
+            }
+            if limit > 0 && points.len() >= limit {
+                break;
+            }
+            if i > 0 {
+                last = Some(hash);
+            }
+
+            let (min, max) = hash.scores();
+            let range = ZRangeSpec::Score(
+                ScoreBound {
+                    score: min as f64,
+                    exclusive: false,
+                },
+                ScoreBound {
+                    score: max as f64,
+                    exclusive: true,
+                },
+            );
+            for (member, score) in zset.range(zset.ranks(&range), false) {
+                let (longitude, latitude) = decode_position(score);
+                if let Some(distance) = self.distance_to(longitude, latitude) {
+                    points.push(GeoPoint {
+                        member,
+                        longitude,
+                        latitude,
+                        distance,
+                        score,
+                    });
+                    if limit > 0 && points.len() >= limit {
+                        break;
+                    }
+                }
+            }
+        }
+        points
+    }
+}
+
+// the number of bits per coordinate of the boxes to search a radius with
+fn estimate_step(mut radius: f64, latitude: f64) -> u8 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    // make sure the radius is included in most cases
+    step -= 2;
+    // the boxes get narrower towards the poles
+    if latitude.abs() > 66.0 {
+        step -= 1;
+        if latitude.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u8
+}
+
+#[cfg(test)]
+mod geo_test {
+    use super::*;
+
+    #[test]
+    fn positions_should_be_encoded_as_scores() {
+        assert_eq!(
+            Some(3479099956230698),
+            encode_position(13.361389, 38.115556)
+        );
+        assert_eq!(
+            Some(3479447370796909),
+            encode_position(15.087269, 37.502669)
+        );
+        assert_eq!(None, encode_position(13.361389, 86.0));
+
+        let (longitude, latitude) = decode_position(3479099956230698.0);
+        assert_eq!("13.36138933897018433", format!("{longitude:.17}"));
+        assert_eq!("38.11555639549629859", format!("{latitude:.17}"));
+        assert_eq!("sqc8b49rny0", geohash_string(3479099956230698.0));
+        assert_eq!("sqdtr74hyu0", geohash_string(3479447370796909.0));
+    }
+
+    #[test]
+    fn distance_should_use_the_haversine_formula() {
+        let (palermo, catania) = (
+            decode_position(3479099956230698.0),
+            decode_position(3479447370796909.0),
+        );
+        let distance = geo_distance(palermo.0, palermo.1, catania.0, catania.1);
+        assert_eq!("166274.1516", format!("{distance:.4}"));
+    }
+
+    #[test]
+    fn search_should_find_the_members_in_the_area() {
+        let mut zset = ZSet::new();
+        for (member, longitude, latitude) in [
+            ("Palermo", 13.361389, 38.115556),
+            ("Catania", 15.087269, 37.502669),
+            ("edge1", 12.758489, 38.788135),
+            ("edge2", 17.241510, 38.788135),
+        ] {
+            let score = encode_position(longitude, latitude).unwrap() as f64;
+            zset.insert(Bytes::from(member), score);
+        }
+        let shape = |area| GeoShape {
+            longitude: 15.0,
+            latitude: 37.0,
+            area,
+            conversion: 1000.0,
+        };
+        let members = |points: Vec<GeoPoint>| {
+            let mut members: Vec<_> = points.into_iter().map(|point| point.member).collect();
+            members.sort();
+            members
+        };
+
+        let points = shape(GeoArea::Radius(200.0)).search(&zset, 0);
+        assert_eq!(vec!["Catania", "Palermo"], members(points));
+        let area = GeoArea::Box {
+            width: 400.0,
+            height: 400.0,
+        };
+        let points = shape(area).search(&zset, 0);
+        assert_eq!(
+            vec!["Catania", "Palermo", "edge1", "edge2"],
+            members(points)
+        );
+        assert_eq!(1, shape(area).search(&zset, 1).len());
+    }
+}
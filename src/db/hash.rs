@@ -0,0 +1,252 @@
+use super::{dict::Dict, StringDbManipulator, Value};
+use anyhow::Result;
+use bytes::Bytes;
+use rand::seq::IteratorRandom;
+use std::{
+    collections::{BTreeSet, HashSet},
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone)]
+struct Field {
+    value: Bytes,
+    // like Entry.expire_at, None means the field never expires
+    expire_at: Option<SystemTime>,
+}
+
+// A hash value, mapping fields to values. It's backed by a Dict so that HSCAN can iterate it
+// with a cursor and HRANDFIELD can sample it.
+//
+// Fields may expire on their own. Expired fields are removed by remove_expired(), which the
+// database calls before handing the hash out, so the other methods don't check the expiries.
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    fields: Dict<Bytes, Field>,
+    // the fields that have an expiry, ordered by expiry
+    expires: BTreeSet<(SystemTime, Bytes)>,
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.fields.get(field).map(|field| &field.value)
+    }
+
+    // return true if the field is new. When keep_ttl is false, an existing field loses its expiry
+    pub fn insert(&mut self, field: Bytes, value: Bytes, keep_ttl: bool) -> bool {
+        if let Some(current) = self.fields.get_mut(&field) {
+            current.value = value;
+            if !keep_ttl {
+                if let Some(expire_at) = current.expire_at.take() {
+                    self.expires.remove(&(expire_at, field));
+                }
+            }
+            return false;
+        }
+        self.fields.insert(
+            field,
+            Field {
+                value,
+                expire_at: None,
+            },
+        );
+        true
+    }
+
+    // return true if the field existed
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match self.fields.remove_entry(field) {
+            Some((field, Field { expire_at, .. })) => {
+                if let Some(expire_at) = expire_at {
+                    self.expires.remove(&(expire_at, field));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        self.fields.iter().map(|(field, f)| (field, &f.value))
+    }
+
+    // visit about `count` fields starting from `cursor`, like StringDbManipulator::scan
+    pub fn scan(&self, mut cursor: u64, count: usize) -> (u64, Vec<(Bytes, Bytes)>) {
+        // COUNT comes from the client, it isn't trusted for the allocation
+        let mut fields = Vec::with_capacity(count.min(self.len()));
+        loop {
+            cursor = self.fields.scan(cursor, |field, f| {
+                fields.push((field.clone(), f.value.clone()))
+            });
+            if cursor == 0 || fields.len() >= count {
+                break;
+            }
+        }
+        (cursor, fields)
+    }
+
+    // `count` random fields, which may repeat unless `distinct` is set, in which case at most all
+    // the fields are returned
+    pub fn random_fields(&self, count: usize, distinct: bool) -> Vec<(&Bytes, &Bytes)> {
+        let mut rng = rand::thread_rng();
+        if !distinct {
+            return (0..count)
+                .filter_map(|_| self.fields.random_entry())
+                .map(|(field, f)| (field, &f.value))
+                .collect();
+        }
+        if count >= self.len() {
+            return self.iter().collect();
+        }
+        // when most of the fields are wanted, picking them one by one would mostly hit fields that
+        // were already picked
+        if count * 3 > self.len() {
+            return self.iter().choose_multiple(&mut rng, count);
+        }
+
+        let mut picked = HashSet::with_capacity(count.min(self.len()));
+        let mut fields = Vec::with_capacity(count.min(self.len()));
+        while fields.len() < count {
+            let (field, f) = self.fields.random_entry().expect("the hash isn't empty");
+            if picked.insert(field) {
+                fields.push((field, &f.value));
+            }
+        }
+        fields
+    }
+
+    // return None if the field doesn't exist, Some(None) if it exists but has no expiry
+    pub fn get_expire_at(&self, field: &[u8]) -> Option<Option<SystemTime>> {
+        self.fields.get(field).map(|field| field.expire_at)
+    }
+
+    // set (or remove, with None) the expiry of an existing field. Return false if the field
+    // doesn't exist
+    pub fn set_expire_at(&mut self, field: &[u8], expire_at: Option<SystemTime>) -> bool {
+        let (field, current) = match self.fields.get_mut(field) {
+            Some(current) => (Bytes::copy_from_slice(field), current),
+            None => return false,
+        };
+        if let Some(old) = std::mem::replace(&mut current.expire_at, expire_at) {
+            self.expires.remove(&(old, field.clone()));
+        }
+        if let Some(expire_at) = expire_at {
+            self.expires.insert((expire_at, field));
+        }
+        true
+    }
+
+    // whether some fields have an expiry
+    pub fn has_expires(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    // remove the fields that expired before `now`, return how many were removed
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let mut removed = 0;
+        while let Some((expire_at, _)) = self.expires.first() {
+            if *expire_at >= now {
+                break;
+            }
+            let (_, field) = self.expires.pop_first().expect("the expiry exists");
+            self.fields.remove(&field);
+            removed += 1;
+        }
+        removed
+    }
+}
+
+// the hash stored at `key`, created empty if the key doesn't exist
+pub async fn hash_or_create<'a>(
+    string_db: &'a mut Box<dyn StringDbManipulator>,
+    key: &str,
+) -> Result<&'a mut Hash> {
+    if !string_db.check_exist(key).await {
+        string_db
+            .insert_value(key.to_string(), Value::Hash(Hash::new()))
+            .await;
+    }
+    string_db
+        .get_value_mut(key)
+        .await
+        .expect("the hash should exist")
+        .as_hash_mut()
+}
+
+#[cfg(test)]
+mod hash_test {
+    use super::*;
+
+    fn hash_of(n: usize) -> Hash {
+        let mut hash = Hash::new();
+        for i in 0..n {
+            hash.insert(format!("f{i}").into(), i.to_string().into(), false);
+        }
+        hash
+    }
+
+    #[test]
+    fn scan_should_visit_every_field() {
+        let hash = hash_of(100);
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next, fields) = hash.scan(cursor, 10);
+            seen.extend(fields.into_iter().map(|(field, _)| field));
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(100, seen.len());
+    }
+
+    #[test]
+    fn fields_should_expire() {
+        let mut hash = hash_of(3);
+        let now = SystemTime::now();
+        let past = now - std::time::Duration::from_secs(1);
+        let future = now + std::time::Duration::from_secs(100);
+
+        assert!(hash.set_expire_at(b"f0", Some(past)));
+        assert!(hash.set_expire_at(b"f1", Some(future)));
+        assert!(!hash.set_expire_at(b"nope", Some(past)));
+        assert_eq!(Some(Some(future)), hash.get_expire_at(b"f1"));
+        assert_eq!(Some(None), hash.get_expire_at(b"f2"));
+
+        assert_eq!(1, hash.remove_expired(now));
+        assert_eq!(None, hash.get(b"f0"));
+        assert_eq!(2, hash.len());
+
+        // keep_ttl decides whether overwriting a field keeps its expiry
+        hash.insert("f1".into(), "x".into(), true);
+        assert_eq!(Some(Some(future)), hash.get_expire_at(b"f1"));
+        hash.insert("f1".into(), "y".into(), false);
+        assert_eq!(Some(None), hash.get_expire_at(b"f1"));
+        assert!(!hash.has_expires());
+    }
+
+    #[test]
+    fn random_fields_should_work() {
+        let hash = hash_of(10);
+        for count in [1, 3, 5, 9, 10, 20] {
+            let fields = hash.random_fields(count, true);
+            assert_eq!(count.min(10), fields.len());
+            let distinct: HashSet<_> = fields.iter().map(|(field, _)| *field).collect();
+            assert_eq!(fields.len(), distinct.len());
+        }
+        assert_eq!(20, hash.random_fields(20, false).len());
+        assert!(Hash::new().random_fields(5, false).is_empty());
+    }
+}
@@ -0,0 +1,537 @@
+// HyperLogLogs are plain strings laid out exactly like redis does, so that they can be exchanged
+// with it: a 16 bytes header followed by the registers, either sparse or dense.
+//
+// header: "HYLL", the encoding (0 dense, 1 sparse), 3 unused bytes, then the cached cardinality as
+// a little endian u64 whose most significant bit is set when the cache is stale.
+// dense: 16384 registers of 6 bits, the least significant bits of a register first.
+// sparse: runs of registers, as opcodes
+//   ZERO  00xxxxxx           xxxxxx + 1 registers set to 0, up to 64
+//   XZERO 01xxxxxx yyyyyyyy  xxxxxxyyyyyyyy + 1 registers set to 0, up to 16384
+//   VAL   1vvvvvxx           xx + 1 registers set to vvvvv + 1, up to 4 registers and a value of 32
+use anyhow::{anyhow, Error, Result};
+
+// the number of bits of the hash addressing a register
+const P: u32 = 14;
+pub const REGISTERS: usize = 1 << P;
+// the number of bits of the hash used to count the leading zeros, hence the greatest register
+const Q: usize = 64 - P as usize;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * REGISTER_BITS).div_ceil(8);
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+// the offset of the cached cardinality in the header
+const CARD: usize = 8;
+
+const ZERO_MAX_LEN: usize = 64;
+const XZERO_MAX_LEN: usize = 16384;
+const VAL_MAX_LEN: usize = 4;
+const VAL_MAX_VALUE: u8 = 32;
+// the size above which a sparse HyperLogLog is converted to dense, hll-sparse-max-bytes
+const SPARSE_MAX_BYTES: usize = 3000;
+
+const SEED: u64 = 0xadc83b19;
+// 0.5 / ln(2)
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+pub fn invalid_hll() -> Error {
+    anyhow!("WRONGTYPE Key is not a valid HyperLogLog string value.")
+}
+
+fn corrupted_hll() -> Error {
+    anyhow!("INVALIDOBJ Corrupted HLL object detected")
+}
+
+// MurmurHash2, 64 bits version by Austin Appleby, reading the words as little endian
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+// the register of an element, and the length of the 0...01 pattern of its hash, from 1 to Q + 1
+fn pattern_len(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // the bit Q stops the count at Q + 1
+    let count = ((hash >> P) | (1 << Q)).trailing_zeros() + 1;
+    (index, count as u8)
+}
+
+fn get_register(registers: &[u8], index: usize) -> u8 {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = index * REGISTER_BITS % 8;
+    // the last register doesn't spill over a next byte
+    let b0 = registers[byte] as u16;
+    let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((b0 >> shift) | (b1 << (8 - shift))) as u8) & REGISTER_MAX
+}
+
+fn set_register(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = index * REGISTER_BITS % 8;
+    let (max, value) = (REGISTER_MAX as u16, value as u16);
+    registers[byte] &= !((max << shift) as u8);
+    registers[byte] |= (value << shift) as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next &= !((max >> (8 - shift)) as u8);
+        *next |= (value >> (8 - shift)) as u8;
+    }
+}
+
+fn is_zero(op: u8) -> bool {
+    op & 0xc0 == 0
+}
+
+fn is_xzero(op: u8) -> bool {
+    op & 0xc0 == 0x40
+}
+
+fn val_value(op: u8) -> u8 {
+    ((op >> 2) & 0x1f) + 1
+}
+
+fn val_op(value: u8, len: usize) -> u8 {
+    0x80 | ((value - 1) << 2) | (len - 1) as u8
+}
+
+// the ZERO or XZERO opcode of a run of zeros
+fn push_zeros(ops: &mut Vec<u8>, len: usize) {
+    if len > ZERO_MAX_LEN {
+        ops.extend([0x40 | ((len - 1) >> 8) as u8, (len - 1) as u8]);
+    } else {
+        ops.push((len - 1) as u8);
+    }
+}
+
+// the number of registers of the opcode at `i`, and the size of the opcode
+fn opcode_span(sparse: &[u8], i: usize) -> Result<(usize, usize)> {
+    let op = sparse[i];
+    Ok(if is_zero(op) {
+        ((op & 0x3f) as usize + 1, 1)
+    } else if is_xzero(op) {
+        let next = *sparse.get(i + 1).ok_or_else(corrupted_hll)?;
+        (((((op & 0x3f) as usize) << 8) | next as usize) + 1, 2)
+    } else {
+        ((op & 0x3) as usize + 1, 1)
+    })
+}
+
+// the runs of registers of a sparse representation, as the number of registers and their value
+fn sparse_runs(sparse: &[u8]) -> Result<Vec<(usize, u8)>> {
+    let mut runs = vec![];
+    let (mut i, mut registers) = (0, 0);
+    while i < sparse.len() {
+        let (span, size) = opcode_span(sparse, i)?;
+        let value = if is_zero(sparse[i]) || is_xzero(sparse[i]) {
+            0
+        } else {
+            val_value(sparse[i])
+        };
+        registers += span;
+        if registers > REGISTERS {
+            return Err(corrupted_hll());
+        }
+        runs.push((span, value));
+        i += size;
+    }
+    if registers != REGISTERS {
+        return Err(corrupted_hll());
+    }
+    Ok(runs)
+}
+
+// the number of registers holding each value
+type Histogram = [u32; 64];
+
+// the cardinality estimated from the histogram of the registers, see "New cardinality estimation
+// algorithms for HyperLogLog sketches", Otmar Ertl, arXiv:1702.01284
+fn estimate(histogram: &Histogram) -> u64 {
+    let m = REGISTERS as f64;
+    let mut z = m * tau((m - histogram[Q + 1] as f64) / m);
+    for j in (1..=Q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+// the cardinality estimated from raw registers, one value per byte, e.g. the union of several
+// HyperLogLogs
+pub fn count_registers(registers: &[u8]) -> u64 {
+    let mut histogram = [0; 64];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+    estimate(&histogram)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    bytes: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    // an empty sparse HyperLogLog
+    pub fn new() -> Self {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(b"HYLL");
+        bytes[4] = SPARSE;
+        let mut registers = REGISTERS;
+        while registers > 0 {
+            let len = registers.min(XZERO_MAX_LEN);
+            push_zeros(&mut bytes, len);
+            registers -= len;
+        }
+        HyperLogLog { bytes }
+    }
+
+    // whether a string holds a HyperLogLog, the sparse registers are checked when they're read
+    pub fn check(bytes: &[u8]) -> Result<()> {
+        if bytes.len() < HEADER_SIZE
+            || &bytes[..4] != b"HYLL"
+            || bytes[4] > SPARSE
+            || (bytes[4] == DENSE && bytes.len() != DENSE_SIZE)
+        {
+            return Err(invalid_hll());
+        }
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::check(&bytes)?;
+        Ok(HyperLogLog { bytes })
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn is_dense(&self) -> bool {
+        self.bytes[4] == DENSE
+    }
+
+    // add an element, true if a register was updated
+    pub fn add(&mut self, element: &[u8]) -> Result<bool> {
+        let (index, count) = pattern_len(element);
+        self.set_register(index, count)
+    }
+
+    // raise a register to `count`, true if it was lower
+    pub fn set_register(&mut self, index: usize, count: u8) -> Result<bool> {
+        let updated = if self.is_dense() {
+            let registers = &mut self.bytes[HEADER_SIZE..];
+            let updated = get_register(registers, index) < count;
+            if updated {
+                set_register(registers, index, count);
+            }
+            updated
+        } else {
+            self.sparse_set(index, count)?
+        };
+        if updated {
+            self.invalidate_cache();
+        }
+        Ok(updated)
+    }
+
+    pub fn invalidate_cache(&mut self) {
+        self.bytes[CARD + 7] |= 0x80;
+    }
+
+    pub fn is_cache_stale(&self) -> bool {
+        self.bytes[CARD + 7] & 0x80 != 0
+    }
+
+    // the estimated cardinality, which is cached in the header
+    pub fn count(&mut self) -> Result<u64> {
+        if !self.is_cache_stale() {
+            let card = &self.bytes[CARD..CARD + 8];
+            return Ok(u64::from_le_bytes(card.try_into().unwrap()));
+        }
+        let mut histogram = [0; 64];
+        if self.is_dense() {
+            let registers = &self.bytes[HEADER_SIZE..];
+            for index in 0..REGISTERS {
+                histogram[get_register(registers, index) as usize] += 1;
+            }
+        } else {
+            for (len, value) in sparse_runs(&self.bytes[HEADER_SIZE..])? {
+                histogram[value as usize] += len as u32;
+            }
+        }
+        let count = estimate(&histogram);
+        self.bytes[CARD..CARD + 8].copy_from_slice(&count.to_le_bytes());
+        Ok(count)
+    }
+
+    // raise the raw registers `max` to the registers of the HyperLogLog
+    pub fn merge_into(&self, max: &mut [u8]) -> Result<()> {
+        if self.is_dense() {
+            let registers = &self.bytes[HEADER_SIZE..];
+            for (index, max) in max.iter_mut().enumerate() {
+                *max = (*max).max(get_register(registers, index));
+            }
+        } else {
+            let mut index = 0;
+            for (len, value) in sparse_runs(&self.bytes[HEADER_SIZE..])? {
+                for max in max[index..index + len].iter_mut() {
+                    *max = (*max).max(value);
+                }
+                index += len;
+            }
+        }
+        Ok(())
+    }
+
+    // convert a sparse HyperLogLog to the dense representation, keeping the header
+    pub fn make_dense(&mut self) -> Result<()> {
+        if self.is_dense() {
+            return Ok(());
+        }
+        let mut bytes = vec![0; DENSE_SIZE];
+        bytes[..HEADER_SIZE].copy_from_slice(&self.bytes[..HEADER_SIZE]);
+        bytes[4] = DENSE;
+        let mut index = 0;
+        for (len, value) in sparse_runs(&self.bytes[HEADER_SIZE..])? {
+            if value > 0 {
+                for i in index..index + len {
+                    set_register(&mut bytes[HEADER_SIZE..], i, value);
+                }
+            }
+            index += len;
+        }
+        self.bytes = bytes;
+        Ok(())
+    }
+
+    // Raise a register of a sparse HyperLogLog, splitting the opcode covering it as needed. The
+    // HyperLogLog becomes dense when the value doesn't fit in a VAL opcode or it grows too large.
+    fn sparse_set(&mut self, index: usize, count: u8) -> Result<bool> {
+        if count > VAL_MAX_VALUE {
+            return self.promote(index, count);
+        }
+
+        // find the opcode covering the register
+        let bytes = &mut self.bytes;
+        let (mut p, mut first, mut span, mut size) = (HEADER_SIZE, 0, 0, 0);
+        let mut prev = None;
+        while p < bytes.len() {
+            (span, size) = opcode_span(bytes, p)?;
+            if index < first + span {
+                break;
+            }
+            prev = Some(p);
+            p += size;
+            first += span;
+        }
+        if span == 0 || p >= bytes.len() {
+            return Err(corrupted_hll());
+        }
+
+        let op = bytes[p];
+        let (zero, xzero) = (is_zero(op), is_xzero(op));
+        let val = !zero && !xzero;
+        if val && val_value(op) >= count {
+            return Ok(false);
+        }
+        if (val || zero) && span == 1 {
+            // the opcode only covers the register
+            bytes[p] = val_op(count, 1);
+        } else {
+            // split the opcode around the register
+            let last = first + span - 1;
+            let mut ops = Vec::with_capacity(5);
+            if val {
+                let value = val_value(op);
+                if index != first {
+                    ops.push(val_op(value, index - first));
+                }
+                ops.push(val_op(count, 1));
+                if index != last {
+                    ops.push(val_op(value, last - index));
+                }
+            } else {
+                if index != first {
+                    push_zeros(&mut ops, index - first);
+                }
+                ops.push(val_op(count, 1));
+                if index != last {
+                    push_zeros(&mut ops, last - index);
+                }
+            }
+            if ops.len() > size && bytes.len() + ops.len() - size > SPARSE_MAX_BYTES {
+                return self.promote(index, count);
+            }
+            bytes.splice(p..p + size, ops);
+        }
+
+        // merge the adjacent VAL opcodes with the same value, up to 5 opcodes from the previous
+        // one
+        let mut p = prev.unwrap_or(HEADER_SIZE);
+        let mut scan = 5;
+        while p < bytes.len() && scan > 0 {
+            scan -= 1;
+            if is_xzero(bytes[p]) {
+                p += 2;
+                continue;
+            } else if is_zero(bytes[p]) {
+                p += 1;
+                continue;
+            }
+            if let Some(&next) = bytes.get(p + 1) {
+                if next & 0x80 != 0 && val_value(bytes[p]) == val_value(next) {
+                    let len = (bytes[p] & 0x3) as usize + (next & 0x3) as usize + 2;
+                    if len <= VAL_MAX_LEN {
+                        bytes[p + 1] = val_op(val_value(next), len);
+                        bytes.remove(p);
+                        // the merged opcode may merge with the next one too
+                        continue;
+                    }
+                }
+            }
+            p += 1;
+        }
+        Ok(true)
+    }
+
+    // convert to the dense representation and set the register there
+    fn promote(&mut self, index: usize, count: u8) -> Result<bool> {
+        self.make_dense()?;
+        let registers = &mut self.bytes[HEADER_SIZE..];
+        let updated = get_register(registers, index) < count;
+        if updated {
+            set_register(registers, index, count);
+        }
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod hyperloglog_test {
+    use super::*;
+
+    #[test]
+    fn new_should_be_sparse() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(b"HYLL\x01\0\0\0\0\0\0\0\0\0\0\0\x7f\xff", &hll.bytes[..]);
+        assert_eq!(0, hll.count().unwrap());
+        assert!(HyperLogLog::check(b"HYLL\x00\0\0\0\0\0\0\0\0\0\0\0\x7f\xff").is_err());
+        assert!(HyperLogLog::check(b"HYLL").is_err());
+    }
+
+    #[test]
+    fn registers_should_be_packed() {
+        let mut registers = vec![0; DENSE_SIZE - HEADER_SIZE];
+        for index in [0, 1, 2, 3, 5000, REGISTERS - 1] {
+            set_register(&mut registers, index, 63);
+            set_register(&mut registers, index, (index % 64) as u8);
+        }
+        assert_eq!(0, get_register(&registers, 4));
+        assert_eq!(3, get_register(&registers, 3));
+        assert_eq!(5000 % 64, get_register(&registers, 5000) as usize);
+        assert_eq!(63, get_register(&registers, REGISTERS - 1));
+    }
+
+    #[test]
+    fn sparse_set_should_split_and_merge_opcodes() {
+        let mut hll = HyperLogLog::new();
+        assert!(hll.set_register(1000, 3).unwrap());
+        // XZERO 1000, VAL 3, XZERO 15383
+        assert_eq!(&[0x43, 0xe7, 0x88, 0x7c, 0x16], &hll.bytes[HEADER_SIZE..]);
+        assert!(!hll.set_register(1000, 2).unwrap());
+        assert!(hll.set_register(1001, 3).unwrap());
+        // the two registers are merged in a single VAL opcode
+        assert_eq!(&[0x43, 0xe7, 0x89, 0x7c, 0x15], &hll.bytes[HEADER_SIZE..]);
+
+        let mut dense = hll.clone();
+        dense.make_dense().unwrap();
+        assert_eq!(DENSE_SIZE, dense.bytes.len());
+        let (mut sparse_max, mut dense_max) = ([0; REGISTERS], [0; REGISTERS]);
+        hll.merge_into(&mut sparse_max).unwrap();
+        dense.merge_into(&mut dense_max).unwrap();
+        assert_eq!(sparse_max, dense_max);
+        assert_eq!(2, hll.count().unwrap());
+        assert_eq!(2, dense.count().unwrap());
+    }
+
+    #[test]
+    fn count_should_estimate_the_cardinality() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..100 {
+            hll.add(format!("element:{i}").as_bytes()).unwrap();
+        }
+        assert!(!hll.is_dense());
+        let count = hll.count().unwrap();
+        assert!((98..=102).contains(&count), "{count}");
+        for i in 0..100_000 {
+            hll.add(format!("element:{i}").as_bytes()).unwrap();
+        }
+        assert!(hll.is_dense());
+        let count = hll.count().unwrap();
+        assert!((98_000..102_000).contains(&count), "{count}");
+    }
+}
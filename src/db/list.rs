@@ -0,0 +1,398 @@
+use super::{StringDbManipulator, Value};
+use anyhow::Result;
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+// the maximum number of elements of a node
+const NODE_CAPACITY: usize = 128;
+
+// an end of a list, LEFT is the head and RIGHT is the tail
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+// A deque split into nodes of at most NODE_CAPACITY elements, like the quicklist of redis. Pushing
+// and popping at both ends is O(1), and inserting or removing in the middle only shifts the
+// elements of one node.
+#[derive(Debug, Clone, Default)]
+pub struct QuickList {
+    nodes: VecDeque<VecDeque<Bytes>>,
+    len: usize,
+}
+
+impl QuickList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the number of nodes, which is roughly the number of allocations needed to free the list
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn push_front(&mut self, value: Bytes) {
+        match self.nodes.front_mut() {
+            Some(node) if node.len() < NODE_CAPACITY => node.push_front(value),
+            _ => self.nodes.push_front(VecDeque::from([value])),
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: Bytes) {
+        match self.nodes.back_mut() {
+            Some(node) if node.len() < NODE_CAPACITY => node.push_back(value),
+            _ => self.nodes.push_back(VecDeque::from([value])),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let node = self.nodes.front_mut()?;
+        let value = node.pop_front();
+        if node.is_empty() {
+            self.nodes.pop_front();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let node = self.nodes.back_mut()?;
+        let value = node.pop_back();
+        if node.is_empty() {
+            self.nodes.pop_back();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn push(&mut self, direction: Direction, value: Bytes) {
+        match direction {
+            Direction::Left => self.push_front(value),
+            Direction::Right => self.push_back(value),
+        }
+    }
+
+    pub fn pop(&mut self, direction: Direction) -> Option<Bytes> {
+        match direction {
+            Direction::Left => self.pop_front(),
+            Direction::Right => self.pop_back(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Bytes> {
+        if index >= self.len {
+            return None;
+        }
+        let (node, offset) = self.locate(index);
+        self.nodes[node].get(offset)
+    }
+
+    // return false if the index is out of range
+    pub fn set(&mut self, index: usize, value: Bytes) -> bool {
+        if index >= self.len {
+            return false;
+        }
+        let (node, offset) = self.locate(index);
+        self.nodes[node][offset] = value;
+        true
+    }
+
+    // insert the value so that it ends up at `index`, shifting the following elements. `index` may
+    // be equal to the length, which appends the value
+    pub fn insert(&mut self, index: usize, value: Bytes) {
+        assert!(index <= self.len, "index out of range");
+        if index == 0 {
+            return self.push_front(value);
+        }
+        if index == self.len {
+            return self.push_back(value);
+        }
+
+        let (mut node, mut offset) = self.locate(index);
+        if self.nodes[node].len() >= NODE_CAPACITY {
+            // split the full node in halves
+            let half = NODE_CAPACITY / 2;
+            let tail = self.nodes[node].split_off(half);
+            self.nodes.insert(node + 1, tail);
+            if offset >= half {
+                node += 1;
+                offset -= half;
+            }
+        }
+        self.nodes[node].insert(offset, value);
+        self.len += 1;
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Bytes> {
+        self.nodes.iter().flatten()
+    }
+
+    // iterate from `start` to the end of the list without walking the elements before `start`
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = &Bytes> {
+        let (node, offset) = if start < self.len {
+            self.locate(start)
+        } else {
+            (self.nodes.len(), 0)
+        };
+        self.nodes.range(node..).flatten().skip(offset)
+    }
+
+    // keep only the elements from `start` to `end`, both inclusive
+    pub fn trim(&mut self, start: usize, end: usize) {
+        if start > end || start >= self.len {
+            self.nodes.clear();
+            self.len = 0;
+            return;
+        }
+        let end = end.min(self.len - 1);
+
+        // drop whole nodes first, then the remaining elements of the first and the last node
+        let mut front = start;
+        while front > 0 && self.nodes[0].len() <= front {
+            front -= self.nodes.pop_front().map_or(0, |node| node.len());
+        }
+        self.nodes[0].drain(..front);
+
+        let mut back = self.len - 1 - end;
+        while back > 0 && self.nodes[self.nodes.len() - 1].len() <= back {
+            back -= self.nodes.pop_back().map_or(0, |node| node.len());
+        }
+        let last = self.nodes.len() - 1;
+        let last_len = self.nodes[last].len();
+        self.nodes[last].truncate(last_len - back);
+
+        self.len = end - start + 1;
+    }
+
+    // remove up to `count` elements equal to `value` (all of them when count is 0), starting from
+    // the tail when `from_tail` is set. Return the number of removed elements
+    pub fn remove_matches(&mut self, value: &[u8], count: usize, from_tail: bool) -> usize {
+        let limit = if count == 0 { usize::MAX } else { count };
+        let mut removed = 0;
+
+        let node_indexes: Vec<usize> = if from_tail {
+            (0..self.nodes.len()).rev().collect()
+        } else {
+            (0..self.nodes.len()).collect()
+        };
+        for i in node_indexes {
+            let node = &mut self.nodes[i];
+            let mut j = if from_tail { node.len() } else { 0 };
+            loop {
+                if removed == limit {
+                    break;
+                }
+                if from_tail {
+                    if j == 0 {
+                        break;
+                    }
+                    j -= 1;
+                    if node[j] == value {
+                        node.remove(j);
+                        removed += 1;
+                    }
+                } else {
+                    if j == node.len() {
+                        break;
+                    }
+                    if node[j] == value {
+                        node.remove(j);
+                        removed += 1;
+                    } else {
+                        j += 1;
+                    }
+                }
+            }
+            if removed == limit {
+                break;
+            }
+        }
+
+        self.nodes.retain(|node| !node.is_empty());
+        self.len -= removed;
+        removed
+    }
+
+    // the node holding the element at `index` and the offset of the element in it, walking from
+    // whichever end is closer. `index` must be in range
+    fn locate(&self, index: usize) -> (usize, usize) {
+        if index < self.len / 2 {
+            let mut offset = index;
+            for (i, node) in self.nodes.iter().enumerate() {
+                if offset < node.len() {
+                    return (i, offset);
+                }
+                offset -= node.len();
+            }
+        } else {
+            let mut offset = self.len - 1 - index;
+            for (i, node) in self.nodes.iter().enumerate().rev() {
+                if offset < node.len() {
+                    return (i, node.len() - 1 - offset);
+                }
+                offset -= node.len();
+            }
+        }
+        unreachable!("index out of range")
+    }
+}
+
+// the list stored at `key`, created empty if the key doesn't exist
+pub async fn list_or_create<'a>(
+    string_db: &'a mut Box<dyn StringDbManipulator>,
+    key: &str,
+) -> Result<&'a mut QuickList> {
+    if !string_db.check_exist(key).await {
+        string_db
+            .insert_value(key.to_string(), Value::List(QuickList::new()))
+            .await;
+    }
+    string_db
+        .get_value_mut(key)
+        .await
+        .expect("the list should exist")
+        .as_list_mut()
+}
+
+// pop up to `count` elements from the list stored at `key`, deleting the key once the list is
+// empty. Return None if the key doesn't exist
+pub async fn pop(
+    string_db: &mut Box<dyn StringDbManipulator>,
+    key: &str,
+    count: usize,
+    direction: Direction,
+) -> Result<Option<Vec<Bytes>>> {
+    let list = match string_db.get_value_mut(key).await {
+        Some(value) => value.as_list_mut()?,
+        None => return Ok(None),
+    };
+
+    let mut elements = Vec::with_capacity(count.min(list.len()));
+    while elements.len() < count {
+        match list.pop(direction) {
+            Some(element) => elements.push(element),
+            None => break,
+        }
+    }
+
+    if list.is_empty() {
+        string_db.del(key).await;
+    } else if !elements.is_empty() {
+        string_db.signal_modified(key).await;
+    }
+    Ok(Some(elements))
+}
+
+#[cfg(test)]
+mod list_test {
+    use super::*;
+
+    fn list_of(range: std::ops::Range<usize>) -> QuickList {
+        let mut list = QuickList::new();
+        for i in range {
+            list.push_back(i.to_string().into());
+        }
+        list
+    }
+
+    fn to_vec(list: &QuickList) -> Vec<usize> {
+        list.iter()
+            .map(|v| std::str::from_utf8(v).unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn push_and_pop_should_work() {
+        let mut list = QuickList::new();
+        for i in 0..1000 {
+            list.push_front(i.to_string().into());
+            list.push_back(i.to_string().into());
+        }
+        assert_eq!(2000, list.len());
+        assert!(list.node_count() >= 2000 / NODE_CAPACITY);
+        assert_eq!(Some(Bytes::from("999")), list.pop_front());
+        assert_eq!(Some(Bytes::from("999")), list.pop_back());
+        assert_eq!(Some(&Bytes::from("998")), list.get(0));
+        assert_eq!(Some(&Bytes::from("998")), list.get(1997));
+        while list.pop_back().is_some() {}
+        assert!(list.is_empty());
+        assert_eq!(0, list.node_count());
+    }
+
+    #[test]
+    fn insert_should_work() {
+        let mut list = list_of(0..300);
+        list.insert(100, "x".into());
+        list.insert(0, "y".into());
+        list.insert(list.len(), "z".into());
+        assert_eq!(303, list.len());
+        assert_eq!(Some(&Bytes::from("x")), list.get(101));
+        assert_eq!(Some(&Bytes::from("100")), list.get(102));
+        assert_eq!(1, list.remove_matches(b"x", 0, false));
+        assert_eq!(Some(Bytes::from("y")), list.pop_front());
+        assert_eq!(Some(Bytes::from("z")), list.pop_back());
+        assert_eq!((0..300).collect::<Vec<_>>(), to_vec(&list));
+
+        // fill a node up so that it splits
+        for _ in 0..NODE_CAPACITY {
+            list.insert(10, "x".into());
+        }
+        assert_eq!(300 + NODE_CAPACITY, list.len());
+        assert_eq!(Some(&Bytes::from("10")), list.get(10 + NODE_CAPACITY));
+        assert!(list.set(10, "y".into()));
+        assert!(!list.set(list.len(), "y".into()));
+        assert_eq!(Some(&Bytes::from("y")), list.get(10));
+    }
+
+    #[test]
+    fn iter_from_should_work() {
+        let list = list_of(0..300);
+        assert_eq!(
+            (150..300).collect::<Vec<_>>(),
+            list.iter_from(150)
+                .map(|v| std::str::from_utf8(v).unwrap().parse().unwrap())
+                .collect::<Vec<usize>>()
+        );
+        assert_eq!(0, list.iter_from(300).count());
+    }
+
+    #[test]
+    fn trim_should_work() {
+        let mut list = list_of(0..1000);
+        list.trim(130, 869);
+        assert_eq!((130..870).collect::<Vec<_>>(), to_vec(&list));
+        assert_eq!(740, list.len());
+
+        list.trim(1, 0);
+        assert!(list.is_empty());
+        assert_eq!(0, list.node_count());
+    }
+
+    #[test]
+    fn remove_matches_should_work() {
+        let mut list = QuickList::new();
+        for _ in 0..200 {
+            list.push_back("a".into());
+            list.push_back("b".into());
+        }
+        assert_eq!(2, list.remove_matches(b"a", 2, false));
+        assert_eq!(Some(&Bytes::from("b")), list.get(0));
+        assert_eq!(Some(&Bytes::from("b")), list.get(1));
+        assert_eq!(3, list.remove_matches(b"b", 3, true));
+        assert_eq!(Some(&Bytes::from("a")), list.get(list.len() - 1));
+        assert_eq!(198, list.remove_matches(b"a", 0, false));
+        assert_eq!(197, list.len());
+        assert!(list.iter().all(|v| v == "b"));
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+
+// A listpack, the compact encoding redis uses for small collections and the nodes of streams. The
+// RDB files store it as is, so this is only needed to read and write them.
+//
+// A listpack is a header with its size in bytes and number of elements, the elements, then a
+// 0xFF terminator. Each element is an encoding byte telling its type and size, its data, then the
+// size of both, which makes it possible to walk the listpack backwards.
+
+const HEADER_SIZE: usize = 6;
+const EOF: u8 = 0xFF;
+// the number of elements stored in the header of a listpack with more of them
+const UNKNOWN_COUNT: u16 = u16::MAX;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Int(i64),
+    Str(Bytes),
+}
+
+impl Element {
+    pub fn into_bytes(self) -> Bytes {
+        match self {
+            Element::Int(value) => value.to_string().into(),
+            Element::Str(value) => value,
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64> {
+        match self {
+            Element::Int(value) => Ok(*value),
+            Element::Str(value) => string_to_int(value).ok_or_else(|| anyhow!("not an integer")),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ListpackWriter {
+    elements: Vec<u8>,
+    count: usize,
+}
+
+impl ListpackWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // append a string, stored as an integer when it's one, like redis does
+    pub fn push(&mut self, value: &[u8]) {
+        match string_to_int(value) {
+            Some(value) => self.push_int(value),
+            None => self.push_str(value),
+        }
+    }
+
+    pub fn push_int(&mut self, value: i64) {
+        let start = self.elements.len();
+        match value {
+            0..=127 => self.elements.push(value as u8),
+            -4096..=4095 => {
+                let value = (value as u16) & 0x1FFF;
+                self.elements
+                    .extend_from_slice(&[0xC0 | (value >> 8) as u8, value as u8]);
+            }
+            -32768..=32767 => {
+                self.elements.push(0xF1);
+                self.elements
+                    .extend_from_slice(&(value as i16).to_le_bytes());
+            }
+            -8388608..=8388607 => {
+                self.elements.push(0xF2);
+                self.elements.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+            -2147483648..=2147483647 => {
+                self.elements.push(0xF3);
+                self.elements
+                    .extend_from_slice(&(value as i32).to_le_bytes());
+            }
+            _ => {
+                self.elements.push(0xF4);
+                self.elements.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        self.end_element(start);
+    }
+
+    fn push_str(&mut self, value: &[u8]) {
+        let start = self.elements.len();
+        let len = value.len();
+        if len < 64 {
+            self.elements.push(0x80 | len as u8);
+        } else if len < 4096 {
+            self.elements
+                .extend_from_slice(&[0xE0 | (len >> 8) as u8, len as u8]);
+        } else {
+            self.elements.push(0xF0);
+            self.elements.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        self.elements.extend_from_slice(value);
+        self.end_element(start);
+    }
+
+    // append the size of the element starting at `start`
+    fn end_element(&mut self, start: usize) {
+        let size = self.elements.len() - start;
+        let backlen = encode_backlen(size);
+        self.elements.extend_from_slice(&backlen);
+        self.count += 1;
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let size = HEADER_SIZE + self.elements.len() + 1;
+        let count = u16::try_from(self.count).unwrap_or(UNKNOWN_COUNT);
+        let mut buf = Vec::with_capacity(size);
+        buf.extend_from_slice(&(size as u32).to_le_bytes());
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(&self.elements);
+        buf.push(EOF);
+        buf
+    }
+}
+
+// the elements of a listpack
+pub fn decode(buf: &[u8]) -> Result<Vec<Element>> {
+    if buf.len() < HEADER_SIZE + 1 || u32::from_le_bytes(buf[..4].try_into()?) as usize != buf.len()
+    {
+        bail!("invalid listpack size");
+    }
+    let mut elements = vec![];
+    let mut pos = HEADER_SIZE;
+    loop {
+        let encoding = *buf.get(pos).ok_or_else(truncated)?;
+        if encoding == EOF {
+            break;
+        }
+        // the size of the encoding byte and the data, and where the data starts
+        let (size, data) = match encoding {
+            0x00..=0x7F => (1, 0),
+            0x80..=0xBF => (1 + (encoding & 0x3F) as usize, 1),
+            0xC0..=0xDF => (2, 1),
+            0xE0..=0xEF => {
+                let len = ((encoding & 0x0F) as usize) << 8
+                    | *buf.get(pos + 1).ok_or_else(truncated)? as usize;
+                (2 + len, 2)
+            }
+            0xF0 => {
+                let len = buf.get(pos + 1..pos + 5).ok_or_else(truncated)?;
+                (5 + u32::from_le_bytes(len.try_into()?) as usize, 5)
+            }
+            0xF1 => (3, 1),
+            0xF2 => (4, 1),
+            0xF3 => (5, 1),
+            0xF4 => (9, 1),
+            _ => bail!("invalid listpack encoding {encoding:#x}"),
+        };
+        let element = buf.get(pos..pos + size).ok_or_else(truncated)?;
+        let value = &element[data..];
+        elements.push(match encoding {
+            0x00..=0x7F => Element::Int(encoding as i64),
+            0xC0..=0xDF => {
+                let value = ((encoding & 0x1F) as i64) << 8 | value[0] as i64;
+                // sign extend the 13 bits
+                Element::Int(value << 51 >> 51)
+            }
+            0xF1 => Element::Int(i16::from_le_bytes(value.try_into()?) as i64),
+            0xF2 => {
+                let value = i32::from_le_bytes([0, value[0], value[1], value[2]]);
+                Element::Int((value >> 8) as i64)
+            }
+            0xF3 => Element::Int(i32::from_le_bytes(value.try_into()?) as i64),
+            0xF4 => Element::Int(i64::from_le_bytes(value.try_into()?)),
+            _ => Element::Str(Bytes::copy_from_slice(value)),
+        });
+        pos += size + encode_backlen(size).len();
+    }
+    if pos + 1 != buf.len() {
+        bail!("invalid listpack size");
+    }
+    Ok(elements)
+}
+
+fn truncated() -> anyhow::Error {
+    anyhow!("truncated listpack")
+}
+
+// The size of an element, stored after it. Each byte holds 7 bits, the high bit is set on all the
+// bytes but the first so that reading it backwards knows where it ends.
+fn encode_backlen(size: usize) -> Vec<u8> {
+    let bytes = match size {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    };
+    (0..bytes)
+        .rev()
+        .enumerate()
+        .map(|(i, shift)| {
+            let byte = (size >> (7 * shift)) as u8 & 0x7F;
+            if i == 0 {
+                byte
+            } else {
+                byte | 0x80
+            }
+        })
+        .collect()
+}
+
+// the integer a string represents, if it's the canonical representation of an i64
+pub fn string_to_int(value: &[u8]) -> Option<i64> {
+    let int = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    (int.to_string().as_bytes() == value).then_some(int)
+}
+
+#[cfg(test)]
+mod listpack_test {
+    use super::*;
+
+    #[test]
+    fn listpack_should_round_trip() {
+        let ints = [
+            0,
+            127,
+            128,
+            -1,
+            4095,
+            -4096,
+            4096,
+            -32768,
+            32767,
+            -8388608,
+            8388607,
+            8388608,
+            i32::MIN as i64,
+            i32::MAX as i64 + 1,
+            i64::MIN,
+            i64::MAX,
+        ];
+        let long = vec![b'x'; 5000];
+        let strings: [&[u8]; 6] = [b"", b"foo", &[b'y'; 63], &[b'z'; 200], &long, b"007"];
+
+        let mut writer = ListpackWriter::new();
+        for int in ints {
+            writer.push_int(int);
+        }
+        for string in strings {
+            writer.push(string);
+        }
+        writer.push(b"-12");
+        let buf = writer.finish();
+        assert_eq!(ints.len() + strings.len() + 1, buf[4] as usize);
+
+        let mut expected: Vec<Element> = ints.into_iter().map(Element::Int).collect();
+        expected.extend(
+            strings
+                .into_iter()
+                .map(|s| Element::Str(Bytes::copy_from_slice(s))),
+        );
+        expected.push(Element::Int(-12));
+        assert_eq!(expected, decode(&buf).unwrap());
+
+        assert!(decode(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn backlen_should_be_readable_backwards() {
+        assert_eq!(vec![127], encode_backlen(127));
+        assert_eq!(vec![1, 0x80], encode_backlen(128));
+        assert_eq!(vec![0x7F, 0xFE], encode_backlen(16382));
+        assert_eq!(vec![1, 0x80, 0x80], encode_backlen(16384));
+    }
+}
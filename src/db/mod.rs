@@ -1,35 +1,328 @@
+mod bitmap;
+mod blocking;
+mod dict;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod list;
+mod listpack;
+mod pubsub;
+mod rdb;
+mod set;
+mod stream;
 mod string_db;
+mod zset;
 
 use bytes::Bytes;
-use std::{sync::Arc, time::Duration};
-use tokio::sync::Mutex;
+use std::ops::{Deref, DerefMut};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    sync::{Mutex, MutexGuard, OwnedMutexGuard},
+    time::Instant,
+};
+use tracing::debug;
 
-pub use string_db::StringDb;
+pub use bitmap::{bit_pos, count_bits, get_bit, popcount, set_bit, BitField, BitOp, Overflow};
+pub use blocking::{BlockedClients, BlockedOp};
+pub use geo::{
+    decode_position, encode_position, geo_distance, geohash_string, valid_coordinates, GeoArea,
+    GeoShape,
+};
+pub use hash::hash_or_create;
+pub use hyperloglog::{count_registers, HyperLogLog, REGISTERS};
+pub use list::{list_or_create, pop, Direction};
+pub use pubsub::{Message, PubSub};
+pub use rdb::{encode_rdb, load_rdb, snapshot};
+pub use set::{set_or_create, Set};
+pub use stream::{stream_or_create, ConsumerGroup, Stream, StreamId, Trim};
+pub use string_db::{string_or_create, wrong_type, Entry, StringDb, Value, WatchedKeys};
+pub use zset::{zset_or_create, LexBound, ScoreBound, ZRangeSpec, ZSet};
 
+// Every connection owns a clone of Db: the databases are shared, while the selected database is
+// per connection.
 #[derive(Debug, Clone)]
 pub struct Db {
     pub inner: Arc<Mutex<DbInner>>,
+    // the index of the database selected by SELECT
+    pub index: usize,
+    // the lock EXEC holds while it runs a transaction, the commands lock it instead of `inner` so
+    // that no other client runs in between
+    held: Option<Arc<Mutex<OwnedMutexGuard<DbInner>>>>,
 }
 
 #[derive(Debug)]
 pub struct DbInner {
-    pub string_db: Box<dyn StringDbManipulator>,
+    // the logical databases, addressed by their index
+    pub string_dbs: Vec<Box<dyn StringDbManipulator>>,
+    // the clients blocked by BLPOP and friends
+    pub blocked: BlockedClients,
+    // the channels and patterns the clients subscribed to
+    pub pubsub: PubSub,
+}
+
+// The locked databases, along with the database selected by the connection that locked them.
+pub struct DbGuard<'a> {
+    inner: InnerGuard<'a>,
+    index: usize,
+}
+
+enum InnerGuard<'a> {
+    Shared(MutexGuard<'a, DbInner>),
+    // the lock held by the transaction of the connection
+    Held(MutexGuard<'a, OwnedMutexGuard<DbInner>>),
+}
+
+impl DbGuard<'_> {
+    // the database selected by the connection
+    pub fn string_db(&mut self) -> &mut Box<dyn StringDbManipulator> {
+        let index = self.index;
+        &mut self.string_dbs[index]
+    }
+}
+
+impl Deref for DbGuard<'_> {
+    type Target = DbInner;
+
+    fn deref(&self) -> &Self::Target {
+        match &self.inner {
+            InnerGuard::Shared(inner) => inner,
+            InnerGuard::Held(inner) => inner,
+        }
+    }
+}
+
+impl DerefMut for DbGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match &mut self.inner {
+            InnerGuard::Shared(inner) => inner,
+            InnerGuard::Held(inner) => inner,
+        }
+    }
 }
 
 #[async_trait::async_trait]
 pub trait StringDbManipulator: Send + std::fmt::Debug {
-    async fn get(&mut self, key: &str) -> Option<Bytes>;
-    async fn set(&mut self, key: String, value: Bytes, expire: Option<Duration>);
-    async fn del(&mut self, key: &str);
+    // get a string value, fail with WRONGTYPE if the key holds another type
+    async fn get(&mut self, key: &str) -> anyhow::Result<Option<Bytes>>;
+    // when keep_ttl is true, an existing key keeps its time to live and `expire` is ignored
+    async fn set(&mut self, key: String, value: Bytes, expire: Option<Duration>, keep_ttl: bool);
+    // the value of any type stored at the key
+    async fn get_value<'a>(&'a mut self, key: &str) -> Option<&'a Value>;
+    // the value to modify. The caller calls signal_modified once it actually modified it
+    async fn get_value_mut<'a>(&'a mut self, key: &str) -> Option<&'a mut Value>;
+    // store a value of any type without expiry, overwriting any existing key
+    async fn insert_value(&mut self, key: String, value: Value);
+    // return the removed value, or None if the key doesn't exist
+    async fn del(&mut self, key: &str) -> Option<Value>;
     async fn check_exist(&mut self, key: &str) -> bool;
-    async fn get_ttl(&mut self, key: &str) -> Option<Duration>;
+    // remove the key and return its entry (value and expiry), None if the key doesn't exist
+    async fn remove_entry(&mut self, key: &str) -> Option<Entry>;
+    // a copy of the entry of the key, None if the key doesn't exist
+    async fn clone_entry(&mut self, key: &str) -> Option<Entry>;
+    // insert an entry taken from remove_entry or clone_entry, overwriting any existing key
+    async fn insert_entry(&mut self, key: String, entry: Entry);
+    // the type name replied by TYPE, "none" if the key doesn't exist
+    async fn key_type(&mut self, key: &str) -> &'static str;
+    // return None if the key doesn't exist, Some(None) if the key exists but has no expiry
+    async fn get_ttl(&mut self, key: &str) -> Option<Option<Duration>>;
+    // like get_ttl, but return the absolute unix time at which the key will expire
+    async fn get_expire_at(&mut self, key: &str) -> Option<Option<SystemTime>>;
+    // set (or remove, with None) the expiry of an existing key. Return false if the key doesn't
+    // exist
+    async fn set_expire_at(&mut self, key: &str, expire_at: Option<SystemTime>) -> bool;
+    // the live keys matching a glob-style pattern
+    async fn keys(&mut self, pattern: &[u8]) -> Vec<String>;
+    // visit about `count` keys starting from `cursor` and return the cursor to continue from (0 once
+    // the iteration is complete) along with the visited keys. The keys may include expired ones.
+    // Every key present during the whole iteration is guaranteed to be returned at least once
+    async fn scan(&mut self, cursor: u64, count: usize) -> (u64, Vec<String>);
+    async fn random_key(&mut self) -> Option<String>;
+    // the number of keys, including the expired ones that haven't been deleted yet
+    async fn dbsize(&mut self) -> usize;
+    // the number of keys with an expiry
+    async fn expires_size(&mut self) -> usize;
+    // empty the database, return its previous content so that the caller decides where to free it
+    async fn flush(&mut self) -> Box<dyn StringDbManipulator>;
+    // check up to `count` random keys that have an expiry and delete the expired ones. Return the
+    // number of keys sampled and the number of keys deleted
+    async fn expire_sample(&mut self, count: usize) -> (usize, usize);
+    // start watching the key for WATCH and return its version, which changes every time the key is
+    // written, expires or is deleted, the key existing or not
+    async fn watch(&mut self, key: &str) -> u64;
+    // stop watching the key, once for every call to watch
+    async fn unwatch(&mut self, key: &str);
+    // the current version of a watched key, None if nobody watches it
+    async fn version(&mut self, key: &str) -> Option<u64>;
+    // let the watchers of the key know it was modified through get_value_mut
+    async fn signal_modified(&mut self, key: &str);
+    // take the watched keys away for SWAPDB, which gives them back to the database swapped in at
+    // the same index with restore_watched. The watched keys existing in either database are
+    // signaled
+    async fn take_watched(&mut self) -> WatchedKeys;
+    async fn restore_watched(&mut self, watched: WatchedKeys);
+    // a copy of every live entry, for SAVE
+    async fn entries(&mut self) -> Vec<(String, Entry)>;
+    // let the active expiration know that the hash at `key` has fields with an expiry, once they
+    // were set through get_value_mut
+    async fn track_field_expires(&mut self, key: &str);
 }
 
+// keys sampled by each round of the active expiration
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+// keep sampling while more than this percentage of the sampled keys were expired
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10;
+// the percentage of each 1/hz period the active expiration may spend
+const ACTIVE_EXPIRE_TIME_PERC: u32 = 25;
+
 impl Db {
-    pub fn new(string_db: Box<dyn StringDbManipulator>) -> Self {
+    pub fn new(string_dbs: Vec<Box<dyn StringDbManipulator>>) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(DbInner { string_db })),
+            inner: Arc::new(Mutex::new(DbInner {
+                string_dbs,
+                blocked: BlockedClients::default(),
+                pubsub: PubSub::default(),
+            })),
+            index: 0,
+            held: None,
+        }
+    }
+
+    pub async fn lock(&self) -> DbGuard<'_> {
+        let inner = match &self.held {
+            Some(held) => InnerGuard::Held(held.lock().await),
+            None => InnerGuard::Shared(self.inner.lock().await),
+        };
+        DbGuard {
+            inner,
+            index: self.index,
+        }
+    }
+
+    // Keep the databases locked until `release`, the commands of the connection don't wait for
+    // the lock in the meantime while the other clients do.
+    // The blocked clients are served on release, as if the commands ran at once.
+    pub async fn hold(&mut self) {
+        let mut inner = self.inner.clone().lock_owned().await;
+        inner.blocked.defer();
+        self.held = Some(Arc::new(Mutex::new(inner)));
+    }
+
+    pub async fn release(&mut self) {
+        if let Some(held) = self.held.take() {
+            held.lock().await.serve_deferred().await;
+        }
+    }
+
+    // whether the connection holds the lock, i.e. it runs a transaction
+    pub fn is_held(&self) -> bool {
+        self.held.is_some()
+    }
+
+    // Keys are only removed lazily when they are accessed, so keys that are never read again
+    // would stay in memory forever. Run a cycle of the active expiration `hz` times per second,
+    // each of them deleting expired keys until few of the sampled keys are expired or its time
+    // budget runs out.
+    pub async fn active_expire(self, hz: u32) {
+        let period = Duration::from_secs(1) / hz;
+        let time_limit = period * ACTIVE_EXPIRE_TIME_PERC / 100;
+        let mut interval = tokio::time::interval(period);
+        // the database the next cycle starts from, so that the databases after one that used up
+        // the time budget get their turn
+        let mut current_db = 0;
+        loop {
+            interval.tick().await;
+
+            let start = Instant::now();
+            let mut inner = self.inner.lock().await;
+            let total_expired = inner.expire_cycle(&mut current_db, time_limit).await;
+            if total_expired > 0 {
+                debug!(
+                    "active expiration deleted {total_expired} keys in {:?}",
+                    start.elapsed()
+                );
+            }
+        }
+    }
+}
+
+impl DbInner {
+    // A cycle of the active expiration, starting from the database at `current_db`, which is left
+    // at the database the next cycle starts from. Return the number of keys deleted
+    async fn expire_cycle(&mut self, current_db: &mut usize, time_limit: Duration) -> usize {
+        let start = Instant::now();
+        let mut total_expired = 0;
+        let dbnum = self.string_dbs.len();
+        'dbs: for _ in 0..dbnum {
+            let string_db = &mut self.string_dbs[*current_db];
+            *current_db = (*current_db + 1) % dbnum;
+            loop {
+                let (sampled, expired) = string_db.expire_sample(ACTIVE_EXPIRE_KEYS_PER_LOOP).await;
+                total_expired += expired;
+                if start.elapsed() > time_limit {
+                    break 'dbs;
+                }
+                if sampled == 0 || expired * 100 <= sampled * ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                    break;
+                }
+            }
+        }
+        total_expired
+    }
+}
+
+#[cfg(test)]
+mod db_test {
+    use super::*;
+
+    #[tokio::test]
+    async fn active_expire_should_resume_from_the_next_db() {
+        let db = Db::new(
+            (0..3)
+                .map(|_| Box::new(StringDb::new()) as Box<dyn StringDbManipulator>)
+                .collect(),
+        );
+        let mut inner = db.lock().await;
+        for string_db in inner.string_dbs.iter_mut() {
+            string_db
+                .set(
+                    "k".into(),
+                    "v".into(),
+                    Some(Duration::from_millis(1)),
+                    false,
+                )
+                .await;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        // without any time budget, a cycle stops after sampling its first database
+        let mut current_db = 0;
+        for expected in [1, 2, 0] {
+            assert_eq!(1, inner.expire_cycle(&mut current_db, Duration::ZERO).await);
+            assert_eq!(expected, current_db);
+        }
+        for string_db in inner.string_dbs.iter_mut() {
+            assert_eq!(0, string_db.dbsize().await);
+        }
+
+        // with enough time, every database is sampled
+        for string_db in inner.string_dbs.iter_mut() {
+            string_db
+                .set(
+                    "k".into(),
+                    "v".into(),
+                    Some(Duration::from_millis(1)),
+                    false,
+                )
+                .await;
         }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        current_db = 1;
+        let time_limit = Duration::from_secs(10);
+        assert_eq!(3, inner.expire_cycle(&mut current_db, time_limit).await);
+        assert_eq!(1, current_db);
     }
 }
 //
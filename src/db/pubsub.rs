@@ -0,0 +1,217 @@
+use crate::util::glob_match;
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+
+// a message published to a channel, along with the pattern it matched for a pattern subscription
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub pattern: Option<Bytes>,
+    pub channel: Bytes,
+    pub payload: Bytes,
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    sender: mpsc::UnboundedSender<Message>,
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+}
+
+impl Subscriber {
+    fn subscriptions(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
+
+// The channels and glob-style patterns the clients subscribed to. PUBLISH sends the message to the
+// subscribers while it holds the lock of the databases, each subscriber writes it to its connection
+// on its own. The messages aren't bounded, a subscriber that doesn't read them keeps them in memory.
+#[derive(Debug, Default)]
+pub struct PubSub {
+    subscribers: HashMap<u64, Subscriber>,
+    // the ids of the clients subscribed to each channel, and to each pattern
+    channels: HashMap<Bytes, HashSet<u64>>,
+    patterns: HashMap<Bytes, HashSet<u64>>,
+}
+
+impl PubSub {
+    // subscribe the client `id` to a channel, or to a pattern when `pattern` is set, the messages
+    // being sent to `sender`. Return the number of subscriptions of the client
+    pub fn subscribe(
+        &mut self,
+        id: u64,
+        sender: &mpsc::UnboundedSender<Message>,
+        name: Bytes,
+        pattern: bool,
+    ) -> usize {
+        let subscriber = self.subscribers.entry(id).or_insert_with(|| Subscriber {
+            sender: sender.clone(),
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+        });
+        let (names, subscribed) = if pattern {
+            (&mut subscriber.patterns, &mut self.patterns)
+        } else {
+            (&mut subscriber.channels, &mut self.channels)
+        };
+        if names.insert(name.clone()) {
+            subscribed.entry(name).or_default().insert(id);
+        }
+        subscriber.subscriptions()
+    }
+
+    // the opposite of subscribe, return the number of subscriptions left
+    pub fn unsubscribe(&mut self, id: u64, name: &Bytes, pattern: bool) -> usize {
+        let subscriber = match self.subscribers.get_mut(&id) {
+            Some(subscriber) => subscriber,
+            None => return 0,
+        };
+        let (names, subscribed) = if pattern {
+            (&mut subscriber.patterns, &mut self.patterns)
+        } else {
+            (&mut subscriber.channels, &mut self.channels)
+        };
+        if names.remove(name) {
+            if let Some(ids) = subscribed.get_mut(name) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    subscribed.remove(name);
+                }
+            }
+        }
+        let subscriptions = subscriber.subscriptions();
+        if subscriptions == 0 {
+            self.subscribers.remove(&id);
+        }
+        subscriptions
+    }
+
+    // the channels the client subscribed to, or its patterns when `pattern` is set
+    pub fn subscriptions(&self, id: u64, pattern: bool) -> Vec<Bytes> {
+        match self.subscribers.get(&id) {
+            Some(subscriber) if pattern => subscriber.patterns.iter().cloned().collect(),
+            Some(subscriber) => subscriber.channels.iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    // unsubscribe the client from all its channels and patterns, e.g. once it disconnects
+    pub fn unsubscribe_all(&mut self, id: u64) {
+        for pattern in [false, true] {
+            for name in self.subscriptions(id, pattern) {
+                self.unsubscribe(id, &name, pattern);
+            }
+        }
+    }
+
+    // send the message to the subscribers of the channel and of the patterns matching it. Return
+    // the number of clients that received it, a client being counted once per subscription
+    pub fn publish(&self, channel: &Bytes, payload: &Bytes) -> usize {
+        let mut receivers = 0;
+        let mut send = |ids: &HashSet<u64>, pattern: Option<&Bytes>| {
+            for id in ids {
+                let message = Message {
+                    pattern: pattern.cloned(),
+                    channel: channel.clone(),
+                    payload: payload.clone(),
+                };
+                // the client may have disconnected without unsubscribing yet
+                let _ = self.subscribers[id].sender.send(message);
+                receivers += 1;
+            }
+        };
+        if let Some(ids) = self.channels.get(channel) {
+            send(ids, None);
+        }
+        for (pattern, ids) in self.patterns.iter() {
+            if glob_match(pattern, channel, false) {
+                send(ids, Some(pattern));
+            }
+        }
+        receivers
+    }
+
+    // the channels with at least one subscriber, only the ones matching `pattern` if any. Pattern
+    // subscriptions don't count
+    pub fn active_channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        self.channels
+            .keys()
+            .filter(|channel| pattern.is_none_or(|pattern| glob_match(pattern, channel, false)))
+            .cloned()
+            .collect()
+    }
+
+    // the number of subscribers of the channel, pattern subscriptions excluded
+    pub fn numsub(&self, channel: &Bytes) -> usize {
+        self.channels.get(channel).map_or(0, |ids| ids.len())
+    }
+
+    // the number of patterns with at least one subscriber
+    pub fn numpat(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+#[cfg(test)]
+mod pubsub_test {
+    use super::*;
+
+    #[test]
+    fn publish_should_reach_channels_and_patterns() {
+        let mut pubsub = PubSub::default();
+        let (sender1, mut receiver1) = mpsc::unbounded_channel();
+        let (sender2, mut receiver2) = mpsc::unbounded_channel();
+        assert_eq!(1, pubsub.subscribe(1, &sender1, "news".into(), false));
+        assert_eq!(2, pubsub.subscribe(1, &sender1, "n*".into(), true));
+        // subscribing twice is a no-op
+        assert_eq!(2, pubsub.subscribe(1, &sender1, "news".into(), false));
+        assert_eq!(1, pubsub.subscribe(2, &sender2, "news".into(), false));
+
+        assert_eq!(3, pubsub.publish(&"news".into(), &"hello".into()));
+        assert_eq!(
+            Message {
+                pattern: None,
+                channel: "news".into(),
+                payload: "hello".into(),
+            },
+            receiver2.try_recv().unwrap()
+        );
+        let mut messages = [receiver1.try_recv().unwrap(), receiver1.try_recv().unwrap()];
+        messages.sort_by_key(|message| message.pattern.clone());
+        assert_eq!(None, messages[0].pattern);
+        assert_eq!(Some("n*".into()), messages[1].pattern);
+        assert!(receiver1.try_recv().is_err());
+
+        // only the pattern matches
+        assert_eq!(1, pubsub.publish(&"nope".into(), &"hello".into()));
+        assert_eq!(0, pubsub.publish(&"other".into(), &"hello".into()));
+    }
+
+    #[test]
+    fn unsubscribe_should_forget_the_subscriptions() {
+        let mut pubsub = PubSub::default();
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        pubsub.subscribe(1, &sender, "a".into(), false);
+        pubsub.subscribe(1, &sender, "b".into(), false);
+        pubsub.subscribe(1, &sender, "*".into(), true);
+        pubsub.subscribe(2, &sender, "a".into(), false);
+        assert_eq!(2, pubsub.numsub(&"a".into()));
+        assert_eq!(1, pubsub.numpat());
+        let mut channels = pubsub.active_channels(None);
+        channels.sort();
+        assert_eq!(vec![Bytes::from("a"), Bytes::from("b")], channels);
+        assert_eq!(vec![Bytes::from("b")], pubsub.active_channels(Some(b"b*")));
+
+        assert_eq!(2, pubsub.unsubscribe(1, &"a".into(), false));
+        // not subscribed
+        assert_eq!(2, pubsub.unsubscribe(1, &"a".into(), false));
+        assert_eq!(1, pubsub.numsub(&"a".into()));
+
+        pubsub.unsubscribe_all(1);
+        assert!(pubsub.subscriptions(1, false).is_empty());
+        assert_eq!(0, pubsub.numpat());
+        assert_eq!(vec![Bytes::from("a")], pubsub.active_channels(None));
+        assert_eq!(1, pubsub.publish(&"a".into(), &"hello".into()));
+    }
+}
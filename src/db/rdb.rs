@@ -0,0 +1,1106 @@
+use super::{
+    hash::Hash,
+    list::QuickList,
+    listpack::{self, Element, ListpackWriter},
+    stream::{Consumer, ConsumerGroup, PendingEntry, Stream, StreamId, STREAM_NODE_MAX_ENTRIES},
+    zset::ZSet,
+    DbInner, Entry, Value,
+};
+use crate::util::to_unix_millis;
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// The RDB file format of redis, a snapshot of every database that SAVE writes and the server
+// loads on startup. See https://rdb.fnordig.de/file_format.html and rdb.c of redis.
+//
+// The file is a header, then for every database a SELECTDB opcode followed by its keys, then an
+// EOF opcode and a checksum. Each key is an optional expiry opcode, the type of the value, the key
+// and the value.
+
+const RDB_VERSION: u32 = 12;
+
+const OPCODE_SLOT_INFO: u8 = 244;
+const OPCODE_FUNCTION2: u8 = 245;
+const OPCODE_MODULE_AUX: u8 = 247;
+const OPCODE_IDLE: u8 = 248;
+const OPCODE_FREQ: u8 = 249;
+const OPCODE_AUX: u8 = 250;
+const OPCODE_RESIZEDB: u8 = 251;
+const OPCODE_EXPIRETIME_MS: u8 = 252;
+const OPCODE_EXPIRETIME: u8 = 253;
+const OPCODE_SELECTDB: u8 = 254;
+const OPCODE_EOF: u8 = 255;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+const TYPE_HASH_METADATA: u8 = 24;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// the special encodings of a string, in place of its length
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
+
+// the containers of the nodes of a quicklist
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+const QUICKLIST_NODE_PACKED: u64 = 2;
+
+// the flags of the entries of a stream node
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+// A copy of the live entries of every database, taken while the databases are locked so that
+// they can be encoded without holding the lock.
+pub async fn snapshot(inner: &mut DbInner) -> Vec<Vec<(String, Entry)>> {
+    let mut dbs = Vec::with_capacity(inner.string_dbs.len());
+    for string_db in inner.string_dbs.iter_mut() {
+        dbs.push(string_db.entries().await);
+    }
+    dbs
+}
+
+// Load the keys of an RDB file into the databases, return how many were loaded. The keys already
+// expired are skipped, and nothing is loaded if the file is invalid.
+pub async fn load_rdb(inner: &mut DbInner, buf: &[u8]) -> Result<usize> {
+    let keys = decode_rdb(buf)?;
+    if let Some((index, _, _)) = keys
+        .iter()
+        .find(|(index, _, _)| *index >= inner.string_dbs.len())
+    {
+        bail!("the RDB file has a database {index}, out of the configured range");
+    }
+    let now = SystemTime::now();
+    let mut loaded = 0;
+    for (index, key, entry) in keys {
+        if entry.is_expired(now) {
+            continue;
+        }
+        inner.string_dbs[index].insert_entry(key, entry).await;
+        loaded += 1;
+    }
+    Ok(loaded)
+}
+
+pub fn encode_rdb(dbs: &[Vec<(String, Entry)>]) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.buf
+        .extend_from_slice(format!("REDIS{RDB_VERSION:04}").as_bytes());
+    let ctime = to_unix_millis(SystemTime::now()) / 1000;
+    for (name, value) in [
+        ("redis-ver", env!("CARGO_PKG_VERSION").to_string()),
+        ("redis-bits", "64".to_string()),
+        ("ctime", ctime.to_string()),
+    ] {
+        w.byte(OPCODE_AUX);
+        w.string(name.as_bytes());
+        w.string(value.as_bytes());
+    }
+
+    for (index, entries) in dbs.iter().enumerate() {
+        if entries.is_empty() {
+            continue;
+        }
+        w.byte(OPCODE_SELECTDB);
+        w.len(index as u64);
+        w.byte(OPCODE_RESIZEDB);
+        w.len(entries.len() as u64);
+        let expires = entries
+            .iter()
+            .filter(|(_, entry)| entry.expire_at.is_some())
+            .count();
+        w.len(expires as u64);
+        for (key, entry) in entries {
+            if let Some(expire_at) = entry.expire_at {
+                w.byte(OPCODE_EXPIRETIME_MS);
+                w.millis(to_unix_millis(expire_at));
+            }
+            w.value(key, &entry.value);
+        }
+    }
+
+    w.byte(OPCODE_EOF);
+    let checksum = crc64(&w.buf);
+    w.buf.extend_from_slice(&checksum.to_le_bytes());
+    w.buf
+}
+
+// the keys of an RDB file, along with the index of their database
+pub fn decode_rdb(buf: &[u8]) -> Result<Vec<(usize, String, Entry)>> {
+    let mut r = Reader { buf, pos: 0 };
+    if r.bytes(5)? != b"REDIS" {
+        bail!("not an RDB file");
+    }
+    let version = std::str::from_utf8(r.bytes(4)?)
+        .ok()
+        .and_then(|version| version.parse::<u32>().ok())
+        .filter(|version| (1..=RDB_VERSION).contains(version))
+        .ok_or_else(|| anyhow!("unsupported RDB version"))?;
+
+    let mut keys = vec![];
+    let mut index = 0;
+    let mut expire_at = None;
+    loop {
+        match r.byte()? {
+            OPCODE_EOF => break,
+            OPCODE_SELECTDB => index = r.len()? as usize,
+            OPCODE_RESIZEDB => {
+                r.len()?;
+                r.len()?;
+            }
+            OPCODE_EXPIRETIME_MS => expire_at = Some(from_unix_millis(r.millis()?)),
+            OPCODE_EXPIRETIME => {
+                let secs = u32::from_le_bytes(r.bytes(4)?.try_into()?);
+                expire_at = Some(UNIX_EPOCH + Duration::from_secs(secs as u64));
+            }
+            OPCODE_AUX => {
+                r.string()?;
+                r.string()?;
+            }
+            // the eviction policies aren't implemented
+            OPCODE_IDLE => {
+                r.len()?;
+            }
+            OPCODE_FREQ => {
+                r.byte()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    r.len()?;
+                }
+            }
+            // nor are the functions
+            OPCODE_FUNCTION2 => {
+                r.string()?;
+            }
+            OPCODE_MODULE_AUX => bail!("modules aren't supported"),
+            value_type => {
+                let key =
+                    String::from_utf8(r.string()?.into()).map_err(|_| anyhow!("invalid key"))?;
+                let value = r.value(value_type)?;
+                let entry = Entry {
+                    value,
+                    expire_at: expire_at.take(),
+                };
+                // redis doesn't store empty collections, streams aside
+                let empty = match &entry.value {
+                    Value::List(list) => list.is_empty(),
+                    Value::Hash(hash) => hash.is_empty(),
+                    Value::Set(set) => set.is_empty(),
+                    Value::ZSet(zset) => zset.is_empty(),
+                    Value::String(_) | Value::Stream(_) => false,
+                };
+                if !empty {
+                    keys.push((index, key, entry));
+                }
+            }
+        }
+    }
+
+    // a checksum of 0 means it wasn't computed
+    if version >= 5 {
+        let end = r.pos;
+        let checksum = u64::from_le_bytes(r.bytes(8)?.try_into()?);
+        if checksum != 0 && checksum != crc64(&buf[..end]) {
+            bail!("wrong RDB checksum");
+        }
+    }
+    Ok(keys)
+}
+
+fn from_unix_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn byte(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    // 6 bits, 14 bits, 32 bits or 64 bits, big endian
+    fn len(&mut self, len: u64) {
+        if len < 1 << 6 {
+            self.buf.push(len as u8);
+        } else if len < 1 << 14 {
+            self.buf
+                .extend_from_slice(&[0x40 | (len >> 8) as u8, len as u8]);
+        } else if len <= u32::MAX as u64 {
+            self.buf.push(0x80);
+            self.buf.extend_from_slice(&(len as u32).to_be_bytes());
+        } else {
+            self.buf.push(0x81);
+            self.buf.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+
+    // a string, stored as an integer when it's one that fits in 32 bits
+    fn string(&mut self, value: &[u8]) {
+        let int = (value.len() <= 11)
+            .then(|| listpack::string_to_int(value))
+            .flatten();
+        match int {
+            Some(int) if i8::try_from(int).is_ok() => {
+                self.buf.push(0xC0 | ENC_INT8);
+                self.buf.push(int as i8 as u8);
+            }
+            Some(int) if i16::try_from(int).is_ok() => {
+                self.buf.push(0xC0 | ENC_INT16);
+                self.buf.extend_from_slice(&(int as i16).to_le_bytes());
+            }
+            Some(int) if i32::try_from(int).is_ok() => {
+                self.buf.push(0xC0 | ENC_INT32);
+                self.buf.extend_from_slice(&(int as i32).to_le_bytes());
+            }
+            _ => {
+                self.len(value.len() as u64);
+                self.buf.extend_from_slice(value);
+            }
+        }
+    }
+
+    fn millis(&mut self, millis: i64) {
+        self.buf.extend_from_slice(&millis.to_le_bytes());
+    }
+
+    fn id(&mut self, id: StreamId) {
+        self.buf.extend_from_slice(&encode_id(id));
+    }
+
+    fn value(&mut self, key: &str, value: &Value) {
+        let value_type = match value {
+            Value::String(_) => TYPE_STRING,
+            Value::List(_) => TYPE_LIST,
+            Value::Set(_) => TYPE_SET,
+            Value::ZSet(_) => TYPE_ZSET_2,
+            Value::Hash(hash) if hash.has_expires() => TYPE_HASH_METADATA,
+            Value::Hash(_) => TYPE_HASH,
+            Value::Stream(_) => TYPE_STREAM_LISTPACKS_3,
+        };
+        self.byte(value_type);
+        self.string(key.as_bytes());
+        match value {
+            Value::String(value) => self.string(value),
+            Value::List(list) => {
+                self.len(list.len() as u64);
+                for value in list.iter() {
+                    self.string(value);
+                }
+            }
+            Value::Set(set) => {
+                self.len(set.len() as u64);
+                for member in set.iter() {
+                    self.string(&member);
+                }
+            }
+            Value::ZSet(zset) => {
+                self.len(zset.len() as u64);
+                for (member, score) in zset.range(0..zset.len(), false) {
+                    self.string(&member);
+                    self.buf.extend_from_slice(&score.to_le_bytes());
+                }
+            }
+            Value::Hash(hash) => self.hash(hash),
+            Value::Stream(stream) => self.stream(stream),
+        }
+    }
+
+    // With field expiries, the earliest one is stored first, then each field is preceded by its
+    // expiry relative to it plus 1, or 0 if it has none.
+    fn hash(&mut self, hash: &Hash) {
+        let millis = |field: &[u8]| {
+            hash.get_expire_at(field)
+                .flatten()
+                .map(|expire_at| to_unix_millis(expire_at).max(0) as u64)
+        };
+        let min_expire = hash.iter().filter_map(|(field, _)| millis(field)).min();
+        if let Some(min_expire) = min_expire {
+            self.millis(min_expire as i64);
+        }
+        self.len(hash.len() as u64);
+        for (field, value) in hash.iter() {
+            if let Some(min_expire) = min_expire {
+                self.len(millis(field).map_or(0, |millis| millis - min_expire + 1));
+            }
+            self.string(field);
+            self.string(value);
+        }
+    }
+
+    // The entries are stored in listpacks of up to STREAM_NODE_MAX_ENTRIES entries, keyed by the ID
+    // of their first entry, then come the metadata of the stream and its consumer groups.
+    fn stream(&mut self, stream: &Stream) {
+        let entries: Vec<_> = stream.entries().collect();
+        let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+        self.len(nodes.len() as u64);
+        for node in nodes {
+            let (master_id, _) = node[0];
+            self.string(&encode_id(*master_id));
+            self.string(&encode_stream_node(node));
+        }
+
+        self.len(stream.len() as u64);
+        let first_id = stream
+            .first_entry()
+            .map_or(StreamId::MIN, |(first_id, _)| first_id);
+        for id in [stream.last_id(), first_id, stream.max_deleted_id()] {
+            self.len(id.ms);
+            self.len(id.seq);
+        }
+        self.len(stream.entries_added());
+
+        self.len(stream.groups().count() as u64);
+        for (name, group) in stream.groups() {
+            self.string(name);
+            self.len(group.last_id.ms);
+            self.len(group.last_id.seq);
+            self.len(group.entries_read.unwrap_or(u64::MAX));
+            self.len(group.pending.len() as u64);
+            for (id, entry) in group.pending.iter() {
+                self.id(*id);
+                self.millis(entry.delivery_time as i64);
+                self.len(entry.delivery_count);
+            }
+            self.len(group.consumers.len() as u64);
+            for (name, consumer) in group.consumers.iter() {
+                self.string(name);
+                self.millis(consumer.seen_time as i64);
+                self.millis(consumer.active_time.map_or(-1, |time| time as i64));
+                self.len(consumer.pending.len() as u64);
+                for id in consumer.pending.iter() {
+                    self.id(*id);
+                }
+            }
+        }
+    }
+}
+
+// A node starts with a master entry: the number of entries, the number of deleted ones and the
+// fields of the first entry. Every entry is then its flags, its ID relative to the ID of the node,
+// its fields and values, with only the values when it has the fields of the master entry, and the
+// number of elements it took.
+fn encode_stream_node(node: &[(&StreamId, &Vec<Bytes>)]) -> Vec<u8> {
+    let (master_id, master) = node[0];
+    let master_fields: Vec<&Bytes> = master.iter().step_by(2).collect();
+    let mut lp = ListpackWriter::new();
+    lp.push_int(node.len() as i64);
+    lp.push_int(0);
+    lp.push_int(master_fields.len() as i64);
+    for field in master_fields.iter() {
+        lp.push(field);
+    }
+    lp.push_int(0);
+
+    for (id, fields) in node {
+        let same_fields = fields.iter().step_by(2).eq(master_fields.iter().copied());
+        let flags = if same_fields {
+            STREAM_ITEM_FLAG_SAMEFIELDS
+        } else {
+            0
+        };
+        lp.push_int(flags);
+        lp.push_int(id.ms.wrapping_sub(master_id.ms) as i64);
+        lp.push_int(id.seq.wrapping_sub(master_id.seq) as i64);
+        let pairs = fields.len() / 2;
+        if same_fields {
+            for value in fields.iter().skip(1).step_by(2) {
+                lp.push(value);
+            }
+            lp.push_int(pairs as i64 + 3);
+        } else {
+            lp.push_int(pairs as i64);
+            for value in fields.iter() {
+                lp.push(value);
+            }
+            lp.push_int(pairs as i64 * 2 + 4);
+        }
+    }
+    lp.finish()
+}
+
+// an ID as stored in the keys of the radix tree of a stream and in the pending entries
+fn encode_id(id: StreamId) -> [u8; 16] {
+    let mut buf = [0; 16];
+    buf[..8].copy_from_slice(&id.ms.to_be_bytes());
+    buf[8..].copy_from_slice(&id.seq.to_be_bytes());
+    buf
+}
+
+fn decode_id(buf: &[u8]) -> Result<StreamId> {
+    if buf.len() != 16 {
+        bail!("invalid stream ID");
+    }
+    Ok(StreamId::new(
+        u64::from_be_bytes(buf[..8].try_into()?),
+        u64::from_be_bytes(buf[8..].try_into()?),
+    ))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+enum Length {
+    Len(u64),
+    // a string stored with one of the ENC_* encodings
+    Encoded(u8),
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or_else(|| anyhow!("unexpected end of the RDB file"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<Length> {
+        let byte = self.byte()?;
+        Ok(match byte >> 6 {
+            0 => Length::Len((byte & 0x3F) as u64),
+            1 => Length::Len(((byte & 0x3F) as u64) << 8 | self.byte()? as u64),
+            2 if byte == 0x80 => Length::Len(u32::from_be_bytes(self.bytes(4)?.try_into()?) as u64),
+            2 if byte == 0x81 => Length::Len(u64::from_be_bytes(self.bytes(8)?.try_into()?)),
+            2 => bail!("invalid length encoding {byte:#x}"),
+            _ => Length::Encoded(byte & 0x3F),
+        })
+    }
+
+    fn len(&mut self) -> Result<u64> {
+        match self.length()? {
+            Length::Len(len) => Ok(len),
+            Length::Encoded(_) => bail!("unexpected string encoding"),
+        }
+    }
+
+    fn string(&mut self) -> Result<Bytes> {
+        match self.length()? {
+            Length::Len(len) => Ok(Bytes::copy_from_slice(self.bytes(len as usize)?)),
+            Length::Encoded(ENC_INT8) => Ok((self.byte()? as i8).to_string().into()),
+            Length::Encoded(ENC_INT16) => {
+                let int = i16::from_le_bytes(self.bytes(2)?.try_into()?);
+                Ok(int.to_string().into())
+            }
+            Length::Encoded(ENC_INT32) => {
+                let int = i32::from_le_bytes(self.bytes(4)?.try_into()?);
+                Ok(int.to_string().into())
+            }
+            Length::Encoded(ENC_LZF) => {
+                let compressed_len = self.len()? as usize;
+                let len = self.len()? as usize;
+                lzf_decompress(self.bytes(compressed_len)?, len).map(Bytes::from)
+            }
+            Length::Encoded(encoding) => bail!("invalid string encoding {encoding}"),
+        }
+    }
+
+    fn millis(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    fn binary_double(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    // a double stored as a string after its length, 253 to 255 meaning NaN and the infinities
+    fn string_double(&mut self) -> Result<f64> {
+        Ok(match self.byte()? {
+            253 => f64::NAN,
+            254 => f64::INFINITY,
+            255 => f64::NEG_INFINITY,
+            len => std::str::from_utf8(self.bytes(len as usize)?)?.parse()?,
+        })
+    }
+
+    fn listpack(&mut self) -> Result<Vec<Element>> {
+        listpack::decode(&self.string()?)
+    }
+
+    fn value(&mut self, value_type: u8) -> Result<Value> {
+        Ok(match value_type {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST => {
+                let mut list = QuickList::new();
+                for _ in 0..self.len()? {
+                    list.push_back(self.string()?);
+                }
+                Value::List(list)
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut list = QuickList::new();
+                for _ in 0..self.len()? {
+                    match self.len()? {
+                        QUICKLIST_NODE_PLAIN => list.push_back(self.string()?),
+                        QUICKLIST_NODE_PACKED => {
+                            for element in self.listpack()? {
+                                list.push_back(element.into_bytes());
+                            }
+                        }
+                        container => bail!("invalid quicklist container {container}"),
+                    }
+                }
+                Value::List(list)
+            }
+            TYPE_SET => {
+                let len = self.len()?;
+                Value::Set((0..len).map(|_| self.string()).collect::<Result<_>>()?)
+            }
+            TYPE_SET_INTSET => Value::Set(decode_intset(&self.string()?)?.into_iter().collect()),
+            TYPE_SET_LISTPACK => Value::Set(
+                self.listpack()?
+                    .into_iter()
+                    .map(Element::into_bytes)
+                    .collect(),
+            ),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = ZSet::new();
+                for _ in 0..self.len()? {
+                    let member = self.string()?;
+                    let score = match value_type {
+                        TYPE_ZSET => self.string_double()?,
+                        _ => self.binary_double()?,
+                    };
+                    if score.is_nan() {
+                        bail!("invalid sorted set score");
+                    }
+                    zset.insert(member, score);
+                }
+                Value::ZSet(zset)
+            }
+            TYPE_ZSET_LISTPACK => {
+                let mut zset = ZSet::new();
+                let elements = self.listpack()?;
+                for pair in elements.chunks(2) {
+                    let [member, score] = pair else {
+                        bail!("invalid sorted set listpack");
+                    };
+                    let score = match score {
+                        Element::Int(score) => *score as f64,
+                        Element::Str(score) => std::str::from_utf8(score)?.parse()?,
+                    };
+                    if f64::is_nan(score) {
+                        bail!("invalid sorted set score");
+                    }
+                    zset.insert(member.clone().into_bytes(), score);
+                }
+                Value::ZSet(zset)
+            }
+            TYPE_HASH | TYPE_HASH_METADATA => {
+                let min_expire = match value_type {
+                    TYPE_HASH_METADATA => Some(self.millis()?),
+                    _ => None,
+                };
+                let mut hash = Hash::new();
+                for _ in 0..self.len()? {
+                    let expire_at = match min_expire {
+                        Some(min_expire) => match self.len()? {
+                            0 => None,
+                            ttl => {
+                                let ttl = i64::try_from(ttl - 1).unwrap_or(i64::MAX);
+                                Some(from_unix_millis(min_expire.saturating_add(ttl)))
+                            }
+                        },
+                        None => None,
+                    };
+                    let field = self.string()?;
+                    hash.insert(field.clone(), self.string()?, false);
+                    hash.set_expire_at(&field, expire_at);
+                }
+                Value::Hash(hash)
+            }
+            TYPE_HASH_LISTPACK | TYPE_HASH_LISTPACK_EX => {
+                // the fields, values and absolute expiries in milliseconds, 0 meaning none
+                let tuple = match value_type {
+                    TYPE_HASH_LISTPACK_EX => {
+                        self.millis()?;
+                        3
+                    }
+                    _ => 2,
+                };
+                let mut hash = Hash::new();
+                let elements = self.listpack()?;
+                if elements.len() % tuple != 0 {
+                    bail!("invalid hash listpack");
+                }
+                for fields in elements.chunks(tuple) {
+                    let field = fields[0].clone().into_bytes();
+                    hash.insert(field.clone(), fields[1].clone().into_bytes(), false);
+                    if tuple == 3 {
+                        let expire_at = match fields[2].as_int()? {
+                            0 => None,
+                            millis => Some(from_unix_millis(millis)),
+                        };
+                        hash.set_expire_at(&field, expire_at);
+                    }
+                }
+                Value::Hash(hash)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                Value::Stream(self.stream(value_type)?)
+            }
+            _ => bail!("unsupported RDB value type {value_type}"),
+        })
+    }
+
+    fn stream(&mut self, value_type: u8) -> Result<Stream> {
+        let mut entries = BTreeMap::new();
+        for _ in 0..self.len()? {
+            let master_id = decode_id(&self.string()?)?;
+            decode_stream_node(master_id, self.listpack()?, &mut entries)?;
+        }
+
+        let len = self.len()?;
+        if len != entries.len() as u64 {
+            bail!("invalid stream length");
+        }
+        let last_id = StreamId::new(self.len()?, self.len()?);
+        let (max_deleted_id, entries_added) = if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            // the first ID, known from the entries
+            self.len()?;
+            self.len()?;
+            (StreamId::new(self.len()?, self.len()?), self.len()?)
+        } else {
+            (StreamId::MIN, len)
+        };
+
+        let mut groups = BTreeMap::new();
+        for _ in 0..self.len()? {
+            let name = self.string()?;
+            let group = self.consumer_group(value_type)?;
+            groups.insert(name, group);
+        }
+        Ok(Stream::restore(
+            entries,
+            last_id,
+            max_deleted_id,
+            entries_added,
+            groups,
+        ))
+    }
+
+    // The pending entries of the group come first, then the consumers along with the IDs of
+    // their pending entries, which give the pending entries of the group their consumer.
+    fn consumer_group(&mut self, value_type: u8) -> Result<ConsumerGroup> {
+        let last_id = StreamId::new(self.len()?, self.len()?);
+        let entries_read = match value_type {
+            TYPE_STREAM_LISTPACKS => None,
+            _ => Some(self.len()?).filter(|read| *read != u64::MAX),
+        };
+        let mut group = ConsumerGroup::new(last_id, entries_read);
+
+        for _ in 0..self.len()? {
+            let id = decode_id(self.bytes(16)?)?;
+            let entry = PendingEntry {
+                consumer: Bytes::new(),
+                delivery_time: self.millis()?.max(0) as u64,
+                delivery_count: self.len()?,
+            };
+            if group.pending.insert(id, entry).is_some() {
+                bail!("duplicated pending entry");
+            }
+        }
+
+        let mut owned = HashSet::new();
+        for _ in 0..self.len()? {
+            let name = self.string()?;
+            let seen_time = self.millis()?.max(0) as u64;
+            let active_time = match value_type {
+                TYPE_STREAM_LISTPACKS_3 => Some(self.millis()?).filter(|time| *time != -1),
+                // older versions didn't track it
+                _ => Some(seen_time as i64),
+            };
+            let mut consumer = Consumer {
+                seen_time,
+                active_time: active_time.map(|time| time.max(0) as u64),
+                ..Default::default()
+            };
+            for _ in 0..self.len()? {
+                let id = decode_id(self.bytes(16)?)?;
+                let entry = group
+                    .pending
+                    .get_mut(&id)
+                    .filter(|_| owned.insert(id))
+                    .ok_or_else(|| anyhow!("invalid pending entry of a consumer"))?;
+                entry.consumer = name.clone();
+                consumer.pending.insert(id);
+            }
+            group.consumers.insert(name, consumer);
+        }
+        if owned.len() != group.pending.len() {
+            bail!("pending entry without consumer");
+        }
+        Ok(group)
+    }
+}
+
+fn decode_stream_node(
+    master_id: StreamId,
+    elements: Vec<Element>,
+    entries: &mut BTreeMap<StreamId, Vec<Bytes>>,
+) -> Result<()> {
+    let mut elements = elements.into_iter();
+    let mut next = || {
+        elements
+            .next()
+            .ok_or_else(|| anyhow!("truncated stream node"))
+    };
+    let count = |next: &mut dyn FnMut() -> Result<Element>| -> Result<usize> {
+        usize::try_from(next()?.as_int()?).map_err(|_| anyhow!("invalid stream node"))
+    };
+
+    // the master entry: the number of valid and deleted entries, then the fields
+    count(&mut next)?;
+    count(&mut next)?;
+    let master_fields = (0..count(&mut next)?)
+        .map(|_| next().map(Element::into_bytes))
+        .collect::<Result<Vec<_>>>()?;
+    next()?;
+
+    loop {
+        let flags = match next() {
+            Ok(flags) => flags.as_int()?,
+            Err(_) => break,
+        };
+        let id = StreamId::new(
+            master_id.ms.wrapping_add(next()?.as_int()? as u64),
+            master_id.seq.wrapping_add(next()?.as_int()? as u64),
+        );
+        let mut fields = vec![];
+        if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            for field in master_fields.iter() {
+                fields.push(field.clone());
+                fields.push(next()?.into_bytes());
+            }
+        } else {
+            for _ in 0..count(&mut next)? * 2 {
+                fields.push(next()?.into_bytes());
+            }
+        }
+        // the number of elements of the entry
+        next()?;
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.insert(id, fields);
+        }
+    }
+    Ok(())
+}
+
+// an intset: the size of its integers, their number, then the integers, little endian
+fn decode_intset(buf: &[u8]) -> Result<Vec<Bytes>> {
+    let invalid = || anyhow!("invalid intset");
+    let header = buf.get(..8).ok_or_else(invalid)?;
+    let size = u32::from_le_bytes(header[..4].try_into()?) as usize;
+    let len = u32::from_le_bytes(header[4..].try_into()?) as usize;
+    if ![2, 4, 8].contains(&size) || buf.len() != 8 + size * len {
+        return Err(invalid());
+    }
+    Ok(buf[8..]
+        .chunks(size)
+        .map(|int| {
+            let int = match size {
+                2 => i16::from_le_bytes([int[0], int[1]]) as i64,
+                4 => i32::from_le_bytes([int[0], int[1], int[2], int[3]]) as i64,
+                _ => i64::from_le_bytes(int.try_into().expect("8 bytes")),
+            };
+            int.to_string().into()
+        })
+        .collect())
+}
+
+// Decompress a string compressed by redis with LZF. Each chunk starts with a control byte: below
+// 32 it's followed by that many literal bytes plus one, otherwise it's a back reference into the
+// decompressed bytes whose length is in the 3 high bits, extended by the next byte when they're
+// all set.
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>> {
+    let invalid = || anyhow!("invalid LZF string");
+    let mut output = Vec::with_capacity(len);
+    let mut pos = 0;
+    while pos < input.len() {
+        let ctrl = input[pos] as usize;
+        pos += 1;
+        if ctrl < 32 {
+            let literal = input.get(pos..pos + ctrl + 1).ok_or_else(invalid)?;
+            output.extend_from_slice(literal);
+            pos += ctrl + 1;
+            continue;
+        }
+        let mut ref_len = ctrl >> 5;
+        if ref_len == 7 {
+            ref_len += *input.get(pos).ok_or_else(invalid)? as usize;
+            pos += 1;
+        }
+        let offset = ((ctrl & 0x1F) << 8) + *input.get(pos).ok_or_else(invalid)? as usize + 1;
+        pos += 1;
+        let start = output.len().checked_sub(offset).ok_or_else(invalid)?;
+        // the reference may overlap the bytes it produces
+        for i in start..start + ref_len + 2 {
+            output.push(output[i]);
+        }
+    }
+    if output.len() != len {
+        return Err(invalid());
+    }
+    Ok(output)
+}
+
+// CRC-64/Jones, the checksum of redis, reflected
+const CRC64_POLY: u64 = 0x95AC9329AC4BC9B5;
+const CRC64_TABLE: [u64; 256] = crc64_table();
+
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC64_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc64(buf: &[u8]) -> u64 {
+    buf.iter().fold(0, |crc, byte| {
+        CRC64_TABLE[((crc ^ *byte as u64) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod rdb_test {
+    use super::*;
+    use crate::db::{StringDb, StringDbManipulator};
+
+    fn millis(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    // the content of a value in a canonical form, to compare the values
+    fn content(value: &Value) -> String {
+        match value {
+            Value::String(value) => format!("{value:?}"),
+            Value::List(list) => format!("{:?}", list.iter().collect::<Vec<_>>()),
+            Value::Set(set) => {
+                let mut members: Vec<_> = set.iter().collect();
+                members.sort();
+                format!("{members:?}")
+            }
+            Value::ZSet(zset) => format!("{:?}", zset.range(0..zset.len(), false)),
+            Value::Hash(hash) => {
+                let mut fields: Vec<_> = hash
+                    .iter()
+                    .map(|(field, value)| (field, value, hash.get_expire_at(field)))
+                    .collect();
+                fields.sort();
+                format!("{fields:?}")
+            }
+            Value::Stream(stream) => format!(
+                "{:?} {} {} {} {:?}",
+                stream.entries().collect::<Vec<_>>(),
+                stream.last_id(),
+                stream.max_deleted_id(),
+                stream.entries_added(),
+                stream.groups().collect::<Vec<_>>()
+            ),
+        }
+    }
+
+    fn stream() -> Stream {
+        let mut stream = Stream::new();
+        // the sequence numbers of the entries of a node go down as well
+        stream.add(StreamId::new(1, 5), vec!["a".into(), "1".into()]);
+        for i in 2..150 {
+            let fields = match i % 3 {
+                0 => vec!["a".into(), i.to_string().into()],
+                1 => vec!["a".into(), "x".into(), "b".into(), "-7".into()],
+                _ => vec!["c".into(), Bytes::from(vec![b'y'; i as usize * 10])],
+            };
+            stream.add(StreamId::new(i, 0), fields);
+        }
+        stream.remove(StreamId::new(7, 0));
+
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+        stream.create_group("g1".into(), ConsumerGroup::new(StreamId::MIN, Some(0)));
+        stream.read_group(b"g1", &alice, Some(3), false, 1000);
+        stream.read_group(b"g1", &bob, Some(2), false, 2000);
+        stream.read_group(b"g1", &bob, Some(2), true, 3000);
+        let group = stream.group_mut(b"g1").unwrap();
+        group.consumer(&"idle".into(), 4000);
+        group.ack(StreamId::new(2, 0));
+        stream.create_group("g2".into(), ConsumerGroup::new(StreamId::new(9, 0), None));
+        stream
+    }
+
+    fn entries() -> Vec<(String, Entry)> {
+        let mut hash = Hash::new();
+        for i in 0..10 {
+            hash.insert(format!("f{i}").into(), i.to_string().into(), false);
+        }
+        let mut hash_with_expires = hash.clone();
+        hash_with_expires.set_expire_at(b"f1", Some(millis(u64::MAX >> 20)));
+        // already expired
+        hash_with_expires.set_expire_at(b"f2", Some(millis(1000)));
+
+        let mut list = QuickList::new();
+        for value in ["a", "", "123", "-1"] {
+            list.push_back(value.into());
+        }
+        let mut zset = ZSet::new();
+        zset.insert("a".into(), 1.5);
+        zset.insert("b".into(), f64::INFINITY);
+        zset.insert("c".into(), f64::NEG_INFINITY);
+        zset.insert("d".into(), -0.1);
+
+        let values = [
+            ("string", Value::String("foo".into())),
+            ("int", Value::String("-2147483648".into())),
+            ("long", Value::String("2147483648".into())),
+            (
+                "binary",
+                Value::String((0..=255).collect::<Vec<u8>>().into()),
+            ),
+            ("list", Value::List(list)),
+            (
+                "intset",
+                Value::Set(["3", "-1", "70000"].map(Bytes::from).into_iter().collect()),
+            ),
+            (
+                "set",
+                Value::Set(["a", "1"].map(Bytes::from).into_iter().collect()),
+            ),
+            ("zset", Value::ZSet(zset)),
+            ("hash", Value::Hash(hash)),
+            ("hash_with_expires", Value::Hash(hash_with_expires)),
+            ("stream", Value::Stream(stream())),
+            ("empty_stream", Value::Stream(Stream::new())),
+        ];
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, value))| {
+                let expire_at = (i % 2 == 0).then(|| millis(u64::MAX >> 20));
+                (key.to_string(), Entry { value, expire_at })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rdb_should_round_trip() {
+        let dbs = vec![entries(), vec![], entries()[..3].to_vec()];
+        let keys = decode_rdb(&encode_rdb(&dbs)).unwrap();
+
+        let expected: Vec<_> = dbs
+            .iter()
+            .enumerate()
+            .flat_map(|(index, entries)| {
+                entries.iter().map(move |(key, entry)| {
+                    (index, key.clone(), entry.expire_at, content(&entry.value))
+                })
+            })
+            .collect();
+        let actual: Vec<_> = keys
+            .iter()
+            .map(|(index, key, entry)| {
+                (*index, key.clone(), entry.expire_at, content(&entry.value))
+            })
+            .collect();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rdb_of_redis_should_be_loaded() {
+        // an empty RDB file written by redis 7.2
+        let buf = include_bytes!("../../dump.rdb");
+        assert!(decode_rdb(buf).unwrap().is_empty());
+
+        let mut corrupted = buf.to_vec();
+        corrupted[20] ^= 1;
+        assert!(decode_rdb(&corrupted).is_err());
+        assert!(decode_rdb(&buf[..buf.len() - 1]).is_err());
+
+        // a checksum of 0 isn't checked
+        let mut unchecked = encode_rdb(&[entries()]);
+        let len = unchecked.len();
+        unchecked[len - 8..].fill(0);
+        assert_eq!(entries().len(), decode_rdb(&unchecked).unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn load_should_skip_expired_keys() {
+        let new_inner = |dbnum: usize| DbInner {
+            string_dbs: (0..dbnum)
+                .map(|_| Box::new(StringDb::new()) as Box<dyn StringDbManipulator>)
+                .collect(),
+            blocked: Default::default(),
+            pubsub: Default::default(),
+        };
+        let mut dbs = vec![vec![], entries()];
+        dbs[1][0].1.expire_at = Some(millis(1000));
+        let buf = encode_rdb(&dbs);
+
+        assert!(load_rdb(&mut new_inner(1), &buf).await.is_err());
+        let mut inner = new_inner(2);
+        assert_eq!(
+            entries().len() - 1,
+            load_rdb(&mut inner, &buf).await.unwrap()
+        );
+        let string_db = &mut inner.string_dbs[1];
+        assert!(!string_db.check_exist("string").await);
+        let hash = string_db.get_value("hash_with_expires").await.unwrap();
+        let hash = hash.as_hash().unwrap();
+        assert_eq!(
+            Some(Some(millis(u64::MAX >> 20))),
+            hash.get_expire_at(b"f1")
+        );
+        assert_eq!(None, hash.get_expire_at(b"f2"));
+
+        let snapshot = snapshot(&mut inner).await;
+        assert_eq!(
+            vec![0, entries().len() - 1],
+            snapshot.iter().map(Vec::len).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lzf_should_decompress() {
+        // 3 literals, a reference to them, then an overlapping reference to the last one
+        let input = [2, b'a', b'b', b'c', 0x20, 2, 0x40, 0];
+        assert_eq!(b"abcabccccc".to_vec(), lzf_decompress(&input, 10).unwrap());
+        assert!(lzf_decompress(&input, 11).is_err());
+        assert!(lzf_decompress(&[0x20, 0], 3).is_err());
+    }
+}
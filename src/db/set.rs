@@ -0,0 +1,263 @@
+use super::{dict::Dict, StringDbManipulator, Value};
+use anyhow::Result;
+use bytes::Bytes;
+use rand::{seq::IteratorRandom, Rng};
+use std::collections::HashSet;
+
+// the maximum number of members of a set stored as an intset, like set-max-intset-entries
+const SET_MAX_INTSET_ENTRIES: usize = 512;
+
+// A set value. Small sets of integers are stored as a sorted vector of integers (an intset, as
+// redis calls it), which is compact and is searched by bisection. A set is converted to a
+// hashtable once it grows too big or a member that isn't an integer is added, and is never
+// converted back.
+#[derive(Debug, Clone)]
+pub enum Set {
+    IntSet(Vec<i64>),
+    HashTable(Dict<Bytes, ()>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(Vec::new())
+    }
+}
+
+// the integer a member represents, only if the member is written the canonical way so that it
+// can be turned back into the same bytes
+fn as_int(member: &[u8]) -> Option<i64> {
+    let value = std::str::from_utf8(member).ok()?.parse::<i64>().ok()?;
+    (value.to_string().as_bytes() == member).then_some(value)
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(ints) => ints.len(),
+            Set::HashTable(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_intset(&self) -> bool {
+        matches!(self, Set::IntSet(_))
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => as_int(member).is_some_and(|int| ints.binary_search(&int).is_ok()),
+            Set::HashTable(members) => members.contains_key(member),
+        }
+    }
+
+    // return true if the member is new
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Set::IntSet(ints) = self {
+            if let Some(int) = as_int(&member) {
+                match ints.binary_search(&int) {
+                    Ok(_) => return false,
+                    Err(_) if ints.len() >= SET_MAX_INTSET_ENTRIES => {}
+                    Err(pos) => {
+                        ints.insert(pos, int);
+                        return true;
+                    }
+                }
+            }
+            self.convert();
+        }
+
+        match self {
+            Set::HashTable(members) => members.insert(member, ()).is_none(),
+            Set::IntSet(_) => unreachable!("the set was converted"),
+        }
+    }
+
+    // return true if the member existed
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => match as_int(member).map(|int| ints.binary_search(&int)) {
+                Some(Ok(pos)) => {
+                    ints.remove(pos);
+                    true
+                }
+                _ => false,
+            },
+            Set::HashTable(members) => members.remove(member).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match self {
+            Set::IntSet(ints) => Box::new(ints.iter().map(|int| Bytes::from(int.to_string()))),
+            Set::HashTable(members) => Box::new(members.iter().map(|(member, _)| member.clone())),
+        }
+    }
+
+    // visit about `count` members starting from `cursor`, like StringDbManipulator::scan. An
+    // intset is small enough to be returned at once
+    pub fn scan(&self, mut cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let members = match self {
+            Set::IntSet(_) => return (0, self.iter().collect()),
+            Set::HashTable(members) => members,
+        };
+        // COUNT comes from the client, it isn't trusted for the allocation
+        let mut res = Vec::with_capacity(count.min(members.len()));
+        loop {
+            cursor = members.scan(cursor, |member, _| res.push(member.clone()));
+            if cursor == 0 || res.len() >= count {
+                break;
+            }
+        }
+        (cursor, res)
+    }
+
+    pub fn random_member(&self) -> Option<Bytes> {
+        match self {
+            Set::IntSet(ints) if ints.is_empty() => None,
+            Set::IntSet(ints) => {
+                let int = ints[rand::thread_rng().gen_range(0..ints.len())];
+                Some(int.to_string().into())
+            }
+            Set::HashTable(members) => members.random_entry().map(|(member, _)| member.clone()),
+        }
+    }
+
+    // `count` random members, which may repeat unless `distinct` is set, in which case at most
+    // all the members are returned
+    pub fn random_members(&self, count: usize, distinct: bool) -> Vec<Bytes> {
+        if !distinct {
+            return (0..count).filter_map(|_| self.random_member()).collect();
+        }
+        if count >= self.len() {
+            return self.iter().collect();
+        }
+        // when most of the members are wanted, picking them one by one would mostly hit members
+        // that were already picked
+        if count * 3 > self.len() {
+            return self.iter().choose_multiple(&mut rand::thread_rng(), count);
+        }
+
+        let mut picked = HashSet::with_capacity(count.min(self.len()));
+        while picked.len() < count {
+            picked.insert(self.random_member().expect("the set isn't empty"));
+        }
+        picked.into_iter().collect()
+    }
+
+    // remove and return up to `count` random members
+    pub fn pop(&mut self, count: usize) -> Vec<Bytes> {
+        if count >= self.len() {
+            return std::mem::take(self).iter().collect();
+        }
+        let members = self.random_members(count, true);
+        for member in members.iter() {
+            self.remove(member);
+        }
+        members
+    }
+
+    fn convert(&mut self) {
+        if let Set::IntSet(ints) = self {
+            let mut members = Dict::new();
+            for int in ints.iter() {
+                members.insert(Bytes::from(int.to_string()), ());
+            }
+            *self = Set::HashTable(members);
+        }
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<T: IntoIterator<Item = Bytes>>(iter: T) -> Self {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+// the set stored at `key`, created empty if the key doesn't exist
+pub async fn set_or_create<'a>(
+    string_db: &'a mut Box<dyn StringDbManipulator>,
+    key: &str,
+) -> Result<&'a mut Set> {
+    if !string_db.check_exist(key).await {
+        string_db
+            .insert_value(key.to_string(), Value::Set(Set::new()))
+            .await;
+    }
+    string_db
+        .get_value_mut(key)
+        .await
+        .expect("the set should exist")
+        .as_set_mut()
+}
+
+#[cfg(test)]
+mod set_test {
+    use super::*;
+
+    #[test]
+    fn intset_should_only_hold_canonical_integers() {
+        assert_eq!(Some(-12), as_int(b"-12"));
+        assert_eq!(None, as_int(b"012"));
+        assert_eq!(None, as_int(b"+1"));
+        assert_eq!(None, as_int(b"-0"));
+        assert_eq!(None, as_int(b"9223372036854775808"));
+
+        let mut set = Set::new();
+        assert!(set.insert("3".into()));
+        assert!(set.insert("-1".into()));
+        assert!(!set.insert("3".into()));
+        assert!(set.is_intset());
+        assert!(set.contains(b"-1"));
+        assert!(!set.contains(b"03"));
+        assert_eq!(
+            vec![Bytes::from("-1"), Bytes::from("3")],
+            set.iter().collect::<Vec<_>>()
+        );
+
+        assert!(set.insert("a".into()));
+        assert!(!set.is_intset());
+        assert!(set.contains(b"3"));
+        assert!(set.remove(b"3"));
+        assert_eq!(2, set.len());
+    }
+
+    #[test]
+    fn big_intset_should_be_converted() {
+        let mut set: Set = (0..SET_MAX_INTSET_ENTRIES)
+            .map(|i| Bytes::from(i.to_string()))
+            .collect();
+        assert!(set.is_intset());
+        assert!(set.insert(SET_MAX_INTSET_ENTRIES.to_string().into()));
+        assert!(!set.is_intset());
+        assert_eq!(SET_MAX_INTSET_ENTRIES + 1, set.len());
+    }
+
+    #[test]
+    fn pop_and_random_members_should_work() {
+        let mut set: Set = (0..10).map(|i| Bytes::from(i.to_string())).collect();
+        for count in [1, 3, 5, 9, 10, 20] {
+            let members = set.random_members(count, true);
+            assert_eq!(count.min(10), members.len());
+            assert_eq!(members.len(), members.iter().collect::<HashSet<_>>().len());
+        }
+        assert_eq!(20, set.random_members(20, false).len());
+
+        let popped = set.pop(4);
+        assert_eq!(4, popped.len());
+        assert_eq!(6, set.len());
+        assert!(popped.iter().all(|member| !set.contains(member)));
+        assert_eq!(6, set.pop(100).len());
+        assert!(set.is_empty());
+    }
+}
@@ -0,0 +1,611 @@
+use super::{StringDbManipulator, Value};
+use anyhow::Result;
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::RangeInclusive,
+};
+
+// the number of entries of a node of the radix tree of redis, approximate trimming only removes
+// whole nodes
+pub(super) const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+// the ID of a stream entry, the milliseconds time it was added at and a sequence number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    // the smallest ID greater than this one
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    // the greatest ID smaller than this one
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+// how a stream is trimmed, by XTRIM or the trimming options of XADD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trim {
+    // keep at most this many entries
+    MaxLen(usize),
+    // remove the entries with a smaller ID
+    MinId(StreamId),
+}
+
+// an entry delivered to a consumer of a group and not acknowledged yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    // the unix time in milliseconds of the last delivery
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Consumer {
+    // the unix time in milliseconds of the last interaction of the consumer with its group
+    pub seen_time: u64,
+    // the unix time in milliseconds the consumer last read or claimed entries, None if it never did
+    pub active_time: Option<u64>,
+    // the IDs of the entries pending for the consumer, also found in the pending entries of the
+    // group
+    pub pending: BTreeSet<StreamId>,
+}
+
+// A consumer group, reading the entries of a stream after `last_id` and tracking the entries its
+// consumers didn't acknowledge yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsumerGroup {
+    // the ID of the last entry delivered to the group
+    pub last_id: StreamId,
+    // the number of entries the group read, None if it can't be known because of deleted entries
+    pub entries_read: Option<u64>,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    // the consumers, ordered by name like redis does
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            ..Default::default()
+        }
+    }
+
+    // the consumer named `name`, created if it doesn't exist, which was seen at `now`
+    pub fn consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.clone()).or_default();
+        consumer.seen_time = now;
+        consumer
+    }
+
+    // return the number of entries that were pending for the consumer, None if it doesn't exist
+    pub fn remove_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in consumer.pending.iter() {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    // return true if the entry was pending
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(entry) => {
+                if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+                    consumer.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Give the entry `id` to `consumer`, which must exist, as delivered at `delivery_time`. The
+    // entry is added to the pending entries if it isn't pending yet. Its delivery count is set to
+    // `delivery_count`, or incremented if None and `increment` is set.
+    pub fn claim(
+        &mut self,
+        id: StreamId,
+        consumer: &Bytes,
+        delivery_time: u64,
+        delivery_count: Option<u64>,
+        increment: bool,
+    ) {
+        let entry = self.pending.entry(id).or_insert_with(|| PendingEntry {
+            consumer: consumer.clone(),
+            delivery_time,
+            delivery_count: 0,
+        });
+        if entry.consumer != consumer {
+            if let Some(previous) = self.consumers.get_mut(&entry.consumer) {
+                previous.pending.remove(&id);
+            }
+            entry.consumer = consumer.clone();
+        }
+        entry.delivery_time = delivery_time;
+        match delivery_count {
+            Some(count) => entry.delivery_count = count,
+            None if increment => entry.delivery_count += 1,
+            None => {}
+        }
+        self.consumers
+            .get_mut(consumer)
+            .expect("the consumer should exist")
+            .pending
+            .insert(id);
+    }
+}
+
+// A stream value, mapping IDs to entries. Redis stores the entries in a radix tree of listpacks,
+// a BTreeMap gives the same ordered access to the IDs.
+//
+// Streams aren't deleted when they become empty, they keep their last ID so that the IDs of new
+// entries keep growing.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    // the fields and values of every entry, interleaved
+    entries: BTreeMap<StreamId, Vec<Bytes>>,
+    // the ID of the last entry ever added
+    last_id: StreamId,
+    // the greatest ID deleted by XDEL
+    max_deleted_id: StreamId,
+    // the number of entries ever added
+    entries_added: u64,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a stream loaded from an RDB file
+    pub fn restore(
+        entries: BTreeMap<StreamId, Vec<Bytes>>,
+        last_id: StreamId,
+        max_deleted_id: StreamId,
+        entries_added: u64,
+        groups: BTreeMap<Bytes, ConsumerGroup>,
+    ) -> Self {
+        Stream {
+            entries,
+            last_id,
+            max_deleted_id,
+            entries_added,
+            groups,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    // the number of listpacks redis would store the entries in
+    pub fn nodes(&self) -> usize {
+        self.len().div_ceil(STREAM_NODE_MAX_ENTRIES)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&StreamId, &Vec<Bytes>)> {
+        self.entries.iter()
+    }
+
+    pub fn entry(&self, id: StreamId) -> Option<&Vec<Bytes>> {
+        self.entries.get(&id)
+    }
+
+    pub fn first_entry(&self) -> Option<(StreamId, &Vec<Bytes>)> {
+        self.entries
+            .first_key_value()
+            .map(|(id, fields)| (*id, fields))
+    }
+
+    pub fn last_entry(&self) -> Option<(StreamId, &Vec<Bytes>)> {
+        self.entries
+            .last_key_value()
+            .map(|(id, fields)| (*id, fields))
+    }
+
+    // the ID of the last entry, which is smaller than the last ID once the last entry is deleted
+    pub fn last_entry_id(&self) -> Option<StreamId> {
+        self.entries.last_key_value().map(|(id, _)| *id)
+    }
+
+    // the ID generated for `*`, or for `<ms>-*` when `ms` is given. None if no valid ID is greater
+    // than the last one
+    pub fn next_id(&self, now_ms: u64, ms: Option<u64>) -> Option<StreamId> {
+        match ms {
+            Some(ms) if ms > self.last_id.ms => Some(StreamId::new(ms, 0)),
+            Some(ms) if ms == self.last_id.ms => {
+                Some(StreamId::new(ms, self.last_id.seq.checked_add(1)?))
+            }
+            Some(_) => None,
+            None if now_ms > self.last_id.ms => Some(StreamId::new(now_ms, 0)),
+            None => self.last_id.next(),
+        }
+    }
+
+    // append an entry, its ID must be greater than the last ID
+    pub fn add(&mut self, id: StreamId, fields: Vec<Bytes>) {
+        debug_assert!(id > self.last_id);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    // return true if the entry existed
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        if self.entries.remove(&id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    // up to `count` entries whose ID is in `range`, from the greatest ID if `rev` is set
+    pub fn range(
+        &self,
+        range: RangeInclusive<StreamId>,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Vec<(StreamId, Vec<Bytes>)> {
+        if range.is_empty() {
+            return vec![];
+        }
+        let entries = self.entries.range(range);
+        let count = count.unwrap_or(usize::MAX);
+        let clone = |(id, fields): (&StreamId, &Vec<Bytes>)| (*id, fields.clone());
+        match rev {
+            true => entries.rev().take(count).map(clone).collect(),
+            false => entries.take(count).map(clone).collect(),
+        }
+    }
+
+    // Remove the oldest entries according to `trim`, return how many were removed. An `approx`
+    // trimming only removes whole nodes of STREAM_NODE_MAX_ENTRIES entries, like redis, and at
+    // most `limit` entries, 0 meaning no limit.
+    pub fn trim(&mut self, trim: Trim, approx: bool, limit: Option<usize>) -> usize {
+        let mut removable = match trim {
+            Trim::MaxLen(maxlen) => self.len().saturating_sub(maxlen),
+            Trim::MinId(minid) => self.entries.range(..minid).count(),
+        };
+        if approx {
+            let limit = match limit {
+                Some(0) => usize::MAX,
+                Some(limit) => limit,
+                None => 100 * STREAM_NODE_MAX_ENTRIES,
+            };
+            removable = removable.min(limit);
+            removable -= removable % STREAM_NODE_MAX_ENTRIES;
+        }
+
+        for _ in 0..removable {
+            self.entries.pop_first();
+        }
+        removable
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Bytes, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    // return false if the group already exists
+    pub fn create_group(&mut self, name: Bytes, group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, group);
+        true
+    }
+
+    // return false if the group doesn't exist
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    // whether an entry at or after `start` was deleted by XDEL
+    fn has_tombstones(&self, start: StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && start <= self.max_deleted_id
+    }
+
+    // The number of entries added up to `id` included, like the entries read by a group whose last
+    // ID is `id`. None if it can't be known because entries were deleted before it.
+    pub fn entries_read_until(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if (self.entries.is_empty() && id <= self.last_id) || id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if id > self.last_id {
+            return None;
+        }
+        let first_id = self.first_entry().map(|(id, _)| id)?;
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            // there is no hole among the remaining entries
+            if id < first_id {
+                return Some(self.entries_added - self.len() as u64);
+            } else if id == first_id {
+                return Some(self.entries_added - self.len() as u64 + 1);
+            }
+        }
+        None
+    }
+
+    // the number of entries the group has still to read, None if it can't be known
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(entries_read) if !self.has_tombstones(group.last_id) => entries_read,
+            _ => self.entries_read_until(group.last_id)?,
+        };
+        Some(self.entries_added.saturating_sub(entries_read))
+    }
+
+    // Deliver to `consumer` up to `count` entries the group didn't read yet, and add them to the
+    // pending entries of the consumer unless `no_ack` is set. The group must exist, the consumer
+    // is created if it doesn't.
+    pub fn read_group(
+        &mut self,
+        name: &[u8],
+        consumer: &Bytes,
+        count: Option<usize>,
+        no_ack: bool,
+        now: u64,
+    ) -> Vec<(StreamId, Vec<Bytes>)> {
+        let group = self.groups.get_mut(name).expect("the group should exist");
+        group.consumer(consumer, now);
+        let after = group.last_id;
+        let entries = match after.next() {
+            Some(start) => self.range(start..=StreamId::MAX, count, false),
+            None => vec![],
+        };
+        for (id, _) in entries.iter() {
+            let entries_read = match self.groups[name].entries_read {
+                Some(entries_read) if !self.has_tombstones(*id) => Some(entries_read + 1),
+                _ => self.entries_read_until(*id),
+            };
+            let group = self.groups.get_mut(name).expect("the group should exist");
+            group.entries_read = entries_read;
+            group.last_id = *id;
+            if !no_ack {
+                group.claim(*id, consumer, now, Some(1), false);
+            }
+        }
+        if !entries.is_empty() {
+            let group = self.groups.get_mut(name).expect("the group should exist");
+            group.consumer(consumer, now).active_time = Some(now);
+        }
+        entries
+    }
+
+    // Deliver again to `consumer` up to `count` of its pending entries after `after`. The fields
+    // are None for the entries deleted since they were delivered. The group must exist, the
+    // consumer is created if it doesn't.
+    pub fn read_pending(
+        &mut self,
+        name: &[u8],
+        consumer: &Bytes,
+        after: StreamId,
+        count: Option<usize>,
+        now: u64,
+    ) -> Vec<(StreamId, Option<Vec<Bytes>>)> {
+        let group = self.groups.get_mut(name).expect("the group should exist");
+        let pending = &group.consumer(consumer, now).pending;
+        let ids: Vec<StreamId> = match after.next() {
+            Some(start) => pending
+                .range(start..)
+                .take(count.unwrap_or(usize::MAX))
+                .copied()
+                .collect(),
+            None => vec![],
+        };
+
+        let mut entries = Vec::with_capacity(ids.len());
+        for id in ids {
+            let fields = self.entries.get(&id).cloned();
+            if fields.is_some() {
+                let entry = group
+                    .pending
+                    .get_mut(&id)
+                    .expect("the entry should be pending");
+                entry.delivery_time = now;
+                entry.delivery_count += 1;
+            }
+            entries.push((id, fields));
+        }
+        entries
+    }
+}
+
+// the stream stored at `key`, created empty if the key doesn't exist
+pub async fn stream_or_create<'a>(
+    string_db: &'a mut Box<dyn StringDbManipulator>,
+    key: &str,
+) -> Result<&'a mut Stream> {
+    if !string_db.check_exist(key).await {
+        string_db
+            .insert_value(key.to_string(), Value::Stream(Stream::new()))
+            .await;
+    }
+    string_db
+        .get_value_mut(key)
+        .await
+        .expect("the stream should exist")
+        .as_stream_mut()
+}
+
+#[cfg(test)]
+mod stream_test {
+    use super::*;
+
+    fn stream_of(n: u64) -> Stream {
+        let mut stream = Stream::new();
+        for i in 1..=n {
+            stream.add(StreamId::new(i, 0), vec!["f".into(), i.to_string().into()]);
+        }
+        stream
+    }
+
+    #[test]
+    fn ids_should_keep_growing() {
+        let mut stream = Stream::new();
+        assert_eq!(Some(StreamId::new(5, 0)), stream.next_id(5, None));
+        stream.add(StreamId::new(5, 0), vec![]);
+        // the clock went backwards
+        assert_eq!(Some(StreamId::new(5, 1)), stream.next_id(3, None));
+        assert_eq!(Some(StreamId::new(5, 1)), stream.next_id(3, Some(5)));
+        assert_eq!(Some(StreamId::new(6, 0)), stream.next_id(3, Some(6)));
+        assert_eq!(None, stream.next_id(3, Some(4)));
+
+        stream.last_id = StreamId::new(7, u64::MAX);
+        assert_eq!(Some(StreamId::new(8, 0)), stream.next_id(3, None));
+        assert_eq!(None, stream.next_id(3, Some(7)));
+        stream.last_id = StreamId::MAX;
+        assert_eq!(None, stream.next_id(3, None));
+
+        assert_eq!(None, StreamId::MIN.prev());
+        assert_eq!(Some(StreamId::new(0, u64::MAX)), StreamId::new(1, 0).prev());
+    }
+
+    #[test]
+    fn range_should_work() {
+        let stream = stream_of(10);
+        let ids = |entries: Vec<(StreamId, Vec<Bytes>)>| -> Vec<u64> {
+            entries.into_iter().map(|(id, _)| id.ms).collect()
+        };
+        let range = StreamId::new(3, 0)..=StreamId::new(6, 0);
+        assert_eq!(
+            vec![3, 4, 5, 6],
+            ids(stream.range(range.clone(), None, false))
+        );
+        assert_eq!(vec![6, 5], ids(stream.range(range, Some(2), true)));
+        let range = StreamId::new(6, 0)..=StreamId::new(3, 0);
+        assert!(stream.range(range, None, false).is_empty());
+    }
+
+    #[test]
+    fn trim_should_work() {
+        let mut stream = stream_of(350);
+        // approximate trimming removes whole nodes only
+        assert_eq!(100, stream.trim(Trim::MaxLen(0), true, Some(150)));
+        assert_eq!(200, stream.trim(Trim::MaxLen(20), true, None));
+        assert_eq!(0, stream.trim(Trim::MaxLen(0), true, None));
+        assert_eq!(40, stream.trim(Trim::MaxLen(10), false, None));
+        assert_eq!(10, stream.len());
+
+        assert_eq!(
+            5,
+            stream.trim(Trim::MinId(StreamId::new(346, 0)), false, None)
+        );
+        assert_eq!(Some(&StreamId::new(346, 0)), stream.entries.keys().next());
+        assert_eq!(StreamId::new(350, 0), stream.last_id());
+    }
+
+    #[test]
+    fn groups_should_track_pending_entries() {
+        let mut stream = stream_of(5);
+        stream.create_group("g".into(), ConsumerGroup::new(StreamId::MIN, Some(0)));
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+
+        let read = stream.read_group(b"g", &alice, Some(2), false, 100);
+        assert_eq!(2, read.len());
+        assert_eq!(1, stream.read_group(b"g", &bob, Some(1), false, 100).len());
+        assert_eq!(Some(2), stream.lag(stream.group(b"g").unwrap()));
+
+        // the history of a consumer counts its deliveries
+        let pending = stream.read_pending(b"g", &alice, StreamId::MIN, None, 200);
+        assert_eq!(2, pending.len());
+        let group = stream.group_mut(b"g").unwrap();
+        assert_eq!(2, group.pending[&StreamId::new(1, 0)].delivery_count);
+        assert!(group.ack(StreamId::new(1, 0)));
+        assert!(!group.ack(StreamId::new(1, 0)));
+
+        group.claim(StreamId::new(2, 0), &bob, 300, None, true);
+        assert_eq!(bob, group.pending[&StreamId::new(2, 0)].consumer);
+        assert!(group.consumers[&alice].pending.is_empty());
+        assert_eq!(Some(2), group.remove_consumer(&bob));
+        assert!(group.pending.is_empty());
+
+        // the entries deleted since their delivery have no fields
+        stream.read_group(b"g", &alice, None, false, 400);
+        stream.remove(StreamId::new(5, 0));
+        let pending = stream.read_pending(b"g", &alice, StreamId::new(3, 0), None, 500);
+        assert_eq!(
+            vec![(StreamId::new(4, 0), true), (StreamId::new(5, 0), false)],
+            pending
+                .into_iter()
+                .map(|(id, fields)| (id, fields.is_some()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lag_should_be_unknown_after_deletions() {
+        let mut stream = stream_of(5);
+        stream.create_group("g".into(), ConsumerGroup::new(StreamId::MIN, None));
+        assert_eq!(Some(5), stream.lag(stream.group(b"g").unwrap()));
+
+        stream.remove(StreamId::new(3, 0));
+        assert_eq!(None, stream.lag(stream.group(b"g").unwrap()));
+        stream.read_group(b"g", &"c".into(), None, true, 0);
+        let group = stream.group(b"g").unwrap();
+        assert_eq!(Some(5), group.entries_read);
+        assert_eq!(Some(0), stream.lag(group));
+    }
+}
@@ -1,100 +1,571 @@
+use super::{dict::Dict, hash::Hash, list::QuickList, set::Set, stream::Stream, zset::ZSet};
+use crate::util::glob_match;
+use anyhow::{anyhow, Error, Result};
 use bytes::Bytes;
-use std::{collections::HashMap, time::Duration};
-use tokio::time::Instant;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+pub const WRONG_TYPE_ERR: &str =
+    "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+// The watched keys get a new version from this counter every time they're modified. It's shared by
+// all the databases so that the versions of a database swapped with another one can't collide.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(Debug)]
 pub struct StringDb {
-    entries: HashMap<String, Entry>,
+    entries: Dict<String, Entry>,
+    // the keys that have an expiry, sampled by the active expiration
+    expires: Dict<String, ()>,
+    // the keys of the hashes whose fields have an expiry, sampled by the active expiration too
+    hash_expires: Dict<String, ()>,
+    watched: WatchedKeys,
+}
+
+// The keys watched by WATCH, along with their version and the number of clients watching them. A
+// key keeps its version while it doesn't exist, so that a key set then deleted after it was
+// watched is still seen as modified.
+#[derive(Debug, Default)]
+pub struct WatchedKeys {
+    keys: HashMap<String, WatchedKey>,
+}
+
+#[derive(Debug)]
+struct WatchedKey {
+    version: u64,
+    watchers: usize,
 }
 
 impl StringDb {
     pub fn new() -> Self {
         Self {
-            entries: HashMap::new(),
+            entries: Dict::new(),
+            expires: Dict::new(),
+            hash_expires: Dict::new(),
+            watched: WatchedKeys::default(),
+        }
+    }
+
+    fn insert(&mut self, key: String, entry: Entry) {
+        self.watched.signal_modified(&key);
+        if entry.expire_at.is_some() {
+            self.expires.insert(key.clone(), ());
+        } else {
+            self.expires.remove(&key);
+        }
+        if matches!(&entry.value, Value::Hash(hash) if hash.has_expires()) {
+            self.hash_expires.insert(key.clone(), ());
+        } else {
+            self.hash_expires.remove(&key);
+        }
+        self.entries.insert(key, entry);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.watched.signal_modified(key);
+        if entry.expire_at.is_some() {
+            self.expires.remove(key);
+        }
+        self.hash_expires.remove(key);
+        Some(entry)
+    }
+
+    // remove the entry if it has expired, or if it's a hash whose fields have all expired. The
+    // expired fields of a hash are removed either way. Return true if the entry was removed
+    fn expire_if_needed(&mut self, key: &str) -> bool {
+        let now = SystemTime::now();
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let expired = entry.is_expired(now)
+            || match &mut entry.value {
+                Value::Hash(hash) if hash.has_expires() => {
+                    if hash.remove_expired(now) > 0 {
+                        self.watched.signal_modified(key);
+                    }
+                    hash.is_empty()
+                }
+                _ => false,
+            };
+        if expired {
+            self.remove(key);
+        }
+        expired
+    }
+
+    // signal the watched keys that exist, e.g. before the database is emptied
+    fn signal_existing_watched(&mut self) {
+        let now = SystemTime::now();
+        for (key, watched) in self.watched.keys.iter_mut() {
+            if self
+                .entries
+                .get(key)
+                .is_some_and(|entry| !entry.is_expired(now))
+            {
+                watched.version = next_version();
+            }
         }
     }
 }
 
-#[derive(Debug)]
+impl WatchedKeys {
+    fn signal_modified(&mut self, key: &str) {
+        if let Some(watched) = self.keys.get_mut(key) {
+            watched.version = next_version();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Entry {
-    value: Bytes,
-    // when expire_at is None, it means the entry never expire
-    expire_at: Option<Instant>,
+    pub(super) value: Value,
+    // when expire_at is None, it means the entry never expire. It's a wall-clock timestamp rather
+    // than an Instant so that it stays meaningful outside of this process
+    pub(super) expire_at: Option<SystemTime>,
+}
+
+impl Entry {
+    pub(super) fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(self.expire_at, Some(expire_at) if expire_at < now)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Bytes),
+    List(QuickList),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl Value {
+    // the type name replied by TYPE
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    // roughly the number of allocations needed to free the value
+    pub fn free_effort(&self) -> usize {
+        match self {
+            Value::String(_) => 1,
+            Value::List(list) => list.node_count(),
+            Value::Hash(hash) => hash.len(),
+            Value::Set(set) if set.is_intset() => 1,
+            Value::Set(set) => set.len(),
+            Value::ZSet(zset) => zset.len(),
+            Value::Stream(stream) => stream.len(),
+        }
+    }
+
+    pub fn as_string(&self) -> Result<&Bytes> {
+        match self {
+            Value::String(value) => Ok(value),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_string_mut(&mut self) -> Result<&mut Bytes> {
+        match self {
+            Value::String(value) => Ok(value),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&QuickList> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut QuickList> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_hash(&self) -> Result<&Hash> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut Hash> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&Set> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut Set> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_zset(&self) -> Result<&ZSet> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Result<&mut ZSet> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_stream(&self) -> Result<&Stream> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    pub fn as_stream_mut(&mut self) -> Result<&mut Stream> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(wrong_type()),
+        }
+    }
+}
+
+pub fn wrong_type() -> Error {
+    anyhow!(WRONG_TYPE_ERR)
+}
+
+// the string stored at `key`, created empty if the key doesn't exist
+pub async fn string_or_create<'a>(
+    string_db: &'a mut Box<dyn super::StringDbManipulator>,
+    key: &str,
+) -> Result<&'a mut Bytes> {
+    if !string_db.check_exist(key).await {
+        string_db
+            .insert_value(key.to_string(), Value::String(Bytes::new()))
+            .await;
+    }
+    string_db
+        .get_value_mut(key)
+        .await
+        .expect("the string should exist")
+        .as_string_mut()
 }
 
 #[async_trait::async_trait]
 impl super::StringDbManipulator for StringDb {
-    async fn get(&mut self, key: &str) -> Option<Bytes> {
-        if let Some(value) = self.entries.get(key) {
-            if let Some(expire_at) = value.expire_at {
-                if expire_at < Instant::now() {
-                    // if the entry is expired, remove it and return None
-                    self.entries.remove(key);
-                    return None;
-                }
-            }
-            return Some(value.value.clone());
+    async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
+        self.expire_if_needed(key);
+        // if the key is not found, return None
+        match self.entries.get(key) {
+            Some(entry) => Ok(Some(entry.value.as_string()?.clone())),
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&mut self, key: String, value: Bytes, expire: Option<Duration>, keep_ttl: bool) {
+        let now = SystemTime::now();
+        let expire_at = match self.entries.get(&key) {
+            // an expired entry is treated as missing, so there is no ttl to keep
+            Some(entry) if keep_ttl && !entry.is_expired(now) => entry.expire_at,
+            _ => expire.map(|e| now + e),
         };
+        self.insert(
+            key,
+            Entry {
+                value: Value::String(value),
+                expire_at,
+            },
+        );
+    }
+
+    async fn get_value<'a>(&'a mut self, key: &str) -> Option<&'a Value> {
+        self.expire_if_needed(key);
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    async fn get_value_mut<'a>(&'a mut self, key: &str) -> Option<&'a mut Value> {
+        self.expire_if_needed(key);
+        self.entries.get_mut(key).map(|entry| &mut entry.value)
+    }
+
+    async fn insert_value(&mut self, key: String, value: Value) {
+        self.insert(
+            key,
+            Entry {
+                value,
+                expire_at: None,
+            },
+        );
+    }
+
+    async fn del(&mut self, key: &str) -> Option<Value> {
+        let entry = self.remove(key)?;
+        // an expired entry is removed as well, but it doesn't count as deleted
+        if entry.is_expired(SystemTime::now()) {
+            return None;
+        }
+        Some(entry.value)
+    }
+
+    async fn remove_entry(&mut self, key: &str) -> Option<Entry> {
+        self.expire_if_needed(key);
+        self.remove(key)
+    }
+
+    async fn clone_entry(&mut self, key: &str) -> Option<Entry> {
+        self.expire_if_needed(key);
+        self.entries.get(key).cloned()
+    }
+
+    async fn insert_entry(&mut self, key: String, entry: Entry) {
+        self.insert(key, entry);
+    }
+
+    async fn key_type(&mut self, key: &str) -> &'static str {
+        match self.get_value(key).await {
+            Some(value) => value.type_name(),
+            None => "none",
+        }
+    }
+
+    async fn check_exist(&mut self, key: &str) -> bool {
+        self.expire_if_needed(key);
+        self.entries.contains_key(key)
+    }
+
+    async fn get_ttl(&mut self, key: &str) -> Option<Option<Duration>> {
+        let expire_at = self.get_expire_at(key).await?;
+        // an entry expiring right now has no time left rather than being already expired
+        Some(expire_at.map(|expire_at| {
+            expire_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        }))
+    }
+
+    async fn get_expire_at(&mut self, key: &str) -> Option<Option<SystemTime>> {
+        self.expire_if_needed(key);
         // if the key is not found, return None
+        self.entries.get(key).map(|entry| entry.expire_at)
+    }
+
+    async fn set_expire_at(&mut self, key: &str, expire_at: Option<SystemTime>) -> bool {
+        if !self.check_exist(key).await {
+            return false;
+        }
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.expire_at = expire_at;
+        }
+        self.watched.signal_modified(key);
+        if expire_at.is_some() {
+            self.expires.insert(key.to_string(), ());
+        } else {
+            self.expires.remove(key);
+        }
+        true
+    }
+
+    async fn keys(&mut self, pattern: &[u8]) -> Vec<String> {
+        let now = SystemTime::now();
+        let all_keys = pattern == b"*";
+        self.entries
+            .iter()
+            .filter(|(key, entry)| {
+                !entry.is_expired(now) && (all_keys || glob_match(pattern, key.as_bytes(), false))
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    async fn scan(&mut self, mut cursor: u64, count: usize) -> (u64, Vec<String>) {
+        // COUNT comes from the client, it isn't trusted for the allocation
+        let mut keys = Vec::with_capacity(count.min(self.entries.len()));
+        // don't spend too long on a sparse table
+        let mut max_iterations = count.saturating_mul(10);
+        loop {
+            cursor = self.entries.scan(cursor, |key, _| keys.push(key.clone()));
+            max_iterations -= 1;
+            if cursor == 0 || keys.len() >= count || max_iterations == 0 {
+                break;
+            }
+        }
+        (cursor, keys)
+    }
+
+    async fn random_key(&mut self) -> Option<String> {
+        // give up deleting expired keys after a while in case all the keys are expired
+        for _ in 0..100 {
+            let key = self.entries.random_entry()?.0.clone();
+            if !self.expire_if_needed(&key) {
+                return Some(key);
+            }
+        }
         None
     }
 
-    async fn set(&mut self, key: String, value: Bytes, expire: Option<Duration>) {
-        if let Some(entry) = self.entries.get_mut(&key) {
-            entry.value = value.clone();
-            match expire {
-                Some(e) if e.as_secs() == 0 => {
-                    entry.expire_at = None;
-                }
-                Some(e) => {
-                    entry.expire_at = Some(Instant::now() + e);
+    async fn dbsize(&mut self) -> usize {
+        self.entries.len()
+    }
+
+    async fn expires_size(&mut self) -> usize {
+        self.expires.len()
+    }
+
+    async fn flush(&mut self) -> Box<dyn super::StringDbManipulator> {
+        self.signal_existing_watched();
+        let watched = std::mem::take(&mut self.watched);
+        Box::new(std::mem::replace(
+            self,
+            StringDb {
+                watched,
+                ..StringDb::new()
+            },
+        ))
+    }
+
+    async fn expire_sample(&mut self, count: usize) -> (usize, usize) {
+        let now = SystemTime::now();
+        let mut sampled = 0;
+        let mut expired = 0;
+        while sampled < count {
+            let key = match self.expires.random_entry() {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            sampled += 1;
+            if self
+                .entries
+                .get(&key)
+                .is_some_and(|entry| entry.is_expired(now))
+            {
+                self.remove(&key);
+                expired += 1;
+            }
+        }
+
+        // then the hashes with expiring fields, a hash counting as expired when some of its fields
+        // were
+        let mut sampled_hashes = 0;
+        while sampled_hashes < count {
+            let key = match self.hash_expires.random_entry() {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            sampled_hashes += 1;
+            let hash = match self.entries.get_mut(&key) {
+                Some(Entry {
+                    value: Value::Hash(hash),
+                    ..
+                }) => hash,
+                _ => {
+                    self.hash_expires.remove(&key);
+                    continue;
                 }
-                None => {}
+            };
+            if hash.remove_expired(now) > 0 {
+                self.watched.signal_modified(&key);
+                expired += 1;
+            }
+            if hash.is_empty() {
+                self.remove(&key);
+            } else if !hash.has_expires() {
+                self.hash_expires.remove(&key);
             }
-        } else {
-            self.entries.insert(
-                key,
-                Entry {
-                    value,
-                    expire_at: expire.map(|e| Instant::now() + e),
-                },
-            );
         }
+        (sampled + sampled_hashes, expired)
     }
 
-    async fn del(&mut self, key: &str) {
-        self.entries.remove(key);
+    async fn watch(&mut self, key: &str) -> u64 {
+        // a key that expired before being watched doesn't count as modified
+        self.expire_if_needed(key);
+        let watched = self
+            .watched
+            .keys
+            .entry(key.to_string())
+            .or_insert_with(|| WatchedKey {
+                version: next_version(),
+                watchers: 0,
+            });
+        watched.watchers += 1;
+        watched.version
     }
 
-    async fn check_exist(&mut self, key: &str) -> bool {
-        if let Some(value) = self.entries.get(key) {
-            if let Some(expire_at) = value.expire_at {
-                if expire_at < Instant::now() {
-                    // if the entry is expired, remove it and return false
-                    self.entries.remove(key);
-                    return false;
-                }
+    async fn unwatch(&mut self, key: &str) {
+        if let Some(watched) = self.watched.keys.get_mut(key) {
+            watched.watchers -= 1;
+            if watched.watchers == 0 {
+                self.watched.keys.remove(key);
             }
-            return true;
         }
-        // if the key is not found, return false
-        false
     }
 
-    async fn get_ttl(&mut self, key: &str) -> Option<Duration> {
-        if let Some(value) = self.entries.get(key) {
-            if let Some(expire_at) = value.expire_at {
-                if expire_at < Instant::now() {
-                    // if the entry is expired, remove it and return None
-                    self.entries.remove(key);
-                    return None;
-                }
-                return Some(expire_at - Instant::now());
+    async fn version(&mut self, key: &str) -> Option<u64> {
+        self.expire_if_needed(key);
+        self.watched.keys.get(key).map(|watched| watched.version)
+    }
+
+    async fn signal_modified(&mut self, key: &str) {
+        self.watched.signal_modified(key);
+    }
+
+    async fn take_watched(&mut self) -> WatchedKeys {
+        self.signal_existing_watched();
+        std::mem::take(&mut self.watched)
+    }
+
+    async fn restore_watched(&mut self, watched: WatchedKeys) {
+        self.watched = watched;
+        self.signal_existing_watched();
+    }
+
+    async fn entries(&mut self) -> Vec<(String, Entry)> {
+        let keys: Vec<String> = self.entries.iter().map(|(key, _)| key.clone()).collect();
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            if !self.expire_if_needed(&key) {
+                let entry = self.entries.get(&key).expect("the entry exists");
+                entries.push((key, entry.clone()));
+            }
+        }
+        entries
+    }
+
+    async fn track_field_expires(&mut self, key: &str) {
+        if let Some(Value::Hash(hash)) = self.entries.get(key).map(|entry| &entry.value) {
+            if hash.has_expires() {
+                self.hash_expires.insert(key.to_string(), ());
             }
-            return Some(Duration::from_secs(0));
         }
-        // if the key is not found, return None
-        None
     }
 }
 
@@ -111,41 +582,223 @@ mod string_db_test {
         //  2. expire_at should work.
 
         let mut db = StringDb::new();
-        assert_eq!(None, db.get("foo").await); // at first, without "foo" key
+        assert_eq!(None, db.get("foo").await.unwrap()); // at first, without "foo" key
 
-        db.set("foo".into(), "bar".into(), None).await; // set "foo" "bar"
-        assert_eq!(Some("bar".into()), db.get("foo").await);
+        db.set("foo".into(), "bar".into(), None, false).await; // set "foo" "bar"
+        assert_eq!(Some("bar".into()), db.get("foo").await.unwrap());
 
         // set with 1 seconds life time
-        db.set("foo".into(), "bar".into(), Some(Duration::from_secs(1)))
-            .await;
+        db.set(
+            "foo".into(),
+            "bar".into(),
+            Some(Duration::from_secs(1)),
+            false,
+        )
+        .await;
         sleep(Duration::from_secs(1)).await; // make it expire
-        assert_eq!(None, db.get("foo").await); // "foo" key has expired
+        assert_eq!(None, db.get("foo").await.unwrap()); // "foo" key has expired
     }
 
     #[tokio::test]
     async fn del_should_work() {
         let mut db = StringDb::new();
-        db.set("foo".into(), "bar".into(), None).await;
-        assert_eq!(Some("bar".into()), db.get("foo").await);
-        db.del("foo").await;
-        assert_eq!(None, db.get("foo").await);
+        db.set("foo".into(), "bar".into(), None, false).await;
+        assert_eq!(Some("bar".into()), db.get("foo").await.unwrap());
+        assert!(matches!(db.del("foo").await, Some(Value::String(v)) if v == "bar"));
+        assert_eq!(None, db.get("foo").await.unwrap());
+        assert!(db.del("foo").await.is_none());
     }
 
     #[tokio::test]
     async fn check_exist_should_work() {
         let mut db = StringDb::new();
-        db.set("foo".into(), "bar".into(), None).await;
+        db.set("foo".into(), "bar".into(), None, false).await;
         assert!(db.check_exist("foo").await);
     }
 
     #[tokio::test]
     async fn get_ttl_should_work() {
         let mut db = StringDb::new();
-        db.set("foo".into(), "bar".into(), Some(Duration::from_secs(1)))
-            .await;
-        let ttl = db.get_ttl("foo").await;
+        db.set(
+            "foo".into(),
+            "bar".into(),
+            Some(Duration::from_secs(1)),
+            false,
+        )
+        .await;
+        let ttl = db.get_ttl("foo").await.flatten();
         assert!(ttl.is_some());
         assert!(Duration::from_secs(1) - ttl.unwrap() < Duration::from_millis(100));
+
+        db.set("bar".into(), "foo".into(), None, false).await;
+        assert_eq!(Some(None), db.get_ttl("bar").await); // no expiry
+        assert_eq!(None, db.get_ttl("baz").await); // missing
+    }
+
+    #[tokio::test]
+    async fn set_expire_at_should_work() {
+        let mut db = StringDb::new();
+        assert!(!db.set_expire_at("foo", None).await); // missing key
+
+        db.set("foo".into(), "bar".into(), None, false).await;
+        let expire_at = SystemTime::now() + Duration::from_secs(10);
+        assert!(db.set_expire_at("foo", Some(expire_at)).await);
+        assert_eq!(Some(Some(expire_at)), db.get_expire_at("foo").await);
+
+        // keep_ttl preserves the expiry, a plain set clears it
+        db.set("foo".into(), "baz".into(), None, true).await;
+        assert_eq!(Some(Some(expire_at)), db.get_expire_at("foo").await);
+        db.set("foo".into(), "baz".into(), None, false).await;
+        assert_eq!(Some(None), db.get_expire_at("foo").await);
+
+        // an expiry in the past makes the key disappear
+        db.set_expire_at("foo", Some(SystemTime::now() - Duration::from_secs(1)))
+            .await;
+        assert_eq!(None, db.get("foo").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn scan_should_accept_a_huge_count() {
+        let mut db = StringDb::new();
+        db.set("foo".into(), "bar".into(), None, false).await;
+        let (cursor, keys) = db.scan(0, usize::MAX).await;
+        assert_eq!(0, cursor);
+        assert_eq!(vec!["foo".to_string()], keys);
+    }
+
+    #[tokio::test]
+    async fn version_should_change_on_write() {
+        let mut db = StringDb::new();
+        db.set("foo".into(), "bar".into(), None, false).await;
+        assert_eq!(None, db.version("foo").await); // not watched
+        let version = db.watch("foo").await;
+        assert_eq!(Some(version), db.version("foo").await);
+        // reads don't change the version
+        db.get("foo").await.unwrap();
+        db.get_value("foo").await;
+        assert_eq!(Some(version), db.version("foo").await);
+
+        db.get_value_mut("foo").await.unwrap();
+        db.signal_modified("foo").await;
+        let modified = db.version("foo").await;
+        assert_ne!(Some(version), modified);
+        db.set_expire_at("foo", Some(SystemTime::now() + Duration::from_secs(10)))
+            .await;
+        assert_ne!(modified, db.version("foo").await);
+
+        // nor does an expired key
+        let version = db.version("foo").await;
+        db.set_expire_at("foo", Some(SystemTime::now() - Duration::from_secs(1)))
+            .await;
+        assert_ne!(version, db.version("foo").await);
+
+        // the version is forgotten once nobody watches the key
+        db.watch("foo").await;
+        db.unwatch("foo").await;
+        assert!(db.version("foo").await.is_some());
+        db.unwatch("foo").await;
+        assert_eq!(None, db.version("foo").await);
+    }
+
+    #[tokio::test]
+    async fn version_should_change_when_a_missing_key_is_set_then_deleted() {
+        let mut db = StringDb::new();
+        let version = db.watch("foo").await;
+        db.set("foo".into(), "bar".into(), None, false).await;
+        db.del("foo").await;
+        assert_ne!(Some(version), db.version("foo").await);
+
+        // the same with a flush
+        let version = db.watch("bar").await;
+        db.set("bar".into(), "foo".into(), None, false).await;
+        let modified = db.version("bar").await;
+        assert_ne!(Some(version), modified);
+        db.flush().await;
+        assert_ne!(modified, db.version("bar").await);
+        // only the keys that existed are touched by a flush
+        let version = db.watch("baz").await;
+        db.flush().await;
+        assert_eq!(Some(version), db.version("baz").await);
+    }
+
+    #[tokio::test]
+    async fn keys_and_scan_should_work() {
+        let mut db = StringDb::new();
+        for key in ["foo", "foobar", "bar"] {
+            db.set(key.into(), "v".into(), None, false).await;
+        }
+        db.set("fooexpired".into(), "v".into(), Some(Duration::ZERO), false)
+            .await;
+        sleep(Duration::from_millis(1)).await;
+
+        let mut keys = db.keys(b"foo*").await;
+        keys.sort();
+        assert_eq!(vec!["foo", "foobar"], keys);
+        assert_eq!(3, db.keys(b"*").await.len());
+
+        let mut scanned = vec![];
+        let mut cursor = 0;
+        loop {
+            let (next, keys) = db.scan(cursor, 1).await;
+            scanned.extend(keys);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        for key in ["foo", "foobar", "bar"] {
+            assert!(scanned.iter().any(|k| k == key));
+        }
+    }
+
+    #[tokio::test]
+    async fn expire_sample_should_work() {
+        let mut db = StringDb::new();
+        db.set("foo".into(), "bar".into(), None, false).await;
+        assert_eq!((0, 0), db.expire_sample(20).await); // no keys with an expiry
+
+        for i in 0..10 {
+            db.set(
+                i.to_string(),
+                "bar".into(),
+                Some(Duration::from_millis(1)),
+                false,
+            )
+            .await;
+        }
+        sleep(Duration::from_millis(10)).await;
+        let mut expired = 0;
+        while expired < 10 {
+            expired += db.expire_sample(20).await.1;
+        }
+        assert!(db.expires.random_entry().is_none());
+        assert!(db.check_exist("foo").await);
+    }
+
+    #[tokio::test]
+    async fn hash_fields_should_expire() {
+        let mut db = StringDb::new();
+        let mut hash = Hash::new();
+        hash.insert("a".into(), "1".into(), false);
+        hash.insert("b".into(), "2".into(), false);
+        db.insert_value("h".into(), Value::Hash(hash)).await;
+
+        let soon = SystemTime::now() + Duration::from_millis(1);
+        let hash = db.get_value_mut("h").await.unwrap().as_hash_mut().unwrap();
+        hash.set_expire_at(b"a", Some(soon));
+        db.track_field_expires("h").await;
+        sleep(Duration::from_millis(10)).await;
+
+        // the active expiration removes the field, the key stays along with the other field
+        assert_eq!((1, 1), db.expire_sample(20).await);
+        assert!(db.hash_expires.is_empty());
+        let hash = db.get_value("h").await.unwrap().as_hash().unwrap();
+        assert_eq!(1, hash.len());
+
+        // a lazily expired last field removes the key
+        let hash = db.get_value_mut("h").await.unwrap().as_hash_mut().unwrap();
+        hash.set_expire_at(b"b", Some(SystemTime::now()));
+        sleep(Duration::from_millis(10)).await;
+        assert!(!db.check_exist("h").await);
     }
 }
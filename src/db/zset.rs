@@ -0,0 +1,495 @@
+use super::{dict::Dict, StringDbManipulator, Value};
+use anyhow::Result;
+use bytes::Bytes;
+use rand::Rng;
+use std::ops::Range;
+
+// the maximum level of a node, enough for 2^64 elements with P = 1/4
+const MAX_LEVEL: usize = 32;
+// the probability for a node to have one more level
+const P: f64 = 0.25;
+
+#[derive(Debug, Clone, Default)]
+struct Level {
+    // the index of the next node at this level, 0 (the head) means there is none
+    forward: usize,
+    // the number of nodes between this node and the next one at this level, which is what makes
+    // rank lookups O(log n)
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    // the index of the previous node, 0 for the first node
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+// whether (a_score, a_member) sorts before (b_score, b_member)
+fn less(a_score: f64, a_member: &[u8], b_score: f64, b_member: &[u8]) -> bool {
+    a_score < b_score || (a_score == b_score && a_member < b_member)
+}
+
+// The skiplist of redis, ordered by score then member. The nodes live in a vector and link to each
+// other by index, the head being the node 0. Freed slots are reused by the next insertions.
+//
+// Ranks are 1-based here, 0 being the rank of the head, and are turned into 0-based ranks by ZSet.
+#[derive(Debug, Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    // the number of levels in use
+    level: usize,
+    len: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: 0,
+            levels: vec![Level::default(); MAX_LEVEL],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: vec![],
+            level: 1,
+            len: 0,
+        }
+    }
+}
+
+impl SkipList {
+    fn random_level() -> usize {
+        let mut rng = rand::thread_rng();
+        let mut level = 1;
+        while level < MAX_LEVEL && rng.gen::<f64>() < P {
+            level += 1;
+        }
+        level
+    }
+
+    // the last node of every level that sorts before (score, member), and the rank of each
+    fn find_update(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [0; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            loop {
+                let next = self.nodes[x].levels[i].forward;
+                if next == 0
+                    || !less(
+                        self.nodes[next].score,
+                        &self.nodes[next].member,
+                        score,
+                        member,
+                    )
+                {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    // the element must not be in the list
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let (mut update, mut rank) = self.find_update(score, &member);
+        let level = Self::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = 0;
+                self.nodes[0].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: update[0],
+            levels: vec![Level::default(); level],
+        };
+        let x = match self.free.pop() {
+            Some(x) => {
+                self.nodes[x] = node;
+                x
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let prev = update[i];
+            self.nodes[x].levels[i] = Level {
+                forward: self.nodes[prev].levels[i].forward,
+                span: self.nodes[prev].levels[i].span - (rank[0] - rank[i]),
+            };
+            self.nodes[prev].levels[i] = Level {
+                forward: x,
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+
+        let next = self.nodes[x].levels[0].forward;
+        if next != 0 {
+            self.nodes[next].backward = x;
+        }
+        self.len += 1;
+    }
+
+    // return false if the element isn't in the list
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.find_update(score, member);
+        let x = self.nodes[update[0]].levels[0].forward;
+        if x == 0 || self.nodes[x].score != score || self.nodes[x].member != member {
+            return false;
+        }
+
+        for (i, prev) in update.into_iter().enumerate().take(self.level) {
+            if self.nodes[prev].levels[i].forward == x {
+                self.nodes[prev].levels[i].span += self.nodes[x].levels[i].span;
+                self.nodes[prev].levels[i].span -= 1;
+                self.nodes[prev].levels[i].forward = self.nodes[x].levels[i].forward;
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        let next = self.nodes[x].levels[0].forward;
+        if next != 0 {
+            self.nodes[next].backward = self.nodes[x].backward;
+        }
+        while self.level > 1 && self.nodes[0].levels[self.level - 1].forward == 0 {
+            self.level -= 1;
+        }
+
+        // drop the member now rather than when the slot is reused
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = vec![];
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    // the number of leading elements for which `before` holds, `before` being true for a prefix
+    // of the list
+    fn count_before(&self, before: impl Fn(f64, &[u8]) -> bool) -> usize {
+        let mut x = 0;
+        let mut rank = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.nodes[x].levels[i].forward;
+                if next == 0 || !before(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+
+    // the node at `rank`, which must be in 1..=len
+    fn by_rank(&self, rank: usize) -> usize {
+        let mut x = 0;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let level = &self.nodes[x].levels[i];
+                if level.forward == 0 || traversed + level.span > rank {
+                    break;
+                }
+                traversed += level.span;
+                x = level.forward;
+            }
+            if traversed == rank {
+                return x;
+            }
+        }
+        unreachable!("the rank is out of range")
+    }
+}
+
+// A bound of a score range, like `1`, `(1` or `-inf`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+// A bound of a lexicographical range, like `[a`, `(a`, `-` or `+`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+// A range of elements of a sorted set, by score or, for sets whose elements all have the same
+// score, by member
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRangeSpec {
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+impl ZRangeSpec {
+    fn below(&self, score: f64, member: &[u8]) -> bool {
+        match self {
+            ZRangeSpec::Score(min, _) => score < min.score || (min.exclusive && score == min.score),
+            ZRangeSpec::Lex(min, _) => match min {
+                LexBound::Min => false,
+                LexBound::Max => true,
+                LexBound::Inclusive(min) => member < min,
+                LexBound::Exclusive(min) => member <= min,
+            },
+        }
+    }
+
+    fn above(&self, score: f64, member: &[u8]) -> bool {
+        match self {
+            ZRangeSpec::Score(_, max) => score > max.score || (max.exclusive && score == max.score),
+            ZRangeSpec::Lex(_, max) => match max {
+                LexBound::Min => true,
+                LexBound::Max => false,
+                LexBound::Inclusive(max) => member > max,
+                LexBound::Exclusive(max) => member >= max,
+            },
+        }
+    }
+}
+
+// A sorted set value. Members are mapped to their score by a Dict, so that ZSCORE is O(1) and
+// ZSCAN can iterate with a cursor, and are ordered by a skiplist, so that rank and range lookups
+// are O(log n).
+//
+// Ranks are 0-based and ascending, the callers turn them around for the REV variants.
+#[derive(Debug, Clone, Default)]
+pub struct ZSet {
+    scores: Dict<Bytes, f64>,
+    list: SkipList,
+}
+
+impl ZSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    // add the member or update its score, return true if the member is new. The score must not be
+    // NaN
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(old) if old == score => false,
+            Some(old) => {
+                self.list.remove(old, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    // return true if the member existed
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.list.count_before(|s, m| less(s, m, score, member)))
+    }
+
+    // the ranks of the elements in `range`
+    pub fn ranks(&self, range: &ZRangeSpec) -> Range<usize> {
+        let start = self.list.count_before(|s, m| range.below(s, m));
+        let end = self.list.count_before(|s, m| !range.above(s, m));
+        start..end.max(start)
+    }
+
+    // the elements whose rank is in `ranks`, in ascending order or descending if `rev` is set
+    pub fn range(&self, ranks: Range<usize>, rev: bool) -> Vec<(Bytes, f64)> {
+        let ranks = ranks.start..ranks.end.min(self.len());
+        if ranks.is_empty() {
+            return vec![];
+        }
+        let mut x = match rev {
+            true => self.list.by_rank(ranks.end),
+            false => self.list.by_rank(ranks.start + 1),
+        };
+        let mut res = Vec::with_capacity(ranks.len());
+        for _ in ranks {
+            let node = &self.list.nodes[x];
+            res.push((node.member.clone(), node.score));
+            x = match rev {
+                true => node.backward,
+                false => node.levels[0].forward,
+            };
+        }
+        res
+    }
+
+    // remove and return up to `count` elements with the lowest scores, or the highest if `max` is
+    // set
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Bytes, f64)> {
+        let count = count.min(self.len());
+        let ranks = match max {
+            true => self.len() - count..self.len(),
+            false => 0..count,
+        };
+        let res = self.range(ranks, max);
+        for (member, _) in res.iter() {
+            self.remove(member);
+        }
+        res
+    }
+
+    // visit about `count` elements starting from `cursor`, like StringDbManipulator::scan
+    pub fn scan(&self, mut cursor: u64, count: usize) -> (u64, Vec<(Bytes, f64)>) {
+        // COUNT comes from the client, it isn't trusted for the allocation
+        let mut res = Vec::with_capacity(count.min(self.len()));
+        loop {
+            cursor = self
+                .scores
+                .scan(cursor, |member, score| res.push((member.clone(), *score)));
+            if cursor == 0 || res.len() >= count {
+                break;
+            }
+        }
+        (cursor, res)
+    }
+}
+
+// the sorted set stored at `key`, created empty if the key doesn't exist
+pub async fn zset_or_create<'a>(
+    string_db: &'a mut Box<dyn StringDbManipulator>,
+    key: &str,
+) -> Result<&'a mut ZSet> {
+    if !string_db.check_exist(key).await {
+        string_db
+            .insert_value(key.to_string(), Value::ZSet(ZSet::new()))
+            .await;
+    }
+    string_db
+        .get_value_mut(key)
+        .await
+        .expect("the sorted set should exist")
+        .as_zset_mut()
+}
+
+#[cfg(test)]
+mod zset_test {
+    use super::*;
+
+    fn members(elements: Vec<(Bytes, f64)>) -> Vec<Bytes> {
+        elements.into_iter().map(|(member, _)| member).collect()
+    }
+
+    #[test]
+    fn ranks_should_follow_scores() {
+        let mut zset = ZSet::new();
+        for i in 0..1000 {
+            // insert in a scrambled order
+            let i = (i * 7919) % 1000;
+            assert!(zset.insert(format!("m{i}").into(), i as f64));
+        }
+        assert_eq!(1000, zset.len());
+        for i in 0..1000 {
+            assert_eq!(Some(i), zset.rank(format!("m{i}").as_bytes()));
+        }
+
+        // moving a member updates the ranks of the ones it passes
+        assert!(!zset.insert("m0".into(), 500.5));
+        assert_eq!(Some(500), zset.rank(b"m0"));
+        assert_eq!(Some(0), zset.rank(b"m1"));
+        assert!(zset.remove(b"m1"));
+        assert!(!zset.remove(b"m1"));
+        assert_eq!(Some(0), zset.rank(b"m2"));
+        assert_eq!(999, zset.len());
+        assert_eq!(None, zset.rank(b"nope"));
+
+        let ranks: Vec<_> = zset
+            .range(0..999, false)
+            .iter()
+            .map(|(member, _)| zset.rank(member))
+            .collect();
+        assert_eq!((0..999).map(Some).collect::<Vec<_>>(), ranks);
+    }
+
+    #[test]
+    fn ranges_should_work() {
+        let mut zset = ZSet::new();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            zset.insert(member.into(), score);
+        }
+        let bound = |score, exclusive| ScoreBound { score, exclusive };
+
+        let range = ZRangeSpec::Score(bound(2.0, false), bound(f64::INFINITY, false));
+        assert_eq!(1..4, zset.ranks(&range));
+        let range = ZRangeSpec::Score(bound(2.0, true), bound(3.0, true));
+        assert!(zset.ranks(&range).is_empty());
+        let range = ZRangeSpec::Score(bound(3.0, false), bound(1.0, false));
+        assert!(zset.ranks(&range).is_empty());
+
+        assert_eq!(vec!["b", "c"], members(zset.range(1..3, false)));
+        assert_eq!(vec!["d", "c", "b"], members(zset.range(1..10, true)));
+        assert!(zset.range(4..6, false).is_empty());
+
+        let range = ZRangeSpec::Lex(
+            LexBound::Exclusive("a".into()),
+            LexBound::Inclusive("c".into()),
+        );
+        assert_eq!(1..3, zset.ranks(&range));
+        let range = ZRangeSpec::Lex(LexBound::Min, LexBound::Max);
+        assert_eq!(0..4, zset.ranks(&range));
+        let range = ZRangeSpec::Lex(LexBound::Max, LexBound::Max);
+        assert!(zset.ranks(&range).is_empty());
+    }
+
+    #[test]
+    fn pop_should_take_the_ends() {
+        let mut zset = ZSet::new();
+        for i in 0..10 {
+            zset.insert(i.to_string().into(), i as f64);
+        }
+        assert_eq!(vec!["0", "1"], members(zset.pop(2, false)));
+        assert_eq!(vec!["9", "8", "7"], members(zset.pop(3, true)));
+        assert_eq!(5, zset.len());
+        assert_eq!(5, zset.pop(10, false).len());
+        assert!(zset.is_empty());
+        // the freed nodes are reused
+        zset.insert("x".into(), 1.0);
+        assert_eq!(Some(0), zset.rank(b"x"));
+    }
+}
@@ -1,21 +1,25 @@
 use crate::{
-    cmd::{self, CmdExecutor, Section},
-    util::{bytes_to_string, bytes_to_u64},
+    cmd::{self, CmdExecutor, HashPart, Section, SetOp, ZSetOp},
+    db::Direction,
+    util::bytes_to_string,
 };
 use anyhow::{anyhow, bail, Error, Result};
 use bytes::Bytes;
 use std::time::Duration;
-use tracing::debug;
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub enum Frame {
     Simple(String), // +<str>\r\n
     Error(String),  // -<err>\r\n
-    Integer(u64),   // :<num>\r\n
+    Integer(i64),   // :<num>\r\n
     Bulk(Bytes),    // $<len>\r\n<bytes>\r\n
     #[default]
     Null, // $-1\r\n
     Array(Vec<Frame>), // *<len>\r\n<Frame>...
+    NullArray,      // *-1\r\n
+    // RESP3 only, see HELLO
+    Map(Vec<(Frame, Frame)>), // %<len>\r\n<key Frame><value Frame>...
+    Push(Vec<Frame>),         // ><len>\r\n<Frame>...
 }
 
 impl TryInto<Vec<Bytes>> for Frame {
@@ -43,23 +47,31 @@ impl From<Vec<Bytes>> for Frame {
 }
 
 impl Frame {
+    // the lowercase name of the command and its number of arguments, None if it isn't a command
+    pub fn cmd_name(&self) -> Option<(String, usize)> {
+        match self {
+            Frame::Array(frames) => match frames.first() {
+                Some(Frame::Bulk(name)) => Some((
+                    String::from_utf8_lossy(name).to_lowercase(),
+                    frames.len() - 1,
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn parse_cmd(self) -> Result<Box<dyn CmdExecutor>> {
         let bulks: Vec<Bytes> = self.try_into()?;
         let len = bulks.len();
         let cmd_name = bytes_to_string(bulks[0].clone())?;
         match cmd_name.to_lowercase().as_str() {
             "command" => return Ok(Box::new(cmd::Command)),
-            "ping" => {
-                if len == 1 {
-                    return Ok(Box::new(cmd::Ping));
-                }
-            }
-            "echo" => {
-                if len == 2 {
-                    return Ok(Box::new(cmd::Echo {
-                        msg: bulks[1].clone(),
-                    }));
-                }
+            "ping" if len == 1 => return Ok(Box::new(cmd::Ping)),
+            "echo" if len == 2 => {
+                return Ok(Box::new(cmd::Echo {
+                    msg: bulks[1].clone(),
+                }))
             }
             "get" => {
                 if len == 2 {
@@ -70,7 +82,170 @@ impl Frame {
                 bail!("ERR wrong number of arguments for 'get' command")
             }
             "set" => return Ok(Box::new(cmd::Set::try_from(bulks)?) as Box<dyn CmdExecutor>),
+            "mget" => return Ok(Box::new(cmd::MGet::try_from(bulks)?)),
+            "mset" => return Ok(Box::new(cmd::MSet::try_from(bulks)?)),
+            "msetnx" => return Ok(Box::new(cmd::MSetNx::try_from(bulks)?)),
+            "setnx" => return Ok(Box::new(cmd::SetNx::try_from(bulks)?)),
+            "setex" => return Ok(Box::new(cmd::SetEx::parse(bulks, false)?)),
+            "psetex" => return Ok(Box::new(cmd::SetEx::parse(bulks, true)?)),
+            "getset" => return Ok(Box::new(cmd::GetSet::try_from(bulks)?)),
+            "getdel" => return Ok(Box::new(cmd::GetDel::try_from(bulks)?)),
+            "getex" => return Ok(Box::new(cmd::GetEx::try_from(bulks)?)),
+            "del" => return Ok(Box::new(cmd::Del::parse(bulks, false)?)),
+            "unlink" => return Ok(Box::new(cmd::Del::parse(bulks, true)?)),
+            "exists" => return Ok(Box::new(cmd::Exists::try_from(bulks)?)),
+            "type" => return Ok(Box::new(cmd::Type::try_from(bulks)?)),
+            "touch" => return Ok(Box::new(cmd::Touch::try_from(bulks)?)),
+            "expire" => return Ok(Box::new(cmd::Expire::parse(bulks, false, false)?)),
+            "pexpire" => return Ok(Box::new(cmd::Expire::parse(bulks, true, false)?)),
+            "expireat" => return Ok(Box::new(cmd::Expire::parse(bulks, false, true)?)),
+            "pexpireat" => return Ok(Box::new(cmd::Expire::parse(bulks, true, true)?)),
+            "ttl" => return Ok(Box::new(cmd::Ttl::parse(bulks, false, false)?)),
+            "pttl" => return Ok(Box::new(cmd::Ttl::parse(bulks, true, false)?)),
+            "expiretime" => return Ok(Box::new(cmd::Ttl::parse(bulks, false, true)?)),
+            "pexpiretime" => return Ok(Box::new(cmd::Ttl::parse(bulks, true, true)?)),
+            "persist" => return Ok(Box::new(cmd::Persist::try_from(bulks)?)),
+            "keys" => return Ok(Box::new(cmd::Keys::try_from(bulks)?)),
+            "scan" => return Ok(Box::new(cmd::Scan::try_from(bulks)?)),
+            "randomkey" if len == 1 => return Ok(Box::new(cmd::RandomKey)),
+            "dbsize" if len == 1 => return Ok(Box::new(cmd::DbSize)),
+            "rename" => return Ok(Box::new(cmd::Rename::parse(bulks, false)?)),
+            "renamenx" => return Ok(Box::new(cmd::Rename::parse(bulks, true)?)),
+            "copy" => return Ok(Box::new(cmd::Copy::try_from(bulks)?)),
+            "move" => return Ok(Box::new(cmd::Move::try_from(bulks)?)),
+            "select" => return Ok(Box::new(cmd::Select::try_from(bulks)?)),
+            "swapdb" => return Ok(Box::new(cmd::SwapDb::try_from(bulks)?)),
+            "flushdb" => return Ok(Box::new(cmd::Flush::parse(bulks, false)?)),
+            "flushall" => return Ok(Box::new(cmd::Flush::parse(bulks, true)?)),
+            "lpush" => return Ok(Box::new(cmd::Push::parse(bulks, Direction::Left, false)?)),
+            "rpush" => return Ok(Box::new(cmd::Push::parse(bulks, Direction::Right, false)?)),
+            "lpushx" => return Ok(Box::new(cmd::Push::parse(bulks, Direction::Left, true)?)),
+            "rpushx" => return Ok(Box::new(cmd::Push::parse(bulks, Direction::Right, true)?)),
+            "lpop" => return Ok(Box::new(cmd::Pop::parse(bulks, Direction::Left)?)),
+            "rpop" => return Ok(Box::new(cmd::Pop::parse(bulks, Direction::Right)?)),
+            "lrange" => return Ok(Box::new(cmd::LRange::try_from(bulks)?)),
+            "llen" => return Ok(Box::new(cmd::LLen::try_from(bulks)?)),
+            "lindex" => return Ok(Box::new(cmd::LIndex::try_from(bulks)?)),
+            "lset" => return Ok(Box::new(cmd::LSet::try_from(bulks)?)),
+            "linsert" => return Ok(Box::new(cmd::LInsert::try_from(bulks)?)),
+            "lrem" => return Ok(Box::new(cmd::LRem::try_from(bulks)?)),
+            "ltrim" => return Ok(Box::new(cmd::LTrim::try_from(bulks)?)),
+            "lpos" => return Ok(Box::new(cmd::LPos::try_from(bulks)?)),
+            "lmove" => return Ok(Box::new(cmd::LMove::try_from(bulks)?)),
+            "blpop" => return Ok(Box::new(cmd::BPop::parse(bulks, Direction::Left)?)),
+            "brpop" => return Ok(Box::new(cmd::BPop::parse(bulks, Direction::Right)?)),
+            "lmpop" => return Ok(Box::new(cmd::LMPop::parse(bulks, false)?)),
+            "blmpop" => return Ok(Box::new(cmd::LMPop::parse(bulks, true)?)),
+            "blmove" => return Ok(Box::new(cmd::BLMove::try_from(bulks)?)),
+            "brpoplpush" => return Ok(Box::new(cmd::BLMove::parse_brpoplpush(bulks)?)),
+            "rpoplpush" => return Ok(Box::new(cmd::LMove::parse_rpoplpush(bulks)?)),
+            "hset" => return Ok(Box::new(cmd::HSet::parse(bulks, false)?)),
+            "hmset" => return Ok(Box::new(cmd::HSet::parse(bulks, true)?)),
+            "hsetnx" => return Ok(Box::new(cmd::HSetNx::try_from(bulks)?)),
+            "hget" => return Ok(Box::new(cmd::HGet::try_from(bulks)?)),
+            "hmget" => return Ok(Box::new(cmd::HMGet::try_from(bulks)?)),
+            "hdel" => return Ok(Box::new(cmd::HDel::try_from(bulks)?)),
+            "hlen" => return Ok(Box::new(cmd::HLen::try_from(bulks)?)),
+            "hexists" => return Ok(Box::new(cmd::HExists::parse(bulks, false)?)),
+            "hstrlen" => return Ok(Box::new(cmd::HExists::parse(bulks, true)?)),
+            "hkeys" => return Ok(Box::new(cmd::HGetAll::parse(bulks, HashPart::Fields)?)),
+            "hvals" => return Ok(Box::new(cmd::HGetAll::parse(bulks, HashPart::Values)?)),
+            "hgetall" => return Ok(Box::new(cmd::HGetAll::parse(bulks, HashPart::Both)?)),
+            "hincrby" => return Ok(Box::new(cmd::HIncrBy::try_from(bulks)?)),
+            "hincrbyfloat" => return Ok(Box::new(cmd::HIncrByFloat::try_from(bulks)?)),
+            "hscan" => return Ok(Box::new(cmd::HScan::try_from(bulks)?)),
+            "hrandfield" => return Ok(Box::new(cmd::HRandField::try_from(bulks)?)),
+            "hexpire" => return Ok(Box::new(cmd::HExpire::parse(bulks, false, false)?)),
+            "hpexpire" => return Ok(Box::new(cmd::HExpire::parse(bulks, true, false)?)),
+            "hexpireat" => return Ok(Box::new(cmd::HExpire::parse(bulks, false, true)?)),
+            "hpexpireat" => return Ok(Box::new(cmd::HExpire::parse(bulks, true, true)?)),
+            "httl" => return Ok(Box::new(cmd::HTtl::parse(bulks, false, false)?)),
+            "hpttl" => return Ok(Box::new(cmd::HTtl::parse(bulks, true, false)?)),
+            "hexpiretime" => return Ok(Box::new(cmd::HTtl::parse(bulks, false, true)?)),
+            "hpexpiretime" => return Ok(Box::new(cmd::HTtl::parse(bulks, true, true)?)),
+            "hpersist" => return Ok(Box::new(cmd::HPersist::try_from(bulks)?)),
+            "sadd" => return Ok(Box::new(cmd::SAdd::try_from(bulks)?)),
+            "srem" => return Ok(Box::new(cmd::SRem::try_from(bulks)?)),
+            "smembers" => return Ok(Box::new(cmd::SMembers::try_from(bulks)?)),
+            "sismember" => return Ok(Box::new(cmd::SIsMember::parse(bulks, false)?)),
+            "smismember" => return Ok(Box::new(cmd::SIsMember::parse(bulks, true)?)),
+            "scard" => return Ok(Box::new(cmd::SCard::try_from(bulks)?)),
+            "spop" => return Ok(Box::new(cmd::SPop::try_from(bulks)?)),
+            "srandmember" => return Ok(Box::new(cmd::SRandMember::try_from(bulks)?)),
+            "smove" => return Ok(Box::new(cmd::SMove::try_from(bulks)?)),
+            "sinter" => return Ok(Box::new(cmd::SCombine::parse(bulks, SetOp::Inter, false)?)),
+            "sunion" => return Ok(Box::new(cmd::SCombine::parse(bulks, SetOp::Union, false)?)),
+            "sdiff" => return Ok(Box::new(cmd::SCombine::parse(bulks, SetOp::Diff, false)?)),
+            "sinterstore" => return Ok(Box::new(cmd::SCombine::parse(bulks, SetOp::Inter, true)?)),
+            "sunionstore" => return Ok(Box::new(cmd::SCombine::parse(bulks, SetOp::Union, true)?)),
+            "sdiffstore" => return Ok(Box::new(cmd::SCombine::parse(bulks, SetOp::Diff, true)?)),
+            "sintercard" => return Ok(Box::new(cmd::SInterCard::try_from(bulks)?)),
+            "sscan" => return Ok(Box::new(cmd::SScan::try_from(bulks)?)),
+            "zadd" => return Ok(Box::new(cmd::ZAdd::try_from(bulks)?)),
+            "zincrby" => return Ok(Box::new(cmd::ZIncrBy::try_from(bulks)?)),
+            "zrem" => return Ok(Box::new(cmd::ZRem::try_from(bulks)?)),
+            "zscore" => return Ok(Box::new(cmd::ZScore::parse(bulks, false)?)),
+            "zmscore" => return Ok(Box::new(cmd::ZScore::parse(bulks, true)?)),
+            "zcard" => return Ok(Box::new(cmd::ZCard::try_from(bulks)?)),
+            "zcount" => return Ok(Box::new(cmd::ZCount::try_from(bulks)?)),
+            "zrank" => return Ok(Box::new(cmd::ZRank::parse(bulks, false)?)),
+            "zrevrank" => return Ok(Box::new(cmd::ZRank::parse(bulks, true)?)),
+            "zrange" => return Ok(Box::new(cmd::ZRange::parse(bulks, false)?)),
+            "zrangestore" => return Ok(Box::new(cmd::ZRange::parse(bulks, true)?)),
+            "zpopmin" => return Ok(Box::new(cmd::ZPop::parse(bulks, false)?)),
+            "zpopmax" => return Ok(Box::new(cmd::ZPop::parse(bulks, true)?)),
+            "zscan" => return Ok(Box::new(cmd::ZScan::try_from(bulks)?)),
+            "zunion" => return Ok(Box::new(cmd::ZCombine::parse(bulks, ZSetOp::Union, false)?)),
+            "zinter" => return Ok(Box::new(cmd::ZCombine::parse(bulks, ZSetOp::Inter, false)?)),
+            "zdiff" => return Ok(Box::new(cmd::ZCombine::parse(bulks, ZSetOp::Diff, false)?)),
+            "zunionstore" => {
+                return Ok(Box::new(cmd::ZCombine::parse(bulks, ZSetOp::Union, true)?))
+            }
+            "zinterstore" => {
+                return Ok(Box::new(cmd::ZCombine::parse(bulks, ZSetOp::Inter, true)?))
+            }
+            "zdiffstore" => return Ok(Box::new(cmd::ZCombine::parse(bulks, ZSetOp::Diff, true)?)),
+            "zintercard" => return Ok(Box::new(cmd::ZInterCard::try_from(bulks)?)),
+            "zmpop" => return Ok(Box::new(cmd::ZMPop::parse(bulks, false)?)),
+            "bzmpop" => return Ok(Box::new(cmd::ZMPop::parse(bulks, true)?)),
+            "bzpopmin" => return Ok(Box::new(cmd::BZPop::parse(bulks, false)?)),
+            "bzpopmax" => return Ok(Box::new(cmd::BZPop::parse(bulks, true)?)),
+            "xadd" => return Ok(Box::new(cmd::XAdd::try_from(bulks)?)),
+            "xtrim" => return Ok(Box::new(cmd::XTrim::try_from(bulks)?)),
+            "xdel" => return Ok(Box::new(cmd::XDel::try_from(bulks)?)),
+            "xlen" => return Ok(Box::new(cmd::XLen::try_from(bulks)?)),
+            "xrange" => return Ok(Box::new(cmd::XRange::parse(bulks, false)?)),
+            "xrevrange" => return Ok(Box::new(cmd::XRange::parse(bulks, true)?)),
+            "xread" => return Ok(Box::new(cmd::XRead::try_from(bulks)?)),
+            "xgroup" => return Ok(Box::new(cmd::XGroup::try_from(bulks)?)),
+            "xreadgroup" => return Ok(Box::new(cmd::XReadGroup::try_from(bulks)?)),
+            "xack" => return Ok(Box::new(cmd::XAck::try_from(bulks)?)),
+            "xpending" => return Ok(Box::new(cmd::XPending::try_from(bulks)?)),
+            "xclaim" => return Ok(Box::new(cmd::XClaim::try_from(bulks)?)),
+            "xautoclaim" => return Ok(Box::new(cmd::XAutoClaim::try_from(bulks)?)),
+            "xinfo" => return Ok(Box::new(cmd::XInfo::try_from(bulks)?)),
+            "setbit" => return Ok(Box::new(cmd::SetBit::try_from(bulks)?)),
+            "getbit" => return Ok(Box::new(cmd::GetBit::try_from(bulks)?)),
+            "bitcount" => return Ok(Box::new(cmd::BitCount::try_from(bulks)?)),
+            "bitpos" => return Ok(Box::new(cmd::BitPos::try_from(bulks)?)),
+            "bitop" => return Ok(Box::new(cmd::BitOpCmd::try_from(bulks)?)),
+            "bitfield" => return Ok(Box::new(cmd::BitFieldCmd::parse(bulks, false)?)),
+            "bitfield_ro" => return Ok(Box::new(cmd::BitFieldCmd::parse(bulks, true)?)),
+            "pfadd" => return Ok(Box::new(cmd::PfAdd::try_from(bulks)?)),
+            "pfcount" => return Ok(Box::new(cmd::PfCount::try_from(bulks)?)),
+            "pfmerge" => return Ok(Box::new(cmd::PfMerge::try_from(bulks)?)),
+            "geoadd" => return Ok(Box::new(cmd::GeoAdd::try_from(bulks)?)),
+            "geodist" => return Ok(Box::new(cmd::GeoDist::try_from(bulks)?)),
+            "geopos" => return Ok(Box::new(cmd::GeoPos::parse(bulks, false)?)),
+            "geohash" => return Ok(Box::new(cmd::GeoPos::parse(bulks, true)?)),
+            "geosearch" => return Ok(Box::new(cmd::GeoSearch::parse(bulks, false)?)),
+            "geosearchstore" => return Ok(Box::new(cmd::GeoSearch::parse(bulks, true)?)),
+            "unwatch" => return Ok(Box::new(cmd::Unwatch::try_from(bulks)?)),
+            "publish" => return Ok(Box::new(cmd::Publish::try_from(bulks)?)),
+            "pubsub" => return Ok(Box::new(cmd::PubSubCmd::try_from(bulks)?)),
             "info" => return Ok(Box::new(cmd::Info::try_from(bulks)?) as Box<dyn CmdExecutor>),
+            "save" => return Ok(Box::new(cmd::Save::parse(bulks, false)?)),
+            "bgsave" => return Ok(Box::new(cmd::Save::parse(bulks, true)?)),
             "replconf" => return Ok(Box::new(cmd::Replconf)),
             "psync" => return Ok(Box::new(cmd::Psync)),
             _ => {}
@@ -100,45 +275,26 @@ impl TryFrom<Vec<Bytes>> for cmd::Set {
                     keep_ttl: false,
                 });
             }
-            if len == 4 {
-                match bulks[4].to_ascii_lowercase().as_slice() {
-                    b"keepttl" => {
-                        return Ok(cmd::Set {
-                            key,
-                            value,
-                            expire: None,
-                            keep_ttl: true,
-                        });
-                    }
-                    _ => {}
-                }
+            if len == 4 && bulks[3].eq_ignore_ascii_case(b"keepttl") {
+                return Ok(cmd::Set {
+                    key,
+                    value,
+                    expire: None,
+                    keep_ttl: true,
+                });
             }
             if len == 5 {
                 let expire_unit = bulks[3].to_ascii_lowercase();
-                let expire = bytes_to_u64(bulks[4].clone())?;
-
-                if expire == 0 {
-                    bail!("ERR invalid expire time in 'set' command")
-                }
+                let in_millis = expire_unit == b"px";
+                let expire = cmd::parse_expire(bulks[4].clone(), in_millis, false, "set")?;
 
-                match expire_unit.as_slice() {
-                    b"ex" => {
-                        return Ok(cmd::Set {
-                            key,
-                            value,
-                            expire: Some(Duration::from_secs(expire)),
-                            keep_ttl: false,
-                        });
-                    }
-                    b"px" => {
-                        return Ok(cmd::Set {
-                            key,
-                            value,
-                            expire: Some(Duration::from_millis(expire)),
-                            keep_ttl: false,
-                        });
-                    }
-                    _ => {}
+                if let b"ex" | b"px" = expire_unit.as_slice() {
+                    return Ok(cmd::Set {
+                        key,
+                        value,
+                        expire: Some(Duration::from_millis(expire)),
+                        keep_ttl: false,
+                    });
                 }
             }
         }
@@ -1,12 +1,21 @@
 use std::net::SocketAddr;
 
-use crate::{db::*, frame::Frame, stream::FrameHandler, CONFIG};
-use anyhow::Result;
+use crate::{
+    cmd::{wrong_args, CmdExecutor, Connection, Exec, Transaction, Watch, Watched},
+    db::*,
+    frame::Frame,
+    stream::FrameHandler,
+    util::bytes_to_u64,
+    CONFIG,
+};
+use anyhow::{bail, Result};
+use bytes::Bytes;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
+    sync::mpsc,
 };
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 
 pub async fn run() {
     // client_test("*2\r\n$4\r\ninfo\r\n$11\r\nreplication\r\n").await;
@@ -19,17 +28,38 @@ pub async fn run() {
         .await
         .expect("Fail to connect");
 
-    let db = Db::new(Box::new(StringDb::new()));
+    let db = Db::new(
+        (0..CONFIG.databases)
+            .map(|_| Box::new(StringDb::new()) as Box<dyn StringDbManipulator>)
+            .collect(),
+    );
+    load_rdb_file(&db).await;
+    tokio::spawn(db.clone().active_expire(CONFIG.hz));
 
+    let mut next_client_id = 1;
     loop {
         match listener.accept().await {
             Ok((mut stream, addr)) => {
                 debug!("accepted new connection from {addr}");
 
                 let mut db = db.clone();
+                let mut client = Client::new(next_client_id);
+                next_client_id += 1;
                 tokio::spawn(async move {
                     loop {
-                        match handle(&mut stream, &mut db, addr).await {
+                        // the published messages are written in between the commands
+                        let mut buf = [0u8; 1];
+                        tokio::select! {
+                            Some(message) = client.receiver.recv() => {
+                                let frame = client.message_frame(message);
+                                if stream.write_frame(frame).await.is_err() {
+                                    break;
+                                }
+                                continue;
+                            }
+                            _ = stream.peek(&mut buf) => {}
+                        }
+                        match handle(&mut stream, &mut db, &mut client, addr).await {
                             Err(e) => {
                                 let _ = stream.write_frame(Frame::Error(e.to_string())).await;
                             }
@@ -37,6 +67,10 @@ pub async fn run() {
                             Ok(None) => break,
                         }
                     }
+                    client.watched.clear(&db).await;
+                    if client.subscriptions > 0 {
+                        db.lock().await.pubsub.unsubscribe_all(client.id);
+                    }
                 });
             }
             Err(e) => {
@@ -46,13 +80,338 @@ pub async fn run() {
     }
 }
 
-async fn handle(stream: &mut TcpStream, db: &mut Db, addr: SocketAddr) -> Result<Option<()>> {
+// load the keys saved by SAVE, unless there is no RDB file yet
+async fn load_rdb_file(db: &Db) {
+    let path = std::path::Path::new(&CONFIG.dir).join(&CONFIG.dbfilename);
+    let buf = match tokio::fs::read(&path).await {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => panic!("Fail to read {}: {e}", path.display()),
+    };
+    let mut inner = db.lock().await;
+    match load_rdb(&mut inner, &buf).await {
+        Ok(loaded) => info!("loaded {loaded} keys from {}", path.display()),
+        Err(e) => panic!("Fail to load {}: {e}", path.display()),
+    }
+}
+
+// The state of a connection, besides the database it selected.
+struct Client {
+    id: u64,
+    // the commands queued since MULTI
+    transaction: Option<Transaction>,
+    // the keys watched by WATCH
+    watched: Watched,
+    // the number of channels and patterns the client subscribed to. With RESP2, the client can
+    // only subscribe, unsubscribe and ping while it's subscribed
+    subscriptions: usize,
+    // the messages published to them
+    sender: mpsc::UnboundedSender<Message>,
+    receiver: mpsc::UnboundedReceiver<Message>,
+    // set by HELLO 3, the messages are push frames then
+    resp3: bool,
+}
+
+impl Client {
+    fn new(id: u64) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            id,
+            transaction: None,
+            watched: Watched::default(),
+            subscriptions: 0,
+            sender,
+            receiver,
+            resp3: false,
+        }
+    }
+
+    // an out of band reply, an array with RESP2 and a push frame with RESP3
+    fn push(&self, frames: Vec<Frame>) -> Frame {
+        if self.resp3 {
+            Frame::Push(frames)
+        } else {
+            Frame::Array(frames)
+        }
+    }
+
+    // *3\r\n$7\r\nmessage\r\n$<len>\r\n<channel>\r\n$<len>\r\n<message>\r\n, or
+    // *4\r\n$8\r\npmessage\r\n$<len>\r\n<pattern>\r\n... for a pattern subscription
+    fn message_frame(&self, message: Message) -> Frame {
+        let mut frames = match message.pattern {
+            Some(pattern) => vec![Frame::Bulk("pmessage".into()), Frame::Bulk(pattern)],
+            None => vec![Frame::Bulk("message".into())],
+        };
+        frames.push(Frame::Bulk(message.channel));
+        frames.push(Frame::Bulk(message.payload));
+        self.push(frames)
+    }
+
+    // https://redis.io/commands/subscribe/
+    // https://redis.io/commands/unsubscribe/
+    // https://redis.io/commands/psubscribe/
+    // https://redis.io/commands/punsubscribe/
+    // SUBSCRIBE channel [channel ...]
+    // UNSUBSCRIBE [channel [channel ...]], from all the channels without arguments
+    // PSUBSCRIBE pattern [pattern ...]
+    // PUNSUBSCRIBE [pattern [pattern ...]], from all the patterns without arguments
+    // return: *3\r\n$<len>\r\n<command>\r\n$<len>\r\n<channel>\r\n:<subscriptions>\r\n for
+    //   each channel, the channel being null when there is nothing to unsubscribe from
+    async fn subscribe(&mut self, db: &Db, name: &str, names: Vec<Bytes>) -> Vec<Frame> {
+        let pattern = name.starts_with('p');
+        let subscribe = !name.ends_with("unsubscribe");
+        let mut inner = db.lock().await;
+        let pubsub = &mut inner.pubsub;
+        let names = if names.is_empty() {
+            pubsub.subscriptions(self.id, pattern)
+        } else {
+            names
+        };
+        let command = Frame::Bulk(Bytes::copy_from_slice(name.as_bytes()));
+        if names.is_empty() {
+            let count = Frame::Integer(self.subscriptions as i64);
+            return vec![self.push(vec![command, Frame::Null, count])];
+        }
+
+        let mut res = Vec::with_capacity(names.len());
+        for name in names {
+            self.subscriptions = if subscribe {
+                pubsub.subscribe(self.id, &self.sender, name.clone(), pattern)
+            } else {
+                pubsub.unsubscribe(self.id, &name, pattern)
+            };
+            let count = Frame::Integer(self.subscriptions as i64);
+            res.push(self.push(vec![command.clone(), Frame::Bulk(name), count]));
+        }
+        res
+    }
+
+    // https://redis.io/commands/hello/
+    // HELLO [protover], the AUTH and SETNAME options aren't supported
+    // return: *14\r\n$6\r\nserver\r\n$5\r\nredis\r\n..., a map with RESP3
+    fn hello(&mut self, bulks: Vec<Bytes>) -> Result<Frame> {
+        if bulks.len() > 2 {
+            bail!("ERR syntax error")
+        }
+        if let Some(version) = bulks.get(1) {
+            self.resp3 = match bytes_to_u64(version.clone()) {
+                Ok(2) => false,
+                Ok(3) => true,
+                Ok(_) => bail!("NOPROTO unsupported protocol version"),
+                Err(_) => bail!("ERR Protocol version is not an integer or out of range"),
+            };
+        }
+
+        let role = if CONFIG.replicaof.is_none() {
+            "master"
+        } else {
+            "replica"
+        };
+        let fields = [
+            ("server", Frame::Bulk("redis".into())),
+            ("version", Frame::Bulk(env!("CARGO_PKG_VERSION").into())),
+            ("proto", Frame::Integer(if self.resp3 { 3 } else { 2 })),
+            ("id", Frame::Integer(self.id as i64)),
+            ("mode", Frame::Bulk("standalone".into())),
+            ("role", Frame::Bulk(role.into())),
+            ("modules", Frame::Array(vec![])),
+        ];
+        let fields = fields
+            .into_iter()
+            .map(|(name, value)| (Frame::Bulk(name.into()), value));
+        Ok(if self.resp3 {
+            Frame::Map(fields.collect())
+        } else {
+            Frame::Array(fields.flat_map(|(name, value)| [name, value]).collect())
+        })
+    }
+
+    // run SUBSCRIBE and friends or HELLO, which may reply several frames
+    async fn connection_cmd(&mut self, db: &Db, name: &str, frame: Frame) -> Result<Vec<Frame>> {
+        let bulks: Vec<Bytes> = frame.try_into()?;
+        Ok(match name {
+            "hello" => vec![self.hello(bulks)?],
+            _ => self.subscribe(db, name, bulks[1..].to_vec()).await,
+        })
+    }
+
+    // https://redis.io/commands/reset/
+    // RESET, discard the transaction, unwatch the keys, unsubscribe, switch back to RESP2 and
+    // select the database 0
+    // return: +RESET\r\n
+    async fn reset(&mut self, db: &mut Db) {
+        self.transaction = None;
+        self.watched.clear(db).await;
+        if self.subscriptions > 0 {
+            db.lock().await.pubsub.unsubscribe_all(self.id);
+            self.subscriptions = 0;
+        }
+        // the messages published before aren't written anymore
+        while self.receiver.try_recv().is_ok() {}
+        self.resp3 = false;
+        db.index = 0;
+    }
+}
+
+#[async_trait::async_trait]
+impl Connection for Client {
+    // inside EXEC, the replies to a command subscribing to several channels are grouped in an array
+    async fn execute(&mut self, db: &mut Db, frame: Frame) -> Result<Frame> {
+        let (name, _) = frame.cmd_name().expect("the command should be queued");
+        let mut res = self.connection_cmd(db, &name, frame).await?;
+        Ok(match res.len() {
+            1 => res.pop().expect("the reply should exist"),
+            _ => Frame::Array(res),
+        })
+    }
+}
+
+async fn handle(
+    stream: &mut TcpStream,
+    db: &mut Db,
+    client: &mut Client,
+    addr: SocketAddr,
+) -> Result<Option<()>> {
     // server_test(&mut stream).await;
     // return Ok(());
 
     if let Some(frame) = stream.read_frame().await? {
-        let cmd = frame.parse_cmd()?;
-        let res = cmd.execute(db).await?;
+        let transaction = &mut client.transaction;
+        let watched = &mut client.watched;
+        let cmd: Box<dyn CmdExecutor> = match frame.cmd_name() {
+            Some((name, _))
+                if client.subscriptions > 0
+                    && !client.resp3
+                    && !matches!(
+                        name.as_str(),
+                        "subscribe"
+                            | "unsubscribe"
+                            | "psubscribe"
+                            | "punsubscribe"
+                            | "ping"
+                            | "quit"
+                            | "reset"
+                    ) =>
+            {
+                bail!(
+                    "ERR Can't execute '{name}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / \
+                     QUIT / RESET are allowed in this context"
+                )
+            }
+            Some((name, _)) if name == "quit" => {
+                stream.write_frame(Frame::Simple("OK".to_string())).await?;
+                return Ok(None);
+            }
+            Some((name, _)) if name == "reset" => {
+                client.reset(db).await;
+                stream
+                    .write_frame(Frame::Simple("RESET".to_string()))
+                    .await?;
+                return Ok(Some(()));
+            }
+            Some((name, args)) if matches!(name.as_str(), "multi" | "exec" | "discard") => {
+                if args > 0 {
+                    if let Some(transaction) = transaction {
+                        transaction.abort();
+                    }
+                    return Err(wrong_args(&name));
+                }
+                let res = match name.as_str() {
+                    "multi" if transaction.is_some() => bail!("ERR MULTI calls can not be nested"),
+                    "multi" => {
+                        *transaction = Some(Transaction::default());
+                        Frame::Simple("OK".to_string())
+                    }
+                    _ => match (name.as_str(), transaction.take()) {
+                        ("exec", Some(transaction)) => {
+                            let watched = std::mem::take(watched);
+                            Exec {
+                                transaction,
+                                watched,
+                            }
+                            .execute(db, client)
+                            .await?
+                        }
+                        ("discard", Some(_)) => {
+                            watched.clear(db).await;
+                            Frame::Simple("OK".to_string())
+                        }
+                        _ => bail!("ERR {} without MULTI", name.to_uppercase()),
+                    },
+                };
+                stream.write_frame(res).await?;
+                return Ok(Some(()));
+            }
+            Some((name, _)) if name == "watch" => {
+                if let Some(transaction) = transaction {
+                    transaction.abort();
+                    bail!("ERR WATCH inside MULTI is not allowed");
+                }
+                let bulks: Vec<Bytes> = frame.try_into()?;
+                let watch = Watch::try_from(bulks)?;
+                watched.watch(db, watch.keys).await;
+                stream.write_frame(Frame::Simple("OK".to_string())).await?;
+                return Ok(Some(()));
+            }
+            Some((name, _)) if name == "unwatch" && transaction.is_none() => {
+                let cmd = frame.parse_cmd()?;
+                watched.clear(db).await;
+                cmd
+            }
+            Some((name, args))
+                if matches!(
+                    name.as_str(),
+                    "subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe" | "hello"
+                ) =>
+            {
+                if matches!(name.as_str(), "subscribe" | "psubscribe") && args == 0 {
+                    if let Some(transaction) = transaction {
+                        transaction.abort();
+                    }
+                    return Err(wrong_args(&name));
+                }
+                // queued like the other commands, EXEC runs them on behalf of the connection
+                if let Some(transaction) = transaction {
+                    let res = transaction.queue_connection(frame);
+                    stream.write_frame(res).await?;
+                    return Ok(Some(()));
+                }
+                for frame in client.connection_cmd(db, &name, frame).await? {
+                    stream.write_frame(frame).await?;
+                }
+                return Ok(Some(()));
+            }
+            // a subscribed client pings in band with RESP2
+            Some((name, args)) if name == "ping" && client.subscriptions > 0 && !client.resp3 => {
+                if args > 1 {
+                    return Err(wrong_args(&name));
+                }
+                let bulks: Vec<Bytes> = frame.try_into()?;
+                let msg = bulks.get(1).cloned().unwrap_or_default();
+                let res = Frame::Array(vec![Frame::Bulk("pong".into()), Frame::Bulk(msg)]);
+                stream.write_frame(res).await?;
+                return Ok(Some(()));
+            }
+            _ => match transaction {
+                Some(transaction) => {
+                    let res = transaction.queue(frame)?;
+                    stream.write_frame(res).await?;
+                    return Ok(Some(()));
+                }
+                None => frame.parse_cmd()?,
+            },
+        };
+        let res = if cmd.may_block() {
+            tokio::select! {
+                res = cmd.execute(db) => res?,
+                _ = closed(stream) => {
+                    debug!("{addr} turn off connection while blocked");
+                    return Ok(None);
+                }
+            }
+        } else {
+            cmd.execute(db).await?
+        };
         stream.write_frame(res).await?;
         Ok(Some(()))
     } else {
@@ -61,6 +420,15 @@ async fn handle(stream: &mut TcpStream, db: &mut Db, addr: SocketAddr) -> Result
     }
 }
 
+// resolve once the client closes the connection. Data sent in the meantime is left unread, and
+// the connection isn't watched anymore
+async fn closed(stream: &TcpStream) {
+    let mut buf = [0u8; 1];
+    if let Ok(1..) = stream.peek(&mut buf).await {
+        std::future::pending::<()>().await
+    }
+}
+
 #[allow(dead_code)]
 async fn client_test(cmd: &'static str) {
     let mut stream = TcpStream::connect("127.0.0.1:6379").await.unwrap();
@@ -76,3 +444,55 @@ async fn server_test(stream: &mut TcpStream) {
     let n = stream.read(&mut buf).await.unwrap();
     println!("{:?}", String::from_utf8(buf[0..n].to_vec()).unwrap());
 }
+
+#[cfg(test)]
+mod server_test {
+    use super::*;
+
+    fn cmd(args: &[&str]) -> Frame {
+        Frame::from(
+            args.iter()
+                .map(|arg| Bytes::copy_from_slice(arg.as_bytes()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn subscribe_should_be_queued_by_multi() {
+        let mut db = Db::new(vec![
+            Box::new(StringDb::new()) as Box<dyn StringDbManipulator>
+        ]);
+        let mut client = Client::new(1);
+        let mut transaction = Transaction::default();
+        transaction.queue_connection(cmd(&["subscribe", "a", "b"]));
+        transaction.queue(cmd(&["publish", "a", "hi"])).unwrap();
+        let res = Exec {
+            transaction,
+            watched: Watched::default(),
+        }
+        .execute(&mut db, &mut client)
+        .await
+        .unwrap();
+
+        let Frame::Array(res) = res else {
+            panic!("EXEC should reply an array")
+        };
+        let subscribed = |channel: &str, count| {
+            Frame::Array(vec![
+                Frame::Bulk("subscribe".into()),
+                Frame::Bulk(Bytes::copy_from_slice(channel.as_bytes())),
+                Frame::Integer(count),
+            ])
+        };
+        assert_eq!(
+            Frame::Array(vec![subscribed("a", 1), subscribed("b", 2)]),
+            res[0]
+        );
+        assert_eq!(Frame::Integer(1), res[1]);
+        assert_eq!(2, client.subscriptions);
+        assert_eq!(
+            Bytes::from("hi"),
+            client.receiver.try_recv().unwrap().payload
+        );
+    }
+}
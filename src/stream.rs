@@ -1,8 +1,6 @@
-use std::usize;
-
 use crate::{
     frame::Frame,
-    util::{bytes_to_string, bytes_to_u64},
+    util::{bytes_to_i64, bytes_to_string, bytes_to_u64},
 };
 use anyhow::{bail, Result};
 use bytes::{BufMut, Bytes};
@@ -45,20 +43,7 @@ impl FrameHandler for TcpStream {
     }
 
     async fn write_frame(&mut self, frame: Frame) -> Result<()> {
-        match frame {
-            // *<len>\r\n<Frame>...
-            Frame::Array(frames) => {
-                let header = format!("*{}\r\n", frames.len());
-                self.write_all(header.as_bytes()).await?;
-
-                for frame in frames {
-                    write_value(self, frame).await?;
-                }
-            }
-            _ => write_value(self, frame).await?,
-        }
-
-        Ok(())
+        write_value(self, frame).await
     }
 }
 
@@ -123,7 +108,8 @@ async fn read_value(stream: &mut TcpStream) -> Result<Frame> {
         b':' => {
             debug!("reading integer");
 
-            let res = read_decimal(stream).await?;
+            let line = read_line(stream).await?;
+            let res = bytes_to_i64(line)?;
 
             debug!(?res);
 
@@ -181,7 +167,39 @@ async fn write_value(stream: &mut TcpStream, frame: Frame) -> Result<()> {
             stream.write_all(b"$-1\r\n").await?;
             stream.flush().await?;
         }
-        Frame::Array(_) => unreachable!(),
+        // *-1\r\n
+        Frame::NullArray => {
+            stream.write_all(b"*-1\r\n").await?;
+            stream.flush().await?;
+        }
+        // *<len>\r\n<Frame>...
+        Frame::Array(frames) => {
+            let header = format!("*{}\r\n", frames.len());
+            stream.write_all(header.as_bytes()).await?;
+
+            for frame in frames {
+                Box::pin(write_value(stream, frame)).await?;
+            }
+        }
+        // %<len>\r\n<key Frame><value Frame>...
+        Frame::Map(entries) => {
+            let header = format!("%{}\r\n", entries.len());
+            stream.write_all(header.as_bytes()).await?;
+
+            for (key, value) in entries {
+                Box::pin(write_value(stream, key)).await?;
+                Box::pin(write_value(stream, value)).await?;
+            }
+        }
+        // ><len>\r\n<Frame>...
+        Frame::Push(frames) => {
+            let header = format!(">{}\r\n", frames.len());
+            stream.write_all(header.as_bytes()).await?;
+
+            for frame in frames {
+                Box::pin(write_value(stream, frame)).await?;
+            }
+        }
     }
 
     Ok(())
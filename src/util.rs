@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn bytes_to_string(bytes: Bytes) -> Result<String> {
     String::from_utf8(bytes.into()).map_err(|_| anyhow!("ERR syntax error"))
@@ -11,3 +12,193 @@ pub fn bytes_to_u64(bytes: Bytes) -> Result<u64> {
         .parse::<u64>()
         .map_err(|_| anyhow!("ERR syntax error"))
 }
+
+pub fn bytes_to_i64(bytes: Bytes) -> Result<i64> {
+    String::from_utf8(bytes.into())
+        .map_err(|_| anyhow!("ERR value is not an integer or out of range"))?
+        .parse::<i64>()
+        .map_err(|_| anyhow!("ERR value is not an integer or out of range"))
+}
+
+// parse a float the way redis does: "inf" and "-inf" are accepted but NaN isn't
+pub fn bytes_to_f64(bytes: Bytes) -> Result<f64> {
+    std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| anyhow!("ERR value is not a valid float"))
+}
+
+// milliseconds since the unix epoch, negative for times before it
+pub fn to_unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+// Match `string` against a glob-style `pattern` the way redis does:
+//  - `*` matches any sequence of bytes, `?` matches a single byte
+//  - `[abc]`, `[a-z]` and `[^a]` match a single byte in (or not in) the set
+//  - `\` escapes the next byte
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer_matches = false;
+    glob_match_impl(pattern, string, nocase, &mut skip_longer_matches)
+}
+
+fn glob_match_impl(
+    mut pattern: &[u8],
+    mut string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    while !pattern.is_empty() && !string.is_empty() {
+        match pattern[0] {
+            b'*' => {
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                while !string.is_empty() {
+                    if glob_match_impl(&pattern[1..], string, nocase, skip_longer_matches) {
+                        return true;
+                    }
+                    // the rest of the pattern failed to match even the whole remaining string, so
+                    // trying shorter suffixes (here or in any outer `*`) is pointless
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    string = &string[1..];
+                }
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => string = &string[1..],
+            b'[' => {
+                pattern = &pattern[1..];
+                let not = pattern.first() == Some(&b'^');
+                if not {
+                    pattern = &pattern[1..];
+                }
+
+                let mut matched = false;
+                loop {
+                    if pattern.len() >= 2 && pattern[0] == b'\\' {
+                        pattern = &pattern[1..];
+                        if pattern[0] == string[0] {
+                            matched = true;
+                        }
+                    } else if pattern.is_empty() || pattern[0] == b']' {
+                        // an unterminated class ends with the pattern
+                        break;
+                    } else if pattern.len() >= 3 && pattern[1] == b'-' {
+                        let (mut start, mut end, mut c) = (pattern[0], pattern[2], string[0]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+                        if nocase {
+                            start = start.to_ascii_lowercase();
+                            end = end.to_ascii_lowercase();
+                            c = c.to_ascii_lowercase();
+                        }
+                        pattern = &pattern[2..];
+                        if start <= c && c <= end {
+                            matched = true;
+                        }
+                    } else if eq(pattern[0], string[0]) {
+                        matched = true;
+                    }
+                    pattern = &pattern[1..];
+                }
+
+                if matched == not {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            b'\\' if pattern.len() >= 2 => {
+                pattern = &pattern[1..];
+                if !eq(pattern[0], string[0]) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            c => {
+                if !eq(c, string[0]) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+        }
+
+        pattern = pattern.get(1..).unwrap_or_default();
+        if string.is_empty() {
+            while pattern.first() == Some(&b'*') {
+                pattern = &pattern[1..];
+            }
+            break;
+        }
+    }
+
+    pattern.is_empty() && string.is_empty()
+}
+
+#[cfg(test)]
+mod util_test {
+    use super::*;
+
+    #[test]
+    fn glob_match_should_work() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "foo", true),
+            ("f*", "foo", true),
+            ("*o", "foo", true),
+            ("*x*", "foo", false),
+            ("f?o", "foo", true),
+            ("f?o", "fo", false),
+            ("h[ae]llo", "hello", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[b-a]llo", "hbllo", true),
+            ("h[a-b]llo", "hcllo", false),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("h[\\]]llo", "h]llo", true),
+            ("foo*", "foo", true),
+            ("foo**", "foo", true),
+            ("a*b*c", "aXbYc", true),
+            ("a*b*c", "aXbY", false),
+            ("[abc", "a", true),
+            ("", "", true),
+            ("", "a", false),
+        ];
+        for (pattern, string, expected) in cases {
+            assert_eq!(
+                *expected,
+                glob_match(pattern.as_bytes(), string.as_bytes(), false),
+                "pattern {pattern:?} against {string:?}"
+            );
+        }
+
+        assert!(glob_match(b"H[A-Z]LLO", b"hello", true));
+        assert!(!glob_match(b"H[A-Z]LLO", b"hello", false));
+        // must not take exponential time
+        assert!(!glob_match(
+            b"a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b",
+            &[b'a'; 100],
+            false
+        ));
+    }
+}